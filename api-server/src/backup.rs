@@ -0,0 +1,288 @@
+// api-server/src/backup.rs
+//
+// Writes encrypted static channel backups to a directory whenever the set of
+// channels of a node changes, keeping the newest `keep` per node. Each file
+// is `<node>-<unix secs>.scb.enc`:
+//
+//     "LCSCB1" | salt (16) | nonce (12) | ChaCha20-Poly1305 ciphertext
+//
+// with the key derived from the passphrase by Argon2id over the salt. Run
+// `api-server --decrypt-backup <file>` to get the backend's blob back.
+use anyhow::{anyhow, Result};
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use lightning_client::{config as driver_config, Capability, LightningClientDyn, NodeRegistry};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const MAGIC: &[u8] = b"LCSCB1";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+// ---------------------------------------------------------------------
+// Config
+// ---------------------------------------------------------------------
+#[derive(Deserialize, Clone)]
+pub struct BackupConfig {
+    pub dir: String,
+    /// Encryption passphrase; set this or `passphrase_file`.
+    #[serde(default)]
+    pub passphrase: Option<String>,
+    #[serde(default)]
+    pub passphrase_file: Option<String>,
+    /// How often nodes are checked for channel changes.
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+    /// Nodes to back up; every node that supports it when unset.
+    #[serde(default)]
+    pub nodes: Option<Vec<String>>,
+    /// Backups kept per node; older ones are deleted.
+    #[serde(default = "default_keep")]
+    pub keep: usize,
+}
+
+fn default_interval_secs() -> u64 {
+    60
+}
+
+fn default_keep() -> usize {
+    10
+}
+
+impl BackupConfig {
+    pub fn passphrase(&self) -> Result<String> {
+        let passphrase = match (&self.passphrase, &self.passphrase_file) {
+            (Some(p), None) => p.clone(),
+            (None, Some(path)) => driver_config::read_secret_file(path)?,
+            _ => {
+                return Err(anyhow!(
+                    "[backup] needs exactly one of passphrase and passphrase_file"
+                ))
+            }
+        };
+        if passphrase.is_empty() {
+            return Err(anyhow!("[backup] passphrase is empty"));
+        }
+        Ok(passphrase)
+    }
+}
+
+// ---------------------------------------------------------------------
+// Encryption
+// ---------------------------------------------------------------------
+fn cipher(passphrase: &str, salt: &[u8]) -> Result<ChaCha20Poly1305> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow!("Key derivation failed: {}", e))?;
+    Ok(ChaCha20Poly1305::new(&Key::from(key)))
+}
+
+pub fn encrypt(passphrase: &str, data: &[u8]) -> Result<Vec<u8>> {
+    let salt: [u8; SALT_LEN] = rand::random();
+    let nonce: [u8; NONCE_LEN] = rand::random();
+    let ciphertext = cipher(passphrase, &salt)?
+        .encrypt(&Nonce::from(nonce), data)
+        .map_err(|_| anyhow!("Backup encryption failed"))?;
+    Ok([MAGIC, &salt, &nonce, &ciphertext].concat())
+}
+
+pub fn decrypt(passphrase: &str, file: &[u8]) -> Result<Vec<u8>> {
+    let body = file
+        .strip_prefix(MAGIC)
+        .filter(|b| b.len() > SALT_LEN + NONCE_LEN)
+        .ok_or_else(|| anyhow!("Not an encrypted channel backup"))?;
+    let (salt, rest) = body.split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    let nonce: [u8; NONCE_LEN] = nonce.try_into()?;
+    cipher(passphrase, salt)?
+        .decrypt(&Nonce::from(nonce), ciphertext)
+        .map_err(|_| anyhow!("Wrong passphrase or corrupted backup"))
+}
+
+// ---------------------------------------------------------------------
+// Job
+// ---------------------------------------------------------------------
+pub struct BackupJob {
+    dir: PathBuf,
+    passphrase: String,
+    interval: Duration,
+    keep: usize,
+    nodes: Vec<(String, LightningClientDyn)>,
+}
+
+impl BackupJob {
+    pub async fn new(cfg: &BackupConfig, registry: &NodeRegistry) -> Result<Self> {
+        let passphrase = cfg.passphrase()?;
+        let dir = PathBuf::from(&cfg.dir);
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .map_err(|e| anyhow!("Cannot create backup dir '{}': {}", dir.display(), e))?;
+
+        let mut nodes = Vec::new();
+        match &cfg.nodes {
+            Some(names) => {
+                for name in names {
+                    let client = registry
+                        .get(name)
+                        .ok_or_else(|| anyhow!("[backup] node '{}' is not configured", name))?;
+                    nodes.push((name.clone(), client));
+                }
+            }
+            None => {
+                for name in registry.names() {
+                    let client = registry.get(name).expect("listed node");
+                    match client.capabilities().await {
+                        Ok(caps) if caps.supports(Capability::ChannelBackup) => {
+                            nodes.push((name.to_string(), client))
+                        }
+                        Ok(_) => {}
+                        Err(e) => eprintln!(
+                            "Backup: skipping node '{}', capabilities unknown: {}",
+                            name, e
+                        ),
+                    }
+                }
+            }
+        }
+
+        Ok(Self {
+            dir,
+            passphrase,
+            interval: Duration::from_secs(cfg.interval_secs.max(1)),
+            keep: cfg.keep.max(1),
+            nodes,
+        })
+    }
+
+    pub fn node_names(&self) -> Vec<&str> {
+        self.nodes.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    pub async fn run(self) {
+        // Channels covered by the last backup written per node.
+        let mut written: HashMap<String, Vec<String>> = HashMap::new();
+        let mut tick = actix_web::rt::time::interval(self.interval);
+        loop {
+            tick.tick().await;
+            for (name, client) in &self.nodes {
+                if let Err(e) = self.backup_if_changed(name, client, &mut written).await {
+                    eprintln!("Backup of node '{}' failed: {}", name, e);
+                }
+            }
+        }
+    }
+
+    async fn backup_if_changed(
+        &self,
+        name: &str,
+        client: &LightningClientDyn,
+        written: &mut HashMap<String, Vec<String>>,
+    ) -> Result<()> {
+        let backup = client.export_channel_backup().await?;
+        let mut channels = backup.channels.clone();
+        channels.sort();
+        if written.get(name) == Some(&channels) {
+            return Ok(());
+        }
+
+        // Never replace a good backup with one the node itself rejects.
+        let check = client.verify_channel_backup(&backup.data).await?;
+        if !check.valid {
+            return Err(anyhow!(
+                "node rejected its own backup: {}",
+                check.error.unwrap_or_default()
+            ));
+        }
+
+        // Key derivation and file I/O would stall the server's workers.
+        let (dir, name_owned, passphrase, keep) = (
+            self.dir.clone(),
+            name.to_string(),
+            self.passphrase.clone(),
+            self.keep,
+        );
+        let path = tokio::task::spawn_blocking(move || {
+            write_backup(&dir, &name_owned, &passphrase, &backup.data, keep)
+        })
+        .await??;
+        println!(
+            "Backup of node '{}' ({} channels) -> {}",
+            name,
+            channels.len(),
+            path.display()
+        );
+        written.insert(name.to_string(), channels);
+        Ok(())
+    }
+}
+
+/// Encrypts `data` into a new backup of `name`, then deletes all but the
+/// newest `keep` backups of that node.
+fn write_backup(
+    dir: &Path,
+    name: &str,
+    passphrase: &str,
+    data: &[u8],
+    keep: usize,
+) -> Result<PathBuf> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let path = dir.join(format!("{}-{}.scb.enc", name, now));
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, encrypt(passphrase, data)?)?;
+    fs::rename(&tmp, &path)?;
+
+    let mut backups: Vec<(u64, PathBuf)> = fs::read_dir(dir)?
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let secs = path
+                .file_name()?
+                .to_str()?
+                .strip_prefix(name)?
+                .strip_prefix('-')?
+                .strip_suffix(".scb.enc")?
+                .parse()
+                .ok()?;
+            Some((secs, path))
+        })
+        .collect();
+    backups.sort();
+    for (_, old) in backups.iter().rev().skip(keep) {
+        if let Err(e) = fs::remove_file(old) {
+            eprintln!("Backup: cannot delete {}: {}", old.display(), e);
+        }
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_only_the_newest_backups_of_the_node() {
+        let dir = std::env::temp_dir().join(format!("scb-{}", rand::random::<u64>()));
+        fs::create_dir_all(&dir).unwrap();
+        for (file, secs) in [("lnd", 100), ("lnd", 200), ("lnd", 300), ("lnd-b", 50)] {
+            fs::write(dir.join(format!("{}-{}.scb.enc", file, secs)), b"old").unwrap();
+        }
+
+        let path = write_backup(&dir, "lnd", "pw", b"scb", 2).unwrap();
+        assert_eq!(decrypt("pw", &fs::read(&path).unwrap()).unwrap(), b"scb");
+
+        let mut left: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        left.sort();
+        let newest = path.file_name().unwrap().to_str().unwrap().to_string();
+        let mut want = vec![newest, "lnd-300.scb.enc".into(), "lnd-b-50.scb.enc".into()];
+        want.sort();
+        assert_eq!(left, want);
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,102 @@
+// api-server/src/fiat.rs
+//
+// Invoices priced in fiat. The amount is converted with the configured rate
+// provider, and the applied rate is appended to a JSON-lines ledger so the
+// original price can be reconstructed for accounting.
+use anyhow::Result;
+use lightning_client::{Bolt11, Msat, RateProviderDyn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The fiat side of an invoice, as quoted when it was created.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FiatQuote {
+    pub amount: f64,
+    /// ISO 4217 code, upper case.
+    pub currency: String,
+    /// Price of one BTC in `currency` that was applied.
+    pub btc_price: f64,
+    pub amount_msat: Msat,
+    /// Unix seconds.
+    pub created_at: u64,
+}
+
+impl FiatQuote {
+    pub fn new(amount: f64, currency: String, btc_price: f64, amount_msat: Msat) -> Self {
+        Self {
+            amount,
+            currency,
+            btc_price,
+            amount_msat,
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct LedgerLine {
+    bolt11: Bolt11,
+    #[serde(flatten)]
+    quote: FiatQuote,
+}
+
+pub struct Fiat {
+    /// `None` when no `[rates]` provider is configured.
+    pub rates: Option<RateProviderDyn>,
+    path: PathBuf,
+    quotes: Mutex<HashMap<Bolt11, FiatQuote>>,
+}
+
+impl Fiat {
+    /// Loads the ledger at `path`, which is created on the first record.
+    pub fn open(rates: Option<RateProviderDyn>, path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let mut quotes = HashMap::new();
+        if path.exists() {
+            for (n, line) in fs::read_to_string(&path)?.lines().enumerate() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str::<LedgerLine>(line) {
+                    Ok(entry) => {
+                        quotes.insert(entry.bolt11, entry.quote);
+                    }
+                    Err(e) => eprintln!("{}:{}: skipping entry: {}", path.display(), n + 1, e),
+                }
+            }
+        }
+        Ok(Self {
+            rates,
+            path,
+            quotes: Mutex::new(quotes),
+        })
+    }
+
+    pub fn record(&self, bolt11: &Bolt11, quote: &FiatQuote) -> Result<()> {
+        let line = serde_json::to_string(&LedgerLine {
+            bolt11: bolt11.clone(),
+            quote: quote.clone(),
+        })?;
+        // Held across the write so lines from concurrent requests don't interleave.
+        let mut quotes = self.quotes.lock().unwrap();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", line)?;
+        quotes.insert(bolt11.clone(), quote.clone());
+        Ok(())
+    }
+
+    pub fn get(&self, bolt11: &Bolt11) -> Option<FiatQuote> {
+        self.quotes.lock().unwrap().get(bolt11).cloned()
+    }
+}
@@ -0,0 +1,696 @@
+// api-server/src/l402.rs
+//
+// L402 paywall for our own endpoints. A request to a priced resource
+// without a token gets `402 Payment Required` with an invoice and a
+// macaroon bound to the invoice's payment hash. Once paid, the client
+// retries with `Authorization: L402 <macaroon>:<preimage>`, which is
+// checked without a round trip to the node: the preimage must hash to the
+// payment hash, and the macaroon's caveats must allow this path, at no less
+// than its current price, and not have expired. Paths are matched after
+// percent-decoding, as the router sees them.
+//
+// Macaroons use the libmacaroons v2 binary format and the L402 identifier
+// layout (version, payment hash, token id), so existing L402 clients can
+// read and cache them.
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header,
+    web::Data,
+    Error, HttpResponse,
+};
+use actix_web_lab::middleware::Next;
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose, Engine as _};
+use hmac::{Hmac, Mac};
+use lightning_client::{LightningClientDyn, Msat, NodeRegistry, PaymentHash, Preimage};
+use serde::Deserialize;
+use serde_json::json;
+use sha2::Sha256;
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Identifier version of L402 tokens.
+const TOKEN_VERSION: u16 = 0;
+const IDENTIFIER_LEN: usize = 2 + 32 + 32;
+
+// ---------------------------------------------------------------------
+// Config
+// ---------------------------------------------------------------------
+#[derive(Deserialize, Clone)]
+pub struct L402Config {
+    /// Node issuing the invoices; the default node when unset.
+    #[serde(default)]
+    pub node: Option<String>,
+    /// 32-byte key macaroons are signed with, created on first start.
+    /// Replacing it invalidates every token handed out.
+    #[serde(default = "default_root_key_file")]
+    pub root_key_file: String,
+    /// How long a paid token stays valid.
+    #[serde(default = "default_token_ttl_secs")]
+    pub token_ttl_secs: u64,
+    #[serde(default)]
+    pub resources: Vec<PricedResource>,
+}
+
+/// Everything under `path` costs `price_msat` per token.
+#[derive(Deserialize, Clone)]
+pub struct PricedResource {
+    pub path: String,
+    pub price_msat: Msat,
+    /// Invoice description; "Access to <path>" when unset.
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+fn default_root_key_file() -> String {
+    "l402_root_key.bin".into()
+}
+fn default_token_ttl_secs() -> u64 {
+    24 * 60 * 60
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// ---------------------------------------------------------------------
+// Macaroons
+// ---------------------------------------------------------------------
+const FIELD_EOS: u8 = 0;
+const FIELD_LOCATION: u8 = 1;
+const FIELD_IDENTIFIER: u8 = 2;
+const FIELD_VID: u8 = 4;
+const FIELD_SIGNATURE: u8 = 6;
+
+/// A macaroon with first-party caveats only.
+#[derive(Debug, Clone)]
+struct Macaroon {
+    identifier: Vec<u8>,
+    caveats: Vec<String>,
+    signature: [u8; 32],
+}
+
+fn hmac_with(key: &[u8], data: &[u8]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC takes any key length");
+    mac.update(data);
+    mac
+}
+
+/// The last HMAC of the signature chain, as libmacaroons computes it;
+/// its output is the macaroon's signature.
+fn signature_mac(root_key: &[u8], identifier: &[u8], caveats: &[String]) -> HmacSha256 {
+    let key = hmac_with(b"macaroons-key-generator", root_key).finalize();
+    let mut mac = hmac_with(&key.into_bytes(), identifier);
+    for caveat in caveats {
+        mac = hmac_with(&mac.finalize().into_bytes(), caveat.as_bytes());
+    }
+    mac
+}
+
+fn write_field(out: &mut Vec<u8>, kind: u8, data: &[u8]) {
+    out.push(kind);
+    let mut len = data.len();
+    while len >= 0x80 {
+        out.push((len as u8) | 0x80);
+        len >>= 7;
+    }
+    out.push(len as u8);
+    out.extend_from_slice(data);
+}
+
+/// Reads the next field as `(type, data)`; `EOS` has no data.
+fn read_field<'a>(buf: &mut &'a [u8]) -> Result<(u8, &'a [u8])> {
+    let (&kind, rest) = buf
+        .split_first()
+        .ok_or_else(|| anyhow!("Truncated macaroon"))?;
+    *buf = rest;
+    if kind == FIELD_EOS {
+        return Ok((kind, &[]));
+    }
+    let (mut len, mut shift) = (0usize, 0);
+    loop {
+        let (&b, rest) = buf
+            .split_first()
+            .filter(|_| shift < 32)
+            .ok_or_else(|| anyhow!("Bad field length in macaroon"))?;
+        *buf = rest;
+        len |= usize::from(b & 0x7f) << shift;
+        if b & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    if buf.len() < len {
+        return Err(anyhow!("Truncated macaroon"));
+    }
+    let (data, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok((kind, data))
+}
+
+impl Macaroon {
+    fn mint(root_key: &[u8], identifier: Vec<u8>, caveats: Vec<String>) -> Self {
+        Self {
+            signature: signature_mac(root_key, &identifier, &caveats)
+                .finalize()
+                .into_bytes()
+                .into(),
+            identifier,
+            caveats,
+        }
+    }
+
+    fn signed_by(&self, root_key: &[u8]) -> bool {
+        signature_mac(root_key, &self.identifier, &self.caveats)
+            .verify_slice(&self.signature)
+            .is_ok()
+    }
+
+    fn encode(&self) -> String {
+        let mut out = vec![2];
+        write_field(&mut out, FIELD_IDENTIFIER, &self.identifier);
+        out.push(FIELD_EOS);
+        for caveat in &self.caveats {
+            write_field(&mut out, FIELD_IDENTIFIER, caveat.as_bytes());
+            out.push(FIELD_EOS);
+        }
+        out.push(FIELD_EOS);
+        write_field(&mut out, FIELD_SIGNATURE, &self.signature);
+        general_purpose::STANDARD.encode(out)
+    }
+
+    fn decode(s: &str) -> Result<Self> {
+        let bytes = general_purpose::STANDARD
+            .decode(s)
+            .or_else(|_| general_purpose::URL_SAFE_NO_PAD.decode(s.trim_end_matches('=')))
+            .map_err(|_| anyhow!("Macaroon is not base64"))?;
+        let mut buf = bytes
+            .strip_prefix(&[2])
+            .ok_or_else(|| anyhow!("Only v2 macaroons are supported"))?;
+
+        let mut field = read_field(&mut buf)?;
+        if field.0 == FIELD_LOCATION {
+            field = read_field(&mut buf)?;
+        }
+        if field.0 != FIELD_IDENTIFIER {
+            return Err(anyhow!("Macaroon has no identifier"));
+        }
+        let identifier = field.1.to_vec();
+        if read_field(&mut buf)?.0 != FIELD_EOS {
+            return Err(anyhow!("Malformed macaroon header"));
+        }
+
+        let mut caveats = Vec::new();
+        loop {
+            let mut field = read_field(&mut buf)?;
+            if field.0 == FIELD_EOS {
+                break;
+            }
+            if field.0 == FIELD_LOCATION {
+                field = read_field(&mut buf)?;
+            }
+            if field.0 != FIELD_IDENTIFIER {
+                return Err(anyhow!("Malformed caveat"));
+            }
+            let caveat =
+                String::from_utf8(field.1.to_vec()).map_err(|_| anyhow!("Caveat is not UTF-8"))?;
+            match read_field(&mut buf)?.0 {
+                FIELD_EOS => caveats.push(caveat),
+                FIELD_VID => return Err(anyhow!("Third-party caveats are not supported")),
+                _ => return Err(anyhow!("Malformed caveat")),
+            }
+        }
+
+        let (kind, sig) = read_field(&mut buf)?;
+        let signature = <[u8; 32]>::try_from(sig)
+            .ok()
+            .filter(|_| kind == FIELD_SIGNATURE && buf.is_empty())
+            .ok_or_else(|| anyhow!("Malformed macaroon signature"))?;
+        Ok(Self {
+            identifier,
+            caveats,
+            signature,
+        })
+    }
+
+    /// Payment hash from an L402 identifier.
+    fn payment_hash(&self) -> Result<PaymentHash> {
+        let id = &self.identifier;
+        if id.len() != IDENTIFIER_LEN || id[..2] != TOKEN_VERSION.to_be_bytes() {
+            return Err(anyhow!("Not an L402 token"));
+        }
+        PaymentHash::from_slice(&id[2..34])
+    }
+}
+
+// ---------------------------------------------------------------------
+// Paywall
+// ---------------------------------------------------------------------
+pub struct L402 {
+    node: LightningClientDyn,
+    root_key: [u8; 32],
+    token_ttl_secs: u64,
+    resources: Vec<PricedResource>,
+}
+
+/// Loads the root key at `path`, creating it on first start.
+fn load_or_create_root_key(path: &Path) -> Result<[u8; 32]> {
+    if path.exists() {
+        <[u8; 32]>::try_from(fs::read(path)?)
+            .map_err(|_| anyhow!("{} must be 32 bytes", path.display()))
+    } else {
+        let key = rand::random::<[u8; 32]>();
+        fs::write(path, key)?;
+        eprintln!("Generated new L402 root key -> {}", path.display());
+        Ok(key)
+    }
+}
+
+impl L402 {
+    pub fn new(cfg: &L402Config, registry: &NodeRegistry) -> Result<Self> {
+        let node = match &cfg.node {
+            Some(name) => registry
+                .get(name)
+                .ok_or_else(|| anyhow!("l402 node '{}' is not configured", name))?,
+            None => registry.default_node(),
+        };
+        let mut resources = cfg.resources.clone();
+        for resource in &mut resources {
+            if !resource.path.starts_with('/') {
+                return Err(anyhow!(
+                    "L402 resource '{}' must start with /",
+                    resource.path
+                ));
+            }
+            if resource.price_msat == Msat::ZERO {
+                return Err(anyhow!("L402 resource '{}' needs a price", resource.path));
+            }
+            resource.path = resource.path.trim_end_matches('/').to_string();
+        }
+        if cfg.token_ttl_secs == 0 {
+            return Err(anyhow!("token_ttl_secs must be positive"));
+        }
+
+        Ok(Self {
+            node,
+            root_key: load_or_create_root_key(Path::new(&cfg.root_key_file))?,
+            token_ttl_secs: cfg.token_ttl_secs,
+            resources,
+        })
+    }
+
+    pub fn resources(&self) -> &[PricedResource] {
+        &self.resources
+    }
+
+    /// The most specific priced resource containing `path`.
+    fn resource_for(&self, path: &str) -> Option<&PricedResource> {
+        self.resources
+            .iter()
+            .filter(|r| {
+                r.path.is_empty()
+                    || path == r.path
+                    || path
+                        .strip_prefix(&r.path)
+                        .is_some_and(|rest| rest.starts_with('/'))
+            })
+            .max_by_key(|r| r.path.len())
+    }
+
+    /// A token for `resource` bound to the invoice with `hash`.
+    fn mint(&self, hash: &PaymentHash, resource: &PricedResource, expires_at: u64) -> Macaroon {
+        let mut identifier = TOKEN_VERSION.to_be_bytes().to_vec();
+        identifier.extend_from_slice(hash.as_bytes());
+        identifier.extend_from_slice(&rand::random::<[u8; 32]>());
+        Macaroon::mint(
+            &self.root_key,
+            identifier,
+            vec![
+                format!("path={}", resource.path),
+                format!("price_msat={}", resource.price_msat.as_msat()),
+                format!("expires_at={}", expires_at),
+            ],
+        )
+    }
+
+    /// Checks a token presented for `resource`. It must name the price it
+    /// was bought at, so raising a price retires the cheaper tokens.
+    fn verify(&self, resource: &PricedResource, macaroon: &str, preimage: &str) -> Result<()> {
+        let macaroon = Macaroon::decode(macaroon)?;
+        if !macaroon.signed_by(&self.root_key) {
+            return Err(anyhow!("Invalid macaroon signature"));
+        }
+        let preimage: Preimage = preimage.parse()?;
+        if preimage.payment_hash() != macaroon.payment_hash()? {
+            return Err(anyhow!("Preimage does not match the token's invoice"));
+        }
+
+        let (mut scoped, mut priced) = (false, false);
+        for caveat in &macaroon.caveats {
+            let (key, value) = caveat
+                .split_once('=')
+                .map(|(k, v)| (k.trim(), v.trim()))
+                .ok_or_else(|| anyhow!("Malformed caveat '{}'", caveat))?;
+            let number = || {
+                value
+                    .parse::<u64>()
+                    .map_err(|_| anyhow!("Malformed caveat '{}'", caveat))
+            };
+            match key {
+                "path" => {
+                    if value != resource.path {
+                        return Err(anyhow!("Token is not valid for this resource"));
+                    }
+                    scoped = true;
+                }
+                "price_msat" => {
+                    if number()? < resource.price_msat.as_msat() {
+                        return Err(anyhow!("Token was paid for at a lower price"));
+                    }
+                    priced = true;
+                }
+                "expires_at" => {
+                    if now() >= number()? {
+                        return Err(anyhow!("Token has expired"));
+                    }
+                }
+                _ => return Err(anyhow!("Unknown caveat '{}'", key)),
+            }
+        }
+        if !scoped {
+            return Err(anyhow!("Token is not scoped to a resource"));
+        }
+        if !priced {
+            return Err(anyhow!("Token does not say what was paid"));
+        }
+        Ok(())
+    }
+
+    /// A 402 with a fresh invoice and macaroon for `resource`.
+    async fn challenge(&self, resource: &PricedResource, reason: Option<String>) -> HttpResponse {
+        let description = resource
+            .description
+            .clone()
+            .unwrap_or_else(|| format!("Access to {}", resource.path));
+        let bolt11 = match self
+            .node
+            .create_invoice(resource.price_msat, None, Some(&description))
+            .await
+        {
+            Ok(bolt11) => bolt11,
+            Err(e) => {
+                eprintln!("L402: could not create an invoice: {}", e);
+                return HttpResponse::ServiceUnavailable()
+                    .json(json!({ "error": "payments are unavailable" }));
+            }
+        };
+        let Some(hash) = bolt11.payment_hash() else {
+            eprintln!("L402: invoice without a payment hash: {}", bolt11);
+            return HttpResponse::ServiceUnavailable()
+                .json(json!({ "error": "payments are unavailable" }));
+        };
+
+        let expires_at = now().saturating_add(self.token_ttl_secs);
+        let macaroon = self.mint(&hash, resource, expires_at).encode();
+
+        HttpResponse::PaymentRequired()
+            .insert_header((
+                header::WWW_AUTHENTICATE,
+                format!("L402 macaroon=\"{}\", invoice=\"{}\"", macaroon, bolt11),
+            ))
+            .json(json!({
+                "error": reason.unwrap_or_else(|| "payment required".into()),
+                "macaroon": macaroon,
+                "invoice": bolt11,
+                "price_msat": resource.price_msat,
+                "expires_at": expires_at,
+            }))
+    }
+}
+
+/// Fully percent-decodes a request path. The router matches a partly
+/// decoded path and extractors decode the rest, so matching the raw path
+/// would let `/paid` be reached as `/%70aid` for free.
+fn decode_path(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|hex| bytes[i] == b'%' && hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(b) => {
+                out.push(b);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// `L402 <macaroon>:<preimage>`, or the older `LSAT` scheme.
+fn parse_authorization(value: &str) -> Option<(&str, &str)> {
+    let (scheme, token) = value.trim().split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("L402") && !scheme.eq_ignore_ascii_case("LSAT") {
+        return None;
+    }
+    token.trim().rsplit_once(':')
+}
+
+/// Middleware charging for the configured resources; requests elsewhere,
+/// or with no `[l402]` config, pass straight through.
+pub async fn paywall(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let Some(l402) = req.app_data::<Data<L402>>().cloned() else {
+        return Ok(next.call(req).await?.map_into_left_body());
+    };
+    let Some(resource) = l402.resource_for(&decode_path(req.path())) else {
+        return Ok(next.call(req).await?.map_into_left_body());
+    };
+
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_authorization);
+    let reason = match token {
+        Some((macaroon, preimage)) => match l402.verify(resource, macaroon, preimage) {
+            Ok(()) => return Ok(next.call(req).await?.map_into_left_body()),
+            Err(e) => Some(e.to_string()),
+        },
+        None => None,
+    };
+
+    let resp = l402.challenge(resource, reason).await;
+    Ok(req.into_response(resp).map_into_right_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use actix_web::{web, App};
+    use actix_web_lab::middleware::from_fn;
+    use lightning_client::cln::ClnClient;
+    use lightning_client::config::ConnectionConfig;
+    use std::sync::Arc;
+
+    const ROOT_KEY: [u8; 32] = [5; 32];
+    const PREIMAGE: [u8; 32] = [7; 32];
+
+    // The example from the libmacaroons README, in the v2 binary format.
+    const README_KEY: &[u8] = b"this is our super secret key; only we should know it";
+    const README_MACAROON: &str = "AgEOaHR0cDovL215YmFuay8CFndlIHVzZWQgb3VyIHNlY3JldCBrZXkAAhRhY2NvdW50ID0gMzczNTkyODU1OQAABiAe_kdj8pDbzgwdCEdzZ-EfTu5FamSTPPZi15dy27ghKA";
+
+    fn resource(path: &str, price_msat: u64) -> PricedResource {
+        PricedResource {
+            path: path.into(),
+            price_msat: Msat::from_msat(price_msat),
+            description: None,
+        }
+    }
+
+    /// A paywall whose node is unreachable, so a challenge answers 503.
+    fn stub(resources: Vec<PricedResource>) -> L402 {
+        let node = ClnClient::new("http://127.0.0.1:1", None, &ConnectionConfig::default());
+        L402 {
+            node: Arc::new(node.unwrap()),
+            root_key: ROOT_KEY,
+            token_ttl_secs: 60,
+            resources,
+        }
+    }
+
+    fn token(l402: &L402, resource: &PricedResource, expires_at: u64) -> String {
+        let hash = Preimage::from_slice(&PREIMAGE).unwrap().payment_hash();
+        l402.mint(&hash, resource, expires_at).encode()
+    }
+
+    #[test]
+    fn macaroon_round_trips() {
+        let minted = Macaroon::mint(&ROOT_KEY, b"id".to_vec(), vec!["a=1".into(), "b=2".into()]);
+        let decoded = Macaroon::decode(&minted.encode()).unwrap();
+        assert_eq!(decoded.identifier, b"id");
+        assert_eq!(decoded.caveats, ["a=1", "b=2"]);
+        assert_eq!(decoded.signature, minted.signature);
+        assert!(decoded.signed_by(&ROOT_KEY));
+        assert!(!decoded.signed_by(&[6; 32]));
+    }
+
+    #[test]
+    fn reads_the_libmacaroons_example() {
+        let m = Macaroon::decode(README_MACAROON).unwrap();
+        assert_eq!(m.identifier, b"we used our secret key");
+        assert_eq!(m.caveats, ["account = 3735928559"]);
+        assert_eq!(
+            hex::encode(m.signature),
+            "1efe4763f290dbce0c1d08477367e11f4eee456a64933cf662d79772dbb82128"
+        );
+        assert!(m.signed_by(README_KEY));
+
+        let bare = Macaroon::mint(README_KEY, m.identifier, Vec::new());
+        assert_eq!(
+            hex::encode(bare.signature),
+            "e3d9e02908526c4c0039ae15114115d97fdd68bf2ba379b342aaf0f617d0552f"
+        );
+    }
+
+    #[test]
+    fn rejects_tampered_and_truncated_macaroons() {
+        let minted = Macaroon::mint(&ROOT_KEY, b"id".to_vec(), vec!["path=/a".into()]);
+        let bytes = general_purpose::STANDARD.decode(minted.encode()).unwrap();
+        for len in 0..bytes.len() {
+            let cut = general_purpose::STANDARD.encode(&bytes[..len]);
+            assert!(Macaroon::decode(&cut).is_err(), "{} bytes", len);
+        }
+        let longer = general_purpose::STANDARD.encode([bytes.as_slice(), &[0]].concat());
+        assert!(Macaroon::decode(&longer).is_err());
+        let v1 = general_purpose::STANDARD.encode([&[1], &bytes[1..]].concat());
+        assert!(Macaroon::decode(&v1).is_err());
+
+        // A widened caveat no longer matches the signature.
+        let widened = Macaroon {
+            caveats: vec!["path=/".into()],
+            ..minted.clone()
+        };
+        assert!(!Macaroon::decode(&widened.encode())
+            .unwrap()
+            .signed_by(&ROOT_KEY));
+        let dropped = Macaroon {
+            caveats: Vec::new(),
+            ..minted
+        };
+        assert!(!Macaroon::decode(&dropped.encode())
+            .unwrap()
+            .signed_by(&ROOT_KEY));
+
+        // Third-party caveats carry a verification id.
+        let mut third_party = vec![2];
+        write_field(&mut third_party, FIELD_IDENTIFIER, b"id");
+        third_party.push(FIELD_EOS);
+        write_field(&mut third_party, FIELD_IDENTIFIER, b"cid");
+        write_field(&mut third_party, FIELD_VID, b"vid");
+        third_party.push(FIELD_EOS);
+        let err = Macaroon::decode(&general_purpose::STANDARD.encode(third_party)).unwrap_err();
+        assert!(err.to_string().contains("Third-party"), "{}", err);
+    }
+
+    #[test]
+    fn tokens_are_checked_against_the_resource() {
+        let paid = resource("/api/paid", 1_000);
+        let l402 = stub(vec![paid.clone()]);
+        let preimage = hex::encode(PREIMAGE);
+        let valid = token(&l402, &paid, now() + 60);
+        l402.verify(&paid, &valid, &preimage).unwrap();
+
+        let err =
+            |r: &PricedResource, t: &str, p: &str| l402.verify(r, t, p).unwrap_err().to_string();
+        assert!(err(&paid, &valid, &hex::encode([8; 32])).contains("Preimage"));
+        assert!(err(&resource("/api/other", 1_000), &valid, &preimage).contains("resource"));
+        assert!(err(&resource("/api/paid", 2_000), &valid, &preimage).contains("lower price"));
+        assert!(err(&paid, &token(&l402, &paid, now() - 1), &preimage).contains("expired"));
+
+        // Tokens minted before prices were recorded are not accepted.
+        let hash = Preimage::from_slice(&PREIMAGE).unwrap().payment_hash();
+        let mut unpriced = l402.mint(&hash, &paid, now() + 60);
+        unpriced.caveats.retain(|c| !c.starts_with("price_msat="));
+        let unpriced = Macaroon::mint(&ROOT_KEY, unpriced.identifier, unpriced.caveats);
+        assert!(err(&paid, &unpriced.encode(), &preimage).contains("what was paid"));
+
+        let other_key = L402 {
+            root_key: [6; 32],
+            ..stub(Vec::new())
+        };
+        assert!(other_key.verify(&paid, &valid, &preimage).is_err());
+    }
+
+    #[test]
+    fn priced_paths_match_however_they_are_encoded() {
+        let l402 = stub(vec![
+            resource("/api/paid", 1_000),
+            resource("/api/paid/big", 5_000),
+        ]);
+        let priced = |path: &str| l402.resource_for(&decode_path(path)).map(|r| r.price_msat);
+        for path in [
+            "/api/paid",
+            "/api/%70aid",
+            "/%61pi/paid/x",
+            "/api/paid%2Fx",
+            "/api%2fpaid",
+        ] {
+            assert_eq!(priced(path), Some(Msat::from_msat(1_000)), "{}", path);
+        }
+        assert_eq!(priced("/api/paid/%62ig"), Some(Msat::from_msat(5_000)));
+        assert_eq!(priced("/api/paidx"), None);
+        assert_eq!(priced("/api/free"), None);
+        assert_eq!(decode_path("/a%2"), "/a%2");
+        assert_eq!(decode_path("/a%zz%41"), "/a%zzA");
+        assert_eq!(decode_path("/a%+1"), "/a%+1");
+    }
+
+    #[actix_web::test]
+    async fn encoded_paths_do_not_skip_the_paywall() {
+        let paid = resource("/api/paid", 1_000);
+        let l402 = Data::new(stub(vec![paid.clone()]));
+        let auth = format!(
+            "L402 {}:{}",
+            token(&l402, &paid, now() + 60),
+            hex::encode(PREIMAGE)
+        );
+        let app = init_service(
+            App::new()
+                .app_data(l402)
+                .wrap(from_fn(paywall))
+                .route("/api/paid", web::get().to(|| async { "content" })),
+        )
+        .await;
+
+        for path in ["/api/paid", "/api/%70aid", "/%61pi/paid"] {
+            let resp = call_service(&app, TestRequest::get().uri(path).to_request()).await;
+            assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE, "{}", path);
+        }
+
+        // The router does send the encoded path to the handler.
+        let req = TestRequest::get()
+            .uri("/api/%70aid")
+            .insert_header((header::AUTHORIZATION, auth))
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,539 @@
+// api-server/src/lightning_address.rs
+//
+// Serves `user@domain` Lightning Addresses (LUD-16) as LNURL-pay endpoints
+// (LUD-06) backed by the configured nodes. Each user has their own amount
+// limits and comment length (LUD-12). With a nostr key configured payers can
+// zap (NIP-57): once a zap invoice is paid, a receipt is published to the
+// relays named in the zap request. Unpaid zap invoices are saved, so their
+// receipts survive a restart. Invoices can be attributed to their user in a
+// JSON-lines ledger.
+use anyhow::{anyhow, Result};
+use lightning_client::nostr_relay::RelayPool;
+use lightning_client::{description_hash, Bolt11, Msat, NodeRegistry};
+use lightning_client::{lnurl, PaymentHash};
+use nostr::event::FinalizeEvent;
+use nostr::prelude::{Event, EventBuilder, Keys, Kind, Tag};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Zap invoices still unpaid after this are no longer watched.
+const ZAP_WATCH_FOR: Duration = Duration::from_secs(60 * 60);
+const ZAP_POLL_INTERVAL: Duration = Duration::from_secs(5);
+const MAX_ZAP_RELAYS: usize = 10;
+/// How long relay connections stay open to deliver a receipt.
+const RECEIPT_LINGER: Duration = Duration::from_secs(30);
+
+// ---------------------------------------------------------------------
+// Config
+// ---------------------------------------------------------------------
+#[derive(Deserialize, Clone)]
+pub struct LightningAddressConfig {
+    #[serde(default)]
+    pub users: HashMap<String, AddressUser>,
+    /// JSON-lines file attributing invoices to users; off when unset.
+    #[serde(default)]
+    pub ledger: Option<String>,
+    /// Key signing zap receipts, hex or nsec; zaps are off when unset.
+    #[serde(default)]
+    pub nostr_secret_key: Option<String>,
+    /// JSON file of zap invoices still waiting to be paid.
+    #[serde(default = "default_pending_zaps")]
+    pub pending_zaps: String,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct AddressUser {
+    /// Node receiving the payments; the default node when unset.
+    #[serde(default)]
+    pub node: Option<String>,
+    #[serde(default = "default_min_msat")]
+    pub min_msat: Msat,
+    #[serde(default = "default_max_msat")]
+    pub max_msat: Msat,
+    /// Longest payer comment accepted; 0 turns comments off.
+    #[serde(default)]
+    pub comment_allowed: usize,
+    /// Shown to payers; "Payment to user@domain" when unset.
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+fn default_pending_zaps() -> String {
+    "pending_zaps.json".into()
+}
+fn default_min_msat() -> Msat {
+    Msat::from_msat(1_000)
+}
+fn default_max_msat() -> Msat {
+    Msat::from_msat(1_000_000_000)
+}
+
+// ---------------------------------------------------------------------
+// Attribution
+// ---------------------------------------------------------------------
+
+/// Who an invoice was created for, and by whom when it is a zap.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddressPayment {
+    pub user: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    /// Author of the zap request, hex.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub zapper: Option<String>,
+    /// Unix seconds.
+    pub created_at: u64,
+}
+
+#[derive(Serialize)]
+pub struct AddressInfo {
+    pub address: String,
+    /// LUD-01 form of the pay endpoint, for wallets without LUD-16.
+    pub lnurl: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LedgerLine {
+    hash: PaymentHash,
+    bolt11: Bolt11,
+    #[serde(flatten)]
+    payment: AddressPayment,
+}
+
+// ---------------------------------------------------------------------
+// Service
+// ---------------------------------------------------------------------
+#[derive(Clone, Serialize, Deserialize)]
+struct PendingZap {
+    /// Node that made the invoice.
+    node: String,
+    bolt11: Bolt11,
+    /// The zap request as received; its hash is in the invoice.
+    raw: String,
+    /// Unix seconds.
+    created_at: u64,
+}
+
+pub struct LightningAddresses {
+    users: HashMap<String, AddressUser>,
+    domain: String,
+    base_url: String,
+    keys: Option<Keys>,
+    ledger: Option<PathBuf>,
+    attributed: Mutex<HashMap<PaymentHash, AddressPayment>>,
+    zaps_path: PathBuf,
+    zaps: Mutex<HashMap<PaymentHash, PendingZap>>,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl LightningAddresses {
+    pub fn new(
+        cfg: &LightningAddressConfig,
+        public_url: &str,
+        registry: &NodeRegistry,
+    ) -> Result<Self> {
+        let base_url = public_url.trim_end_matches('/').to_string();
+        let domain = base_url
+            .split_once("://")
+            .map_or(base_url.as_str(), |(_, rest)| rest)
+            .split('/')
+            .next()
+            .filter(|d| !d.is_empty())
+            .ok_or_else(|| anyhow!("Invalid [api] public_url '{}'", public_url))?
+            .to_string();
+
+        let mut users = HashMap::new();
+        for (name, user) in &cfg.users {
+            let name = name.to_ascii_lowercase();
+            if name.is_empty()
+                || !name
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b"-_.+".contains(&b))
+            {
+                return Err(anyhow!("Invalid Lightning Address user '{}'", name));
+            }
+            if user.min_msat == Msat::ZERO || user.min_msat > user.max_msat {
+                return Err(anyhow!("{}: need 0 < min_msat <= max_msat", name));
+            }
+            if let Some(node) = &user.node {
+                if registry.get(node).is_none() {
+                    return Err(anyhow!("{}: node '{}' is not configured", name, node));
+                }
+            }
+            users.insert(name, user.clone());
+        }
+
+        let keys = cfg
+            .nostr_secret_key
+            .as_deref()
+            .map(Keys::parse)
+            .transpose()
+            .map_err(|e| anyhow!("Invalid Lightning Address nostr key: {}", e))?;
+
+        let ledger = cfg.ledger.as_ref().map(PathBuf::from);
+        let mut attributed = HashMap::new();
+        if let Some(path) = ledger.as_ref().filter(|p| p.exists()) {
+            for (n, line) in fs::read_to_string(path)?.lines().enumerate() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str::<LedgerLine>(line) {
+                    Ok(entry) => {
+                        attributed.insert(entry.hash, entry.payment);
+                    }
+                    Err(e) => eprintln!("{}:{}: skipping entry: {}", path.display(), n + 1, e),
+                }
+            }
+        }
+
+        let zaps_path = PathBuf::from(&cfg.pending_zaps);
+        let zaps = match &keys {
+            Some(_) => load_zaps(&zaps_path)?,
+            None => HashMap::new(),
+        };
+
+        Ok(Self {
+            users,
+            domain,
+            base_url,
+            keys,
+            ledger,
+            attributed: Mutex::new(attributed),
+            zaps_path,
+            zaps: Mutex::new(zaps),
+        })
+    }
+
+    pub fn addresses(&self) -> Vec<AddressInfo> {
+        let mut addresses: Vec<_> = self
+            .users
+            .keys()
+            .map(|name| AddressInfo {
+                address: format!("{}@{}", name, self.domain),
+                lnurl: lnurl::encode_lnurl(&format!(
+                    "{}/.well-known/lnurlp/{}",
+                    self.base_url, name
+                )),
+            })
+            .collect();
+        addresses.sort_by(|a, b| a.address.cmp(&b.address));
+        addresses
+    }
+
+    pub fn zaps_enabled(&self) -> bool {
+        self.keys.is_some()
+    }
+
+    pub fn attribution(&self, hash: &PaymentHash) -> Option<AddressPayment> {
+        self.attributed.lock().unwrap().get(hash).cloned()
+    }
+
+    fn user(&self, name: &str) -> Result<(String, &AddressUser)> {
+        let name = name.to_ascii_lowercase();
+        let user = self
+            .users
+            .get(&name)
+            .ok_or_else(|| anyhow!("Unknown user '{}'", name))?;
+        Ok((name, user))
+    }
+
+    /// Same string every time: its hash goes into the invoices.
+    fn metadata(&self, name: &str, user: &AddressUser) -> String {
+        let address = format!("{}@{}", name, self.domain);
+        let description = user
+            .description
+            .clone()
+            .unwrap_or_else(|| format!("Payment to {}", address));
+        json!([["text/plain", description], ["text/identifier", address]]).to_string()
+    }
+
+    /// LUD-06 pay request for `name`.
+    pub fn pay_request(&self, name: &str) -> Result<Value> {
+        let (name, user) = self.user(name)?;
+        let mut res = json!({
+            "tag": "payRequest",
+            "callback": format!("{}/lnurlp/{}/callback", self.base_url, name),
+            "minSendable": user.min_msat,
+            "maxSendable": user.max_msat,
+            "metadata": self.metadata(&name, user),
+            "commentAllowed": user.comment_allowed,
+        });
+        if let Some(keys) = &self.keys {
+            res["allowsNostr"] = json!(true);
+            res["nostrPubkey"] = json!(keys.public_key().to_hex());
+        }
+        Ok(res)
+    }
+
+    /// Creates the invoice for a pay callback. With `nostr` it is a zap
+    /// and the invoice commits to the zap request instead of the metadata.
+    pub async fn invoice(
+        &self,
+        registry: &NodeRegistry,
+        name: &str,
+        amount: Msat,
+        comment: Option<&str>,
+        nostr: Option<&str>,
+    ) -> Result<Value> {
+        let (name, user) = self.user(name)?;
+        if amount < user.min_msat || amount > user.max_msat {
+            return Err(anyhow!(
+                "Amount must be between {} and {}",
+                user.min_msat,
+                user.max_msat
+            ));
+        }
+        let comment = comment.map(str::trim).filter(|c| !c.is_empty());
+        if comment.is_some_and(|c| c.chars().count() > user.comment_allowed) {
+            return Err(anyhow!(
+                "Comments are limited to {} characters",
+                user.comment_allowed
+            ));
+        }
+        let zap = nostr.map(|raw| self.zap_request(raw, amount)).transpose()?;
+        let description = match &zap {
+            Some((raw, _)) => raw.clone(),
+            None => self.metadata(&name, user),
+        };
+
+        let node_name = user.node.as_deref().unwrap_or(registry.default_name());
+        let node = registry
+            .get(node_name)
+            .ok_or_else(|| anyhow!("Node '{}' is not configured", node_name))?;
+        let bolt11 = node
+            .create_invoice_hashed(amount, None, &description)
+            .await?;
+        // A backend that drops the hash would hand out unverifiable invoices.
+        if bolt11.description_hash() != Some(description_hash(&description)) {
+            return Err(anyhow!(
+                "Node did not put the description hash in the invoice"
+            ));
+        }
+        let hash = bolt11
+            .payment_hash()
+            .ok_or_else(|| anyhow!("Invoice has no payment hash"))?;
+
+        if let Some(path) = &self.ledger {
+            let payment = AddressPayment {
+                user: name.clone(),
+                comment: comment.map(str::to_string),
+                zapper: zap.as_ref().map(|(_, req)| req.pubkey.to_hex()),
+                created_at: now_secs(),
+            };
+            let line = serde_json::to_string(&LedgerLine {
+                hash,
+                bolt11: bolt11.clone(),
+                payment: payment.clone(),
+            })?;
+            // Held across the write so lines from concurrent requests don't interleave.
+            let mut attributed = self.attributed.lock().unwrap();
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(file, "{}", line)?;
+            attributed.insert(hash, payment);
+        }
+        if let Some((raw, _)) = zap {
+            let mut zaps = self.zaps.lock().unwrap();
+            zaps.insert(
+                hash,
+                PendingZap {
+                    node: node_name.to_string(),
+                    bolt11: bolt11.clone(),
+                    raw,
+                    created_at: now_secs(),
+                },
+            );
+            // Without it saved, a restart would lose the receipt.
+            if let Err(e) = save_zaps(&self.zaps_path, &zaps) {
+                zaps.remove(&hash);
+                return Err(e);
+            }
+        }
+
+        Ok(json!({ "pr": bolt11, "routes": [] }))
+    }
+
+    /// Checks a kind 9734 zap request as NIP-57 appendix D asks.
+    fn zap_request(&self, raw: &str, amount: Msat) -> Result<(String, Event)> {
+        if self.keys.is_none() {
+            return Err(anyhow!("This server does not accept zaps"));
+        }
+        let request = Event::from_json(raw).map_err(|e| anyhow!("Invalid zap request: {}", e))?;
+        request
+            .verify()
+            .map_err(|e| anyhow!("Invalid zap request: {}", e))?;
+        if request.kind != Kind::ZapRequest {
+            return Err(anyhow!("Zap request must be kind 9734"));
+        }
+
+        let count = |kind: &str| request.tags.iter().filter(|t| t.kind() == kind).count();
+        if count("p") != 1 {
+            return Err(anyhow!("Zap request must have exactly one p tag"));
+        }
+        if count("e") > 1 {
+            return Err(anyhow!("Zap request may have at most one e tag"));
+        }
+        if relays_of(&request).is_empty() {
+            return Err(anyhow!("Zap request names no relays"));
+        }
+        let tagged_amount = request
+            .tags
+            .iter()
+            .find(|t| t.kind() == "amount")
+            .and_then(|t| t.content());
+        if tagged_amount.is_some_and(|a| a != amount.as_msat().to_string()) {
+            return Err(anyhow!("Zap request amount does not match"));
+        }
+        Ok((raw.to_string(), request))
+    }
+
+    /// Publishes receipts for zap invoices as they are paid. Runs forever.
+    pub async fn watch_zaps(&self, registry: &NodeRegistry) {
+        let Some(keys) = &self.keys else {
+            return;
+        };
+        let mut tick = actix_web::rt::time::interval(ZAP_POLL_INTERVAL);
+        loop {
+            tick.tick().await;
+            let pending: Vec<(PaymentHash, PendingZap)> = {
+                let mut zaps = self.zaps.lock().unwrap();
+                let before = zaps.len();
+                let cutoff = now_secs().saturating_sub(ZAP_WATCH_FOR.as_secs());
+                zaps.retain(|_, zap| zap.created_at > cutoff);
+                if zaps.len() != before {
+                    if let Err(e) = save_zaps(&self.zaps_path, &zaps) {
+                        eprintln!("Zaps: could not save pending zaps: {}", e);
+                    }
+                }
+                zaps.iter().map(|(h, z)| (*h, z.clone())).collect()
+            };
+
+            // Looked up one by one: listings are capped and, on LND, oldest first.
+            for (hash, zap) in pending {
+                let Some(node) = registry.get(&zap.node) else {
+                    eprintln!("Zaps: node '{}' is no longer configured", zap.node);
+                    self.finish_zap(&hash);
+                    continue;
+                };
+                let paid = match node.lookup_invoice(&hash).await {
+                    Ok(invoice) => {
+                        invoice.is_some_and(|i| matches!(i.state.as_str(), "paid" | "settled"))
+                    }
+                    Err(e) => {
+                        eprintln!("Zaps: could not look up {}: {}", hash, e);
+                        continue;
+                    }
+                };
+                if paid {
+                    self.finish_zap(&hash);
+                    if let Err(e) = publish_receipt(keys, &zap) {
+                        eprintln!("Zaps: receipt for {} not published: {}", hash, e);
+                    }
+                }
+            }
+        }
+    }
+
+    fn finish_zap(&self, hash: &PaymentHash) {
+        let mut zaps = self.zaps.lock().unwrap();
+        if zaps.remove(hash).is_some() {
+            if let Err(e) = save_zaps(&self.zaps_path, &zaps) {
+                eprintln!("Zaps: could not save pending zaps: {}", e);
+            }
+        }
+    }
+}
+
+fn load_zaps(path: &Path) -> Result<HashMap<PaymentHash, PendingZap>> {
+    if !path.exists() {
+        return Ok(HashMap::new());
+    }
+    serde_json::from_slice(&fs::read(path)?).map_err(|e| anyhow!("{}: {}", path.display(), e))
+}
+
+fn save_zaps(path: &Path, zaps: &HashMap<PaymentHash, PendingZap>) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, serde_json::to_vec_pretty(zaps)?)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+fn relays_of(request: &Event) -> Vec<String> {
+    request
+        .tags
+        .iter()
+        .filter(|t| t.kind() == "relays")
+        .flat_map(|t| t.as_slice().iter().skip(1))
+        .filter(|r| r.starts_with("wss://") || r.starts_with("ws://"))
+        .take(MAX_ZAP_RELAYS)
+        .cloned()
+        .collect()
+}
+
+/// Kind 9735 receipt, sent to the zap request's relays.
+fn publish_receipt(keys: &Keys, zap: &PendingZap) -> Result<()> {
+    let request = Event::from_json(&zap.raw)?;
+    let mut tags = vec![
+        Tag::parse(["bolt11", zap.bolt11.as_str()])?,
+        Tag::parse(["description", zap.raw.as_str()])?,
+    ];
+    for kind in ["p", "e", "a"] {
+        if let Some(tag) = request.tags.iter().find(|t| t.kind() == kind) {
+            tags.push(tag.clone());
+        }
+    }
+    tags.push(Tag::parse(["P".to_string(), request.pubkey.to_hex()])?);
+    let receipt = EventBuilder::new(Kind::ZapReceipt, "")
+        .tags(tags)
+        .finalize(keys)?;
+
+    let pool = RelayPool::new(&relays_of(&request))?;
+    pool.publish(receipt);
+    actix_web::rt::spawn(async move {
+        actix_web::rt::time::sleep(RECEIPT_LINGER).await;
+        drop(pool);
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PR: &str = "lnbc2500u1pvjluezsp5zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zygspp5qqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqypqdq5xysxxatsyp3k7enxv4jsxqzpu9qrsgquk0rl77nj30yxdy8j9vdx85fkpmdla2087ne0xh8nhedh8w27kyke0lp53ut353s06fv3qfegext0eh0ymjpf39tuven09sam30g4vgpfna3rh";
+
+    #[test]
+    fn pending_zaps_survive_a_restart() {
+        let path = std::env::temp_dir().join(format!("zaps-{}.json", rand::random::<u64>()));
+        assert!(load_zaps(&path).unwrap().is_empty());
+
+        let bolt11: Bolt11 = PR.parse().unwrap();
+        let hash = bolt11.payment_hash().unwrap();
+        let zap = PendingZap {
+            node: "lnd".into(),
+            bolt11,
+            raw: r#"{"kind":9734}"#.into(),
+            created_at: 1_700_000_000,
+        };
+        save_zaps(&path, &HashMap::from([(hash, zap)])).unwrap();
+
+        let loaded = load_zaps(&path).unwrap();
+        let zap = &loaded[&hash];
+        assert_eq!(zap.node, "lnd");
+        assert_eq!(zap.bolt11.as_str(), PR);
+        assert_eq!(zap.raw, r#"{"kind":9734}"#);
+        assert_eq!(zap.created_at, 1_700_000_000);
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,303 @@
+// api-server/src/lnurl_auth.rs
+//
+// Login with a Lightning wallet (LNURL-auth, LUD-04). The frontend shows a
+// challenge as a QR code; the wallet signs its k1 with the linking key it
+// derives for this domain and calls back. A signature from a key listed
+// under an account approves the challenge, and the browser that asked for
+// it picks up the login by polling.
+use anyhow::{anyhow, Result};
+use lightning_client::lnurl;
+use secp256k1::{ecdsa::Signature, Message, PublicKey, Secp256k1, VerifyOnly};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Deserialize, Clone)]
+pub struct LnurlAuthConfig {
+    /// Account name → the hex linking keys allowed to log in as it.
+    #[serde(default)]
+    pub accounts: HashMap<String, Vec<String>>,
+    #[serde(default = "default_challenge_ttl_secs")]
+    pub challenge_ttl_secs: u64,
+    /// Unexpired challenges held at once; anyone can ask for one.
+    #[serde(default = "default_max_challenges")]
+    pub max_challenges: usize,
+}
+
+fn default_challenge_ttl_secs() -> u64 {
+    300
+}
+fn default_max_challenges() -> usize {
+    1_000
+}
+
+/// A challenge to show as a QR code.
+#[derive(Debug, Serialize)]
+pub struct Challenge {
+    pub k1: String,
+    pub url: String,
+    pub lnurl: String,
+    /// Unix seconds.
+    pub expires_at: u64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ChallengeStatus {
+    Pending,
+    Approved { account: String },
+    Expired,
+}
+
+struct Pending {
+    expires_at: u64,
+    account: Option<String>,
+}
+
+pub struct LnurlAuth {
+    callback: String,
+    accounts: HashMap<PublicKey, String>,
+    ttl_secs: u64,
+    max_challenges: usize,
+    challenges: Mutex<HashMap<String, Pending>>,
+    secp: Secp256k1<VerifyOnly>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl LnurlAuth {
+    pub fn new(cfg: &LnurlAuthConfig, public_url: &str) -> Result<Self> {
+        let mut accounts = HashMap::new();
+        for (account, keys) in &cfg.accounts {
+            for key in keys {
+                let key: PublicKey = key
+                    .parse()
+                    .map_err(|e| anyhow!("{}: invalid linking key '{}': {}", account, key, e))?;
+                if let Some(other) = accounts.insert(key, account.clone()) {
+                    return Err(anyhow!(
+                        "Linking key {} is listed under both '{}' and '{}'",
+                        key,
+                        other,
+                        account
+                    ));
+                }
+            }
+        }
+        if cfg.challenge_ttl_secs == 0 {
+            return Err(anyhow!("challenge_ttl_secs must be positive"));
+        }
+        if cfg.max_challenges == 0 {
+            return Err(anyhow!("max_challenges must be positive"));
+        }
+
+        Ok(Self {
+            callback: format!("{}/auth/lnurl/callback", public_url.trim_end_matches('/')),
+            accounts,
+            ttl_secs: cfg.challenge_ttl_secs,
+            max_challenges: cfg.max_challenges,
+            challenges: Mutex::new(HashMap::new()),
+            secp: Secp256k1::verification_only(),
+        })
+    }
+
+    pub fn key_count(&self) -> usize {
+        self.accounts.len()
+    }
+
+    /// Fails while `max_challenges` are open, rather than letting anyone
+    /// grow the map without bound.
+    pub fn challenge(&self) -> Result<Challenge> {
+        let now = now();
+        let k1 = hex::encode(rand::random::<[u8; 32]>());
+        let expires_at = now + self.ttl_secs;
+
+        let mut challenges = self.challenges.lock().unwrap();
+        challenges.retain(|_, c| c.expires_at > now);
+        if challenges.len() >= self.max_challenges {
+            return Err(anyhow!("Too many logins in progress; try again shortly"));
+        }
+        challenges.insert(
+            k1.clone(),
+            Pending {
+                expires_at,
+                account: None,
+            },
+        );
+
+        let url = format!("{}?tag=login&k1={}&action=login", self.callback, k1);
+        Ok(Challenge {
+            lnurl: lnurl::encode_lnurl(&url),
+            url,
+            k1,
+            expires_at,
+        })
+    }
+
+    /// Checks the wallet's DER signature `sig` over `k1` by linking key
+    /// `key`, both hex, and approves the challenge for the key's account.
+    pub fn verify(&self, k1: &str, sig: &str, key: &str) -> Result<String> {
+        let digest: [u8; 32] = hex::decode(k1)
+            .ok()
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(|| anyhow!("Invalid k1"))?;
+        let key: PublicKey = key
+            .parse()
+            .map_err(|e| anyhow!("Invalid linking key: {}", e))?;
+        let mut sig = hex::decode(sig)
+            .ok()
+            .and_then(|der| Signature::from_der(&der).ok())
+            .ok_or_else(|| anyhow!("Invalid signature encoding"))?;
+        sig.normalize_s();
+
+        let mut challenges = self.challenges.lock().unwrap();
+        let challenge = challenges
+            .get_mut(k1)
+            .filter(|c| c.expires_at > now())
+            .ok_or_else(|| anyhow!("Unknown or expired challenge"))?;
+        if challenge.account.is_some() {
+            return Err(anyhow!("This challenge was already used"));
+        }
+        self.secp
+            .verify_ecdsa(&Message::from_digest(digest), &sig, &key)
+            .map_err(|_| anyhow!("Signature does not match"))?;
+        let account = self
+            .accounts
+            .get(&key)
+            .ok_or_else(|| anyhow!("This key is not allowed to log in"))?;
+
+        challenge.account = Some(account.clone());
+        Ok(account.clone())
+    }
+
+    /// Where the challenge stands. An approved challenge is handed out
+    /// once, so the login can only be picked up by one poll.
+    pub fn status(&self, k1: &str) -> ChallengeStatus {
+        let mut challenges = self.challenges.lock().unwrap();
+        let Some(challenge) = challenges.get(k1) else {
+            return ChallengeStatus::Expired;
+        };
+        if challenge.expires_at <= now() {
+            challenges.remove(k1);
+            return ChallengeStatus::Expired;
+        }
+        if challenge.account.is_none() {
+            return ChallengeStatus::Pending;
+        }
+        match challenges.remove(k1).and_then(|c| c.account) {
+            Some(account) => ChallengeStatus::Approved { account },
+            None => ChallengeStatus::Expired,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Linking key of secret key 1, its DER signature over K1, and the same
+    // signature with the high S some wallets still send.
+    const KEY: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+    const K1: &str = "e2af6254a8df433264fa23f67eb8188635d15ce883e8fc020989d5f82ae6f11e";
+    const SIG: &str = "3045022100892d9245c5c57bcb9d3e3e25c50c74cddd002aabab98b50f46c6e9b359067295022020172385a0ed3c10db0d3b5f999d89805c1cd095545f2bd44e2d2969aa20d37e";
+    const HIGH_S_SIG: &str = "3046022100892d9245c5c57bcb9d3e3e25c50c74cddd002aabab98b50f46c6e9b359067295022100dfe8dc7a5f12c3ef24f2c4a06662767e5e920c515ae9746771a5352326156dc3";
+
+    fn auth(keys: &[&str], max_challenges: usize) -> LnurlAuth {
+        let cfg = LnurlAuthConfig {
+            accounts: HashMap::from([(
+                "alice".to_string(),
+                keys.iter().map(|k| k.to_string()).collect(),
+            )]),
+            challenge_ttl_secs: 300,
+            max_challenges,
+        };
+        LnurlAuth::new(&cfg, "https://example.com/").unwrap()
+    }
+
+    /// Opens a challenge with a known k1.
+    fn open(auth: &LnurlAuth, k1: &str, expires_at: u64) {
+        auth.challenges.lock().unwrap().insert(
+            k1.to_string(),
+            Pending {
+                expires_at,
+                account: None,
+            },
+        );
+    }
+
+    #[test]
+    fn verify_approves_a_listed_key_once() {
+        let auth = auth(&[KEY], 10);
+        open(&auth, K1, now() + 60);
+        assert!(matches!(auth.status(K1), ChallengeStatus::Pending));
+
+        assert_eq!(auth.verify(K1, SIG, KEY).unwrap(), "alice");
+        let err = auth.verify(K1, SIG, KEY).unwrap_err();
+        assert!(err.to_string().contains("already used"), "{}", err);
+
+        match auth.status(K1) {
+            ChallengeStatus::Approved { account } => assert_eq!(account, "alice"),
+            other => panic!("{:?}", other),
+        }
+        // Picked up once; the next poll finds nothing.
+        assert!(matches!(auth.status(K1), ChallengeStatus::Expired));
+    }
+
+    #[test]
+    fn verify_accepts_a_high_s_signature() {
+        let auth = auth(&[KEY], 10);
+        open(&auth, K1, now() + 60);
+        assert_eq!(auth.verify(K1, HIGH_S_SIG, KEY).unwrap(), "alice");
+    }
+
+    #[test]
+    fn verify_rejects_bad_challenges_and_signatures() {
+        let auth = auth(&[KEY], 10);
+        let other_k1 = "11".repeat(32);
+        open(&auth, &other_k1, now() + 60);
+        let err = auth.verify(&other_k1, SIG, KEY).unwrap_err();
+        assert!(err.to_string().contains("does not match"), "{}", err);
+
+        let err = auth.verify(K1, SIG, KEY).unwrap_err();
+        assert!(err.to_string().contains("Unknown or expired"), "{}", err);
+        open(&auth, K1, now() - 1);
+        assert!(auth.verify(K1, SIG, KEY).is_err());
+        assert!(matches!(auth.status(K1), ChallengeStatus::Expired));
+
+        open(&auth, K1, now() + 60);
+        assert!(auth.verify(K1, &SIG[..SIG.len() - 2], KEY).is_err());
+        assert!(auth.verify(K1, SIG, &KEY[2..]).is_err());
+        assert!(matches!(auth.status(K1), ChallengeStatus::Pending));
+    }
+
+    #[test]
+    fn verify_rejects_an_unlisted_key() {
+        let auth = auth(&[], 10);
+        open(&auth, K1, now() + 60);
+        let err = auth.verify(K1, SIG, KEY).unwrap_err();
+        assert!(err.to_string().contains("not allowed"), "{}", err);
+        assert!(matches!(auth.status(K1), ChallengeStatus::Pending));
+    }
+
+    #[test]
+    fn open_challenges_are_capped() {
+        let auth = auth(&[KEY], 2);
+        let first = auth.challenge().unwrap();
+        assert!(first
+            .url
+            .starts_with("https://example.com/auth/lnurl/callback?tag=login&k1="));
+        auth.challenge().unwrap();
+        assert!(auth.challenge().is_err());
+
+        // Expired ones make room again.
+        for c in auth.challenges.lock().unwrap().values_mut() {
+            c.expires_at = 0;
+        }
+        auth.challenge().unwrap();
+    }
+}
@@ -1,20 +1,56 @@
 // api-server/src/main.rs
+mod backup;
+mod fiat;
+mod l402;
+mod lightning_address;
+mod lnurl_auth;
+mod nwc;
+mod withdraw;
+
 use actix_session::{storage::CookieSessionStore, Session, SessionMiddleware};
 use actix_web::{
-    delete, get,
+    delete,
+    dev::Payload,
+    error::ErrorNotFound,
+    get,
     middleware::Logger,
     post,
     web::{self, Data, Json, Query},
-    App, HttpResponse, HttpServer, Responder,
+    App, FromRequest, HttpRequest, HttpResponse, HttpServer, Responder,
 };
+use actix_web_lab::middleware::from_fn;
 use anyhow::Result;
 use argon2::{Argon2, PasswordHash, PasswordVerifier};
-use lightning_client::{connect_from_config, Invoice, LightningClientDyn};
+use fiat::{Fiat, FiatQuote};
+use l402::{L402Config, L402};
+use lightning_address::{AddressPayment, LightningAddressConfig, LightningAddresses};
+use lightning_client::lnurl::{self, LnurlClient};
+use lightning_client::{
+    await_payment, config as driver_config, rates, Bolt11, Capabilities, FeeEstimate, ForwardQuery,
+    FundPsbtRequest, Invoice, LightningClientDyn, Msat, NetworkMismatch, NodeInfo, NodeRegistry,
+    OnchainSendEstimate, PaymentHash, PaymentPending, PaymentState, Preimage, Psbt,
+    PsbtChannelOpen, PublicKey, Sat, Settings, ShortChannelId,
+};
+use lnurl_auth::{ChallengeStatus, LnurlAuth, LnurlAuthConfig};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::collections::HashMap;
 use std::fs;
+use std::future::{ready, Ready};
+use std::io::Write;
 use std::net::SocketAddr;
+use std::ops::Deref;
 use std::path::Path;
+use std::time::Duration;
+use withdraw::{LinkState, WithdrawLinks};
+
+/// How often pool members are probed with `get_info`.
+const POOL_HEALTH_INTERVAL: Duration = Duration::from_secs(30);
+/// Virtual size of a channel funding transaction: 10.5 vbytes of overhead,
+/// one P2WPKH input (68), the P2WSH funding output (43) and P2WPKH change
+/// (31). Fixed because the node can't size it yet: the funding address only
+/// exists once a peer accepts the open, and no amount is known.
+const FUNDING_TX_VBYTES: f64 = 153.0;
 
 // ---------------------------------------------------------------------
 // Payloads
@@ -24,9 +60,28 @@ struct LoginReq {
     password: String,
 }
 
+/// What a wallet sends to the LNURL-auth callback.
+#[derive(Deserialize)]
+struct LnurlAuthCallbackReq {
+    k1: String,
+    sig: String,
+    key: String,
+}
+
+#[derive(Deserialize)]
+struct LnurlAuthPath {
+    k1: String,
+}
+
+/// Either `msat`, or a fiat `amount` with its `currency`.
 #[derive(Deserialize)]
 struct InvoiceReq {
-    msat: u64,
+    #[serde(default)]
+    msat: Option<Msat>,
+    #[serde(default)]
+    amount: Option<f64>,
+    #[serde(default)]
+    currency: Option<String>,
     #[serde(default)]
     desc: Option<String>,
 }
@@ -39,36 +94,261 @@ struct ListInvoicesReq {
 
 #[derive(Deserialize)]
 struct DecodeReq {
-    bolt11: String,
+    bolt11: Bolt11,
 }
 
 #[derive(Deserialize)]
 struct PayReq {
-    bolt11: String,
+    bolt11: Bolt11,
+}
+
+#[derive(Deserialize)]
+struct RoutesReq {
+    dest: PublicKey,
+    msat: Msat,
+}
+
+#[derive(Deserialize)]
+struct PaymentPath {
+    hash: PaymentHash,
+}
+
+#[derive(Deserialize)]
+struct GraphNodePath {
+    pubkey: PublicKey,
+}
+
+#[derive(Deserialize)]
+struct GraphChannelPath {
+    scid: ShortChannelId,
+}
+
+#[derive(Deserialize)]
+struct SignReq {
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct VerifyReq {
+    message: String,
+    signature: String,
+}
+
+/// Base64, both ways.
+#[derive(Deserialize, Serialize)]
+struct PsbtBody {
+    psbt: Psbt,
+}
+
+#[derive(Deserialize)]
+struct PsbtOpenPath {
+    id: String,
+}
+
+/// `address` and `amount_sat` together add what that send would cost.
+#[derive(Deserialize)]
+struct FeesReq {
+    #[serde(default = "default_target_conf")]
+    target_conf: u32,
+    #[serde(default)]
+    address: Option<String>,
+    #[serde(default)]
+    amount_sat: Option<Sat>,
+}
+
+fn default_target_conf() -> u32 {
+    6
+}
+
+/// A Lightning Address, `lnurl1...` string or `lnurlp://` URL.
+#[derive(Deserialize)]
+struct LnurlReq {
+    target: String,
+}
+
+#[derive(Deserialize)]
+struct LnurlPayReq {
+    target: String,
+    msat: Msat,
+    #[serde(default)]
+    comment: Option<String>,
+}
+
+/// Redeems someone else's withdraw link into an invoice on our node.
+#[derive(Deserialize)]
+struct LnurlWithdrawReq {
+    target: String,
+    /// The most the link allows when unset.
+    #[serde(default)]
+    msat: Option<Msat>,
+    #[serde(default)]
+    desc: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct WithdrawLinkReq {
+    min_msat: Msat,
+    max_msat: Msat,
+    #[serde(default = "default_uses")]
+    uses: u32,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    expires_in_secs: Option<u64>,
+}
+
+fn default_uses() -> u32 {
+    1
+}
+
+#[derive(Deserialize)]
+struct WithdrawLinkPath {
+    k1: String,
+}
+
+#[derive(Deserialize)]
+struct LnurlpPath {
+    username: String,
+}
+
+/// What a wallet sends to a pay callback.
+#[derive(Deserialize)]
+struct LnurlpCallbackReq {
+    #[serde(default)]
+    amount: Option<Msat>,
+    #[serde(default)]
+    comment: Option<String>,
+    /// NIP-57 zap request, JSON.
+    #[serde(default)]
+    nostr: Option<String>,
+}
+
+/// What a wallet sends to a withdraw link's callback.
+#[derive(Deserialize)]
+struct WithdrawCallbackReq {
+    k1: String,
+    pr: String,
+}
+
+/// Unix seconds; `start` is inclusive and `end` exclusive, as in `ForwardQuery`.
+#[derive(Deserialize)]
+struct RevenueReq {
+    #[serde(default)]
+    start: Option<u64>,
+    #[serde(default)]
+    end: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct InfoResp {
+    #[serde(flatten)]
+    info: NodeInfo,
+    capabilities: Capabilities,
 }
 
 #[derive(Serialize)]
 struct InvoiceResp {
-    bolt11: String,
+    bolt11: Bolt11,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fiat: Option<FiatQuote>,
 }
 
 #[derive(Serialize)]
 struct ListInvoicesResp {
-    invoices: Vec<Invoice>,
+    invoices: Vec<InvoiceEntry>,
+}
+
+#[derive(Serialize)]
+struct InvoiceEntry {
+    #[serde(flatten)]
+    invoice: Invoice,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fiat: Option<FiatQuote>,
+    /// The Lightning Address user the invoice was created for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    address: Option<AddressPayment>,
 }
 
 #[derive(Serialize)]
 struct DecodeResp {
-    amount_msat: Option<u64>,
+    amount_msat: Option<Msat>,
     desc: Option<String>,
-    payee: Option<String>,
+    payee: Option<PublicKey>,
+}
+
+#[derive(Serialize)]
+struct SignResp {
+    signature: String,
+}
+
+#[derive(Serialize)]
+struct RevenueResp {
+    start: Option<u64>,
+    end: Option<u64>,
+    #[serde(flatten)]
+    total: RevenueTotals,
+    /// Per outgoing channel, highest fees first.
+    channels: Vec<ChannelRevenue>,
+}
+
+#[derive(Serialize)]
+struct ChannelRevenue {
+    chan_out: Option<ShortChannelId>,
+    #[serde(flatten)]
+    totals: RevenueTotals,
+}
+
+#[derive(Serialize, Default, Clone, Copy)]
+struct RevenueTotals {
+    forwards: u64,
+    volume_msat: Msat,
+    fee_msat: Msat,
+}
+
+#[derive(Serialize)]
+struct FeesResp {
+    #[serde(flatten)]
+    rate: FeeEstimate,
+    /// A typical open at this rate: one P2WPKH input, the funding output
+    /// and change.
+    channel_open_fee_sat: Sat,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    send: Option<OnchainSendEstimate>,
+}
+
+#[derive(Serialize)]
+struct LnurlResp {
+    description: Option<String>,
+    identifier: Option<String>,
+    min_sendable_msat: Msat,
+    max_sendable_msat: Msat,
+    comment_allowed: usize,
+}
+
+#[derive(Serialize)]
+struct WithdrawLinkResp {
+    #[serde(flatten)]
+    state: LinkState,
+    url: String,
+    /// Bech32 form of `url`, for QR codes.
+    lnurl: String,
 }
 
 #[derive(Serialize)]
 struct PayResp {
-    hash: String,
-    amount_msat: u64,
-    fee_msat: Option<u64>,
+    hash: PaymentHash,
+    amount_msat: Option<Msat>,
+    fee_msat: Option<Msat>,
+    preimage: Option<Preimage>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum PaymentStatusResp {
+    Pending,
+    Succeeded(PayResp),
+    Failed,
+    Unknown,
 }
 
 // ---------------------------------------------------------------------
@@ -76,11 +356,25 @@ struct PayResp {
 // ---------------------------------------------------------------------
 #[derive(Deserialize, Clone)]
 struct ApiConfig {
+    #[serde(default)]
     password_hash: String,
+    /// File holding the hash, so it needn't sit inline in the config.
+    #[serde(default)]
+    password_hash_file: Option<String>,
     #[serde(default = "default_host")]
     host: String,
     #[serde(default = "default_port")]
     port: u16,
+    /// JSON-lines file recording the rate applied to each fiat invoice.
+    #[serde(default = "default_fiat_ledger")]
+    fiat_ledger: String,
+    /// Base URL wallets reach this server at, e.g. `https://pay.example.com`.
+    /// Needed for LNURL links.
+    #[serde(default)]
+    public_url: Option<String>,
+    /// JSON-lines file of LNURL-withdraw links and their uses.
+    #[serde(default = "default_withdraw_ledger")]
+    withdraw_ledger: String,
 }
 
 fn default_host() -> String {
@@ -89,6 +383,30 @@ fn default_host() -> String {
 fn default_port() -> u16 {
     8080
 }
+fn default_fiat_ledger() -> String {
+    "fiat_invoices.jsonl".into()
+}
+fn default_withdraw_ledger() -> String {
+    "withdraw_links.jsonl".into()
+}
+
+/// `--<flag> <path>` or `--<flag>=<path>`.
+fn path_arg(flag: &str) -> Result<Option<String>> {
+    let long = format!("--{}", flag);
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == long {
+            return args
+                .next()
+                .map(Some)
+                .ok_or_else(|| anyhow::anyhow!("{} needs a path", long));
+        }
+        if let Some(path) = arg.strip_prefix(&format!("{}=", long)) {
+            return Ok(Some(path.to_string()));
+        }
+    }
+    Ok(None)
+}
 
 // ---------------------------------------------------------------------
 // Session key: load from file or generate once
@@ -131,6 +449,67 @@ async fn login(payload: Json<LoginReq>, session: Session, cfg: Data<ApiConfig>)
     }
 }
 
+/// Starts an LNURL-auth login. The browser polls `/auth/lnurl/{k1}` while
+/// the wallet signs; only the session that asked can pick up the login.
+#[get("/auth/lnurl")]
+async fn lnurl_auth_challenge(auth: Option<Data<LnurlAuth>>, session: Session) -> impl Responder {
+    let Some(auth) = auth else {
+        return HttpResponse::NotFound().json(json!({ "error": "LNURL-auth is not enabled" }));
+    };
+    let challenge = match auth.challenge() {
+        Ok(challenge) => challenge,
+        Err(e) => return HttpResponse::TooManyRequests().json(json!({ "error": e.to_string() })),
+    };
+    if let Err(e) = session.insert("lnurl_auth_k1", &challenge.k1) {
+        return HttpResponse::InternalServerError().json(json!({ "error": e.to_string() }));
+    }
+    HttpResponse::Ok().json(challenge)
+}
+
+#[get("/auth/lnurl/callback")]
+async fn lnurl_auth_callback(
+    query: Query<LnurlAuthCallbackReq>,
+    auth: Option<Data<LnurlAuth>>,
+) -> impl Responder {
+    let Some(auth) = auth else {
+        return lnurl_error("LNURL-auth is not enabled");
+    };
+    match auth.verify(&query.k1, &query.sig, &query.key) {
+        Ok(_) => HttpResponse::Ok().json(json!({ "status": "OK" })),
+        Err(e) => lnurl_error(e),
+    }
+}
+
+#[get("/auth/lnurl/{k1}")]
+async fn lnurl_auth_status(
+    path: web::Path<LnurlAuthPath>,
+    auth: Option<Data<LnurlAuth>>,
+    session: Session,
+) -> impl Responder {
+    let Some(auth) = auth else {
+        return HttpResponse::NotFound().json(json!({ "error": "LNURL-auth is not enabled" }));
+    };
+    if session
+        .get::<String>("lnurl_auth_k1")
+        .ok()
+        .flatten()
+        .as_deref()
+        != Some(&path.k1)
+    {
+        return HttpResponse::Forbidden()
+            .json(json!({ "error": "challenge belongs to another session" }));
+    }
+
+    let status = auth.status(&path.k1);
+    if let ChallengeStatus::Approved { account } = &status {
+        session.remove("lnurl_auth_k1");
+        session.renew();
+        let _ = session.insert("logged_in", true);
+        let _ = session.insert("account", account);
+    }
+    HttpResponse::Ok().json(status)
+}
+
 #[delete("/logout")]
 async fn logout(session: Session) -> impl Responder {
     session.purge();
@@ -148,48 +527,163 @@ async fn require_auth(session: &mut Session) -> Result<(), HttpResponse> {
     }
 }
 
+// ---------------------------------------------------------------------
+// Node selection: /api/nodes/{name}/... or the default node under /api/...
+// ---------------------------------------------------------------------
+struct Node(LightningClientDyn);
+
+impl FromRequest for Node {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let registry = req
+            .app_data::<Data<NodeRegistry>>()
+            .expect("NodeRegistry app data");
+        let node = match req.match_info().get("name") {
+            Some(name) => registry
+                .get(name)
+                .ok_or_else(|| ErrorNotFound(format!("unknown node '{}'", name))),
+            None => Ok(registry.default_node()),
+        };
+        ready(node.map(Node))
+    }
+}
+
+impl Deref for Node {
+    type Target = LightningClientDyn;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[get("/nodes")]
+async fn list_nodes(registry: Data<NodeRegistry>, mut session: Session) -> impl Responder {
+    if require_auth(&mut session).await.is_err() {
+        return HttpResponse::Unauthorized().json(json!({ "error": "login required" }));
+    }
+
+    HttpResponse::Ok().json(json!({
+        "default": registry.default_name(),
+        "nodes": registry.names().collect::<Vec<_>>(),
+        "pools": registry
+            .pools()
+            .map(|(name, pool)| (name.to_string(), json!(pool.health())))
+            .collect::<serde_json::Map<_, _>>(),
+    }))
+}
+
+fn node_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_info)
+        .service(create_invoice)
+        .service(get_balance)
+        .service(list_invoices)
+        .service(decode_invoice)
+        .service(pay_invoice)
+        .service(lookup_payment)
+        .service(sign_message)
+        .service(verify_message)
+        .service(get_revenue)
+        .service(query_routes)
+        .service(get_graph_node)
+        .service(get_graph_channel)
+        .service(probe_payment)
+        .service(open_channel_psbt)
+        .service(update_channel_psbt)
+        .service(finalize_channel_psbt)
+        .service(cancel_channel_psbt)
+        .service(fund_psbt)
+        .service(sign_psbt)
+        .service(finalize_psbt)
+        .service(get_fees)
+        .service(resolve_lnurl)
+        .service(pay_lnurl)
+        .service(withdraw_lnurl)
+        .service(create_withdraw_link);
+}
+
 // ---------------------------------------------------------------------
 // Protected routes
 // ---------------------------------------------------------------------
 #[get("/info")]
-async fn get_info(driver: Data<LightningClientDyn>, mut session: Session) -> impl Responder {
+async fn get_info(driver: Node, mut session: Session) -> impl Responder {
     if require_auth(&mut session).await.is_err() {
         return HttpResponse::Unauthorized().json(json!({ "error": "login required" }));
     }
 
-    let mut guard = driver.lock().unwrap();
-    match guard.get_info().await {
-        Ok(info) => HttpResponse::Ok().json(info),
+    let info = match driver.get_info().await {
+        Ok(info) => info,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+    match driver.capabilities().await {
+        Ok(capabilities) => HttpResponse::Ok().json(InfoResp { info, capabilities }),
         Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
     }
 }
 
 #[post("/invoice")]
 async fn create_invoice(
-    driver: Data<LightningClientDyn>,
+    driver: Node,
     payload: Json<InvoiceReq>,
+    fiat: Data<Fiat>,
     mut session: Session,
 ) -> impl Responder {
     if require_auth(&mut session).await.is_err() {
         return HttpResponse::Unauthorized().json(json!({ "error": "login required" }));
     }
 
-    let mut guard = driver.lock().unwrap();
+    let (msat, quote) = match (payload.msat, payload.amount, payload.currency.as_deref()) {
+        (Some(msat), None, None) => (msat, None),
+        (None, Some(amount), Some(currency)) => match quote_fiat(&fiat, amount, currency).await {
+            Ok(quote) => (quote.amount_msat, Some(quote)),
+            Err(resp) => return resp,
+        },
+        _ => {
+            return HttpResponse::BadRequest()
+                .json(json!({ "error": "give either msat, or amount and currency" }))
+        }
+    };
+
     let desc = payload.desc.as_deref();
-    match guard.create_invoice(payload.msat, None, desc).await {
-        Ok(bolt11) => HttpResponse::Ok().json(InvoiceResp { bolt11 }),
-        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    let bolt11 = match driver.create_invoice(msat, None, desc).await {
+        Ok(bolt11) => bolt11,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+    if let Some(quote) = &quote {
+        // An invoice whose price can't be accounted for is not handed out.
+        if let Err(e) = fiat.record(&bolt11, quote) {
+            eprintln!("Failed to record fiat quote for {}: {}", bolt11, e);
+            return HttpResponse::InternalServerError().body(e.to_string());
+        }
     }
+    HttpResponse::Ok().json(InvoiceResp {
+        bolt11,
+        fiat: quote,
+    })
+}
+
+async fn quote_fiat(fiat: &Fiat, amount: f64, currency: &str) -> Result<FiatQuote, HttpResponse> {
+    let bad_request =
+        |e: anyhow::Error| HttpResponse::BadRequest().json(json!({ "error": e.to_string() }));
+    let rates = fiat.rates.as_ref().ok_or_else(|| {
+        HttpResponse::BadRequest().json(json!({ "error": "no [rates] provider configured" }))
+    })?;
+    let currency = rates::currency_code(currency).map_err(bad_request)?;
+    let btc_price = rates.btc_price(&currency).await.map_err(|e| {
+        HttpResponse::BadGateway().json(json!({ "error": format!("rate lookup failed: {}", e) }))
+    })?;
+    let msat = rates::fiat_to_msat(amount, btc_price).map_err(bad_request)?;
+    Ok(FiatQuote::new(amount, currency, btc_price, msat))
 }
 
 #[get("/balance")]
-async fn get_balance(driver: Data<LightningClientDyn>, mut session: Session) -> impl Responder {
+async fn get_balance(driver: Node, mut session: Session) -> impl Responder {
     if require_auth(&mut session).await.is_err() {
         return HttpResponse::Unauthorized().json(json!({ "error": "login required" }));
     }
 
-    let mut guard = driver.lock().unwrap();
-    match guard.get_balance().await {
+    match driver.get_balance().await {
         Ok(balance) => HttpResponse::Ok().json(balance),
         Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
     }
@@ -197,8 +691,10 @@ async fn get_balance(driver: Data<LightningClientDyn>, mut session: Session) ->
 
 #[get("/invoices")]
 async fn list_invoices(
-    driver: Data<LightningClientDyn>,
+    driver: Node,
     query: Query<ListInvoicesReq>,
+    fiat: Data<Fiat>,
+    addresses: Option<Data<LightningAddresses>>,
     mut session: Session,
 ) -> impl Responder {
     if require_auth(&mut session).await.is_err() {
@@ -206,16 +702,26 @@ async fn list_invoices(
     }
 
     let limit = query.limit.unwrap_or(10);
-    let mut guard = driver.lock().unwrap();
-    match guard.list_invoices(Some(limit)).await {
-        Ok(invoices) => HttpResponse::Ok().json(ListInvoicesResp { invoices }),
+    match driver.list_invoices(Some(limit)).await {
+        Ok(invoices) => HttpResponse::Ok().json(ListInvoicesResp {
+            invoices: invoices
+                .into_iter()
+                .map(|invoice| InvoiceEntry {
+                    fiat: invoice.bolt11.as_ref().and_then(|b| fiat.get(b)),
+                    address: addresses
+                        .as_ref()
+                        .and_then(|a| a.attribution(&invoice.hash)),
+                    invoice,
+                })
+                .collect(),
+        }),
         Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
     }
 }
 
 #[post("/decode")]
 async fn decode_invoice(
-    driver: Data<LightningClientDyn>,
+    driver: Node,
     payload: Json<DecodeReq>,
     mut session: Session,
 ) -> impl Responder {
@@ -223,8 +729,7 @@ async fn decode_invoice(
         return HttpResponse::Unauthorized().json(json!({ "error": "login required" }));
     }
 
-    let mut guard = driver.lock().unwrap();
-    match guard.decode_invoice(&payload.bolt11).await {
+    match driver.decode_invoice(&payload.bolt11).await {
         Ok(decoded) => HttpResponse::Ok().json(DecodeResp {
             amount_msat: decoded.amount_msat,
             desc: decoded.desc,
@@ -235,50 +740,865 @@ async fn decode_invoice(
 }
 
 #[post("/pay")]
-async fn pay_invoice(
-    driver: Data<LightningClientDyn>,
-    payload: Json<PayReq>,
-    mut session: Session,
-) -> impl Responder {
+async fn pay_invoice(driver: Node, payload: Json<PayReq>, mut session: Session) -> impl Responder {
     if require_auth(&mut session).await.is_err() {
         return HttpResponse::Unauthorized().json(json!({ "error": "login required" }));
     }
 
-    let mut guard = driver.lock().unwrap();
-    match guard.pay_invoice(&payload.bolt11).await {
+    match driver.pay_invoice(&payload.bolt11).await {
         Ok(payment) => HttpResponse::Ok().json(PayResp {
             hash: payment.hash,
             amount_msat: payment.amount_msat,
             fee_msat: payment.fee_msat,
+            preimage: payment.preimage,
+        }),
+        Err(e) if e.is::<NetworkMismatch>() => {
+            HttpResponse::BadRequest().json(json!({ "error": e.to_string() }))
+        }
+        // Not failed: poll /payments/{hash} for the outcome.
+        Err(e) if PaymentPending::is(&e) => {
+            HttpResponse::Accepted().json(json!({ "status": "pending", "error": e.to_string() }))
+        }
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[get("/payments/{hash}")]
+async fn lookup_payment(
+    driver: Node,
+    path: web::Path<PaymentPath>,
+    mut session: Session,
+) -> impl Responder {
+    if require_auth(&mut session).await.is_err() {
+        return HttpResponse::Unauthorized().json(json!({ "error": "login required" }));
+    }
+
+    match driver.lookup_payment(&path.hash).await {
+        Ok(state) => HttpResponse::Ok().json(match state {
+            PaymentState::Pending => PaymentStatusResp::Pending,
+            PaymentState::Succeeded(payment) => PaymentStatusResp::Succeeded(PayResp {
+                hash: payment.hash,
+                amount_msat: payment.amount_msat,
+                fee_msat: payment.fee_msat,
+                preimage: payment.preimage,
+            }),
+            PaymentState::Failed => PaymentStatusResp::Failed,
+            PaymentState::Unknown => PaymentStatusResp::Unknown,
+        }),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[get("/lnurl")]
+async fn resolve_lnurl(
+    query: Query<LnurlReq>,
+    lnurl: Data<LnurlClient>,
+    mut session: Session,
+) -> impl Responder {
+    if require_auth(&mut session).await.is_err() {
+        return HttpResponse::Unauthorized().json(json!({ "error": "login required" }));
+    }
+
+    match lnurl.resolve(&query.target).await {
+        Ok(pay) => HttpResponse::Ok().json(LnurlResp {
+            description: pay.description(),
+            identifier: pay.identifier(),
+            min_sendable_msat: pay.min_sendable,
+            max_sendable_msat: pay.max_sendable,
+            comment_allowed: pay.comment_allowed,
+        }),
+        Err(e) => HttpResponse::BadRequest().json(json!({ "error": e.to_string() })),
+    }
+}
+
+#[post("/lnurl/pay")]
+async fn pay_lnurl(
+    driver: Node,
+    payload: Json<LnurlPayReq>,
+    lnurl: Data<LnurlClient>,
+    mut session: Session,
+) -> impl Responder {
+    if require_auth(&mut session).await.is_err() {
+        return HttpResponse::Unauthorized().json(json!({ "error": "login required" }));
+    }
+
+    // Anything the service gets wrong is refused before the node pays.
+    let invoice = match lnurl.resolve(&payload.target).await {
+        Ok(pay) => {
+            lnurl
+                .fetch_invoice(&pay, payload.msat, payload.comment.as_deref())
+                .await
+        }
+        Err(e) => Err(e),
+    };
+    let invoice = match invoice {
+        Ok(invoice) => invoice,
+        Err(e) => return HttpResponse::BadRequest().json(json!({ "error": e.to_string() })),
+    };
+
+    match invoice.pay(driver.as_ref()).await {
+        Ok(payment) => HttpResponse::Ok().json(payment),
+        Err(e) if e.is::<NetworkMismatch>() => {
+            HttpResponse::BadRequest().json(json!({ "error": e.to_string() }))
+        }
+        // Not failed: poll /payments/{hash} for the outcome.
+        Err(e) if PaymentPending::is(&e) => {
+            HttpResponse::Accepted().json(json!({ "status": "pending", "error": e.to_string() }))
+        }
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[post("/lnurl/withdraw")]
+async fn withdraw_lnurl(
+    driver: Node,
+    payload: Json<LnurlWithdrawReq>,
+    lnurl: Data<LnurlClient>,
+    mut session: Session,
+) -> impl Responder {
+    if require_auth(&mut session).await.is_err() {
+        return HttpResponse::Unauthorized().json(json!({ "error": "login required" }));
+    }
+
+    let withdraw = match lnurl.resolve_withdraw(&payload.target).await {
+        Ok(withdraw) => withdraw,
+        Err(e) => return HttpResponse::BadRequest().json(json!({ "error": e.to_string() })),
+    };
+    let msat = payload.msat.unwrap_or(withdraw.max_withdrawable);
+    match lnurl
+        .withdraw(driver.as_ref(), &withdraw, msat, payload.desc.as_deref())
+        .await
+    {
+        Ok(bolt11) => HttpResponse::Ok().json(json!({ "bolt11": bolt11, "amount_msat": msat })),
+        Err(e) => HttpResponse::BadRequest().json(json!({ "error": e.to_string() })),
+    }
+}
+
+#[get("/routes")]
+async fn query_routes(
+    driver: Node,
+    query: Query<RoutesReq>,
+    mut session: Session,
+) -> impl Responder {
+    if require_auth(&mut session).await.is_err() {
+        return HttpResponse::Unauthorized().json(json!({ "error": "login required" }));
+    }
+
+    match driver.query_routes(&query.dest, query.msat).await {
+        Ok(route) => HttpResponse::Ok().json(route),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[get("/graph/nodes/{pubkey}")]
+async fn get_graph_node(
+    driver: Node,
+    path: web::Path<GraphNodePath>,
+    mut session: Session,
+) -> impl Responder {
+    if require_auth(&mut session).await.is_err() {
+        return HttpResponse::Unauthorized().json(json!({ "error": "login required" }));
+    }
+
+    match driver.get_node(&path.pubkey).await {
+        Ok(node) => HttpResponse::Ok().json(node),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[get("/graph/channels/{scid}")]
+async fn get_graph_channel(
+    driver: Node,
+    path: web::Path<GraphChannelPath>,
+    mut session: Session,
+) -> impl Responder {
+    if require_auth(&mut session).await.is_err() {
+        return HttpResponse::Unauthorized().json(json!({ "error": "login required" }));
+    }
+
+    match driver.get_channel(path.scid).await {
+        Ok(channel) => HttpResponse::Ok().json(channel),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[post("/probe")]
+async fn probe_payment(
+    driver: Node,
+    payload: Json<PayReq>,
+    mut session: Session,
+) -> impl Responder {
+    if require_auth(&mut session).await.is_err() {
+        return HttpResponse::Unauthorized().json(json!({ "error": "login required" }));
+    }
+
+    match driver.probe_payment(&payload.bolt11).await {
+        Ok(probe) => HttpResponse::Ok().json(probe),
+        Err(e) if e.is::<NetworkMismatch>() => {
+            HttpResponse::BadRequest().json(json!({ "error": e.to_string() }))
+        }
+        // Not failed: poll /payments/{hash} for the outcome.
+        Err(e) if PaymentPending::is(&e) => {
+            HttpResponse::Accepted().json(json!({ "status": "pending", "error": e.to_string() }))
+        }
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[post("/channels/psbt")]
+async fn open_channel_psbt(
+    driver: Node,
+    payload: Json<PsbtChannelOpen>,
+    mut session: Session,
+) -> impl Responder {
+    if require_auth(&mut session).await.is_err() {
+        return HttpResponse::Unauthorized().json(json!({ "error": "login required" }));
+    }
+
+    match driver.open_channel_psbt(&payload).await {
+        Ok(funding) => HttpResponse::Ok().json(funding),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[post("/channels/psbt/{id}/update")]
+async fn update_channel_psbt(
+    driver: Node,
+    path: web::Path<PsbtOpenPath>,
+    payload: Json<PsbtBody>,
+    mut session: Session,
+) -> impl Responder {
+    if require_auth(&mut session).await.is_err() {
+        return HttpResponse::Unauthorized().json(json!({ "error": "login required" }));
+    }
+
+    match driver.update_channel_psbt(&path.id, &payload.psbt).await {
+        Ok(psbt) => HttpResponse::Ok().json(PsbtBody { psbt }),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[post("/channels/psbt/{id}/finalize")]
+async fn finalize_channel_psbt(
+    driver: Node,
+    path: web::Path<PsbtOpenPath>,
+    payload: Json<PsbtBody>,
+    mut session: Session,
+) -> impl Responder {
+    if require_auth(&mut session).await.is_err() {
+        return HttpResponse::Unauthorized().json(json!({ "error": "login required" }));
+    }
+
+    match driver.finalize_channel_psbt(&path.id, &payload.psbt).await {
+        Ok(opened) => HttpResponse::Ok().json(opened),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[delete("/channels/psbt/{id}")]
+async fn cancel_channel_psbt(
+    driver: Node,
+    path: web::Path<PsbtOpenPath>,
+    mut session: Session,
+) -> impl Responder {
+    if require_auth(&mut session).await.is_err() {
+        return HttpResponse::Unauthorized().json(json!({ "error": "login required" }));
+    }
+
+    match driver.cancel_channel_psbt(&path.id).await {
+        Ok(()) => HttpResponse::Ok().json(json!({ "status": "cancelled" })),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[post("/psbt/fund")]
+async fn fund_psbt(
+    driver: Node,
+    payload: Json<FundPsbtRequest>,
+    mut session: Session,
+) -> impl Responder {
+    if require_auth(&mut session).await.is_err() {
+        return HttpResponse::Unauthorized().json(json!({ "error": "login required" }));
+    }
+
+    match driver.fund_psbt(&payload).await {
+        Ok(funded) => HttpResponse::Ok().json(funded),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[post("/psbt/sign")]
+async fn sign_psbt(driver: Node, payload: Json<PsbtBody>, mut session: Session) -> impl Responder {
+    if require_auth(&mut session).await.is_err() {
+        return HttpResponse::Unauthorized().json(json!({ "error": "login required" }));
+    }
+
+    match driver.sign_psbt(&payload.psbt).await {
+        Ok(psbt) => HttpResponse::Ok().json(PsbtBody { psbt }),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[post("/psbt/finalize")]
+async fn finalize_psbt(
+    driver: Node,
+    payload: Json<PsbtBody>,
+    mut session: Session,
+) -> impl Responder {
+    if require_auth(&mut session).await.is_err() {
+        return HttpResponse::Unauthorized().json(json!({ "error": "login required" }));
+    }
+
+    match driver.finalize_psbt(&payload.psbt).await {
+        Ok(tx) => HttpResponse::Ok().json(tx),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[get("/fees")]
+async fn get_fees(driver: Node, query: Query<FeesReq>, mut session: Session) -> impl Responder {
+    if require_auth(&mut session).await.is_err() {
+        return HttpResponse::Unauthorized().json(json!({ "error": "login required" }));
+    }
+
+    let send = match (&query.address, query.amount_sat) {
+        (Some(address), Some(amount)) => Some((address, amount)),
+        (None, None) => None,
+        _ => {
+            return HttpResponse::BadRequest()
+                .json(json!({ "error": "address and amount_sat go together" }))
+        }
+    };
+
+    let rate = match driver.estimate_fee(query.target_conf).await {
+        Ok(rate) => rate,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+    let send = match send {
+        Some((address, amount)) => match driver
+            .estimate_onchain_send(address, amount, query.target_conf)
+            .await
+        {
+            Ok(estimate) => Some(estimate),
+            Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+        },
+        None => None,
+    };
+
+    HttpResponse::Ok().json(FeesResp {
+        channel_open_fee_sat: Sat::from_sat((rate.sat_per_vbyte * FUNDING_TX_VBYTES).ceil() as u64),
+        rate,
+        send,
+    })
+}
+
+#[post("/sign")]
+async fn sign_message(
+    driver: Node,
+    payload: Json<SignReq>,
+    mut session: Session,
+) -> impl Responder {
+    if require_auth(&mut session).await.is_err() {
+        return HttpResponse::Unauthorized().json(json!({ "error": "login required" }));
+    }
+
+    match driver.sign_message(&payload.message).await {
+        Ok(signature) => HttpResponse::Ok().json(SignResp { signature }),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[post("/verify")]
+async fn verify_message(
+    driver: Node,
+    payload: Json<VerifyReq>,
+    mut session: Session,
+) -> impl Responder {
+    if require_auth(&mut session).await.is_err() {
+        return HttpResponse::Unauthorized().json(json!({ "error": "login required" }));
+    }
+
+    match driver
+        .verify_message(&payload.message, &payload.signature)
+        .await
+    {
+        Ok(check) => HttpResponse::Ok().json(check),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[get("/revenue")]
+async fn get_revenue(
+    driver: Node,
+    query: Query<RevenueReq>,
+    mut session: Session,
+) -> impl Responder {
+    if require_auth(&mut session).await.is_err() {
+        return HttpResponse::Unauthorized().json(json!({ "error": "login required" }));
+    }
+
+    match forwarding_revenue(&driver, query.start, query.end).await {
+        Ok((total, channels)) => HttpResponse::Ok().json(RevenueResp {
+            start: query.start,
+            end: query.end,
+            total,
+            channels,
         }),
         Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
     }
 }
 
+/// Walks the whole forwarding history in `[start, end)`.
+async fn forwarding_revenue(
+    driver: &LightningClientDyn,
+    start: Option<u64>,
+    end: Option<u64>,
+) -> Result<(RevenueTotals, Vec<ChannelRevenue>)> {
+    fn add(totals: &mut RevenueTotals, volume: Msat, fee: Msat) -> Result<()> {
+        let overflow = || anyhow::anyhow!("Revenue total overflows");
+        totals.forwards += 1;
+        totals.volume_msat = totals
+            .volume_msat
+            .checked_add(volume)
+            .ok_or_else(overflow)?;
+        totals.fee_msat = totals.fee_msat.checked_add(fee).ok_or_else(overflow)?;
+        Ok(())
+    }
+
+    let mut total = RevenueTotals::default();
+    let mut per_channel: HashMap<Option<ShortChannelId>, RevenueTotals> = HashMap::new();
+    let mut query = ForwardQuery {
+        start,
+        end,
+        offset: 0,
+        limit: Some(1000),
+    };
+    loop {
+        let page = driver.list_forwards(&query).await?;
+        for fwd in &page.forwards {
+            add(&mut total, fwd.amount_out_msat, fwd.fee_msat)?;
+            let channel = per_channel.entry(fwd.chan_out).or_default();
+            add(channel, fwd.amount_out_msat, fwd.fee_msat)?;
+        }
+        match page.next_offset {
+            // A backend that doesn't advance would loop forever.
+            Some(next) if next > query.offset => query.offset = next,
+            _ => break,
+        }
+    }
+
+    let mut channels: Vec<ChannelRevenue> = per_channel
+        .into_iter()
+        .map(|(chan_out, totals)| ChannelRevenue { chan_out, totals })
+        .collect();
+    channels.sort_by_key(|c| std::cmp::Reverse(c.totals.fee_msat));
+    Ok((total, channels))
+}
+
+// ---------------------------------------------------------------------
+// LNURL-withdraw links: managed under /api, redeemed by wallets at /lnurlw
+// ---------------------------------------------------------------------
+fn withdraw_link_resp(cfg: &ApiConfig, state: LinkState) -> Option<WithdrawLinkResp> {
+    let base = cfg.public_url.as_deref()?.trim_end_matches('/');
+    let url = format!("{}/lnurlw/{}", base, state.link.k1);
+    Some(WithdrawLinkResp {
+        lnurl: lnurl::encode_lnurl(&url),
+        url,
+        state,
+    })
+}
+
+/// LUD-03 errors; wallets show `reason` to the user.
+fn lnurl_error(reason: impl std::fmt::Display) -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "status": "ERROR", "reason": reason.to_string() }))
+}
+
+#[post("/withdraw-links")]
+async fn create_withdraw_link(
+    req: HttpRequest,
+    payload: Json<WithdrawLinkReq>,
+    links: Data<WithdrawLinks>,
+    registry: Data<NodeRegistry>,
+    cfg: Data<ApiConfig>,
+    mut session: Session,
+) -> impl Responder {
+    if require_auth(&mut session).await.is_err() {
+        return HttpResponse::Unauthorized().json(json!({ "error": "login required" }));
+    }
+    if cfg.public_url.is_none() {
+        return HttpResponse::BadRequest()
+            .json(json!({ "error": "set [api] public_url to hand out LNURL links" }));
+    }
+
+    // Links made under /api/nodes/{name} are paid by that node.
+    let node = req.match_info().get("name").map(str::to_string);
+    if let Some(name) = &node {
+        if registry.get(name).is_none() {
+            return HttpResponse::NotFound().json(json!({ "error": "unknown node" }));
+        }
+    }
+    let WithdrawLinkReq {
+        min_msat,
+        max_msat,
+        uses,
+        description,
+        expires_in_secs,
+    } = payload.into_inner();
+    match links.create(node, min_msat, max_msat, uses, description, expires_in_secs) {
+        Ok(state) => HttpResponse::Ok().json(withdraw_link_resp(&cfg, state)),
+        Err(e) => HttpResponse::BadRequest().json(json!({ "error": e.to_string() })),
+    }
+}
+
+#[get("/withdraw-links")]
+async fn list_withdraw_links(
+    links: Data<WithdrawLinks>,
+    cfg: Data<ApiConfig>,
+    mut session: Session,
+) -> impl Responder {
+    if require_auth(&mut session).await.is_err() {
+        return HttpResponse::Unauthorized().json(json!({ "error": "login required" }));
+    }
+
+    let links: Vec<_> = links
+        .list()
+        .into_iter()
+        .filter_map(|state| withdraw_link_resp(&cfg, state))
+        .collect();
+    HttpResponse::Ok().json(links)
+}
+
+#[delete("/withdraw-links/{k1}")]
+async fn revoke_withdraw_link(
+    path: web::Path<WithdrawLinkPath>,
+    links: Data<WithdrawLinks>,
+    mut session: Session,
+) -> impl Responder {
+    if require_auth(&mut session).await.is_err() {
+        return HttpResponse::Unauthorized().json(json!({ "error": "login required" }));
+    }
+
+    match links.revoke(&path.k1) {
+        Ok(()) => HttpResponse::Ok().json(json!({ "status": "revoked" })),
+        Err(e) => HttpResponse::NotFound().json(json!({ "error": e.to_string() })),
+    }
+}
+
+#[get("/lnurlw/{k1}")]
+async fn withdraw_request(
+    path: web::Path<WithdrawLinkPath>,
+    links: Data<WithdrawLinks>,
+    cfg: Data<ApiConfig>,
+) -> impl Responder {
+    let state = match links.usable(&path.k1) {
+        Ok(state) => state,
+        Err(e) => return lnurl_error(e),
+    };
+    let Some(resp) = withdraw_link_resp(&cfg, state) else {
+        return lnurl_error("LNURL links are not enabled");
+    };
+
+    HttpResponse::Ok().json(json!({
+        "tag": "withdrawRequest",
+        "callback": format!("{}/callback", resp.url),
+        "k1": resp.state.link.k1,
+        "defaultDescription": resp.state.link.description,
+        "minWithdrawable": resp.state.link.min_msat,
+        "maxWithdrawable": resp.state.link.max_msat,
+    }))
+}
+
+#[get("/lnurlw/{k1}/callback")]
+async fn withdraw_callback(
+    path: web::Path<WithdrawLinkPath>,
+    query: Query<WithdrawCallbackReq>,
+    links: Data<WithdrawLinks>,
+    registry: Data<NodeRegistry>,
+) -> impl Responder {
+    if query.k1 != path.k1 {
+        return lnurl_error("k1 does not match this link");
+    }
+    let bolt11: Bolt11 = match query.pr.parse() {
+        Ok(bolt11) => bolt11,
+        Err(e) => return lnurl_error(e),
+    };
+    let link = match links.claim(&path.k1, &bolt11) {
+        Ok(link) => link,
+        Err(e) => return lnurl_error(e),
+    };
+    let driver = match &link.node {
+        Some(name) => registry.get(name),
+        None => Some(registry.default_node()),
+    };
+    let Some(driver) = driver else {
+        let _ = links.release(&link.k1, &bolt11);
+        return lnurl_error("The node for this link is no longer configured");
+    };
+
+    // LUD-03: confirm first, then pay.
+    let links = links.clone();
+    actix_web::rt::spawn(async move {
+        let id = &link.k1[..8];
+        let failed = match driver.pay_invoice(&bolt11).await {
+            Ok(payment) => {
+                println!("Withdraw link {}…: paid {}", id, payment.hash);
+                false
+            }
+            // The payment may still go through; the use stays taken until
+            // the node says how it ended.
+            Err(e) if PaymentPending::is(&e) => {
+                eprintln!("Withdraw link {}…: {}", id, e);
+                let Some(hash) = bolt11.payment_hash() else {
+                    return;
+                };
+                match await_payment(driver.as_ref(), &hash).await {
+                    Ok(Some(_)) => {
+                        println!("Withdraw link {}…: paid {}", id, hash);
+                        false
+                    }
+                    Ok(None) => {
+                        eprintln!("Withdraw link {}…: payment {} failed", id, hash);
+                        true
+                    }
+                    Err(e) => {
+                        eprintln!("Withdraw link {}…: {}; keeping the use taken", id, e);
+                        false
+                    }
+                }
+            }
+            Err(e) => {
+                eprintln!("Withdraw link {}…: payment failed: {}", id, e);
+                true
+            }
+        };
+        if failed {
+            if let Err(e) = links.release(&link.k1, &bolt11) {
+                eprintln!("Withdraw link {}…: could not give the use back: {}", id, e);
+            }
+        }
+    });
+    HttpResponse::Ok().json(json!({ "status": "OK" }))
+}
+
+// ---------------------------------------------------------------------
+// Lightning Addresses: user@domain pay endpoints, public
+// ---------------------------------------------------------------------
+#[get("/lightning-addresses")]
+async fn list_lightning_addresses(
+    addresses: Option<Data<LightningAddresses>>,
+    mut session: Session,
+) -> impl Responder {
+    if require_auth(&mut session).await.is_err() {
+        return HttpResponse::Unauthorized().json(json!({ "error": "login required" }));
+    }
+
+    match addresses {
+        Some(addresses) => HttpResponse::Ok().json(json!({
+            "addresses": addresses.addresses(),
+            "zaps": addresses.zaps_enabled(),
+        })),
+        None => HttpResponse::Ok().json(json!({ "addresses": [], "zaps": false })),
+    }
+}
+
+#[get("/.well-known/lnurlp/{username}")]
+async fn lnurlp_request(
+    path: web::Path<LnurlpPath>,
+    addresses: Option<Data<LightningAddresses>>,
+) -> impl Responder {
+    let Some(addresses) = addresses else {
+        return lnurl_error("Lightning Addresses are not enabled");
+    };
+    match addresses.pay_request(&path.username) {
+        Ok(res) => HttpResponse::Ok().json(res),
+        Err(e) => lnurl_error(e),
+    }
+}
+
+#[get("/lnurlp/{username}/callback")]
+async fn lnurlp_callback(
+    path: web::Path<LnurlpPath>,
+    query: Query<LnurlpCallbackReq>,
+    addresses: Option<Data<LightningAddresses>>,
+    registry: Data<NodeRegistry>,
+) -> impl Responder {
+    let Some(addresses) = addresses else {
+        return lnurl_error("Lightning Addresses are not enabled");
+    };
+    let Some(amount) = query.amount else {
+        return lnurl_error("amount is required");
+    };
+    match addresses
+        .invoice(
+            &registry,
+            &path.username,
+            amount,
+            query.comment.as_deref(),
+            query.nostr.as_deref(),
+        )
+        .await
+    {
+        Ok(res) => HttpResponse::Ok().json(res),
+        Err(e) => lnurl_error(e),
+    }
+}
+
 // ---------------------------------------------------------------------
 // Main
 // ---------------------------------------------------------------------
 #[actix_web::main]
 async fn main() -> Result<()> {
-    let settings = config::Config::builder()
-        .add_source(config::File::with_name("config.toml"))
-        .build()?
-        .try_deserialize::<serde_json::Value>()?;
+    let config_path = path_arg("config")?;
+    let raw = driver_config::load(config_path.as_deref())?;
+    let node_settings = raw.clone().try_deserialize::<Settings>()?;
+    let settings = raw.try_deserialize::<serde_json::Value>()?;
+
+    let backup_cfg = settings
+        .get("backup")
+        .map(|v| serde_json::from_value::<backup::BackupConfig>(v.clone()))
+        .transpose()?;
+    if let Some(path) = path_arg("decrypt-backup")? {
+        // Writes the backend's blob to stdout, e.g. for `lncli restorechanbackup`.
+        let cfg = backup_cfg.ok_or_else(|| anyhow::anyhow!("No [backup] config"))?;
+        let blob = backup::decrypt(&cfg.passphrase()?, &fs::read(&path)?)?;
+        std::io::stdout().write_all(&blob)?;
+        return Ok(());
+    }
 
-    let api_cfg = settings
+    let mut api_cfg = settings
         .get("api")
         .and_then(|v| serde_json::from_value::<ApiConfig>(v.clone()).ok())
         .unwrap_or(ApiConfig {
             password_hash: "".into(),
+            password_hash_file: None,
             host: default_host(),
             port: default_port(),
+            fiat_ledger: default_fiat_ledger(),
+            public_url: None,
+            withdraw_ledger: default_withdraw_ledger(),
         });
+    if api_cfg.password_hash.is_empty() {
+        if let Some(path) = &api_cfg.password_hash_file {
+            api_cfg.password_hash = driver_config::read_secret_file(path)?;
+        }
+    }
 
     if api_cfg.password_hash.is_empty() {
         eprintln!("WARNING: No password_hash → API is open!");
     }
 
-    let driver = connect_from_config().await?;
+    let rates = node_settings
+        .rates
+        .as_ref()
+        .map(rates::rate_provider)
+        .transpose()?;
+    let fiat = Data::new(Fiat::open(rates, &api_cfg.fiat_ledger)?);
+    let lnurl = Data::new(LnurlClient::new());
+    let withdraw_links = Data::new(WithdrawLinks::open(&api_cfg.withdraw_ledger)?);
+
+    let registry = Data::new(NodeRegistry::connect(node_settings).await?);
+    println!(
+        "Nodes: {} (default: {})",
+        registry.names().collect::<Vec<_>>().join(", "),
+        registry.default_name()
+    );
+
+    let paywall = match settings.get("l402") {
+        Some(v) => {
+            let cfg = serde_json::from_value::<L402Config>(v.clone())?;
+            let paywall = L402::new(&cfg, &registry)?;
+            for resource in paywall.resources() {
+                println!("L402: {} costs {}", resource.path, resource.price_msat);
+            }
+            Some(Data::new(paywall))
+        }
+        None => None,
+    };
+
+    let lnurl_auth = match settings.get("lnurl_auth") {
+        Some(v) => {
+            let cfg = serde_json::from_value::<LnurlAuthConfig>(v.clone())?;
+            let public_url = api_cfg
+                .public_url
+                .as_deref()
+                .ok_or_else(|| anyhow::anyhow!("[lnurl_auth] needs [api] public_url"))?;
+            let auth = LnurlAuth::new(&cfg, public_url)?;
+            println!("LNURL-auth: {} linking key(s)", auth.key_count());
+            Some(Data::new(auth))
+        }
+        None => None,
+    };
+
+    let addresses = match settings.get("lightning_address") {
+        Some(v) => {
+            let cfg = serde_json::from_value::<LightningAddressConfig>(v.clone())?;
+            let public_url = api_cfg
+                .public_url
+                .as_deref()
+                .ok_or_else(|| anyhow::anyhow!("[lightning_address] needs [api] public_url"))?;
+            let addresses = Data::new(LightningAddresses::new(&cfg, public_url, &registry)?);
+            let names: Vec<_> = addresses
+                .addresses()
+                .into_iter()
+                .map(|a| a.address)
+                .collect();
+            println!("Lightning Addresses: {}", names.join(", "));
+            if addresses.zaps_enabled() {
+                let (watcher, registry) = (addresses.clone(), registry.clone());
+                actix_web::rt::spawn(async move { watcher.watch_zaps(&registry).await });
+            }
+            Some(addresses)
+        }
+        None => None,
+    };
+
+    for (name, pool) in registry.pools() {
+        let (name, pool) = (name.to_string(), pool.clone());
+        actix_web::rt::spawn(async move {
+            let mut tick = actix_web::rt::time::interval(POOL_HEALTH_INTERVAL);
+            loop {
+                tick.tick().await;
+                for member in pool.check_health().await {
+                    if !member.healthy {
+                        eprintln!(
+                            "Pool '{}': member '{}' failed its health check",
+                            name, member.name
+                        );
+                    }
+                }
+            }
+        });
+    }
+
+    if let Some(backup_cfg) = &backup_cfg {
+        let job = backup::BackupJob::new(backup_cfg, &registry).await?;
+        println!(
+            "Channel backups of [{}] -> {}",
+            job.node_names().join(", "),
+            backup_cfg.dir
+        );
+        actix_web::rt::spawn(job.run());
+    }
+
+    if let Some(nwc_cfg) = settings
+        .get("nwc_service")
+        .map(|v| serde_json::from_value::<nwc::NwcServiceConfig>(v.clone()))
+        .transpose()?
+    {
+        let driver = match &nwc_cfg.node {
+            Some(name) => registry
+                .get(name)
+                .ok_or_else(|| anyhow::anyhow!("nwc_service node '{}' is not configured", name))?,
+            None => registry.default_node(),
+        };
+        let service = nwc::NwcService::start(&nwc_cfg, driver).await?;
+        println!("NWC wallet service on {} relay(s)", nwc_cfg.relays.len());
+        actix_web::rt::spawn(async move {
+            if let Err(e) = service.run().await {
+                eprintln!("NWC wallet service stopped: {}", e);
+            }
+        });
+    }
 
     let port = std::env::var("PORT")
         .ok()
@@ -292,6 +1612,9 @@ async fn main() -> Result<()> {
 
     println!("API → http://{}", addr);
     println!("Login: POST /login {{ \"password\": \"...\" }}");
+    if lnurl_auth.is_some() {
+        println!("   or: GET /auth/lnurl, then poll GET /auth/lnurl/{{k1}}");
+    }
 
     HttpServer::new(move || {
         let session_mw =
@@ -303,20 +1626,42 @@ async fn main() -> Result<()> {
                 .build();
 
         App::new()
-            .app_data(Data::new(driver.clone()))
+            .app_data(registry.clone())
+            .app_data(fiat.clone())
+            .app_data(lnurl.clone())
+            .app_data(withdraw_links.clone())
+            .configure(|cfg| {
+                if let Some(addresses) = &addresses {
+                    cfg.app_data(addresses.clone());
+                }
+                if let Some(auth) = &lnurl_auth {
+                    cfg.app_data(auth.clone());
+                }
+                if let Some(paywall) = &paywall {
+                    cfg.app_data(paywall.clone());
+                }
+            })
             .app_data(Data::new(api_cfg.clone()))
+            .wrap(from_fn(l402::paywall))
             .wrap(Logger::default())
             .wrap(session_mw)
             .service(login)
             .service(logout)
+            .service(lnurl_auth_challenge)
+            .service(lnurl_auth_callback)
+            .service(lnurl_auth_status)
+            .service(withdraw_request)
+            .service(withdraw_callback)
+            .service(lnurlp_request)
+            .service(lnurlp_callback)
             .service(
                 web::scope("/api")
-                    .service(get_info)
-                    .service(create_invoice)
-                    .service(get_balance)
-                    .service(list_invoices)
-                    .service(decode_invoice)
-                    .service(pay_invoice),
+                    .service(list_nodes)
+                    .service(list_withdraw_links)
+                    .service(revoke_withdraw_link)
+                    .service(list_lightning_addresses)
+                    .service(web::scope("/nodes/{name}").configure(node_routes))
+                    .configure(node_routes),
             )
     })
     .bind(addr)?
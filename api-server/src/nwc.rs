@@ -0,0 +1,673 @@
+// api-server/src/nwc.rs
+//
+// Exposes the configured node as a Nostr Wallet Connect (NIP-47) wallet
+// service. Each configured connection gets its own client key and an
+// optional spending budget, which is kept on disk so a restart doesn't
+// reset it.
+use anyhow::{anyhow, Result};
+use lightning_client::nostr_relay::RelayPool;
+use lightning_client::{await_payment, Bolt11, Invoice, LightningClientDyn, Msat, PaymentPending};
+use nostr::event::FinalizeEvent;
+use nostr::nips::nip47::{
+    ErrorCode, GetBalanceResponse, GetInfoResponse, ListTransactionsRequest, LookupInvoiceRequest,
+    LookupInvoiceResponse, MakeInvoiceRequest, MakeInvoiceResponse, Method, NIP47Error,
+    Nip47Ciphers, Nip47Tag, PayInvoiceRequest, PayInvoiceResponse, Request, RequestParams,
+    Response, ResponseResult, TransactionState, TransactionType,
+};
+use nostr::prelude::{
+    Event, EventBuilder, Filter, Keys, Kind, PublicKey, RelayUrl, SecretKey, Tag, Timestamp,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast::error::RecvError;
+
+const METHODS: [Method; 6] = [
+    Method::PayInvoice,
+    Method::MakeInvoice,
+    Method::LookupInvoice,
+    Method::ListTransactions,
+    Method::GetBalance,
+    Method::GetInfo,
+];
+
+// ---------------------------------------------------------------------
+// Config
+// ---------------------------------------------------------------------
+#[derive(Deserialize, Clone)]
+pub struct NwcServiceConfig {
+    /// Wallet service key, hex or nsec.
+    pub secret_key: String,
+    pub relays: Vec<String>,
+    /// Node to expose; the default node when unset.
+    #[serde(default)]
+    pub node: Option<String>,
+    #[serde(default)]
+    pub connections: Vec<NwcConnectionConfig>,
+    /// What each connection has spent in its current period.
+    #[serde(default = "default_budget_file")]
+    pub budget_file: String,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct NwcConnectionConfig {
+    pub name: String,
+    /// Client secret shared with the app through the connection URI.
+    pub secret: String,
+    /// Maximum spend per renewal period, routing fees included; unlimited
+    /// when absent.
+    #[serde(default)]
+    pub budget_msat: Option<u64>,
+    #[serde(default = "default_budget_renewal")]
+    pub budget_renewal: String,
+}
+
+fn default_budget_renewal() -> String {
+    "never".into()
+}
+
+fn default_budget_file() -> String {
+    "nwc_budgets.json".into()
+}
+
+// ---------------------------------------------------------------------
+// Budgets
+// ---------------------------------------------------------------------
+struct Connection {
+    name: String,
+    budget_msat: Option<u64>,
+    renewal_secs: Option<u64>,
+    spent_msat: u64,
+    period_start: u64,
+}
+
+impl Connection {
+    /// Reserves `msat` against the budget, starting a new period if due.
+    fn reserve(&mut self, msat: u64, now: u64) -> Result<(), NIP47Error> {
+        if let Some(period) = self.renewal_secs {
+            if now >= self.period_start + period {
+                self.period_start = now;
+                self.spent_msat = 0;
+            }
+        }
+        if let Some(budget) = self.budget_msat {
+            if self.spent_msat.saturating_add(msat) > budget {
+                return Err(nip47_error(
+                    ErrorCode::QuotaExceeded,
+                    format!(
+                        "budget of {} msat exceeded ({} msat spent)",
+                        budget, self.spent_msat
+                    ),
+                ));
+            }
+        }
+        self.spent_msat += msat;
+        Ok(())
+    }
+
+    fn release(&mut self, msat: u64) {
+        self.spent_msat = self.spent_msat.saturating_sub(msat);
+    }
+}
+
+/// A connection's spending, as saved by name.
+#[derive(Serialize, Deserialize)]
+struct Spent {
+    spent_msat: u64,
+    period_start: u64,
+}
+
+/// The connections and their spending, written to `path` after every
+/// change.
+struct Budgets {
+    path: PathBuf,
+    connections: Mutex<HashMap<PublicKey, Connection>>,
+}
+
+impl Budgets {
+    /// Picks up what the connections in `connections` had spent before.
+    fn open(path: PathBuf, mut connections: HashMap<PublicKey, Connection>) -> Result<Self> {
+        if path.exists() {
+            let saved: HashMap<String, Spent> = serde_json::from_slice(&fs::read(&path)?)
+                .map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+            for conn in connections.values_mut() {
+                if let Some(spent) = saved.get(&conn.name) {
+                    conn.spent_msat = spent.spent_msat;
+                    conn.period_start = spent.period_start;
+                }
+            }
+        }
+        Ok(Self {
+            path,
+            connections: Mutex::new(connections),
+        })
+    }
+
+    fn contains(&self, client: &PublicKey) -> bool {
+        self.connections.lock().unwrap().contains_key(client)
+    }
+
+    /// Reserves `msat` for a payment and returns the connection's name.
+    /// Nothing is reserved unless it could be saved.
+    fn reserve(&self, client: &PublicKey, msat: u64) -> Result<String, NIP47Error> {
+        let mut conns = self.connections.lock().unwrap();
+        let conn = conns
+            .get_mut(client)
+            .ok_or_else(|| nip47_error(ErrorCode::Unauthorized, "unknown connection"))?;
+        conn.reserve(msat, now_secs())?;
+        let name = conn.name.clone();
+        if let Err(e) = self.save(&conns) {
+            if let Some(conn) = conns.get_mut(client) {
+                conn.release(msat);
+            }
+            return Err(internal(e));
+        }
+        Ok(name)
+    }
+
+    /// Replaces a `reserved` amount with what the payment really cost.
+    fn settle(&self, client: &PublicKey, reserved: u64, cost: u64) {
+        self.update(client, |conn| {
+            conn.spent_msat = conn
+                .spent_msat
+                .saturating_sub(reserved)
+                .saturating_add(cost)
+        });
+    }
+
+    /// Gives back a reservation for a payment that failed.
+    fn release(&self, client: &PublicKey, msat: u64) {
+        self.update(client, |conn| conn.release(msat));
+    }
+
+    fn update(&self, client: &PublicKey, f: impl FnOnce(&mut Connection)) {
+        let mut conns = self.connections.lock().unwrap();
+        if let Some(conn) = conns.get_mut(client) {
+            f(conn);
+            if let Err(e) = self.save(&conns) {
+                eprintln!("NWC budgets could not be saved: {}", e);
+            }
+        }
+    }
+
+    /// Replaces the file in one step, so a crash leaves the old or the new
+    /// version.
+    fn save(&self, conns: &HashMap<PublicKey, Connection>) -> Result<()> {
+        let spent: HashMap<&str, Spent> = conns
+            .values()
+            .map(|c| {
+                (
+                    c.name.as_str(),
+                    Spent {
+                        spent_msat: c.spent_msat,
+                        period_start: c.period_start,
+                    },
+                )
+            })
+            .collect();
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(&spent)?)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+fn renewal_secs(renewal: &str) -> Result<Option<u64>> {
+    Ok(match renewal {
+        "daily" => Some(24 * 60 * 60),
+        "weekly" => Some(7 * 24 * 60 * 60),
+        "monthly" => Some(30 * 24 * 60 * 60),
+        "yearly" => Some(365 * 24 * 60 * 60),
+        "never" => None,
+        other => return Err(anyhow!("Unknown budget_renewal '{}'", other)),
+    })
+}
+
+/// Held against the budget for routing fees until a payment's real fee is
+/// known: 1% of the amount, and at least 1 sat.
+fn fee_allowance(amount: u64) -> u64 {
+    (amount / 100).max(1_000)
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn nip47_error(code: ErrorCode, message: impl Into<String>) -> NIP47Error {
+    NIP47Error {
+        code,
+        message: message.into(),
+    }
+}
+
+// ---------------------------------------------------------------------
+// Service
+// ---------------------------------------------------------------------
+pub struct NwcService {
+    keys: Keys,
+    pool: RelayPool,
+    driver: LightningClientDyn,
+    budgets: Arc<Budgets>,
+}
+
+impl NwcService {
+    pub async fn start(cfg: &NwcServiceConfig, driver: LightningClientDyn) -> Result<Self> {
+        let keys = Keys::parse(&cfg.secret_key).map_err(|e| anyhow!("Invalid NWC key: {}", e))?;
+
+        let relays = cfg
+            .relays
+            .iter()
+            .map(|r| RelayUrl::parse(r).map_err(|e| anyhow!("Invalid relay '{}': {}", r, e)))
+            .collect::<Result<Vec<_>>>()?;
+
+        let now = now_secs();
+        let mut connections = HashMap::new();
+        for conn in &cfg.connections {
+            let secret = SecretKey::parse(&conn.secret)
+                .map_err(|e| anyhow!("Invalid secret for NWC connection '{}': {}", conn.name, e))?;
+            // The URI carries the secret, so only the client key is shown.
+            let client = Keys::new(secret).public_key();
+            println!("NWC connection '{}': client {}", conn.name, client);
+
+            connections.insert(
+                client,
+                Connection {
+                    name: conn.name.clone(),
+                    budget_msat: conn.budget_msat,
+                    renewal_secs: renewal_secs(&conn.budget_renewal)?,
+                    spent_msat: 0,
+                    period_start: now,
+                },
+            );
+        }
+
+        let urls: Vec<String> = relays.iter().map(|r| r.to_string()).collect();
+        let service = Self {
+            keys,
+            pool: RelayPool::new(&urls)?,
+            driver,
+            budgets: Arc::new(Budgets::open(cfg.budget_file.clone().into(), connections)?),
+        };
+        service.publish_info()?;
+        Ok(service)
+    }
+
+    /// Announces supported methods and ciphers (kind 13194).
+    fn publish_info(&self) -> Result<()> {
+        let methods: Vec<&str> = METHODS.iter().map(Method::as_str).collect();
+        let ciphers = Nip47Ciphers::NIP44V2.add(Nip47Ciphers::NIP04);
+        let event = EventBuilder::new(Kind::WalletConnectInfo, methods.join(" "))
+            .tag(Tag::from(Nip47Tag::Encryption(ciphers)))
+            .finalize(&self.keys)?;
+        self.pool.publish(event);
+        Ok(())
+    }
+
+    /// Serves requests until the relay pool shuts down.
+    pub async fn run(self) -> Result<()> {
+        let filter = Filter::new()
+            .kind(Kind::WalletConnectRequest)
+            .pubkey(self.keys.public_key())
+            .since(Timestamp::now());
+        let mut events = self.pool.events();
+        self.pool.subscribe("nwc-requests", filter);
+
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(n)) => {
+                    eprintln!("NWC service dropped {} requests", n);
+                    continue;
+                }
+                Err(RecvError::Closed) => return Ok(()),
+            };
+            if event.kind != Kind::WalletConnectRequest {
+                continue;
+            }
+            if let Err(e) = self.handle(&event).await {
+                eprintln!("NWC request {} failed: {}", event.id, e);
+            }
+        }
+    }
+
+    async fn handle(&self, event: &Event) -> Result<()> {
+        let cipher = event
+            .tags
+            .iter()
+            .find_map(|t| match Nip47Tag::try_from(t).ok()? {
+                Nip47Tag::Encryption(c) => Some(c.latest()),
+            })
+            .unwrap_or(Nip47Ciphers::NIP04);
+
+        let plain = cipher.decrypt(self.keys.secret_key(), &event.pubkey, &event.content)?;
+        let value: Value = serde_json::from_str(&plain)?;
+        let method: Method = value["method"]
+            .as_str()
+            .unwrap_or_default()
+            .parse()
+            .unwrap_or_else(|_| Method::Unknown(String::new()));
+
+        let response = match Request::from_value(value) {
+            Err(_) => error_response(
+                method,
+                nip47_error(ErrorCode::NotImplemented, "unsupported request"),
+            ),
+            Ok(req) => match self.dispatch(&event.pubkey, req.params).await {
+                Ok(result) => Response {
+                    result_type: method,
+                    error: None,
+                    result: Some(result),
+                },
+                Err(err) => error_response(method, err),
+            },
+        };
+
+        let content = cipher.encrypt(
+            self.keys.secret_key(),
+            &event.pubkey,
+            &serde_json::to_string(&response)?,
+        )?;
+        let mut builder = EventBuilder::new(Kind::WalletConnectResponse, content)
+            .tag(Tag::public_key(event.pubkey))
+            .tag(Tag::event(event.id));
+        if cipher == Nip47Ciphers::NIP44V2 {
+            builder = builder.tag(Tag::from(Nip47Tag::Encryption(cipher)));
+        }
+        let reply = builder.finalize(&self.keys)?;
+        self.pool.publish(reply);
+        Ok(())
+    }
+
+    async fn dispatch(
+        &self,
+        client: &PublicKey,
+        params: RequestParams,
+    ) -> Result<ResponseResult, NIP47Error> {
+        if !self.budgets.contains(client) {
+            return Err(nip47_error(ErrorCode::Unauthorized, "unknown connection"));
+        }
+
+        match params {
+            RequestParams::PayInvoice(req) => self.pay_invoice(client, req).await,
+            RequestParams::MakeInvoice(req) => self.make_invoice(req).await,
+            RequestParams::LookupInvoice(req) => self.lookup_invoice(req).await,
+            RequestParams::ListTransactions(req) => self.list_transactions(req).await,
+            RequestParams::GetBalance => {
+                let balance = self.driver.get_balance().await.map_err(internal)?;
+                Ok(ResponseResult::GetBalance(GetBalanceResponse {
+                    balance: balance.channel_msat.as_msat(),
+                }))
+            }
+            RequestParams::GetInfo => {
+                let info = self.driver.get_info().await.map_err(internal)?;
+                Ok(ResponseResult::GetInfo(GetInfoResponse {
+                    alias: Some(info.alias),
+                    color: info.color,
+                    pubkey: info.identity_pubkey.map(|k| k.to_string()),
+                    network: info.network,
+                    block_height: info.block_height,
+                    block_hash: info.block_hash,
+                    methods: METHODS.to_vec(),
+                    notifications: vec![],
+                }))
+            }
+            _ => Err(nip47_error(
+                ErrorCode::NotImplemented,
+                "method not supported",
+            )),
+        }
+    }
+
+    async fn pay_invoice(
+        &self,
+        client: &PublicKey,
+        req: PayInvoiceRequest,
+    ) -> Result<ResponseResult, NIP47Error> {
+        let invoice: Bolt11 = req
+            .invoice
+            .parse()
+            .map_err(|e: anyhow::Error| nip47_error(ErrorCode::Other, e.to_string()))?;
+        let decoded = self
+            .driver
+            .decode_invoice(&invoice)
+            .await
+            .map_err(|e| nip47_error(ErrorCode::Other, e.to_string()))?;
+        let amount = decoded.amount_msat.map(Msat::as_msat).ok_or_else(|| {
+            nip47_error(ErrorCode::Other, "amountless invoices are not supported")
+        })?;
+
+        // Fees count against the budget too, so room for them is held back
+        // until the node says what they came to.
+        let allowance = fee_allowance(amount);
+        let reserved = amount.saturating_add(allowance);
+        let name = self.budgets.reserve(client, reserved)?;
+
+        match self.driver.pay_invoice(&invoice).await {
+            Ok(payment) => {
+                let fee_msat = payment.fee_msat.map(Msat::as_msat);
+                let cost = amount.saturating_add(fee_msat.unwrap_or(allowance));
+                self.budgets.settle(client, reserved, cost);
+                println!("NWC '{}' paid {} msat ({})", name, amount, payment.hash);
+                Ok(ResponseResult::PayInvoice(PayInvoiceResponse {
+                    preimage: payment.preimage.map(|p| p.to_string()).unwrap_or_default(),
+                    fees_paid: fee_msat,
+                }))
+            }
+            // The payment may still go through, so the reservation stands
+            // until the node knows. After a restart it stays spent.
+            Err(e) if PaymentPending::is(&e) => {
+                if let Some(hash) = invoice.payment_hash() {
+                    let driver = Arc::clone(&self.driver);
+                    let budgets = Arc::clone(&self.budgets);
+                    let client = *client;
+                    tokio::spawn(async move {
+                        match await_payment(driver.as_ref(), &hash).await {
+                            Ok(Some(payment)) => {
+                                let fee_msat = payment.fee_msat.map_or(allowance, Msat::as_msat);
+                                budgets.settle(&client, reserved, amount.saturating_add(fee_msat));
+                                println!("NWC '{}' paid {} msat ({})", name, amount, hash);
+                            }
+                            Ok(None) => budgets.release(&client, reserved),
+                            Err(e) => eprintln!("NWC '{}': {}; keeping it spent", name, e),
+                        }
+                    });
+                }
+                Err(nip47_error(ErrorCode::Other, e.to_string()))
+            }
+            Err(e) => {
+                self.budgets.release(client, reserved);
+                Err(nip47_error(ErrorCode::PaymentFailed, e.to_string()))
+            }
+        }
+    }
+
+    async fn make_invoice(&self, req: MakeInvoiceRequest) -> Result<ResponseResult, NIP47Error> {
+        let bolt11 = self
+            .driver
+            .create_invoice(
+                Msat::from_msat(req.amount),
+                None,
+                req.description.as_deref(),
+            )
+            .await
+            .map_err(internal)?;
+        Ok(ResponseResult::MakeInvoice(MakeInvoiceResponse {
+            invoice: bolt11.to_string(),
+            payment_hash: None,
+            description: req.description,
+            description_hash: None,
+            preimage: None,
+            amount: Some(req.amount),
+            created_at: Some(Timestamp::now()),
+            expires_at: None,
+        }))
+    }
+
+    async fn lookup_invoice(
+        &self,
+        req: LookupInvoiceRequest,
+    ) -> Result<ResponseResult, NIP47Error> {
+        let invoices = self
+            .driver
+            .list_invoices(Some(100))
+            .await
+            .map_err(internal)?;
+        // Compare parsed values so hex and invoice casing don't matter.
+        let hash = req.payment_hash.as_deref().and_then(|h| h.parse().ok());
+        let bolt11: Option<Bolt11> = req.invoice.as_deref().and_then(|i| i.parse().ok());
+        invoices
+            .into_iter()
+            .find(|inv| {
+                (hash.is_some() && hash == Some(inv.hash))
+                    || (bolt11.is_some() && bolt11 == inv.bolt11)
+            })
+            .map(|inv| ResponseResult::LookupInvoice(transaction(inv)))
+            .ok_or_else(|| nip47_error(ErrorCode::NotFound, "invoice not found"))
+    }
+
+    async fn list_transactions(
+        &self,
+        req: ListTransactionsRequest,
+    ) -> Result<ResponseResult, NIP47Error> {
+        // Only incoming invoices are tracked by the driver.
+        if req.transaction_type == Some(TransactionType::Outgoing) {
+            return Ok(ResponseResult::ListTransactions(vec![]));
+        }
+
+        let offset = req.offset.unwrap_or(0) as usize;
+        let limit = req.limit.unwrap_or(10) as usize;
+        let invoices = self
+            .driver
+            .list_invoices(Some(offset + limit))
+            .await
+            .map_err(internal)?;
+
+        let include_unpaid = req.unpaid.unwrap_or(false);
+        let txs = invoices
+            .into_iter()
+            .map(transaction)
+            .filter(|tx| include_unpaid || tx.state == Some(TransactionState::Settled))
+            .skip(offset)
+            .take(limit)
+            .collect();
+        Ok(ResponseResult::ListTransactions(txs))
+    }
+}
+
+fn transaction(inv: Invoice) -> LookupInvoiceResponse {
+    let state = match inv.state.as_str() {
+        "paid" | "settled" => TransactionState::Settled,
+        "expired" | "canceled" => TransactionState::Expired,
+        "failed" => TransactionState::Failed,
+        "accepted" => TransactionState::Accepted,
+        _ => TransactionState::Pending,
+    };
+    LookupInvoiceResponse {
+        transaction_type: Some(TransactionType::Incoming),
+        state: Some(state),
+        invoice: inv.bolt11.map(String::from),
+        description: inv.desc,
+        description_hash: None,
+        preimage: None,
+        payment_hash: inv.hash.to_string(),
+        amount: inv.amount_msat.as_msat(),
+        fees_paid: 0,
+        created_at: Timestamp::from(0),
+        expires_at: None,
+        settled_at: None,
+        metadata: None,
+    }
+}
+
+fn error_response(method: Method, error: NIP47Error) -> Response {
+    Response {
+        result_type: method,
+        error: Some(error),
+        result: None,
+    }
+}
+
+fn internal(e: anyhow::Error) -> NIP47Error {
+    nip47_error(ErrorCode::Internal, e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn budgets(path: &Path, client: PublicKey) -> Budgets {
+        let conn = Connection {
+            name: "app".into(),
+            budget_msat: Some(10_000),
+            renewal_secs: None,
+            spent_msat: 0,
+            period_start: now_secs(),
+        };
+        Budgets::open(path.to_path_buf(), HashMap::from([(client, conn)])).unwrap()
+    }
+
+    fn spent(budgets: &Budgets, client: &PublicKey) -> u64 {
+        budgets.connections.lock().unwrap()[client].spent_msat
+    }
+
+    #[test]
+    fn spending_survives_a_restart() {
+        let path = std::env::temp_dir().join(format!("nwc-budgets-{}.json", rand::random::<u64>()));
+        let client = Keys::generate().public_key();
+
+        let first = budgets(&path, client);
+        first.reserve(&client, 7_000).unwrap();
+        first.settle(&client, 7_000, 6_100);
+        drop(first);
+
+        let second = budgets(&path, client);
+        assert_eq!(spent(&second, &client), 6_100);
+        let err = second.reserve(&client, 4_000).unwrap_err();
+        assert_eq!(err.code, ErrorCode::QuotaExceeded);
+
+        second.release(&client, 6_000);
+        drop(second);
+        assert_eq!(spent(&budgets(&path, client), &client), 100);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn fees_are_reserved_until_they_are_known() {
+        let path = std::env::temp_dir().join(format!("nwc-budgets-{}.json", rand::random::<u64>()));
+        let client = Keys::generate().public_key();
+        let budgets = budgets(&path, client);
+
+        // 9_500 msat fits the 10_000 msat budget, but not with its fees.
+        assert_eq!(fee_allowance(9_500), 1_000);
+        let err = budgets.reserve(&client, 9_500 + 1_000).unwrap_err();
+        assert_eq!(err.code, ErrorCode::QuotaExceeded);
+        assert_eq!(spent(&budgets, &client), 0);
+
+        // The unused part of the allowance comes back once the fee is known.
+        budgets.reserve(&client, 5_000 + 1_000).unwrap();
+        budgets.settle(&client, 6_000, 5_000 + 20);
+        assert_eq!(spent(&budgets, &client), 5_020);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn nothing_is_reserved_when_it_cannot_be_saved() {
+        let path = std::env::temp_dir()
+            .join(format!("nwc-missing-{}", rand::random::<u64>()))
+            .join("budgets.json");
+        let client = Keys::generate().public_key();
+
+        let budgets = budgets(&path, client);
+        let err = budgets.reserve(&client, 1_000).unwrap_err();
+        assert_eq!(err.code, ErrorCode::Internal);
+        assert_eq!(spent(&budgets, &client), 0);
+    }
+}
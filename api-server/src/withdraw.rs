@@ -0,0 +1,346 @@
+// api-server/src/withdraw.rs
+//
+// LNURL-withdraw (LUD-03) links handed out as vouchers. A link allows a
+// number of withdrawals within an amount range until it expires or is
+// revoked. Every change is appended to a JSON-lines ledger, so a used-up
+// link stays used up across restarts.
+use anyhow::{anyhow, Result};
+use lightning_client::{Bolt11, Msat};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WithdrawLink {
+    /// Secret in the link's URL; whoever holds it can withdraw.
+    pub k1: String,
+    /// Node paying the withdrawals; the default node when `None`.
+    pub node: Option<String>,
+    pub min_msat: Msat,
+    pub max_msat: Msat,
+    pub uses: u32,
+    pub description: String,
+    /// Unix seconds.
+    pub created_at: u64,
+    pub expires_at: Option<u64>,
+}
+
+/// A link and what has been withdrawn from it.
+#[derive(Debug, Clone, Serialize)]
+pub struct LinkState {
+    #[serde(flatten)]
+    pub link: WithdrawLink,
+    pub used: u32,
+    pub revoked: bool,
+    #[serde(skip)]
+    claimed: HashSet<Bolt11>,
+}
+
+impl LinkState {
+    fn check_usable(&self, now: u64) -> Result<()> {
+        if self.revoked {
+            return Err(anyhow!("This withdraw link was revoked"));
+        }
+        if self.link.expires_at.is_some_and(|at| now >= at) {
+            return Err(anyhow!("This withdraw link has expired"));
+        }
+        if self.used >= self.link.uses {
+            return Err(anyhow!("This withdraw link has been used up"));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum LedgerLine {
+    Created(WithdrawLink),
+    Claimed {
+        k1: String,
+        bolt11: Bolt11,
+    },
+    /// The payment failed, so the use is given back.
+    Failed {
+        k1: String,
+        bolt11: Bolt11,
+    },
+    Revoked {
+        k1: String,
+    },
+}
+
+pub struct WithdrawLinks {
+    path: PathBuf,
+    links: Mutex<HashMap<String, LinkState>>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl WithdrawLinks {
+    /// Replays the ledger at `path`, which is created on the first link.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let mut links = HashMap::new();
+        if path.exists() {
+            for (n, line) in fs::read_to_string(&path)?.lines().enumerate() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str::<LedgerLine>(line) {
+                    Ok(entry) => apply(&mut links, entry),
+                    Err(e) => eprintln!("{}:{}: skipping entry: {}", path.display(), n + 1, e),
+                }
+            }
+        }
+        Ok(Self {
+            path,
+            links: Mutex::new(links),
+        })
+    }
+
+    /// Writes `entry` to the ledger, then applies it. Callers hold the lock
+    /// so lines from concurrent requests don't interleave.
+    fn record(&self, links: &mut HashMap<String, LinkState>, entry: LedgerLine) -> Result<()> {
+        let line = serde_json::to_string(&entry)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", line)?;
+        apply(links, entry);
+        Ok(())
+    }
+
+    pub fn create(
+        &self,
+        node: Option<String>,
+        min_msat: Msat,
+        max_msat: Msat,
+        uses: u32,
+        description: String,
+        expires_in_secs: Option<u64>,
+    ) -> Result<LinkState> {
+        if min_msat == Msat::ZERO || min_msat > max_msat {
+            return Err(anyhow!("Need 0 < min_msat <= max_msat"));
+        }
+        if uses == 0 {
+            return Err(anyhow!("A link needs at least one use"));
+        }
+        let created_at = now();
+        let link = WithdrawLink {
+            k1: hex::encode(rand::random::<[u8; 32]>()),
+            node,
+            min_msat,
+            max_msat,
+            uses,
+            description,
+            created_at,
+            expires_at: expires_in_secs.map(|secs| created_at.saturating_add(secs)),
+        };
+
+        let mut links = self.links.lock().unwrap();
+        let k1 = link.k1.clone();
+        self.record(&mut links, LedgerLine::Created(link))?;
+        Ok(links[&k1].clone())
+    }
+
+    /// Newest first.
+    pub fn list(&self) -> Vec<LinkState> {
+        let mut links: Vec<_> = self.links.lock().unwrap().values().cloned().collect();
+        links.sort_by_key(|l| std::cmp::Reverse(l.link.created_at));
+        links
+    }
+
+    /// The link behind `k1` if it can still be withdrawn from.
+    pub fn usable(&self, k1: &str) -> Result<LinkState> {
+        let links = self.links.lock().unwrap();
+        let state = links
+            .get(k1)
+            .ok_or_else(|| anyhow!("Unknown withdraw link"))?;
+        state.check_usable(now())?;
+        Ok(state.clone())
+    }
+
+    /// Takes one use of the link for `bolt11`, which must be within the
+    /// link's amount range. Returns the link so the caller can pay.
+    pub fn claim(&self, k1: &str, bolt11: &Bolt11) -> Result<WithdrawLink> {
+        let mut links = self.links.lock().unwrap();
+        let state = links
+            .get(k1)
+            .ok_or_else(|| anyhow!("Unknown withdraw link"))?;
+        state.check_usable(now())?;
+        if state.claimed.contains(bolt11) {
+            return Err(anyhow!("This invoice was already submitted"));
+        }
+        let amount = bolt11
+            .amount()
+            .ok_or_else(|| anyhow!("Invoice must have an amount"))?;
+        if amount < state.link.min_msat || amount > state.link.max_msat {
+            return Err(anyhow!(
+                "Amount {} is outside {}..{}",
+                amount,
+                state.link.min_msat,
+                state.link.max_msat
+            ));
+        }
+
+        let link = state.link.clone();
+        self.record(
+            &mut links,
+            LedgerLine::Claimed {
+                k1: k1.to_string(),
+                bolt11: bolt11.clone(),
+            },
+        )?;
+        Ok(link)
+    }
+
+    /// Gives back the use taken by a claim whose payment failed.
+    pub fn release(&self, k1: &str, bolt11: &Bolt11) -> Result<()> {
+        let mut links = self.links.lock().unwrap();
+        self.record(
+            &mut links,
+            LedgerLine::Failed {
+                k1: k1.to_string(),
+                bolt11: bolt11.clone(),
+            },
+        )
+    }
+
+    pub fn revoke(&self, k1: &str) -> Result<()> {
+        let mut links = self.links.lock().unwrap();
+        if !links.contains_key(k1) {
+            return Err(anyhow!("Unknown withdraw link"));
+        }
+        self.record(&mut links, LedgerLine::Revoked { k1: k1.to_string() })
+    }
+}
+
+fn apply(links: &mut HashMap<String, LinkState>, entry: LedgerLine) {
+    match entry {
+        LedgerLine::Created(link) => {
+            links.insert(
+                link.k1.clone(),
+                LinkState {
+                    link,
+                    used: 0,
+                    revoked: false,
+                    claimed: HashSet::new(),
+                },
+            );
+        }
+        LedgerLine::Claimed { k1, bolt11 } => {
+            if let Some(state) = links.get_mut(&k1) {
+                if state.claimed.insert(bolt11) {
+                    state.used += 1;
+                }
+            }
+        }
+        LedgerLine::Failed { k1, bolt11 } => {
+            if let Some(state) = links.get_mut(&k1) {
+                if state.claimed.remove(&bolt11) {
+                    state.used -= 1;
+                }
+            }
+        }
+        LedgerLine::Revoked { k1 } => {
+            if let Some(state) = links.get_mut(&k1) {
+                state.revoked = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Regtest invoices for 5, 6 and 100 sat with zero signatures.
+    const FIVE_SAT: &str = "lnbcrt50000p1qqqqqqqpp5qyqszqgpqyqszqgpqyqszqgpqyqszqgpqyqszqgpqyqszqgpqyqsqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqql673v7";
+    const SIX_SAT: &str = "lnbcrt60000p1qqqqqqqpp5qgpqyqszqgpqyqszqgpqyqszqgpqyqszqgpqyqszqgpqyqszqgpqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqpuct4e";
+    const HUNDRED_SAT: &str = "lnbcrt1000000p1qqqqqqqpp5qvpsxqcrqvpsxqcrqvpsxqcrqvpsxqcrqvpsxqcrqvpsxqcrqvpsqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqq93jtlr";
+
+    fn ledger() -> PathBuf {
+        std::env::temp_dir().join(format!("withdraw-{}.jsonl", rand::random::<u64>()))
+    }
+
+    fn link(links: &WithdrawLinks, uses: u32) -> String {
+        links
+            .create(
+                None,
+                Msat::from_msat(1_000),
+                Msat::from_msat(10_000),
+                uses,
+                "Voucher".into(),
+                None,
+            )
+            .unwrap()
+            .link
+            .k1
+    }
+
+    #[test]
+    fn claims_use_up_the_link_and_failures_give_uses_back() {
+        let path = ledger();
+        let links = WithdrawLinks::open(&path).unwrap();
+        let k1 = link(&links, 1);
+        let (five, six) = (FIVE_SAT.parse().unwrap(), SIX_SAT.parse().unwrap());
+
+        links.claim(&k1, &five).unwrap();
+        let err = links.claim(&k1, &six).unwrap_err();
+        assert!(err.to_string().contains("used up"), "{}", err);
+
+        links.release(&k1, &five).unwrap();
+        assert_eq!(links.usable(&k1).unwrap().used, 0);
+        links.claim(&k1, &six).unwrap();
+        assert!(links.usable(&k1).is_err());
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn claims_are_checked_against_the_link() {
+        let path = ledger();
+        let links = WithdrawLinks::open(&path).unwrap();
+        let k1 = link(&links, 2);
+        let five: Bolt11 = FIVE_SAT.parse().unwrap();
+
+        let err = links.claim(&k1, &HUNDRED_SAT.parse().unwrap()).unwrap_err();
+        assert!(err.to_string().contains("outside"), "{}", err);
+        links.claim(&k1, &five).unwrap();
+        let err = links.claim(&k1, &five).unwrap_err();
+        assert!(err.to_string().contains("already submitted"), "{}", err);
+
+        links.revoke(&k1).unwrap();
+        let err = links.claim(&k1, &SIX_SAT.parse().unwrap()).unwrap_err();
+        assert!(err.to_string().contains("revoked"), "{}", err);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn ledger_replays_claims_and_failures() {
+        let path = ledger();
+        let links = WithdrawLinks::open(&path).unwrap();
+        let k1 = link(&links, 3);
+        let (five, six) = (FIVE_SAT.parse().unwrap(), SIX_SAT.parse().unwrap());
+        links.claim(&k1, &five).unwrap();
+        links.claim(&k1, &six).unwrap();
+        links.release(&k1, &six).unwrap();
+        drop(links);
+
+        let links = WithdrawLinks::open(&path).unwrap();
+        assert_eq!(links.usable(&k1).unwrap().used, 1);
+        let err = links.claim(&k1, &five).unwrap_err();
+        assert!(err.to_string().contains("already submitted"), "{}", err);
+        links.claim(&k1, &six).unwrap();
+        let _ = fs::remove_file(path);
+    }
+}
@@ -1,140 +1,303 @@
 // lightning-client/src/cln.rs
 use super::*;
+use crate::config::ConnectionConfig;
+use reqwest::header::{HeaderMap, HeaderValue};
 use reqwest::Client;
 use serde_json::{json, Value};
+use std::time::Duration;
+
+/// `openchannel_update` calls before giving up on the peer.
+const PSBT_UPDATE_ROUNDS: usize = 16;
 
 pub struct ClnClient {
-    url: String,
-    http: Client,
+    transport: Transport,
+    payment_timeout: Duration,
+}
+
+enum Transport {
+    /// The `clnrest` plugin.
+    Rest { url: String, http: Client },
+    /// Commando over a Lightning peer connection to the node itself.
+    #[cfg(feature = "cln")]
+    Commando(crate::commando::Commando),
 }
 
 impl ClnClient {
-    pub fn new(url: &str) -> Self {
-        Self {
-            url: url.trim_end_matches('/').to_string(),
-            http: Client::new(),
+    pub fn new(url: &str, rune: Option<&str>, connection: &ConnectionConfig) -> Result<Self> {
+        let mut headers = HeaderMap::new();
+        if let Some(rune) = rune {
+            let value =
+                HeaderValue::from_str(rune.trim()).map_err(|_| anyhow::anyhow!("Invalid rune"))?;
+            headers.insert("Rune", value);
+        }
+        let http = Client::builder()
+            .default_headers(headers)
+            .connect_timeout(connection.connect_timeout())
+            .timeout(connection.request_timeout())
+            .build()?;
+
+        Ok(Self {
+            transport: Transport::Rest {
+                url: url.trim_end_matches('/').to_string(),
+                http,
+            },
+            payment_timeout: connection.payment_timeout(),
+        })
+    }
+
+    /// Talks to the node at `addr` (`host:port`) as a Lightning peer and
+    /// sends calls through Commando. `node_id` is the node's public key.
+    pub fn commando(
+        node_id: &str,
+        addr: &str,
+        rune: Option<&str>,
+        connection: &ConnectionConfig,
+    ) -> Result<Self> {
+        let rune = rune.ok_or_else(|| anyhow::anyhow!("Commando needs a rune"))?;
+        #[cfg(feature = "cln")]
+        {
+            Ok(Self {
+                transport: Transport::Commando(crate::commando::Commando::new(
+                    node_id,
+                    addr,
+                    rune,
+                    connection.connect_timeout(),
+                    connection.request_timeout(),
+                )?),
+                payment_timeout: connection.payment_timeout(),
+            })
+        }
+        #[cfg(not(feature = "cln"))]
+        {
+            let _ = (node_id, addr, rune, connection);
+            Err(anyhow::anyhow!("CLN feature not enabled"))
         }
     }
+
+    /// Runs an RPC method. A refused call still returns CLN's reply, with
+    /// its `code` and `message`.
+    async fn call(&self, method: &str, params: &Value) -> Result<Value> {
+        match &self.transport {
+            Transport::Rest { url, http } => Ok(http
+                .post(format!("{}/v1/{}", url, method))
+                .json(params)
+                .send()
+                .await?
+                .json()
+                .await?),
+            #[cfg(feature = "cln")]
+            Transport::Commando(commando) => commando.call(method, params).await,
+        }
+    }
+
+    /// Posts a call that sends HTLCs, with the payment timeout instead of
+    /// the request timeout. Once the request may have reached the node, a
+    /// lost connection or timeout leaves the payment pending, not failed.
+    async fn post_payment(
+        &self,
+        method: &str,
+        payload: &Value,
+        hash: Option<PaymentHash>,
+    ) -> Result<Value> {
+        let (url, http) = match &self.transport {
+            Transport::Rest { url, http } => (url, http),
+            #[cfg(feature = "cln")]
+            Transport::Commando(commando) => {
+                return match commando.send(method, payload, self.payment_timeout).await {
+                    Ok(res) => Ok(res),
+                    Err(crate::commando::CallError::Unsent(e)) => Err(e),
+                    Err(crate::commando::CallError::Lost(e)) => Err(PaymentPending::error(
+                        hash,
+                        format!("{} did not return: {}", method, e),
+                    )),
+                };
+            }
+        };
+        let res = http
+            .post(format!("{}/v1/{}", url, method))
+            .timeout(self.payment_timeout)
+            .json(payload)
+            .send()
+            .await;
+        match res {
+            Ok(res) => res
+                .json()
+                .await
+                .map_err(|e| PaymentPending::error(hash, format!("{} reply lost: {}", method, e))),
+            Err(e) if e.is_connect() => Err(e.into()),
+            Err(e) => Err(PaymentPending::error(
+                hash,
+                format!("{} did not return: {}", method, e),
+            )),
+        }
+    }
+
+    /// CLN's estimate for the slowest target that still meets
+    /// `target_conf`, or its fastest one.
+    async fn feerate_perkw(&self, target_conf: u32) -> Result<u64> {
+        let res: Value = self.call("feerates", &json!({ "style": "perkw" })).await?;
+        let estimates: Vec<(u64, u64)> = res["perkw"]["estimates"]
+            .as_array()
+            .ok_or_else(|| anyhow::anyhow!("feerates failed: {}", res))?
+            .iter()
+            .filter_map(|e| {
+                let rate = e["smoothed_feerate"].as_u64().or(e["feerate"].as_u64())?;
+                Some((e["blockcount"].as_u64()?, rate))
+            })
+            .collect();
+        estimates
+            .iter()
+            .filter(|(blocks, _)| *blocks <= u64::from(target_conf))
+            .max_by_key(|(blocks, _)| *blocks)
+            .or_else(|| estimates.iter().min_by_key(|(blocks, _)| *blocks))
+            .map(|(_, rate)| *rate)
+            .ok_or_else(|| anyhow::anyhow!("CLN has no fee estimates yet"))
+    }
 }
 
 #[async_trait]
 impl LightningClient for ClnClient {
-    async fn get_info(&mut self) -> Result<NodeInfo> {
-        let res: Value = self
-            .http
-            .get(format!("{}/v1/getinfo", self.url))
-            .send()
-            .await?
-            .json()
-            .await?;
+    async fn get_info(&self) -> Result<NodeInfo> {
+        let res: Value = self.call("getinfo", &json!({})).await?;
+        let id = res["id"].as_str().unwrap_or("unknown").to_string();
+        let count = |key: &str| res[key].as_u64().map(|n| n as u32);
+        // CLN announces only its addresses; build LND-style URIs from them.
+        let uris = res["address"]
+            .as_array()
+            .map(|addrs| {
+                addrs
+                    .iter()
+                    .filter_map(|a| {
+                        let host = a["address"].as_str()?;
+                        let port = a["port"].as_u64()?;
+                        Some(match a["type"].as_str() {
+                            Some("ipv6") => format!("{}@[{}]:{}", id, host, port),
+                            _ => format!("{}@{}:{}", id, host, port),
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        // getinfo only carries these warnings while it's still catching up.
+        let synced = res.get("warning_bitcoind_sync").is_none()
+            && res.get("warning_lightningd_sync").is_none();
+
         Ok(NodeInfo {
             alias: res["alias"].as_str().unwrap_or("CLN").to_string(),
-            identity_pubkey: res["id"].as_str().unwrap_or("unknown").to_string(),
+            identity_pubkey: id.parse().ok(),
+            implementation: "cln".into(),
+            version: res["version"].as_str().map(ToString::to_string),
+            network: res["network"].as_str().map(normalize_network),
+            block_height: count("blockheight"),
+            block_hash: None,
+            synced_to_chain: Some(synced),
+            synced_to_graph: None,
+            num_active_channels: count("num_active_channels"),
+            num_pending_channels: count("num_pending_channels"),
+            num_inactive_channels: count("num_inactive_channels"),
+            num_peers: count("num_peers"),
+            uris,
+            color: res["color"].as_str().map(ToString::to_string),
         })
     }
 
     async fn create_invoice(
-        &mut self,
-        msat: u64,
-        _label: Option<&str>,
+        &self,
+        amount: Msat,
+        label: Option<&str>,
         desc: Option<&str>,
-    ) -> Result<String> {
+    ) -> Result<Bolt11> {
         #[cfg(feature = "cln")]
         {
-            use chrono::Utc;
-            let default_label = format!("rust-{}", Utc::now().timestamp());
             let payload = json!({
-                "msatoshi": msat,
-                "label": default_label,
+                "msatoshi": amount,
+                "label": invoice_label(label),
                 "description": desc.unwrap_or("rust")
             });
-            let res: Value = self
-                .http
-                .post(format!("{}/v1/invoice", self.url))
-                .json(&payload)
-                .send()
-                .await?
-                .json()
-                .await?;
-            Ok(res["bolt11"]
+            let res: Value = self.call("invoice", &payload).await?;
+            res["bolt11"]
                 .as_str()
                 .ok_or_else(|| anyhow::anyhow!("no bolt11"))?
-                .to_string())
+                .parse()
         }
         #[cfg(not(feature = "cln"))]
         {
+            let _ = (amount, label, desc);
             Err(anyhow::anyhow!("CLN feature not enabled"))
         }
     }
 
-    async fn get_balance(&mut self) -> Result<Balance> {
-        let res: Value = self
-            .http
-            .post(format!("{}/v1/listfunds", self.url))
-            .json(&json!({}))
-            .send()
-            .await?
-            .json()
-            .await?;
+    async fn create_invoice_hashed(
+        &self,
+        amount: Msat,
+        label: Option<&str>,
+        description: &str,
+    ) -> Result<Bolt11> {
+        #[cfg(feature = "cln")]
+        {
+            let payload = json!({
+                "msatoshi": amount,
+                "label": invoice_label(label),
+                "description": description,
+                "deschashonly": true
+            });
+            let res: Value = self.call("invoice", &payload).await?;
+            res["bolt11"]
+                .as_str()
+                .ok_or_else(|| anyhow::anyhow!("no bolt11"))?
+                .parse()
+        }
+        #[cfg(not(feature = "cln"))]
+        {
+            let _ = (amount, label, description);
+            Err(anyhow::anyhow!("CLN feature not enabled"))
+        }
+    }
+
+    async fn get_balance(&self) -> Result<Balance> {
+        let res: Value = self.call("listfunds", &json!({})).await?;
 
-        let mut onchain_msat = 0u64;
+        let overflow = || anyhow::anyhow!("Balance overflow");
+        let mut onchain_msat = Msat::ZERO;
         if let Some(outputs) = res["outputs"].as_array() {
             for out in outputs {
                 if out["status"].as_str() == Some("confirmed") {
                     if let Some(msat) = out["msatoshi"].as_u64() {
-                        onchain_msat += msat;
+                        onchain_msat = onchain_msat
+                            .checked_add(Msat::from_msat(msat))
+                            .ok_or_else(overflow)?;
                     }
                 }
             }
         }
 
-        let mut channel_msat = 0u64;
+        let mut channel_msat = Msat::ZERO;
         if let Some(chans) = res["channels"].as_array() {
             for ch in chans {
                 if let Some(msat) = ch["our_msatoshi"].as_u64() {
-                    channel_msat += msat;
+                    channel_msat = channel_msat
+                        .checked_add(Msat::from_msat(msat))
+                        .ok_or_else(overflow)?;
                 }
             }
         }
 
         Ok(Balance {
-            onchain_sat: onchain_msat / 1000,
+            onchain_sat: onchain_msat.to_sat_floor(),
             channel_msat,
         })
     }
 
-    async fn list_invoices(&mut self, limit: Option<usize>) -> Result<Vec<Invoice>> {
+    async fn list_invoices(&self, limit: Option<usize>) -> Result<Vec<Invoice>> {
         #[cfg(feature = "cln")]
         {
             let payload = json!({ "count": limit.unwrap_or(10) });
-            let res: Value = self
-                .http
-                .post(format!("{}/v1/listinvoices", self.url))
-                .json(&payload)
-                .send()
-                .await?
-                .json()
-                .await?;
+            let res: Value = self.call("listinvoices", &payload).await?;
 
-            let mut invoices = vec![];
-            if let Some(inv_list) = res["invoices"].as_array() {
-                for inv in inv_list {
-                    let state_num: u32 = inv["state"].as_u64().unwrap_or(0) as u32;
-                    let state_str = match state_num {
-                        0 => "unpaid",
-                        1 => "paid",
-                        2 => "expired",
-                        _ => "unknown",
-                    };
-                    invoices.push(Invoice {
-                        hash: inv["payment_hash"].as_str().unwrap_or("").to_string(),
-                        amount_msat: inv["msatoshi_received"].as_u64().unwrap_or(0),
-                        state: state_str.to_string(),
-                        bolt11: inv["bolt11"].as_str().map(ToString::to_string),
-                        desc: None,
-                    });
-                }
-            }
+            let invoices = res["invoices"]
+                .as_array()
+                .map(|list| list.iter().filter_map(invoice_of).collect())
+                .unwrap_or_default();
             Ok(invoices)
         }
         #[cfg(not(feature = "cln"))]
@@ -143,22 +306,33 @@ impl LightningClient for ClnClient {
         }
     }
 
-    async fn decode_invoice(&mut self, bolt11: &str) -> Result<DecodedInvoice> {
+    async fn lookup_invoice(&self, hash: &PaymentHash) -> Result<Option<Invoice>> {
         #[cfg(feature = "cln")]
         {
-            let payload = json!({ "bolt11": bolt11 });
             let res: Value = self
-                .http
-                .post(format!("{}/v1/decodepay", self.url))
-                .json(&payload)
-                .send()
-                .await?
-                .json()
+                .call("listinvoices", &json!({ "payment_hash": hash }))
                 .await?;
+            let invoices = res["invoices"]
+                .as_array()
+                .ok_or_else(|| anyhow::anyhow!("listinvoices failed: {}", res))?;
+            Ok(invoices.first().and_then(invoice_of))
+        }
+        #[cfg(not(feature = "cln"))]
+        {
+            let _ = hash;
+            Err(anyhow::anyhow!("CLN feature not enabled"))
+        }
+    }
+
+    async fn decode_invoice(&self, bolt11: &Bolt11) -> Result<DecodedInvoice> {
+        #[cfg(feature = "cln")]
+        {
+            let payload = json!({ "bolt11": bolt11 });
+            let res: Value = self.call("decodepay", &payload).await?;
 
-            let amount_msat = res["msatoshi"].as_u64();
+            let amount_msat = res["msatoshi"].as_u64().map(Msat::from_msat);
             let desc = res["description"].as_str().map(ToString::to_string);
-            let payee = res["payee"].as_str().map(ToString::to_string);
+            let payee = res["payee"].as_str().map(str::parse).transpose()?;
 
             Ok(DecodedInvoice {
                 amount_msat,
@@ -172,41 +346,725 @@ impl LightningClient for ClnClient {
         }
     }
 
-    async fn pay_invoice(&mut self, bolt11: &str) -> Result<PaymentResult> {
+    async fn pay_invoice(&self, bolt11: &Bolt11) -> Result<PaymentResult> {
         #[cfg(feature = "cln")]
         {
             let payload = json!({ "bolt11": bolt11 });
-            let res: Value = self
-                .http
-                .post(format!("{}/v1/pay", self.url))
-                .json(&payload)
-                .send()
-                .await?
-                .json()
+            let res = self
+                .post_payment("pay", &payload, bolt11.payment_hash())
                 .await?;
 
+            // PAY_IN_PROGRESS: another pay for this invoice is still running.
+            if res["status"].as_str() == Some("pending") || res["code"].as_i64() == Some(200) {
+                return Err(PaymentPending::error(
+                    bolt11.payment_hash(),
+                    "CLN reports it in flight",
+                ));
+            }
             if let Some(err) = res["error"].as_str() {
                 return Err(anyhow::anyhow!("Payment failed: {}", err));
             }
+            if res.get("code").is_some() {
+                return Err(anyhow::anyhow!(
+                    "Payment failed: {}",
+                    res["message"].as_str().unwrap_or("unknown error")
+                ));
+            }
 
             let hash = res["payment_hash"]
                 .as_str()
                 .ok_or_else(|| anyhow::anyhow!("no payment_hash"))?
-                .to_string();
-            let amount_msat = res["amount_sent_msat"]
-                .as_u64()
-                .ok_or_else(|| anyhow::anyhow!("no amount_sent_msat"))?;
-            let fee_msat = res["total_fees_msats"].as_u64();
+                .parse()?;
+            let amount_msat = res["amount_sent_msat"].as_u64().map(Msat::from_msat);
+            let fee_msat = res["total_fees_msats"].as_u64().map(Msat::from_msat);
+            let preimage = res["payment_preimage"]
+                .as_str()
+                .map(str::parse)
+                .transpose()?;
 
             Ok(PaymentResult {
                 hash,
                 amount_msat,
                 fee_msat,
+                preimage,
+            })
+        }
+        #[cfg(not(feature = "cln"))]
+        {
+            Err(anyhow::anyhow!("CLN feature not enabled"))
+        }
+    }
+
+    async fn lookup_payment(&self, hash: &PaymentHash) -> Result<PaymentState> {
+        #[cfg(feature = "cln")]
+        {
+            let res: Value = self
+                .call("listpays", &json!({ "payment_hash": hash }))
+                .await?;
+            let pays = res["pays"]
+                .as_array()
+                .ok_or_else(|| anyhow::anyhow!("listpays failed: {}", res))?;
+
+            // A pay that was retried after failing shows up once per attempt.
+            if let Some(pay) = pays.iter().find(|p| p["status"] == "complete") {
+                let amount_msat = pay["amount_sent_msat"].as_u64();
+                let fee_msat = amount_msat
+                    .zip(pay["amount_msat"].as_u64())
+                    .and_then(|(sent, delivered)| sent.checked_sub(delivered))
+                    .map(Msat::from_msat);
+                let preimage = pay["preimage"].as_str().map(str::parse).transpose()?;
+                return Ok(PaymentState::Succeeded(PaymentResult {
+                    hash: *hash,
+                    amount_msat: amount_msat.map(Msat::from_msat),
+                    fee_msat,
+                    preimage,
+                }));
+            }
+            Ok(if pays.iter().any(|p| p["status"] == "pending") {
+                PaymentState::Pending
+            } else if pays.is_empty() {
+                PaymentState::Unknown
+            } else {
+                PaymentState::Failed
             })
         }
         #[cfg(not(feature = "cln"))]
         {
+            let _ = hash;
             Err(anyhow::anyhow!("CLN feature not enabled"))
         }
     }
+
+    async fn sign_message(&self, message: &str) -> Result<String> {
+        let res: Value = self
+            .call("signmessage", &json!({ "message": message }))
+            .await?;
+
+        res["zbase"]
+            .as_str()
+            .map(ToString::to_string)
+            .ok_or_else(|| anyhow::anyhow!("signmessage failed: {}", res))
+    }
+
+    async fn verify_message(&self, message: &str, signature: &str) -> Result<SignatureCheck> {
+        let res: Value = self
+            .call(
+                "checkmessage",
+                &json!({ "message": message, "zbase": signature }),
+            )
+            .await?;
+
+        let Some(valid) = res["verified"].as_bool() else {
+            return Err(anyhow::anyhow!("checkmessage failed: {}", res));
+        };
+        Ok(SignatureCheck {
+            valid,
+            pubkey: res["pubkey"].as_str().and_then(|k| k.parse().ok()),
+        })
+    }
+
+    async fn update_channel_policy(
+        &self,
+        channel: &ChannelSelector,
+        policy: &ChannelPolicy,
+    ) -> Result<()> {
+        if policy.time_lock_delta.is_some() {
+            return Err(anyhow::anyhow!(
+                "CLN sets the time lock delta node-wide (cltv-delta), not per channel"
+            ));
+        }
+        let id = match channel {
+            ChannelSelector::All => "all".to_string(),
+            ChannelSelector::Short(scid) => scid.to_string(),
+            ChannelSelector::Outpoint { txid, vout } => channel_id(txid, *vout)?,
+        };
+        let mut payload = json!({
+            "id": id,
+            "feebase": policy.base_fee_msat,
+            "feeppm": policy.fee_ppm,
+        });
+        if let Some(min) = policy.min_htlc_msat {
+            payload["htlcmin"] = json!(min);
+        }
+        if let Some(max) = policy.max_htlc_msat {
+            payload["htlcmax"] = json!(max);
+        }
+
+        let res: Value = self.call("setchannel", &payload).await?;
+        if res["channels"].is_array() {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("setchannel failed: {}", res))
+        }
+    }
+
+    async fn list_forwards(&self, query: &ForwardQuery) -> Result<ForwardPage> {
+        let limit = query.limit.unwrap_or(100);
+        let payload = json!({
+            "status": "settled",
+            "index": "created",
+            "start": query.offset,
+            "limit": limit,
+        });
+        let res: Value = self.call("listforwards", &payload).await?;
+        let list = res["forwards"]
+            .as_array()
+            .ok_or_else(|| anyhow::anyhow!("listforwards failed: {}", res))?;
+
+        let mut next_offset = (list.len() >= limit)
+            .then(|| list.last()?.get("created_index")?.as_u64().map(|i| i + 1))
+            .flatten();
+        let mut forwards = Vec::new();
+        for fwd in list {
+            let msat = |key: &str| Msat::from_msat(fwd[key].as_u64().unwrap_or(0));
+            let timestamp = fwd["received_time"].as_f64().unwrap_or(0.0) as u64;
+            if query.start.is_some_and(|start| timestamp < start) {
+                continue;
+            }
+            // Created in order, so nothing later is in range either.
+            if query.end.is_some_and(|end| timestamp >= end) {
+                next_offset = None;
+                break;
+            }
+            forwards.push(Forward {
+                timestamp,
+                chan_in: fwd["in_channel"].as_str().and_then(|c| c.parse().ok()),
+                chan_out: fwd["out_channel"].as_str().and_then(|c| c.parse().ok()),
+                amount_in_msat: msat("in_msat"),
+                amount_out_msat: msat("out_msat"),
+                fee_msat: msat("fee_msat"),
+            });
+        }
+        Ok(ForwardPage {
+            forwards,
+            next_offset,
+        })
+    }
+
+    async fn query_routes(&self, dest: &PublicKey, amount: Msat) -> Result<Route> {
+        let payload = json!({ "id": dest, "amount_msat": amount, "riskfactor": 1 });
+        let res: Value = self.call("getroute", &payload).await?;
+        let hops = res["route"]
+            .as_array()
+            .ok_or_else(|| anyhow::anyhow!("getroute failed: {}", res))?;
+        route(hops)
+    }
+
+    async fn get_node(&self, pubkey: &PublicKey) -> Result<GraphNode> {
+        let res: Value = self.call("listnodes", &json!({ "id": pubkey })).await?;
+        let nodes = res["nodes"]
+            .as_array()
+            .ok_or_else(|| anyhow::anyhow!("listnodes failed: {}", res))?;
+        let node = nodes
+            .first()
+            .ok_or_else(|| anyhow::anyhow!("Node {} is not in the graph", pubkey))?;
+
+        let addresses = node["addresses"]
+            .as_array()
+            .map(|addrs| {
+                addrs
+                    .iter()
+                    .filter_map(|a| {
+                        let host = a["address"].as_str()?;
+                        let port = a["port"].as_u64()?;
+                        Some(match a["type"].as_str() {
+                            Some("ipv6") => format!("[{}]:{}", host, port),
+                            _ => format!("{}:{}", host, port),
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(GraphNode {
+            pubkey: *pubkey,
+            alias: node["alias"].as_str().map(ToString::to_string),
+            color: node["color"].as_str().map(ToString::to_string),
+            addresses,
+            last_update: node["last_timestamp"].as_u64(),
+        })
+    }
+
+    async fn get_channel(&self, scid: ShortChannelId) -> Result<GraphChannel> {
+        let res: Value = self
+            .call("listchannels", &json!({ "short_channel_id": scid }))
+            .await?;
+        // One entry per direction.
+        let halves = res["channels"]
+            .as_array()
+            .ok_or_else(|| anyhow::anyhow!("listchannels failed: {}", res))?;
+        let first = halves
+            .first()
+            .ok_or_else(|| anyhow::anyhow!("Channel {} is not in the graph", scid))?;
+
+        let key = |v: &Value| -> Result<PublicKey> {
+            v.as_str()
+                .ok_or_else(|| anyhow::anyhow!("listchannels entry without node id"))?
+                .parse()
+        };
+        let (a, b) = (key(&first["source"])?, key(&first["destination"])?);
+        // BOLT 7 orders the endpoints by their serialized keys.
+        let (node1, node2) = if a.as_bytes() <= b.as_bytes() {
+            (a, b)
+        } else {
+            (b, a)
+        };
+        let policies = halves
+            .iter()
+            .map(|h| {
+                Ok(EdgePolicy {
+                    source: key(&h["source"])?,
+                    base_fee_msat: Msat::from_msat(
+                        h["base_fee_millisatoshi"].as_u64().unwrap_or(0),
+                    ),
+                    fee_ppm: h["fee_per_millionth"].as_u64().unwrap_or(0) as u32,
+                    time_lock_delta: h["delay"].as_u64().unwrap_or(0) as u32,
+                    min_htlc_msat: Msat::from_msat(h["htlc_minimum_msat"].as_u64().unwrap_or(0)),
+                    max_htlc_msat: h["htlc_maximum_msat"].as_u64().map(Msat::from_msat),
+                    disabled: !h["active"].as_bool().unwrap_or(true),
+                    last_update: h["last_update"].as_u64(),
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(GraphChannel {
+            scid,
+            node1,
+            node2,
+            capacity_sat: first["amount_msat"]
+                .as_u64()
+                .map(|msat| Msat::from_msat(msat).to_sat_floor()),
+            policies,
+        })
+    }
+
+    async fn probe_payment(&self, bolt11: &Bolt11) -> Result<ProbeResult> {
+        let amount = bolt11
+            .amount()
+            .ok_or_else(|| anyhow::anyhow!("Cannot probe an invoice without an amount"))?;
+        let decoded: Value = self.call("decodepay", &json!({ "bolt11": bolt11 })).await?;
+        let payee = decoded["payee"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("decodepay failed: {}", decoded))?;
+
+        let mut payload = json!({ "id": payee, "amount_msat": amount, "riskfactor": 1 });
+        if let Some(cltv) = decoded["min_final_cltv_expiry"].as_u64() {
+            payload["cltv"] = json!(cltv);
+        }
+        let res: Value = self.call("getroute", &payload).await?;
+        let Some(hops) = res["route"].as_array() else {
+            return Ok(ProbeResult {
+                reachable: false,
+                route: None,
+                failure: Some(res["message"].as_str().unwrap_or("no route").to_string()),
+            });
+        };
+        let route = route(hops)?;
+
+        let hash = PaymentHash::random();
+        let mut payload = json!({
+            "route": hops,
+            "payment_hash": hash,
+            "amount_msat": amount,
+        });
+        if let Some(secret) = decoded["payment_secret"].as_str() {
+            payload["payment_secret"] = json!(secret);
+        }
+        let res: Value = self.call("sendpay", &payload).await?;
+        if res.get("code").is_some() {
+            return Err(anyhow::anyhow!("sendpay failed: {}", res));
+        }
+        let wait = json!({
+            "payment_hash": hash,
+            "timeout": self.payment_timeout.as_secs().saturating_sub(5).max(1),
+        });
+        let res = self.post_payment("waitsendpay", &wait, Some(hash)).await?;
+        // WAIT_TIMEOUT: the HTLC is still out.
+        if res["code"].as_i64() == Some(200) {
+            return Err(PaymentPending::error(
+                Some(hash),
+                "probe HTLC still in flight",
+            ));
+        }
+
+        // The payee answers an unknown hash with incorrect_or_unknown_payment_details.
+        let data = &res["data"];
+        let reached = data["erring_index"].as_u64() == Some(hops.len() as u64)
+            && data["failcode"].as_u64() == Some(0x400f);
+        let failure = (!reached).then(|| {
+            data["failcodename"]
+                .as_str()
+                .or(res["message"].as_str())
+                .unwrap_or("unknown failure")
+                .to_string()
+        });
+        Ok(ProbeResult {
+            reachable: reached,
+            route: Some(route),
+            failure,
+        })
+    }
+
+    async fn export_channel_backup(&self) -> Result<ChannelBackup> {
+        let res: Value = self.call("staticbackup", &json!({})).await?;
+        let scb = res["scb"]
+            .as_array()
+            .ok_or_else(|| anyhow::anyhow!("staticbackup failed: {}", res))?;
+        let entries: Vec<String> = scb
+            .iter()
+            .filter_map(|e| e.as_str().map(ToString::to_string))
+            .collect();
+        let channels = scb_channel_ids(&entries)?;
+        Ok(ChannelBackup {
+            data: serde_json::to_vec(&entries)?,
+            channels,
+        })
+    }
+
+    /// CLN can only check a backup by recovering from it, so this checks its
+    /// structure locally. The node's own `emergency.recover` file is
+    /// encrypted with its hsm_secret and is not covered.
+    async fn verify_channel_backup(&self, data: &[u8]) -> Result<BackupCheck> {
+        let checked = serde_json::from_slice::<Vec<String>>(data)
+            .map_err(|_| anyhow::anyhow!("not a JSON array of staticbackup entries"))
+            .and_then(|entries| scb_channel_ids(&entries));
+        Ok(match checked {
+            Ok(channels) => BackupCheck {
+                valid: true,
+                channels,
+                error: None,
+            },
+            Err(e) => BackupCheck {
+                valid: false,
+                channels: Vec::new(),
+                error: Some(e.to_string()),
+            },
+        })
+    }
+
+    /// Uses the dual-funding (v2) open, on by default since v24.08 and
+    /// behind `experimental-dual-fund` before.
+    async fn open_channel_psbt(&self, open: &PsbtChannelOpen) -> Result<PsbtFunding> {
+        let initial = open.psbt.as_ref().ok_or_else(|| {
+            anyhow::anyhow!("CLN needs a PSBT with the funding inputs to start the open")
+        })?;
+        let res: Value = self
+            .call(
+                "openchannel_init",
+                &json!({
+                    "id": open.peer,
+                    "amount": open.amount_sat,
+                    "initialpsbt": initial,
+                    "announce": !open.private,
+                }),
+            )
+            .await?;
+        let id = res["channel_id"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("openchannel_init failed: {}", res))?;
+        Ok(PsbtFunding {
+            id: id.to_string(),
+            funding_address: None,
+            amount_sat: open.amount_sat,
+            psbt: res["psbt"]
+                .as_str()
+                .ok_or_else(|| anyhow::anyhow!("openchannel_init returned no PSBT"))?
+                .parse()?,
+        })
+    }
+
+    /// Trades PSBT updates with the peer until both sides have signed the
+    /// commitment transactions.
+    async fn update_channel_psbt(&self, id: &str, psbt: &Psbt) -> Result<Psbt> {
+        let mut psbt = psbt.clone();
+        for _ in 0..PSBT_UPDATE_ROUNDS {
+            let res: Value = self
+                .call(
+                    "openchannel_update",
+                    &json!({ "channel_id": id, "psbt": psbt }),
+                )
+                .await?;
+            psbt = res["psbt"]
+                .as_str()
+                .ok_or_else(|| anyhow::anyhow!("openchannel_update failed: {}", res))?
+                .parse()?;
+            if res["commitments_secured"].as_bool() == Some(true) {
+                return Ok(psbt);
+            }
+        }
+        Err(anyhow::anyhow!(
+            "Peer did not secure the commitments after {} updates",
+            PSBT_UPDATE_ROUNDS
+        ))
+    }
+
+    async fn finalize_channel_psbt(&self, id: &str, signed: &Psbt) -> Result<PsbtChannelOpened> {
+        let res: Value = self
+            .call(
+                "openchannel_signed",
+                &json!({ "channel_id": id, "signed_psbt": signed }),
+            )
+            .await?;
+        let txid = res["txid"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("openchannel_signed failed: {}", res))?;
+        Ok(PsbtChannelOpened {
+            txid: txid.to_string(),
+            channel: res["channel_id"].as_str().unwrap_or(id).to_string(),
+        })
+    }
+
+    async fn cancel_channel_psbt(&self, id: &str) -> Result<()> {
+        let res: Value = self
+            .call("openchannel_abort", &json!({ "channel_id": id }))
+            .await?;
+        if res.get("code").is_some() {
+            return Err(anyhow::anyhow!("openchannel_abort failed: {}", res));
+        }
+        Ok(())
+    }
+
+    /// `txprepare` under the hood; `txdiscard` releases the inputs early.
+    async fn fund_psbt(&self, request: &FundPsbtRequest) -> Result<FundedPsbt> {
+        let outputs: Vec<Value> = request
+            .outputs
+            .iter()
+            .map(|o| json!({ o.address.as_str(): o.amount_sat }))
+            .collect();
+        let mut payload = json!({ "outputs": outputs });
+        if let Some(rate) = request.sat_per_vbyte {
+            payload["feerate"] = json!(format!("{}perkb", rate.saturating_mul(1000)));
+        }
+        let res: Value = self.call("txprepare", &payload).await?;
+        Ok(FundedPsbt {
+            psbt: res["psbt"]
+                .as_str()
+                .ok_or_else(|| anyhow::anyhow!("txprepare failed: {}", res))?
+                .parse()?,
+            change_index: None,
+        })
+    }
+
+    async fn sign_psbt(&self, psbt: &Psbt) -> Result<Psbt> {
+        let res: Value = self.call("signpsbt", &json!({ "psbt": psbt })).await?;
+        res["signed_psbt"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("signpsbt failed: {}", res))?
+            .parse()
+    }
+
+    async fn finalize_psbt(&self, psbt: &Psbt) -> Result<BroadcastTx> {
+        let res: Value = self.call("sendpsbt", &json!({ "psbt": psbt })).await?;
+        match (res["txid"].as_str(), res["tx"].as_str()) {
+            (Some(txid), Some(tx)) => Ok(BroadcastTx {
+                txid: txid.to_string(),
+                raw_tx: tx.to_string(),
+            }),
+            _ => Err(anyhow::anyhow!("sendpsbt failed: {}", res)),
+        }
+    }
+
+    async fn estimate_fee(&self, target_conf: u32) -> Result<FeeEstimate> {
+        Ok(FeeEstimate {
+            target_conf,
+            sat_per_vbyte: kw_to_vbyte(self.feerate_perkw(target_conf).await?),
+        })
+    }
+
+    /// Asks `fundpsbt` for the weight without reserving anything. CLN's
+    /// `txprepare` is a plugin over the same `fundpsbt` coin selection, but
+    /// it reserves the inputs until `txdiscard`, which could fail a real
+    /// send running meanwhile, and its reply carries no fee. The address
+    /// only sizes the output and is not checked.
+    async fn estimate_onchain_send(
+        &self,
+        address: &str,
+        amount: Sat,
+        target_conf: u32,
+    ) -> Result<OnchainSendEstimate> {
+        let perkw = self.feerate_perkw(target_conf).await?;
+        let res: Value = self
+            .call(
+                "fundpsbt",
+                &json!({
+                    "satoshi": amount,
+                    "feerate": format!("{}perkw", perkw),
+                    "startweight": send_startweight(address),
+                    "reserve": 0,
+                    "excess_as_change": true,
+                }),
+            )
+            .await?;
+        let weight = res["estimated_final_weight"]
+            .as_u64()
+            .ok_or_else(|| anyhow::anyhow!("fundpsbt failed: {}", res))?;
+        let perkw = res["feerate_per_kw"].as_u64().unwrap_or(perkw);
+        Ok(OnchainSendEstimate {
+            fee_sat: Sat::from_sat((weight * perkw).div_ceil(1000)),
+            sat_per_vbyte: kw_to_vbyte(perkw),
+        })
+    }
+
+    async fn capabilities(&self) -> Result<Capabilities> {
+        let info = self.get_info().await?;
+        // Without the `cln` feature the invoice and payment calls are
+        // compiled out, so nothing is advertised.
+        let supported = if cfg!(feature = "cln") {
+            BTreeSet::from([
+                Capability::CreateInvoice,
+                Capability::DecodeInvoice,
+                Capability::PayInvoice,
+                Capability::ListInvoices,
+                Capability::SignMessage,
+                Capability::ChannelPolicy,
+                Capability::ForwardingHistory,
+                Capability::QueryRoutes,
+                Capability::NetworkGraph,
+                Capability::ProbePayment,
+                Capability::ChannelBackup,
+                Capability::Psbt,
+                Capability::FeeEstimates,
+            ])
+        } else {
+            BTreeSet::new()
+        };
+
+        Ok(Capabilities {
+            backend: "cln".into(),
+            version: info.version,
+            network: info.network,
+            supported,
+        })
+    }
+}
+
+/// Labels must be unique, and public endpoints can ask for several
+/// invoices a second.
+#[cfg(feature = "cln")]
+fn invoice_label(label: Option<&str>) -> String {
+    label.map(str::to_string).unwrap_or_else(|| {
+        format!(
+            "rust-{}-{}",
+            chrono::Utc::now().timestamp(),
+            hex::encode(rand::random::<[u8; 4]>())
+        )
+    })
+}
+
+/// An entry of `listinvoices`. `state` is a string; older clnrest
+/// wrappers gave it as a number.
+#[cfg(feature = "cln")]
+fn invoice_of(inv: &Value) -> Option<Invoice> {
+    let state = match (&inv["state"], inv["state"].as_u64()) {
+        (Value::String(state), _) => state.as_str(),
+        (_, Some(0)) => "unpaid",
+        (_, Some(1)) => "paid",
+        (_, Some(2)) => "expired",
+        _ => "unknown",
+    };
+    Some(Invoice {
+        hash: inv["payment_hash"].as_str()?.parse().ok()?,
+        amount_msat: Msat::from_msat(
+            inv["amount_received_msat"]
+                .as_u64()
+                .or_else(|| inv["msatoshi_received"].as_u64())
+                .unwrap_or(0),
+        ),
+        state: state.to_string(),
+        bolt11: inv["bolt11"].as_str().and_then(|b| b.parse().ok()),
+        desc: inv["description"]
+            .as_str()
+            .filter(|d| !d.is_empty())
+            .map(str::to_string),
+    })
+}
+
+/// `fundpsbt` startweight of a transaction with one output to `address`:
+/// version, locktime, the input and output counts, the segwit marker and
+/// flag, then the output.
+fn send_startweight(address: &str) -> u64 {
+    // Taproot and P2WSH scripts, the largest common ones, when unknown.
+    let script_len = output_script_len(address).unwrap_or(34);
+    (4 + 4 + 1 + 1) * 4 + 2 + (8 + 1 + script_len) * 4
+}
+
+fn output_script_len(address: &str) -> Option<u64> {
+    let lower = address.trim().to_ascii_lowercase();
+    if ["bc1", "tb1", "bcrt1"].iter().any(|p| lower.starts_with(p)) {
+        // Witness program: what is left after the separator, the version
+        // and the checksum, in 5-bit groups. '1' is not a bech32 character.
+        let sep = lower.rfind('1')?;
+        let groups = lower.len().checked_sub(sep + 1 + 1 + 6)?;
+        return Some(2 + (groups * 5 / 8) as u64);
+    }
+    match lower.chars().next()? {
+        '1' | 'm' | 'n' => Some(25),
+        '3' | '2' => Some(23),
+        _ => None,
+    }
+}
+
+/// BOLT 2 channel id: the funding txid in internal byte order with the
+/// output index XORed into its last two bytes.
+fn channel_id(txid: &str, vout: u32) -> Result<String> {
+    let mut id = hex::decode(txid).map_err(|_| anyhow::anyhow!("Invalid txid '{}'", txid))?;
+    id.reverse();
+    let vout =
+        u16::try_from(vout).map_err(|_| anyhow::anyhow!("Output index {} too large", vout))?;
+    let [hi, lo] = vout.to_be_bytes();
+    id[30] ^= hi;
+    id[31] ^= lo;
+    Ok(hex::encode(id))
+}
+
+/// Converts a `getroute` route, whose first hop carries the total amount
+/// and delay.
+fn route(hops: &[Value]) -> Result<Route> {
+    let hops = hops
+        .iter()
+        .map(|h| {
+            let field = |key: &str| {
+                h[key]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("getroute hop without {}", key))
+            };
+            Ok(RouteHop {
+                pubkey: field("id")?.parse()?,
+                channel: field("channel")?.parse()?,
+                amount_msat: Msat::from_msat(h["amount_msat"].as_u64().unwrap_or(0)),
+                delay: h["delay"].as_u64().unwrap_or(0) as u32,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let (Some(first), Some(last)) = (hops.first(), hops.last()) else {
+        return Err(anyhow::anyhow!("getroute returned an empty route"));
+    };
+    Ok(Route {
+        amount_msat: last.amount_msat,
+        fee_msat: first
+            .amount_msat
+            .checked_sub(last.amount_msat)
+            .unwrap_or(Msat::ZERO),
+        total_delay: first.delay,
+        hops,
+    })
+}
+
+/// Channel ids of hex `scb_chan` entries, which start with a u64 id, the
+/// 32-byte channel id and the peer's node id.
+fn scb_channel_ids(entries: &[String]) -> Result<Vec<String>> {
+    entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            let bytes =
+                hex::decode(entry).map_err(|_| anyhow::anyhow!("Backup entry {} is not hex", i))?;
+            if bytes.len() < 8 + 32 + 33 || !matches!(bytes[40], 2 | 3) {
+                return Err(anyhow::anyhow!(
+                    "Backup entry {} is not a channel backup",
+                    i
+                ));
+            }
+            Ok(hex::encode(&bytes[8..40]))
+        })
+        .collect()
 }
@@ -0,0 +1,759 @@
+// lightning-client/src/commando.rs
+//
+// Core Lightning's Commando: JSON-RPC calls sent as custom messages over an
+// ordinary Lightning peer connection (BOLT 8 transport, BOLT 1 messages)
+// and authorized by a rune. No channel with the node is needed.
+use anyhow::{anyhow, Result};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hkdf::Hkdf;
+use secp256k1::ecdh::SharedSecret;
+use secp256k1::{PublicKey, Secp256k1, SecretKey};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::{self, ErrorKind};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+const PROTOCOL_NAME: &[u8] = b"Noise_XK_secp256k1_ChaChaPoly_SHA256";
+const PROLOGUE: &[u8] = b"lightning";
+/// Each direction rekeys after this many encryptions.
+const REKEY_AFTER: u64 = 1000;
+
+const MSG_INIT: u16 = 16;
+const MSG_ERROR: u16 = 17;
+const MSG_PING: u16 = 18;
+const MSG_PONG: u16 = 19;
+const MSG_CMD_CONTINUES: u16 = 0x4c4d;
+const MSG_CMD_TERM: u16 = 0x4c4f;
+const MSG_REPLY_CONTINUES: u16 = 0x594b;
+const MSG_REPLY_TERM: u16 = 0x594d;
+
+/// Command bytes per message, well under the 65535-byte message limit.
+const CMD_CHUNK: usize = 65_000;
+/// Optional var_onion_optin, option_static_remotekey and payment_secret,
+/// which CLN expects every peer to understand.
+const INIT_FEATURES: [u8; 2] = [0xa2, 0x00];
+
+/// A call that failed, and whether the request may have reached the node.
+pub(crate) enum CallError {
+    /// Nothing was sent.
+    Unsent(anyhow::Error),
+    /// The request went out, but no reply came back.
+    Lost(anyhow::Error),
+}
+
+impl From<CallError> for anyhow::Error {
+    fn from(e: CallError) -> Self {
+        match e {
+            CallError::Unsent(e) | CallError::Lost(e) => e,
+        }
+    }
+}
+
+/// Calls into one CLN node over a peer connection that is opened on first
+/// use and reopened after it drops.
+pub(crate) struct Commando {
+    node_id: PublicKey,
+    addr: String,
+    rune: String,
+    connect_timeout: Duration,
+    request_timeout: Duration,
+    peer: tokio::sync::Mutex<Option<Arc<Peer>>>,
+    next_id: AtomicU64,
+}
+
+impl Commando {
+    /// `addr` is the node's `host:port`, `node_id` its hex public key.
+    pub(crate) fn new(
+        node_id: &str,
+        addr: &str,
+        rune: &str,
+        connect_timeout: Duration,
+        request_timeout: Duration,
+    ) -> Result<Self> {
+        let node_id = node_id
+            .trim()
+            .parse()
+            .map_err(|_| anyhow!("Invalid Commando node id '{}'", node_id))?;
+        Ok(Self {
+            node_id,
+            addr: addr.to_string(),
+            rune: rune.trim().to_string(),
+            connect_timeout,
+            request_timeout,
+            peer: tokio::sync::Mutex::new(None),
+            next_id: AtomicU64::new(1),
+        })
+    }
+
+    /// Runs `method` and returns CLN's `result`, or its `error` object as
+    /// `clnrest` would.
+    pub(crate) async fn call(&self, method: &str, params: &Value) -> Result<Value> {
+        Ok(self.send(method, params, self.request_timeout).await?)
+    }
+
+    /// Like [`Commando::call`], waiting up to `timeout` for the reply.
+    pub(crate) async fn send(
+        &self,
+        method: &str,
+        params: &Value,
+        timeout: Duration,
+    ) -> Result<Value, CallError> {
+        let peer = self.peer().await.map_err(CallError::Unsent)?;
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let request = json!({
+            "method": method,
+            "params": params,
+            "rune": self.rune,
+            "id": id.to_string(),
+        })
+        .to_string();
+
+        let (tx, rx) = oneshot::channel();
+        peer.waiting.lock().unwrap().insert(
+            id,
+            Waiting {
+                reply: Vec::new(),
+                tx,
+            },
+        );
+        for msg in command_messages(id, request.as_bytes()) {
+            if peer.outgoing.send(msg).is_err() {
+                peer.waiting.lock().unwrap().remove(&id);
+                return Err(CallError::Unsent(
+                    io::Error::new(ErrorKind::ConnectionAborted, "Commando connection closed")
+                        .into(),
+                ));
+            }
+        }
+
+        match tokio::time::timeout(timeout, rx).await {
+            Ok(Ok(reply)) => reply.map_err(CallError::Lost),
+            Ok(Err(_)) => Err(CallError::Lost(
+                io::Error::new(ErrorKind::ConnectionAborted, "Commando connection closed").into(),
+            )),
+            Err(_) => {
+                peer.waiting.lock().unwrap().remove(&id);
+                Err(CallError::Lost(
+                    io::Error::new(
+                        ErrorKind::TimedOut,
+                        format!("{} got no Commando reply", method),
+                    )
+                    .into(),
+                ))
+            }
+        }
+    }
+
+    /// The open connection, or a new one when there is none or it dropped.
+    async fn peer(&self) -> Result<Arc<Peer>> {
+        let mut slot = self.peer.lock().await;
+        if let Some(peer) = slot.as_ref().filter(|p| !p.reader.is_finished()) {
+            return Ok(Arc::clone(peer));
+        }
+        let peer = tokio::time::timeout(self.connect_timeout, self.connect())
+            .await
+            .map_err(|_| {
+                io::Error::new(
+                    ErrorKind::TimedOut,
+                    format!("Commando connect to {} timed out", self.addr),
+                )
+            })??;
+        let peer = Arc::new(peer);
+        *slot = Some(Arc::clone(&peer));
+        Ok(peer)
+    }
+
+    async fn connect(&self) -> Result<Peer> {
+        let mut stream = TcpStream::connect(&self.addr).await?;
+        let mut handshake = Handshake::new(random_key(), random_key(), self.node_id);
+        stream.write_all(&handshake.act_one()).await?;
+        let mut act_two = [0u8; 50];
+        stream
+            .read_exact(&mut act_two)
+            .await
+            .map_err(|e| match e.kind() {
+                // The node hangs up when act one was not meant for its key.
+                ErrorKind::UnexpectedEof => anyhow!(
+                    "{} closed the connection during the handshake; is {} its node id?",
+                    self.addr,
+                    self.node_id
+                ),
+                _ => e.into(),
+            })?;
+        let (act_three, mut sending, mut receiving) = handshake.act_two(&act_two)?;
+        stream.write_all(&act_three).await?;
+
+        // BOLT 1: both sides send init before anything else.
+        let mut init = MSG_INIT.to_be_bytes().to_vec();
+        init.extend(0u16.to_be_bytes());
+        init.extend((INIT_FEATURES.len() as u16).to_be_bytes());
+        init.extend(INIT_FEATURES);
+        stream.write_all(&sending.seal(&init)).await?;
+        loop {
+            let msg = read_message(&mut stream, &mut receiving).await?;
+            match msg_type(&msg) {
+                Some(MSG_INIT) => break,
+                Some(MSG_ERROR) => {
+                    return Err(anyhow!("Node refused the connection: {}", error_text(&msg)))
+                }
+                _ => {}
+            }
+        }
+
+        let (read, write) = stream.into_split();
+        let (outgoing, rx) = mpsc::unbounded_channel();
+        let waiting = Arc::new(Mutex::new(HashMap::new()));
+        tokio::spawn(write_messages(write, sending, rx));
+        let reader = tokio::spawn(read_messages(
+            read,
+            receiving,
+            outgoing.clone(),
+            Arc::clone(&waiting),
+        ));
+        Ok(Peer {
+            outgoing,
+            waiting,
+            reader,
+        })
+    }
+}
+
+// ---------------------------------------------------------------------
+// Connection
+// ---------------------------------------------------------------------
+
+/// An open connection. Dropping it stops the reader, which ends the writer
+/// and closes the socket.
+struct Peer {
+    outgoing: mpsc::UnboundedSender<Vec<u8>>,
+    waiting: Arc<Mutex<HashMap<u64, Waiting>>>,
+    reader: JoinHandle<()>,
+}
+
+impl Drop for Peer {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+/// A call's reply as far as it has arrived.
+struct Waiting {
+    reply: Vec<u8>,
+    tx: oneshot::Sender<Result<Value>>,
+}
+
+async fn write_messages(
+    mut stream: OwnedWriteHalf,
+    mut cipher: CipherState,
+    mut outgoing: mpsc::UnboundedReceiver<Vec<u8>>,
+) {
+    while let Some(msg) = outgoing.recv().await {
+        if stream.write_all(&cipher.seal(&msg)).await.is_err() {
+            return;
+        }
+    }
+}
+
+async fn read_messages(
+    mut stream: impl AsyncRead + Unpin,
+    mut cipher: CipherState,
+    outgoing: mpsc::UnboundedSender<Vec<u8>>,
+    waiting: Arc<Mutex<HashMap<u64, Waiting>>>,
+) {
+    let reason = loop {
+        let msg = match read_message(&mut stream, &mut cipher).await {
+            Ok(msg) => msg,
+            Err(e) => break e.to_string(),
+        };
+        let body = msg.get(2..).unwrap_or_default();
+        match msg_type(&msg) {
+            Some(MSG_PING) if body.len() >= 2 => {
+                let pong_len = u16::from_be_bytes([body[0], body[1]]);
+                // BOLT 1: no pong is wanted for 65532 bytes or more.
+                if pong_len < 65532 {
+                    let mut pong = MSG_PONG.to_be_bytes().to_vec();
+                    pong.extend(pong_len.to_be_bytes());
+                    pong.resize(pong.len() + usize::from(pong_len), 0);
+                    let _ = outgoing.send(pong);
+                }
+            }
+            Some(kind @ (MSG_REPLY_CONTINUES | MSG_REPLY_TERM)) if body.len() >= 8 => {
+                let id = u64::from_be_bytes(body[..8].try_into().expect("8 bytes"));
+                let mut waiting = waiting.lock().unwrap();
+                if let Some(call) = waiting.get_mut(&id) {
+                    call.reply.extend_from_slice(&body[8..]);
+                }
+                if kind == MSG_REPLY_TERM {
+                    if let Some(call) = waiting.remove(&id) {
+                        let _ = call.tx.send(parse_reply(&call.reply));
+                    }
+                }
+            }
+            Some(MSG_ERROR) => break format!("node sent an error: {}", error_text(&msg)),
+            _ => {}
+        }
+    };
+    for (_, call) in waiting.lock().unwrap().drain() {
+        let _ = call.tx.send(Err(io::Error::new(
+            ErrorKind::ConnectionAborted,
+            format!("Commando connection lost: {}", reason),
+        )
+        .into()));
+    }
+}
+
+async fn read_message(
+    stream: &mut (impl AsyncRead + Unpin),
+    cipher: &mut CipherState,
+) -> io::Result<Vec<u8>> {
+    let mut header = [0u8; 18];
+    stream.read_exact(&mut header).await?;
+    let len = cipher.decrypt(&header)?;
+    let mut body = vec![0u8; usize::from(u16::from_be_bytes([len[0], len[1]])) + 16];
+    stream.read_exact(&mut body).await?;
+    cipher.decrypt(&body)
+}
+
+fn msg_type(msg: &[u8]) -> Option<u16> {
+    Some(u16::from_be_bytes(msg.get(..2)?.try_into().ok()?))
+}
+
+/// The `data` of an `error` message: channel_id, length, text.
+fn error_text(msg: &[u8]) -> String {
+    String::from_utf8_lossy(msg.get(36..).unwrap_or_default()).into_owned()
+}
+
+/// A command split into messages, each carrying the request id.
+fn command_messages(id: u64, command: &[u8]) -> Vec<Vec<u8>> {
+    let chunks: Vec<&[u8]> = command.chunks(CMD_CHUNK).collect();
+    let last = chunks.len().saturating_sub(1);
+    chunks
+        .iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            let kind = if i == last {
+                MSG_CMD_TERM
+            } else {
+                MSG_CMD_CONTINUES
+            };
+            let mut msg = kind.to_be_bytes().to_vec();
+            msg.extend(id.to_be_bytes());
+            msg.extend_from_slice(chunk);
+            msg
+        })
+        .collect()
+}
+
+fn parse_reply(reply: &[u8]) -> Result<Value> {
+    let mut reply: Value =
+        serde_json::from_slice(reply).map_err(|e| anyhow!("Malformed Commando reply: {}", e))?;
+    if let Some(result) = reply.get_mut("result") {
+        return Ok(result.take());
+    }
+    match reply.get_mut("error") {
+        Some(error) => Ok(error.take()),
+        None => Err(anyhow!("Commando reply without result: {}", reply)),
+    }
+}
+
+// ---------------------------------------------------------------------
+// BOLT 8 transport
+// ---------------------------------------------------------------------
+
+/// One direction of an established connection.
+struct CipherState {
+    key: [u8; 32],
+    ck: [u8; 32],
+    nonce: u64,
+}
+
+impl CipherState {
+    /// Length prefix and body, each encrypted with its own nonce.
+    fn seal(&mut self, msg: &[u8]) -> Vec<u8> {
+        let len = u16::try_from(msg.len()).expect("messages are split below 65535 bytes");
+        let mut out = self.encrypt(&len.to_be_bytes());
+        out.extend(self.encrypt(msg));
+        out
+    }
+
+    fn encrypt(&mut self, plain: &[u8]) -> Vec<u8> {
+        let out = encrypt(&self.key, self.nonce, &[], plain);
+        self.advance();
+        out
+    }
+
+    fn decrypt(&mut self, cipher: &[u8]) -> io::Result<Vec<u8>> {
+        let out = decrypt(&self.key, self.nonce, &[], cipher).map_err(|_| {
+            io::Error::new(ErrorKind::InvalidData, "Commando message failed to decrypt")
+        })?;
+        self.advance();
+        Ok(out)
+    }
+
+    fn advance(&mut self) {
+        self.nonce += 1;
+        if self.nonce == REKEY_AFTER {
+            (self.ck, self.key) = hkdf(&self.ck, &self.key);
+            self.nonce = 0;
+        }
+    }
+}
+
+/// Initiator side of the Noise_XK handshake.
+struct Handshake {
+    secp: Secp256k1<secp256k1::All>,
+    s: SecretKey,
+    e: SecretKey,
+    h: [u8; 32],
+    ck: [u8; 32],
+    rs: PublicKey,
+}
+
+impl Handshake {
+    /// `s` is our static key, `e` the ephemeral one and `rs` the node's.
+    fn new(s: SecretKey, e: SecretKey, rs: PublicKey) -> Self {
+        let ck = sha256(&[PROTOCOL_NAME]);
+        let h = sha256(&[&ck, PROLOGUE]);
+        let h = sha256(&[&h, &rs.serialize()]);
+        Self {
+            secp: Secp256k1::new(),
+            s,
+            e,
+            h,
+            ck,
+            rs,
+        }
+    }
+
+    fn act_one(&mut self) -> [u8; 50] {
+        let e = PublicKey::from_secret_key(&self.secp, &self.e).serialize();
+        self.h = sha256(&[&self.h, &e]);
+        let temp_k1;
+        (self.ck, temp_k1) = hkdf(&self.ck, &ecdh(&self.rs, &self.e));
+        let c = encrypt(&temp_k1, 0, &self.h, &[]);
+        self.h = sha256(&[&self.h, &c]);
+
+        let mut act = [0u8; 50];
+        act[1..34].copy_from_slice(&e);
+        act[34..].copy_from_slice(&c);
+        act
+    }
+
+    /// Checks the node's reply and returns act three with the sending and
+    /// receiving ciphers.
+    fn act_two(mut self, act: &[u8; 50]) -> Result<([u8; 66], CipherState, CipherState)> {
+        if act[0] != 0 {
+            return Err(anyhow!("Unsupported handshake version {}", act[0]));
+        }
+        let re = PublicKey::from_slice(&act[1..34])
+            .map_err(|_| anyhow!("Node sent an invalid ephemeral key"))?;
+        self.h = sha256(&[&self.h, &act[1..34]]);
+        let temp_k2;
+        (self.ck, temp_k2) = hkdf(&self.ck, &ecdh(&re, &self.e));
+        decrypt(&temp_k2, 0, &self.h, &act[34..])
+            .map_err(|_| anyhow!("Handshake failed: bad act two"))?;
+        self.h = sha256(&[&self.h, &act[34..]]);
+
+        let s = PublicKey::from_secret_key(&self.secp, &self.s).serialize();
+        let c = encrypt(&temp_k2, 1, &self.h, &s);
+        self.h = sha256(&[&self.h, &c]);
+        let temp_k3;
+        (self.ck, temp_k3) = hkdf(&self.ck, &ecdh(&re, &self.s));
+        let t = encrypt(&temp_k3, 0, &self.h, &[]);
+        let (sk, rk) = hkdf(&self.ck, &[]);
+
+        let mut act = [0u8; 66];
+        act[1..50].copy_from_slice(&c);
+        act[50..].copy_from_slice(&t);
+        let cipher = |key| CipherState {
+            key,
+            ck: self.ck,
+            nonce: 0,
+        };
+        Ok((act, cipher(sk), cipher(rk)))
+    }
+}
+
+fn random_key() -> SecretKey {
+    loop {
+        // All but a negligible fraction of 32-byte strings are valid keys.
+        if let Ok(key) = SecretKey::from_slice(&rand::random::<[u8; 32]>()) {
+            return key;
+        }
+    }
+}
+
+fn sha256(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+/// SHA-256 of the compressed shared point, as BOLT 8 specifies.
+fn ecdh(point: &PublicKey, key: &SecretKey) -> [u8; 32] {
+    SharedSecret::new(point, key).secret_bytes()
+}
+
+fn hkdf(salt: &[u8; 32], ikm: &[u8]) -> ([u8; 32], [u8; 32]) {
+    let mut okm = [0u8; 64];
+    Hkdf::<Sha256>::new(Some(salt), ikm)
+        .expand(&[], &mut okm)
+        .expect("HKDF-SHA256 can expand to 64 bytes");
+    let (a, b) = okm.split_at(32);
+    (
+        a.try_into().expect("32 bytes"),
+        b.try_into().expect("32 bytes"),
+    )
+}
+
+/// The nonce is 32 zero bits followed by the little-endian counter.
+fn nonce(n: u64) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[4..].copy_from_slice(&n.to_le_bytes());
+    nonce
+}
+
+fn encrypt(key: &[u8; 32], n: u64, ad: &[u8], plain: &[u8]) -> Vec<u8> {
+    ChaCha20Poly1305::new(&Key::from(*key))
+        .encrypt(
+            &Nonce::from(nonce(n)),
+            Payload {
+                msg: plain,
+                aad: ad,
+            },
+        )
+        .expect("ChaCha20-Poly1305 encrypts any message this size")
+}
+
+fn decrypt(key: &[u8; 32], n: u64, ad: &[u8], cipher: &[u8]) -> Result<Vec<u8>> {
+    ChaCha20Poly1305::new(&Key::from(*key))
+        .decrypt(
+            &Nonce::from(nonce(n)),
+            Payload {
+                msg: cipher,
+                aad: ad,
+            },
+        )
+        .map_err(|_| anyhow!("Decryption failed"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    fn key(byte: u8) -> SecretKey {
+        SecretKey::from_slice(&[byte; 32]).unwrap()
+    }
+
+    fn bytes(s: &str) -> Vec<u8> {
+        hex::decode(s).unwrap()
+    }
+
+    /// The initiator vectors from BOLT 8's appendix.
+    fn handshake() -> (Handshake, [u8; 50]) {
+        let rs = "028d7500dd4c12685d1f568b4c2b5048e8534b873319f3a8daa612b469132ec7f7";
+        let mut handshake = Handshake::new(key(0x11), key(0x12), rs.parse().unwrap());
+        let act_one = handshake.act_one();
+        (handshake, act_one)
+    }
+
+    #[test]
+    fn handshake_matches_bolt8() {
+        let (handshake, act_one) = handshake();
+        assert_eq!(
+            hex::encode(act_one),
+            "00036360e856310ce5d294e8be33fc807077dc56ac80d95d9cd4ddbd21325eff73f70df6086551151f58b8afe6c195782c6a"
+        );
+
+        let act_two = bytes("0002466d7fcae563e5cb09a0d1870bb580344804617879a14949cf22285f1bae3f276e2470b93aac583c9ef6eafca3f730ae");
+        let (act_three, sending, receiving) =
+            handshake.act_two(&act_two.try_into().unwrap()).unwrap();
+        assert_eq!(
+            hex::encode(act_three),
+            "00b9e3a702e93e3a9948c2ed6e5fd7590a6e1c3a0344cfc9d5b57357049aa22355361aa02e55a8fc28fef5bd6d71ad0c38228dc68b1c466263b47fdf31e560e139ba"
+        );
+        assert_eq!(
+            hex::encode(sending.key),
+            "969ab31b4d288cedf6218839b27a3e2140827047f2c0f01bf5c04435d43511a9"
+        );
+        assert_eq!(
+            hex::encode(receiving.key),
+            "bb9020b8965f4df047e07f955f3c4b88418984aadc5cdb35096b9ea8fa5c3442"
+        );
+    }
+
+    #[test]
+    fn messages_match_bolt8_across_rekeying() {
+        let (handshake, _) = handshake();
+        let act_two = bytes("0002466d7fcae563e5cb09a0d1870bb580344804617879a14949cf22285f1bae3f276e2470b93aac583c9ef6eafca3f730ae");
+        let (_, mut sending, _) = handshake.act_two(&act_two.try_into().unwrap()).unwrap();
+
+        let expected = HashMap::from([
+            (
+                0,
+                "cf2b30ddf0cf3f80e7c35a6e6730b59fe802473180f396d88a8fb0db8cbcf25d2f214cf9ea1d95",
+            ),
+            (
+                1,
+                "72887022101f0b6753e0c7de21657d35a4cb2a1f5cde2650528bbc8f837d0f0d7ad833b1a256a1",
+            ),
+            (
+                500,
+                "178cb9d7387190fa34db9c2d50027d21793c9bc2d40b1e14dcf30ebeeeb220f48364f7a4c68bf8",
+            ),
+            (
+                501,
+                "1b186c57d44eb6de4c057c49940d79bb838a145cb528d6e8fd26dbe50a60ca2c104b56b60e45bd",
+            ),
+            (
+                1000,
+                "4a2f3cc3b5e78ddb83dcb426d9863d9d9a723b0337c89dd0b005d89f8d3c05c52b76b29b740f09",
+            ),
+            (
+                1001,
+                "2ecd8c8a5629d0d02ab457a0fdd0f7b90a192cd46be5ecb6ca570bfc5e268338b1a16cf4ef2d36",
+            ),
+        ]);
+        for i in 0..=1001 {
+            let sealed = sending.seal(b"hello");
+            if let Some(want) = expected.get(&i) {
+                assert_eq!(hex::encode(sealed), *want, "message {}", i);
+            }
+        }
+    }
+
+    /// Responder side of the handshake, as a node would run it.
+    async fn accept(stream: &mut TcpStream, s: &SecretKey) -> (CipherState, CipherState) {
+        let secp = Secp256k1::new();
+        let e = random_key();
+        let ck = sha256(&[PROTOCOL_NAME]);
+        let h = sha256(&[&ck, PROLOGUE]);
+        let h = sha256(&[&h, &PublicKey::from_secret_key(&secp, s).serialize()]);
+
+        let mut act = [0u8; 50];
+        stream.read_exact(&mut act).await.unwrap();
+        let re = PublicKey::from_slice(&act[1..34]).unwrap();
+        let h = sha256(&[&h, &act[1..34]]);
+        let (ck, temp_k1) = hkdf(&ck, &ecdh(&re, s));
+        decrypt(&temp_k1, 0, &h, &act[34..]).unwrap();
+        let h = sha256(&[&h, &act[34..]]);
+
+        let e_pub = PublicKey::from_secret_key(&secp, &e).serialize();
+        let h = sha256(&[&h, &e_pub]);
+        let (ck, temp_k2) = hkdf(&ck, &ecdh(&re, &e));
+        let c = encrypt(&temp_k2, 0, &h, &[]);
+        let h = sha256(&[&h, &c]);
+        stream
+            .write_all(&[&[0][..], &e_pub, &c].concat())
+            .await
+            .unwrap();
+
+        let mut act = [0u8; 66];
+        stream.read_exact(&mut act).await.unwrap();
+        let rs = decrypt(&temp_k2, 1, &h, &act[1..50]).unwrap();
+        let h = sha256(&[&h, &act[1..50]]);
+        let (ck, temp_k3) = hkdf(&ck, &ecdh(&PublicKey::from_slice(&rs).unwrap(), &e));
+        decrypt(&temp_k3, 0, &h, &act[50..]).unwrap();
+        let (rk, sk) = hkdf(&ck, &[]);
+        let cipher = |key| CipherState { key, ck, nonce: 0 };
+        (cipher(sk), cipher(rk))
+    }
+
+    /// A node that answers each command with its params echoed back in two
+    /// reply messages, after checking that pings get their pong.
+    async fn fake_node(listener: TcpListener, s: SecretKey) {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let (mut sending, mut receiving) = accept(&mut stream, &s).await;
+        let init = read_message(&mut stream, &mut receiving).await.unwrap();
+        assert_eq!(msg_type(&init), Some(MSG_INIT));
+        let init = [MSG_INIT.to_be_bytes(), [0, 0], [0, 0]].concat();
+        stream.write_all(&sending.seal(&init)).await.unwrap();
+
+        let ping = [MSG_PING.to_be_bytes(), 4u16.to_be_bytes(), [0, 0]].concat();
+        stream.write_all(&sending.seal(&ping)).await.unwrap();
+
+        let mut pong = false;
+        for n in 0.. {
+            let mut command = Vec::new();
+            let id = loop {
+                let Ok(msg) = read_message(&mut stream, &mut receiving).await else {
+                    return;
+                };
+                if msg_type(&msg) == Some(MSG_PONG) {
+                    assert_eq!(msg[2..], [0, 4, 0, 0, 0, 0]);
+                    pong = true;
+                    continue;
+                }
+                command.extend_from_slice(&msg[10..]);
+                if msg_type(&msg) == Some(MSG_CMD_TERM) {
+                    break msg[2..10].to_vec();
+                }
+            };
+            // The ping went out before the first reply, so the pong is in by
+            // the time a second command follows it.
+            assert!(pong || n == 0, "no pong");
+            let command: Value = serde_json::from_slice(&command).unwrap();
+            let reply = if command["rune"] == "good-rune" {
+                json!({ "jsonrpc": "2.0", "id": command["id"], "result": command["params"] })
+            } else {
+                json!({ "jsonrpc": "2.0", "id": command["id"], "error": { "code": 19537, "message": "Not authorized" } })
+            }
+            .to_string();
+            let (first, rest) = reply.as_bytes().split_at(reply.len() / 2);
+            for (kind, part) in [(MSG_REPLY_CONTINUES, first), (MSG_REPLY_TERM, rest)] {
+                let msg = [&kind.to_be_bytes()[..], &id, part].concat();
+                stream.write_all(&sending.seal(&msg)).await.unwrap();
+            }
+        }
+    }
+
+    async fn commando(rune: &str) -> Commando {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let s = random_key();
+        let node_id = PublicKey::from_secret_key(&Secp256k1::new(), &s).to_string();
+        tokio::spawn(fake_node(listener, s));
+        let timeout = Duration::from_secs(5);
+        Commando::new(&node_id, &addr, rune, timeout, timeout).unwrap()
+    }
+
+    #[tokio::test]
+    async fn calls_reach_the_node_and_replies_come_back() {
+        let commando = commando("good-rune").await;
+        let params = json!({ "label": "x".repeat(100_000) });
+        assert_eq!(commando.call("invoice", &params).await.unwrap(), params);
+        // The same connection serves the next call.
+        let params = json!({ "id": 2 });
+        assert_eq!(commando.call("getinfo", &params).await.unwrap(), params);
+    }
+
+    #[tokio::test]
+    async fn refused_calls_return_the_error_object() {
+        let commando = commando("bad-rune").await;
+        let res = commando.call("getinfo", &json!({})).await.unwrap();
+        assert_eq!(res["code"], 19537);
+    }
+
+    #[tokio::test]
+    async fn wrong_node_id_fails_the_handshake() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(fake_node(listener, random_key()));
+        let other = PublicKey::from_secret_key(&Secp256k1::new(), &random_key()).to_string();
+        let timeout = Duration::from_secs(5);
+        let commando = Commando::new(&other, &addr, "good-rune", timeout, timeout).unwrap();
+        assert!(commando.call("getinfo", &json!({})).await.is_err());
+    }
+}
@@ -1,38 +1,359 @@
+use ::config::{Config, File};
+use anyhow::{anyhow, Result};
 use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::time::Duration;
 
-#[derive(Debug, Deserialize)]
+/// Environment variable naming the config file when `--config` isn't given.
+pub const CONFIG_PATH_ENV: &str = "LIGHTNING_DRIVER_CONFIG";
+const DEFAULT_CONFIG_PATH: &str = "config.toml";
+/// `LD__LND_REST__HOST=...` overrides `[lnd-rest] host`.
+const ENV_OVERRIDE_PREFIX: &str = "LD__";
+/// Name given to the node of a single-node (`[node]`) config.
+pub const DEFAULT_NODE_NAME: &str = "default";
+
+#[derive(Debug, Default, Deserialize)]
 pub struct NodeConfig {
     #[serde(rename = "type")]
     pub node_type: String,
 }
 
-#[derive(Debug, Deserialize)]
-pub struct LndGrpcConfig {
+// lightning-client/src/config.rs
+/// Credentials for either LND transport. Each secret may be given in several
+/// ways; explicit fields win over what an `lndconnect` URI carries.
+#[derive(Debug, Default, Deserialize)]
+pub struct LndConfig {
+    /// `host:port` for gRPC, `https://host:port` for REST. May come from `lndconnect`.
+    #[serde(default)]
     pub host: String,
+    /// Macaroon as a file path, hex or base64.
+    #[serde(default)]
+    pub macaroon: Option<String>,
+    #[serde(default)]
     pub macaroon_hex: String,
+    /// Binary macaroon file, used when `macaroon_hex` is empty.
+    #[serde(default)]
+    pub macaroon_path: Option<String>,
+    /// TLS certificate (PEM or DER) as a file path, hex, base64 or inline PEM.
+    #[serde(default)]
+    pub cert: Option<String>,
     #[serde(default)]
     pub cert_hex: String,
+    #[serde(default)]
+    pub cert_path: Option<String>,
+    /// `lndconnect://host:port?cert=...&macaroon=...`
+    #[serde(default)]
+    pub lndconnect: Option<String>,
+    /// Name to check the certificate against when it doesn't cover `host`,
+    /// e.g. `localhost` for a self-signed cert reached by IP.
+    #[serde(default)]
+    pub tls_server_name: Option<String>,
 }
 
-// lightning-client/src/config.rs
+pub type LndGrpcConfig = LndConfig;
+pub type LndRestConfig = LndConfig;
+
 #[derive(Debug, Deserialize)]
-pub struct LndRestConfig {
+pub struct ClnConfig {
+    /// `clnrest` URL, or the node's `host:port` when `node_id` is set.
     pub host: String,
-    pub macaroon_hex: String,
-    pub cert_path: String,  // ← path, not hex
+    /// Sent as the `Rune` header; required by the `clnrest` plugin and by
+    /// Commando.
+    #[serde(default)]
+    pub rune: Option<String>,
+    /// The node's public key. When set, calls go through Commando over a
+    /// Lightning peer connection to `host` instead of `clnrest`.
+    #[serde(default)]
+    pub node_id: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
-pub struct ClnConfig {
-    pub host: String,
+pub struct LdkConfig {
+    #[serde(default = "default_ldk_network")]
+    pub network: String,
+    pub storage_dir: String,
+    #[serde(default)]
+    pub listening_address: Option<String>,
+    #[serde(default)]
+    pub alias: Option<String>,
+    /// Esplora chain source, e.g. `http://127.0.0.1:3002`.
+    #[serde(default)]
+    pub esplora_url: Option<String>,
+    /// bitcoind RPC chain source, used when `esplora_url` is not set.
+    #[serde(default)]
+    pub bitcoind: Option<LdkBitcoindConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LdkBitcoindConfig {
+    pub rpc_host: String,
+    pub rpc_port: u16,
+    pub rpc_user: String,
+    pub rpc_password: String,
 }
 
 #[derive(Debug, Deserialize)]
+pub struct NwcConfig {
+    /// `nostr+walletconnect://...` connection string handed out by the wallet.
+    pub uri: String,
+}
+
+/// `type = "pool"`: one logical node spread over other configured nodes.
+#[derive(Debug, Deserialize)]
+pub struct PoolConfig {
+    /// Node names in priority order; the first is the primary.
+    pub members: Vec<String>,
+    #[serde(default)]
+    pub invoice_strategy: InvoiceStrategy,
+    #[serde(default)]
+    pub payment_strategy: PaymentStrategy,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InvoiceStrategy {
+    /// Primary first, the next member when it is down.
+    #[default]
+    Failover,
+    /// Spread invoices over all healthy members.
+    RoundRobin,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PaymentStrategy {
+    Failover,
+    /// First member (in priority order) with enough outbound liquidity.
+    #[default]
+    Liquidity,
+}
+
+/// Timeouts and retry policy for a node's transport, `[connection]` or
+/// `[nodes.<name>.connection]`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ConnectionConfig {
+    pub connect_timeout_secs: u64,
+    pub request_timeout_secs: u64,
+    /// How long a payment (or probe) may take before it is reported as
+    /// pending rather than waited for. Multi-path payments can take minutes.
+    pub payment_timeout_secs: u64,
+    /// Extra attempts for read-only calls after a transport error. Invoice
+    /// creation and payments are never retried.
+    pub max_retries: u32,
+    /// Delay before the first retry; doubles on each further attempt.
+    pub retry_backoff_ms: u64,
+}
+
+impl Default for ConnectionConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout_secs: 10,
+            request_timeout_secs: 30,
+            payment_timeout_secs: 300,
+            max_retries: 3,
+            retry_backoff_ms: 250,
+        }
+    }
+}
+
+impl ConnectionConfig {
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_secs(self.connect_timeout_secs)
+    }
+
+    pub fn request_timeout(&self) -> Duration {
+        Duration::from_secs(self.request_timeout_secs)
+    }
+
+    pub fn payment_timeout(&self) -> Duration {
+        Duration::from_secs(self.payment_timeout_secs)
+    }
+
+    pub fn retry_backoff(&self) -> Duration {
+        Duration::from_millis(self.retry_backoff_ms)
+    }
+}
+
+/// Exchange rates for fiat-denominated invoices, `[rates]`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RatesConfig {
+    /// `static` or `http`.
+    pub provider: String,
+    /// `static`: price of one BTC per currency code, e.g. `USD = 65000.0`.
+    #[serde(default)]
+    pub prices: BTreeMap<String, f64>,
+    /// `http`: URL to fetch; `{currency}` and `{currency_lower}` are filled in.
+    #[serde(default)]
+    pub url: Option<String>,
+    /// `http`: JSON pointer to the price in the response, with the same
+    /// placeholders as `url`. The whole body when unset.
+    #[serde(default)]
+    pub json_pointer: Option<String>,
+    /// `http`: how long a fetched price is reused.
+    #[serde(default = "default_rate_cache_secs")]
+    pub cache_secs: u64,
+}
+
+fn default_rate_cache_secs() -> u64 {
+    60
+}
+
+fn default_ldk_network() -> String {
+    "regtest".into()
+}
+
+#[derive(Debug, Default, Deserialize)]
 pub struct Settings {
-    pub node: NodeConfig,
+    /// Single-node layout: `[node]` plus the top-level backend tables.
+    #[serde(default)]
+    pub node: Option<NodeConfig>,
+    #[serde(rename = "lnd-grpc")]
+    pub lnd_grpc: Option<LndGrpcConfig>,
+    #[serde(rename = "lnd-rest")]
+    pub lnd_rest: Option<LndRestConfig>,
+    pub cln: Option<ClnConfig>,
+    pub ldk: Option<LdkConfig>,
+    pub nwc: Option<NwcConfig>,
+    #[serde(default)]
+    pub connection: ConnectionConfig,
+    /// `mainnet`, `testnet`, `signet` or `regtest`; applies to every node
+    /// that doesn't set its own.
+    #[serde(default)]
+    pub expected_network: Option<String>,
+    #[serde(default)]
+    pub rates: Option<RatesConfig>,
+    /// Named nodes, `[nodes.<name>]`, each with its own backend tables.
+    #[serde(default)]
+    pub nodes: BTreeMap<String, NodeSettings>,
+    /// Node used when a caller doesn't name one.
+    #[serde(default)]
+    pub default_node: Option<String>,
+}
+
+/// One node's backend, e.g. `[nodes.hot]` with `type = "lnd-grpc"` and a
+/// `[nodes.hot.lnd-grpc]` table, or just a connection string in `uri`.
+#[derive(Debug, Default, Deserialize)]
+pub struct NodeSettings {
+    #[serde(rename = "type", default)]
+    pub node_type: String,
+    /// `lndconnect://`, `clnrest://` or `nostr+walletconnect://` string used
+    /// instead of the backend tables.
+    #[serde(default)]
+    pub uri: Option<String>,
     #[serde(rename = "lnd-grpc")]
     pub lnd_grpc: Option<LndGrpcConfig>,
     #[serde(rename = "lnd-rest")]
     pub lnd_rest: Option<LndRestConfig>,
     pub cln: Option<ClnConfig>,
-}
\ No newline at end of file
+    pub ldk: Option<LdkConfig>,
+    pub nwc: Option<NwcConfig>,
+    pub pool: Option<PoolConfig>,
+    #[serde(default)]
+    pub connection: ConnectionConfig,
+    /// Refuse to connect to a node, or pay an invoice, on another chain.
+    #[serde(default)]
+    pub expected_network: Option<String>,
+}
+
+impl Settings {
+    /// Loads settings the same way the API server does; see [`load`].
+    pub fn load(path: Option<&str>) -> Result<Self> {
+        Ok(load(path)?.try_deserialize()?)
+    }
+
+    /// All configured nodes by name, plus the name of the default one.
+    ///
+    /// A `[node]` config becomes a node called `default`. Without
+    /// `default_node`, a lone node is the default.
+    pub fn into_nodes(self) -> Result<(BTreeMap<String, NodeSettings>, String)> {
+        let mut nodes = self.nodes;
+        if let Some(node) = self.node {
+            if nodes.contains_key(DEFAULT_NODE_NAME) {
+                return Err(anyhow!(
+                    "[node] and [nodes.{}] cannot both be set",
+                    DEFAULT_NODE_NAME
+                ));
+            }
+            nodes.insert(
+                DEFAULT_NODE_NAME.to_string(),
+                NodeSettings {
+                    node_type: node.node_type,
+                    uri: None,
+                    lnd_grpc: self.lnd_grpc,
+                    lnd_rest: self.lnd_rest,
+                    cln: self.cln,
+                    ldk: self.ldk,
+                    nwc: self.nwc,
+                    pool: None,
+                    connection: self.connection,
+                    expected_network: None,
+                },
+            );
+        }
+        if let Some(network) = &self.expected_network {
+            for node in nodes.values_mut() {
+                node.expected_network.get_or_insert_with(|| network.clone());
+            }
+        }
+
+        let default = match self.default_node {
+            Some(name) if nodes.contains_key(&name) => name,
+            Some(name) => return Err(anyhow!("default_node '{}' is not configured", name)),
+            None if nodes.len() == 1 => nodes.keys().next().cloned().unwrap_or_default(),
+            None if nodes.contains_key(DEFAULT_NODE_NAME) => DEFAULT_NODE_NAME.to_string(),
+            None if nodes.is_empty() => {
+                return Err(anyhow!("No node configured; add [node] or [nodes.<name>]"))
+            }
+            None => return Err(anyhow!("Several nodes configured; set default_node")),
+        };
+        Ok((nodes, default))
+    }
+}
+
+/// Reads the config file and applies `LD__SECTION__KEY` environment overrides.
+///
+/// The file is `path` if given, else `$LIGHTNING_DRIVER_CONFIG`, else
+/// `./config.toml`. Only the implicit default may be missing, so a setup can
+/// be configured from the environment alone.
+pub fn load(path: Option<&str>) -> Result<Config> {
+    let explicit = path
+        .map(str::to_string)
+        .or_else(|| std::env::var(CONFIG_PATH_ENV).ok());
+    let file = match &explicit {
+        Some(path) => File::with_name(path),
+        None => File::with_name(DEFAULT_CONFIG_PATH).required(false),
+    };
+
+    let mut builder = Config::builder().add_source(file);
+    for (key, value) in std::env::vars() {
+        if let Some(path) = env_override_key(&key) {
+            builder = builder.set_override(path, value)?;
+        }
+    }
+    Ok(builder.build()?)
+}
+
+/// `LD__NODES__HOT__LND_REST__HOST` -> `nodes.hot.lnd-rest.host`.
+fn env_override_key(var: &str) -> Option<String> {
+    let rest = var.strip_prefix(ENV_OVERRIDE_PREFIX)?;
+    let mut parts: Vec<String> = rest.split("__").map(str::to_ascii_lowercase).collect();
+    if parts.iter().any(|p| p.is_empty()) {
+        return None;
+    }
+    // Section names use dashes, which env var names cannot carry.
+    for part in parts.iter_mut() {
+        if part == "lnd_grpc" || part == "lnd_rest" {
+            *part = part.replace('_', "-");
+        }
+    }
+    Some(parts.join("."))
+}
+
+/// Reads a secret kept in its own file, e.g. `password_hash_file`.
+pub fn read_secret_file(path: &str) -> Result<String> {
+    let value = fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read secret file '{}': {}", path, e))?;
+    Ok(value.trim().to_string())
+}
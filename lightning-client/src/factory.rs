@@ -1,23 +1,49 @@
 // lightning-client/src/factory.rs
 use super::*;
-use ::config::{Config as AppConfig, File}; // explicit crate import
 
+/// Connects using `$LIGHTNING_DRIVER_CONFIG` or `./config.toml`.
 pub async fn connect_from_config() -> Result<LightningClientDyn> {
-    let settings = AppConfig::builder()
-        .add_source(File::with_name("config.toml"))
-        .build()?
-        .try_deserialize::<Settings>()?;
+    connect_with_settings(Settings::load(None)?).await
+}
+
+/// Connects from a single connection string; see [`uri::settings_from_uri`].
+pub async fn connect_uri(uri: &str) -> Result<LightningClientDyn> {
+    connect_node(uri::node_from_uri(uri)?).await
+}
+
+/// Connects the default node only; use [`NodeRegistry`] for all of them.
+pub async fn connect_with_settings(settings: Settings) -> Result<LightningClientDyn> {
+    let (mut nodes, default) = settings.into_nodes()?;
+    let node = nodes
+        .remove(&default)
+        .ok_or_else(|| anyhow::anyhow!("Node '{}' missing", default))?;
+    connect_node(node).await
+}
 
-    let driver: Box<dyn LightningClient + Send + Sync> = match settings.node.node_type.as_str() {
+pub async fn connect_node(node: NodeSettings) -> Result<LightningClientDyn> {
+    let node = match &node.uri {
+        Some(uri) => NodeSettings {
+            connection: node.connection.clone(),
+            expected_network: node.expected_network.clone(),
+            ..uri::node_from_uri(uri)?
+        },
+        None => node,
+    };
+    let connection = node.connection.clone();
+
+    let driver: LightningClientDyn = match node.node_type.as_str() {
         "lnd-grpc" => {
             #[cfg(feature = "lnd-grpc")]
             {
-                let lnd = settings
+                let lnd = node
                     .lnd_grpc
                     .ok_or_else(|| anyhow::anyhow!("LND gRPC config missing"))?;
-                Box::new(
-                    lnd_grpc::LndGrpcWrapper::connect(&lnd.cert_hex, &lnd.macaroon_hex, &lnd.host)
-                        .await?,
+                Arc::new(
+                    lnd_grpc::LndGrpcWrapper::connect(
+                        &lnd_auth::LndCredentials::from_config(&lnd)?,
+                        &connection,
+                    )
+                    .await?,
                 )
             }
             #[cfg(not(feature = "lnd-grpc"))]
@@ -26,23 +52,60 @@ pub async fn connect_from_config() -> Result<LightningClientDyn> {
             }
         }
         "lnd-rest" => {
-            let lnd = settings
+            let lnd = node
                 .lnd_rest
                 .ok_or_else(|| anyhow::anyhow!("LND REST config missing"))?;
-            Box::new(lnd_rest::LndRestClient::new(
-                &lnd.host,
-                &lnd.macaroon_hex,
-                &lnd.cert_path,
+            Arc::new(lnd_rest::LndRestClient::new(
+                &lnd_auth::LndCredentials::from_config(&lnd)?,
+                &connection,
             )?)
         }
         "cln" => {
-            let cln = settings
+            let cln = node
                 .cln
                 .ok_or_else(|| anyhow::anyhow!("CLN config missing"))?;
-            Box::new(cln::ClnClient::new(&cln.host))
+            Arc::new(match &cln.node_id {
+                Some(node_id) => {
+                    cln::ClnClient::commando(node_id, &cln.host, cln.rune.as_deref(), &connection)?
+                }
+                None => cln::ClnClient::new(&cln.host, cln.rune.as_deref(), &connection)?,
+            })
+        }
+        "ldk" => {
+            #[cfg(feature = "ldk")]
+            {
+                let ldk = node
+                    .ldk
+                    .ok_or_else(|| anyhow::anyhow!("LDK config missing"))?;
+                Arc::new(ldk::LdkNode::start(&ldk).await?)
+            }
+            #[cfg(not(feature = "ldk"))]
+            {
+                return Err(anyhow::anyhow!("ldk feature not enabled"));
+            }
+        }
+        "nwc" => {
+            #[cfg(feature = "nwc")]
+            {
+                let nwc = node
+                    .nwc
+                    .ok_or_else(|| anyhow::anyhow!("NWC config missing"))?;
+                Arc::new(nwc::NwcClient::connect(&nwc.uri).await?)
+            }
+            #[cfg(not(feature = "nwc"))]
+            {
+                return Err(anyhow::anyhow!("nwc feature not enabled"));
+            }
         }
+        "pool" => return Err(anyhow::anyhow!("Pools are built by NodeRegistry")),
         _ => return Err(anyhow::anyhow!("Unsupported node type")),
     };
 
-    Ok(Arc::new(Mutex::new(driver)))
+    let driver: LightningClientDyn = Arc::new(retry::RetryingClient::new(driver, &connection));
+    match &node.expected_network {
+        Some(network) => Ok(Arc::new(
+            network::NetworkGuard::connect(driver, network).await?,
+        )),
+        None => Ok(driver),
+    }
 }
@@ -0,0 +1,690 @@
+// lightning-client/src/ldk.rs
+use super::*;
+use crate::config::LdkConfig;
+use anyhow::{anyhow, Result};
+use ldk_node::bitcoin::hashes::{sha256, Hash as _};
+use ldk_node::bitcoin::Network;
+use ldk_node::config::ChannelConfig;
+use ldk_node::lightning::ln::channelmanager::PaymentId;
+use ldk_node::lightning::ln::msgs::SocketAddress;
+use ldk_node::lightning::routing::gossip::NodeId;
+use ldk_node::lightning::util::message_signing;
+use ldk_node::lightning_invoice::{Bolt11Invoice, Bolt11InvoiceDescription, Description, Sha256};
+use ldk_node::payment::{PaymentDetails, PaymentDirection, PaymentKind, PaymentStatus};
+use ldk_node::{Builder, Node, NodeError};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::runtime::Runtime;
+
+const INVOICE_EXPIRY_SECS: u32 = 3600;
+/// How long `pay_invoice` waits before reporting the payment as pending.
+const PAYMENT_TIMEOUT: Duration = Duration::from_secs(300);
+const PAYMENT_POLL_INTERVAL: Duration = Duration::from_millis(200);
+/// A wallet sync older than this means the node has lost the chain. LDK
+/// Node syncs every 30 seconds by default.
+const SYNC_STALE_AFTER: Duration = Duration::from_secs(300);
+/// File in the storage dir holding the invoices created here, one per line.
+const INVOICE_FILE: &str = "bolt11_invoices";
+
+/// Embedded Lightning node running inside this process.
+///
+/// LDK Node drives its own background tasks and blocks internally on many
+/// calls, so it gets a dedicated multi-threaded runtime instead of sharing the
+/// caller's (actix runs one current-thread runtime per worker).
+pub struct LdkNode {
+    node: Arc<Node>,
+    runtime: Option<Runtime>,
+    invoices: Arc<InvoiceLog>,
+}
+
+/// LDK Node's payment store keeps an inbound payment's hash but not the
+/// invoice text, so invoices created through this client are kept here.
+struct InvoiceLog {
+    path: PathBuf,
+    by_hash: Mutex<HashMap<PaymentHash, Bolt11>>,
+}
+
+impl InvoiceLog {
+    fn open(path: PathBuf) -> Result<Self> {
+        let mut by_hash = HashMap::new();
+        if path.exists() {
+            for line in fs::read_to_string(&path)?.lines() {
+                let Ok(bolt11) = line.parse::<Bolt11>() else {
+                    continue;
+                };
+                if let Some(hash) = bolt11.payment_hash() {
+                    by_hash.insert(hash, bolt11);
+                }
+            }
+        }
+        Ok(Self {
+            path,
+            by_hash: Mutex::new(by_hash),
+        })
+    }
+
+    fn record(&self, bolt11: &Bolt11) -> Result<()> {
+        let hash = bolt11
+            .payment_hash()
+            .ok_or_else(|| anyhow!("LDK created an invoice without a payment hash"))?;
+        let mut by_hash = self.by_hash.lock().unwrap();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", bolt11)?;
+        by_hash.insert(hash, bolt11.clone());
+        Ok(())
+    }
+
+    fn get(&self, hash: &PaymentHash) -> Option<Bolt11> {
+        self.by_hash.lock().unwrap().get(hash).cloned()
+    }
+}
+
+impl LdkNode {
+    pub async fn start(cfg: &LdkConfig) -> Result<Self> {
+        let network: Network = cfg
+            .network
+            .parse()
+            .map_err(|_| anyhow!("Invalid LDK network '{}'", cfg.network))?;
+
+        let invoices = InvoiceLog::open(Path::new(&cfg.storage_dir).join(INVOICE_FILE))?;
+        let mut builder = Builder::new();
+        builder.set_network(network);
+        builder.set_storage_dir_path(cfg.storage_dir.clone());
+
+        if let Some(addr) = &cfg.listening_address {
+            let addr: SocketAddress = addr
+                .parse()
+                .map_err(|_| anyhow!("Invalid LDK listening address '{}'", addr))?;
+            builder.set_listening_addresses(vec![addr])?;
+        }
+        if let Some(alias) = &cfg.alias {
+            builder.set_node_alias(alias.clone())?;
+        }
+
+        match (&cfg.esplora_url, &cfg.bitcoind) {
+            (Some(url), _) => {
+                builder.set_chain_source_esplora(url.clone(), None);
+            }
+            (None, Some(rpc)) => {
+                builder.set_chain_source_bitcoind_rpc(
+                    rpc.rpc_host.clone(),
+                    rpc.rpc_port,
+                    rpc.rpc_user.clone(),
+                    rpc.rpc_password.clone(),
+                );
+            }
+            (None, None) => {
+                return Err(anyhow!(
+                    "LDK config needs either esplora_url or a [ldk.bitcoind] section"
+                ))
+            }
+        }
+
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .thread_name("ldk-node")
+            .build()?;
+        builder.set_runtime(runtime.handle().clone());
+
+        let started = runtime
+            .spawn_blocking(move || -> Result<Arc<Node>> {
+                let node = Arc::new(builder.build()?);
+                node.start()?;
+                Ok(node)
+            })
+            .await
+            .map_err(|e| anyhow!("LDK startup task failed: {}", e))
+            .and_then(|r| r);
+        let node = match started {
+            Ok(node) => node,
+            Err(e) => {
+                // A runtime may not be dropped from async context.
+                runtime.shutdown_background();
+                return Err(e);
+            }
+        };
+
+        // Nothing here reacts to events yet, but the queue must be drained.
+        let events = Arc::clone(&node);
+        runtime.spawn(async move {
+            loop {
+                let _ = events.next_event_async().await;
+                if events.event_handled().is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self {
+            node,
+            runtime: Some(runtime),
+            invoices: Arc::new(invoices),
+        })
+    }
+
+    /// Runs a (possibly blocking) LDK Node call on the node's own runtime.
+    async fn call<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Node) -> Result<T> + Send + 'static,
+    {
+        let runtime = self
+            .runtime
+            .as_ref()
+            .ok_or_else(|| anyhow!("LDK node is shut down"))?;
+        let node = Arc::clone(&self.node);
+        runtime
+            .spawn_blocking(move || f(&node))
+            .await
+            .map_err(|e| anyhow!("LDK task failed: {}", e))?
+    }
+}
+
+impl Drop for LdkNode {
+    fn drop(&mut self) {
+        let _ = self.node.stop();
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+#[async_trait]
+impl LightningClient for LdkNode {
+    async fn get_info(&self) -> Result<NodeInfo> {
+        self.call(|node| {
+            let id = node.node_id().to_string();
+            let status = node.status();
+            let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+            let channels = node.list_channels();
+            let uris = node
+                .announcement_addresses()
+                .or_else(|| node.listening_addresses())
+                .unwrap_or_default()
+                .iter()
+                .map(|addr| format!("{}@{}", id, addr))
+                .collect();
+
+            Ok(NodeInfo {
+                alias: node
+                    .node_alias()
+                    .map(|a| a.to_string())
+                    .unwrap_or_else(|| "LDK".to_string()),
+                identity_pubkey: PublicKey::from_slice(&node.node_id().serialize()).ok(),
+                implementation: "ldk".into(),
+                version: None,
+                network: Some(normalize_network(&node.config().network.to_string())),
+                block_height: Some(status.current_best_block.height),
+                block_hash: Some(status.current_best_block.block_hash.to_string()),
+                synced_to_chain: Some(
+                    status
+                        .latest_lightning_wallet_sync_timestamp
+                        .is_some_and(|at| now.saturating_sub(at) < SYNC_STALE_AFTER.as_secs()),
+                ),
+                synced_to_graph: None,
+                num_active_channels: Some(channels.iter().filter(|c| c.is_usable).count() as u32),
+                num_pending_channels: Some(
+                    channels.iter().filter(|c| !c.is_channel_ready).count() as u32
+                ),
+                num_inactive_channels: Some(
+                    channels
+                        .iter()
+                        .filter(|c| c.is_channel_ready && !c.is_usable)
+                        .count() as u32,
+                ),
+                num_peers: Some(node.list_peers().iter().filter(|p| p.is_connected).count() as u32),
+                uris,
+                color: None,
+            })
+        })
+        .await
+    }
+
+    async fn create_invoice(
+        &self,
+        amount: Msat,
+        _label: Option<&str>,
+        desc: Option<&str>,
+    ) -> Result<Bolt11> {
+        let desc = desc.unwrap_or("rust").to_string();
+        let invoices = Arc::clone(&self.invoices);
+        self.call(move |node| {
+            let desc = Description::new(desc).map_err(|e| anyhow!("Invalid description: {}", e))?;
+            let invoice = node.bolt11_payment().receive(
+                amount.as_msat(),
+                &Bolt11InvoiceDescription::Direct(desc),
+                INVOICE_EXPIRY_SECS,
+            )?;
+            let bolt11 = invoice.to_string().parse()?;
+            invoices.record(&bolt11)?;
+            Ok(bolt11)
+        })
+        .await
+    }
+
+    async fn create_invoice_hashed(
+        &self,
+        amount: Msat,
+        _label: Option<&str>,
+        description: &str,
+    ) -> Result<Bolt11> {
+        let hash = sha256::Hash::from_byte_array(description_hash(description));
+        let invoices = Arc::clone(&self.invoices);
+        self.call(move |node| {
+            let invoice = node.bolt11_payment().receive(
+                amount.as_msat(),
+                &Bolt11InvoiceDescription::Hash(Sha256(hash)),
+                INVOICE_EXPIRY_SECS,
+            )?;
+            let bolt11 = invoice.to_string().parse()?;
+            invoices.record(&bolt11)?;
+            Ok(bolt11)
+        })
+        .await
+    }
+
+    async fn get_balance(&self) -> Result<Balance> {
+        self.call(|node| {
+            let balances = node.list_balances();
+            Ok(Balance {
+                onchain_sat: Sat::from_sat(balances.spendable_onchain_balance_sats),
+                channel_msat: Sat::from_sat(balances.total_lightning_balance_sats).to_msat()?,
+            })
+        })
+        .await
+    }
+
+    async fn list_invoices(&self, limit: Option<usize>) -> Result<Vec<Invoice>> {
+        let invoices = Arc::clone(&self.invoices);
+        self.call(move |node| {
+            let mut payments = node.list_payments_with_filter(|p| {
+                p.direction == PaymentDirection::Inbound
+                    && matches!(p.kind, PaymentKind::Bolt11 { .. })
+            });
+            payments.sort_by_key(|p| std::cmp::Reverse(p.latest_update_timestamp));
+
+            let invoices = payments
+                .into_iter()
+                .take(limit.unwrap_or(10))
+                .filter_map(|p| invoice_of(p, &invoices))
+                .collect();
+            Ok(invoices)
+        })
+        .await
+    }
+
+    async fn lookup_invoice(&self, hash: &PaymentHash) -> Result<Option<Invoice>> {
+        let invoices = Arc::clone(&self.invoices);
+        // Inbound BOLT11 payments are stored under their payment hash too.
+        let id = PaymentId(*hash.as_bytes());
+        self.call(move |node| {
+            Ok(node
+                .payment(&id)
+                .filter(|p| p.direction == PaymentDirection::Inbound)
+                .and_then(|p| invoice_of(p, &invoices)))
+        })
+        .await
+    }
+
+    async fn decode_invoice(&self, bolt11: &Bolt11) -> Result<DecodedInvoice> {
+        let invoice: Bolt11Invoice = bolt11
+            .as_str()
+            .parse()
+            .map_err(|e| anyhow!("Invalid bolt11: {}", e))?;
+
+        let desc = direct_description(&invoice);
+        let payee = invoice
+            .payee_pub_key()
+            .copied()
+            .unwrap_or_else(|| invoice.recover_payee_pub_key());
+
+        Ok(DecodedInvoice {
+            amount_msat: invoice.amount_milli_satoshis().map(Msat::from_msat),
+            desc,
+            payee: Some(PublicKey::from_slice(&payee.serialize())?),
+        })
+    }
+
+    async fn pay_invoice(&self, bolt11: &Bolt11) -> Result<PaymentResult> {
+        let invoice: Bolt11Invoice = bolt11
+            .as_str()
+            .parse()
+            .map_err(|e| anyhow!("Invalid bolt11: {}", e))?;
+
+        let hash = bolt11.payment_hash();
+        self.call(move |node| {
+            let id = match node.bolt11_payment().send(&invoice, None) {
+                Ok(id) => id,
+                // Paid or being paid already; `lookup_payment` tells which.
+                Err(NodeError::DuplicatePayment) => {
+                    return Err(PaymentPending::error(hash, "already sent before"))
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            // `send` only dispatches the HTLCs; wait for the outcome.
+            let started = Instant::now();
+            loop {
+                let payment = node
+                    .payment(&id)
+                    .ok_or_else(|| anyhow!("Payment vanished from the store"))?;
+                match payment.status {
+                    PaymentStatus::Succeeded => {
+                        let preimage = match payment.kind {
+                            PaymentKind::Bolt11 {
+                                preimage: Some(p), ..
+                            } => Some(Preimage::from_slice(&p.0)?),
+                            _ => None,
+                        };
+                        return Ok(PaymentResult {
+                            hash: PaymentHash::from_slice(invoice.payment_hash().as_ref())?,
+                            amount_msat: payment.amount_msat.map(Msat::from_msat),
+                            fee_msat: payment.fee_paid_msat.map(Msat::from_msat),
+                            preimage,
+                        });
+                    }
+                    PaymentStatus::Failed => return Err(anyhow!("Payment failed")),
+                    PaymentStatus::Pending if started.elapsed() > PAYMENT_TIMEOUT => {
+                        return Err(PaymentPending::error(
+                            hash,
+                            format!("still in flight after {:?}", PAYMENT_TIMEOUT),
+                        ))
+                    }
+                    PaymentStatus::Pending => std::thread::sleep(PAYMENT_POLL_INTERVAL),
+                }
+            }
+        })
+        .await
+    }
+
+    async fn lookup_payment(&self, hash: &PaymentHash) -> Result<PaymentState> {
+        // BOLT11 payments are stored under their payment hash.
+        let id = PaymentId(*hash.as_bytes());
+        let hash = *hash;
+        self.call(move |node| {
+            let Some(payment) = node.payment(&id) else {
+                return Ok(PaymentState::Unknown);
+            };
+            if payment.direction != PaymentDirection::Outbound {
+                return Ok(PaymentState::Unknown);
+            }
+            Ok(match payment.status {
+                PaymentStatus::Succeeded => {
+                    let preimage = match payment.kind {
+                        PaymentKind::Bolt11 {
+                            preimage: Some(p), ..
+                        } => Some(Preimage::from_slice(&p.0)?),
+                        _ => None,
+                    };
+                    PaymentState::Succeeded(PaymentResult {
+                        hash,
+                        amount_msat: payment.amount_msat.map(Msat::from_msat),
+                        fee_msat: payment.fee_paid_msat.map(Msat::from_msat),
+                        preimage,
+                    })
+                }
+                PaymentStatus::Failed => PaymentState::Failed,
+                PaymentStatus::Pending => PaymentState::Pending,
+            })
+        })
+        .await
+    }
+
+    async fn sign_message(&self, message: &str) -> Result<String> {
+        let message = message.to_string();
+        self.call(move |node| Ok(node.sign_message(message.as_bytes())))
+            .await
+    }
+
+    async fn verify_message(&self, message: &str, signature: &str) -> Result<SignatureCheck> {
+        let Ok(key) = message_signing::recover_pk(message.as_bytes(), signature) else {
+            return Ok(SignatureCheck {
+                valid: false,
+                pubkey: None,
+            });
+        };
+        self.call(move |node| {
+            // Match LND and CLN: only keys of nodes we know count as valid.
+            let known = key == node.node_id()
+                || node
+                    .network_graph()
+                    .node(&NodeId::from_pubkey(&key))
+                    .is_some();
+            Ok(SignatureCheck {
+                valid: known,
+                pubkey: Some(PublicKey::from_slice(&key.serialize())?),
+            })
+        })
+        .await
+    }
+
+    async fn update_channel_policy(
+        &self,
+        channel: &ChannelSelector,
+        policy: &ChannelPolicy,
+    ) -> Result<()> {
+        if policy.min_htlc_msat.is_some() || policy.max_htlc_msat.is_some() {
+            return Err(anyhow!(
+                "LDK Node cannot change HTLC limits of open channels"
+            ));
+        }
+        let forwarding_fee_base_msat = u32::try_from(policy.base_fee_msat.as_msat())
+            .map_err(|_| anyhow!("LDK Node base fee is limited to {} msat", u32::MAX))?;
+        let cltv_expiry_delta = policy.time_lock_delta.map(u16::try_from).transpose()?;
+        let channel = channel.clone();
+        let fee_ppm = policy.fee_ppm;
+
+        self.call(move |node| {
+            let channels: Vec<_> = node
+                .list_channels()
+                .into_iter()
+                .filter(|c| match &channel {
+                    ChannelSelector::All => true,
+                    ChannelSelector::Short(scid) => c.short_channel_id == Some(scid.as_u64()),
+                    ChannelSelector::Outpoint { txid, vout } => c
+                        .funding_txo
+                        .is_some_and(|o| o.txid.to_string() == *txid && o.vout == *vout),
+                })
+                .collect();
+            if channels.is_empty() && channel != ChannelSelector::All {
+                return Err(anyhow!("No channel {}", channel));
+            }
+            for c in channels {
+                let config = ChannelConfig {
+                    forwarding_fee_base_msat,
+                    forwarding_fee_proportional_millionths: fee_ppm,
+                    cltv_expiry_delta: cltv_expiry_delta.unwrap_or(c.config.cltv_expiry_delta),
+                    ..c.config
+                };
+                node.update_channel_config(&c.user_channel_id, c.counterparty_node_id, config)?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn list_forwards(&self, _query: &ForwardQuery) -> Result<ForwardPage> {
+        Err(anyhow!("LDK Node keeps no forwarding history"))
+    }
+
+    async fn query_routes(&self, _dest: &PublicKey, _amount: Msat) -> Result<Route> {
+        Err(anyhow!("LDK Node does not expose its pathfinding"))
+    }
+
+    async fn get_node(&self, pubkey: &PublicKey) -> Result<GraphNode> {
+        let pubkey = *pubkey;
+        self.call(move |node| {
+            let id = NodeId::from_slice(pubkey.as_bytes())
+                .map_err(|_| anyhow!("Invalid node id {}", pubkey))?;
+            let info = node
+                .network_graph()
+                .node(&id)
+                .ok_or_else(|| anyhow!("Node {} is not in the graph", pubkey))?;
+            let announced = info.announcement_info.as_ref();
+            Ok(GraphNode {
+                pubkey,
+                alias: announced.map(|a| a.alias().to_string()),
+                color: announced.map(|a| hex::encode(a.rgb())),
+                addresses: announced
+                    .map(|a| a.addresses().iter().map(ToString::to_string).collect())
+                    .unwrap_or_default(),
+                last_update: announced.map(|a| u64::from(a.last_update())),
+            })
+        })
+        .await
+    }
+
+    async fn get_channel(&self, scid: ShortChannelId) -> Result<GraphChannel> {
+        self.call(move |node| {
+            let info = node
+                .network_graph()
+                .channel(scid.as_u64())
+                .ok_or_else(|| anyhow!("Channel {} is not in the graph", scid))?;
+            let node1 = PublicKey::from_slice(info.node_one.as_slice())?;
+            let node2 = PublicKey::from_slice(info.node_two.as_slice())?;
+            let policies = [(node1, &info.one_to_two), (node2, &info.two_to_one)]
+                .into_iter()
+                .filter_map(|(source, update)| {
+                    let u = update.as_ref()?;
+                    Some(EdgePolicy {
+                        source,
+                        base_fee_msat: Msat::from_msat(u64::from(u.fees.base_msat)),
+                        fee_ppm: u.fees.proportional_millionths,
+                        time_lock_delta: u32::from(u.cltv_expiry_delta),
+                        min_htlc_msat: Msat::from_msat(u.htlc_minimum_msat),
+                        max_htlc_msat: Some(Msat::from_msat(u.htlc_maximum_msat)),
+                        disabled: !u.enabled,
+                        last_update: Some(u64::from(u.last_update)),
+                    })
+                })
+                .collect();
+
+            Ok(GraphChannel {
+                scid,
+                node1,
+                node2,
+                capacity_sat: info.capacity_sats.map(Sat::from_sat),
+                policies,
+            })
+        })
+        .await
+    }
+
+    async fn probe_payment(&self, _bolt11: &Bolt11) -> Result<ProbeResult> {
+        // `send_probes` only dispatches them; the outcome is never reported.
+        Err(anyhow!("LDK Node does not report probe results"))
+    }
+
+    async fn export_channel_backup(&self) -> Result<ChannelBackup> {
+        Err(anyhow!(
+            "LDK Node has no static channel backups; back up its storage directory"
+        ))
+    }
+
+    async fn verify_channel_backup(&self, _data: &[u8]) -> Result<BackupCheck> {
+        Err(anyhow!("LDK Node has no static channel backups"))
+    }
+
+    async fn open_channel_psbt(&self, _open: &PsbtChannelOpen) -> Result<PsbtFunding> {
+        Err(anyhow!("LDK Node only funds channels from its own wallet"))
+    }
+
+    async fn update_channel_psbt(&self, _id: &str, _psbt: &Psbt) -> Result<Psbt> {
+        Err(anyhow!("LDK Node only funds channels from its own wallet"))
+    }
+
+    async fn finalize_channel_psbt(&self, _id: &str, _signed: &Psbt) -> Result<PsbtChannelOpened> {
+        Err(anyhow!("LDK Node only funds channels from its own wallet"))
+    }
+
+    async fn cancel_channel_psbt(&self, _id: &str) -> Result<()> {
+        Err(anyhow!("LDK Node only funds channels from its own wallet"))
+    }
+
+    async fn fund_psbt(&self, _request: &FundPsbtRequest) -> Result<FundedPsbt> {
+        Err(anyhow!("LDK Node has no PSBT interface"))
+    }
+
+    async fn sign_psbt(&self, _psbt: &Psbt) -> Result<Psbt> {
+        Err(anyhow!("LDK Node has no PSBT interface"))
+    }
+
+    async fn finalize_psbt(&self, _psbt: &Psbt) -> Result<BroadcastTx> {
+        Err(anyhow!("LDK Node has no PSBT interface"))
+    }
+
+    async fn estimate_fee(&self, _target_conf: u32) -> Result<FeeEstimate> {
+        Err(anyhow!("LDK Node has no fee estimation API"))
+    }
+
+    async fn estimate_onchain_send(
+        &self,
+        _address: &str,
+        _amount: Sat,
+        _target_conf: u32,
+    ) -> Result<OnchainSendEstimate> {
+        Err(anyhow!("LDK Node has no fee estimation API"))
+    }
+
+    async fn capabilities(&self) -> Result<Capabilities> {
+        let network = self.call(|node| Ok(node.config().network)).await?;
+        Ok(Capabilities {
+            backend: "ldk".into(),
+            // The embedded node has no version to report beyond this crate's.
+            version: None,
+            network: Some(normalize_network(&network.to_string())),
+            supported: [
+                Capability::CreateInvoice,
+                Capability::DecodeInvoice,
+                Capability::PayInvoice,
+                Capability::ListInvoices,
+                Capability::SignMessage,
+                Capability::ChannelPolicy,
+                Capability::NetworkGraph,
+            ]
+            .into(),
+        })
+    }
+}
+
+/// An inbound BOLT11 payment as an invoice, with its text from the log.
+fn invoice_of(p: PaymentDetails, invoices: &InvoiceLog) -> Option<Invoice> {
+    let PaymentKind::Bolt11 { hash, .. } = p.kind else {
+        return None;
+    };
+    let hash = PaymentHash::from_slice(&hash.0).ok()?;
+    // Invoices made before the log existed, or by another client of the
+    // same node, have no text.
+    let bolt11 = invoices.get(&hash);
+    let desc = bolt11
+        .as_ref()
+        .and_then(|b| b.as_str().parse::<Bolt11Invoice>().ok())
+        .and_then(|invoice| direct_description(&invoice));
+    Some(Invoice {
+        hash,
+        amount_msat: Msat::from_msat(p.amount_msat.unwrap_or(0)),
+        state: match p.status {
+            PaymentStatus::Pending => "unpaid",
+            PaymentStatus::Succeeded => "paid",
+            PaymentStatus::Failed => "failed",
+        }
+        .to_string(),
+        bolt11,
+        desc,
+    })
+}
+
+/// The invoice's description, unless it only commits to one by hash.
+fn direct_description(invoice: &Bolt11Invoice) -> Option<String> {
+    match invoice.description() {
+        ldk_node::lightning_invoice::Bolt11InvoiceDescriptionRef::Direct(d) => Some(d.to_string()),
+        ldk_node::lightning_invoice::Bolt11InvoiceDescriptionRef::Hash(_) => None,
+    }
+}
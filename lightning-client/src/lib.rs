@@ -1,66 +1,555 @@
 // lightning-client/src/lib.rs
 pub mod cln;
+#[cfg(feature = "cln")]
+mod commando;
 pub mod config;
 pub mod factory;
+#[cfg(feature = "ldk")]
+pub mod ldk;
+pub mod lnd_auth;
 pub mod lnd_grpc;
 pub mod lnd_rest;
+pub mod lnurl;
+pub mod network;
+#[cfg(feature = "nwc")]
+pub mod nostr_relay;
+#[cfg(feature = "nwc")]
+pub mod nwc;
+pub mod pool;
+mod psbt;
+pub mod rates;
+pub mod registry;
+pub mod retry;
+pub mod types;
+pub mod uri;
 
 use anyhow::Result;
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
-use std::sync::{Arc, Mutex};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Fields a backend doesn't report are `None`.
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct NodeInfo {
     pub alias: String,
-    pub identity_pubkey: String,
+    /// Missing when the backend only exposes a wallet, not the node.
+    pub identity_pubkey: Option<PublicKey>,
+    /// `lnd`, `cln`, `ldk` or `nwc`.
+    pub implementation: String,
+    pub version: Option<String>,
+    /// `mainnet`, `testnet`, `signet` or `regtest`.
+    pub network: Option<String>,
+    pub block_height: Option<u32>,
+    pub block_hash: Option<String>,
+    pub synced_to_chain: Option<bool>,
+    pub synced_to_graph: Option<bool>,
+    pub num_active_channels: Option<u32>,
+    pub num_pending_channels: Option<u32>,
+    pub num_inactive_channels: Option<u32>,
+    pub num_peers: Option<u32>,
+    /// `pubkey@host:port` addresses the node is reachable at.
+    #[serde(default)]
+    pub uris: Vec<String>,
+    pub color: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Balance {
-    pub onchain_sat: u64,
-    pub channel_msat: u64,
+    pub onchain_sat: Sat,
+    pub channel_msat: Msat,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Invoice {
-    pub hash: String,
-    pub amount_msat: u64,
+    pub hash: PaymentHash,
+    pub amount_msat: Msat,
     pub state: String,
-    pub bolt11: Option<String>,
+    pub bolt11: Option<Bolt11>,
     pub desc: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DecodedInvoice {
-    pub amount_msat: Option<u64>,
+    pub amount_msat: Option<Msat>,
     pub desc: Option<String>,
-    pub payee: Option<String>,
+    pub payee: Option<PublicKey>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PaymentResult {
-    pub hash: String,
-    pub amount_msat: u64,
-    pub fee_msat: Option<u64>,
+    pub hash: PaymentHash,
+    /// `None` when the backend doesn't say, e.g. an NWC wallet paying an
+    /// invoice without an amount.
+    pub amount_msat: Option<Msat>,
+    pub fee_msat: Option<Msat>,
+    pub preimage: Option<Preimage>,
+}
+
+/// What a node knows about an outgoing payment, from `lookup_payment`.
+#[derive(Debug)]
+pub enum PaymentState {
+    /// HTLCs are still in flight.
+    Pending,
+    Succeeded(PaymentResult),
+    Failed,
+    /// The node has no payment with this hash.
+    Unknown,
+}
+
+/// `pay_invoice` stopped waiting before the payment settled or failed: the
+/// request timed out, the connection dropped after it was sent, or the
+/// node reports it in flight. The payment may still succeed, so it must not
+/// be treated as failed; `lookup_payment` tells how it ended.
+#[derive(Debug)]
+pub struct PaymentPending {
+    pub hash: Option<PaymentHash>,
+    pub reason: String,
+}
+
+impl fmt::Display for PaymentPending {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.hash {
+            Some(hash) => write!(f, "Payment {} is pending: {}", hash, self.reason),
+            None => write!(f, "Payment is pending: {}", self.reason),
+        }
+    }
+}
+
+impl std::error::Error for PaymentPending {}
+
+impl PaymentPending {
+    pub fn error(hash: Option<PaymentHash>, reason: impl fmt::Display) -> anyhow::Error {
+        Self {
+            hash,
+            reason: reason.to_string(),
+        }
+        .into()
+    }
+
+    /// Whether `err` leaves the payment's outcome open.
+    pub fn is(err: &anyhow::Error) -> bool {
+        err.chain().any(|cause| cause.is::<PaymentPending>())
+    }
+}
+
+/// Polls `lookup_payment` until a payment `pay_invoice` left pending
+/// settles (`Some`) or fails (`None`). A hash the node still has no record
+/// of after two minutes counts as failed: the request never reached it.
+pub async fn await_payment(
+    node: &dyn LightningClient,
+    hash: &PaymentHash,
+) -> Result<Option<PaymentResult>> {
+    const UNKNOWN_GRACE: Duration = Duration::from_secs(120);
+    const MAX_LOOKUP_ERRORS: u32 = 10;
+    let started = Instant::now();
+    let mut delay = Duration::from_secs(1);
+    let mut errors = 0;
+    loop {
+        match node.lookup_payment(hash).await {
+            Ok(PaymentState::Succeeded(payment)) => return Ok(Some(payment)),
+            Ok(PaymentState::Failed) => return Ok(None),
+            Ok(PaymentState::Unknown) if started.elapsed() >= UNKNOWN_GRACE => return Ok(None),
+            Ok(_) => errors = 0,
+            Err(e) => {
+                errors += 1;
+                if errors >= MAX_LOOKUP_ERRORS {
+                    return Err(e.context(format!("Cannot look up payment {}", hash)));
+                }
+            }
+        }
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(Duration::from_secs(60));
+    }
+}
+
+/// SHA-256 of an invoice description, for the `h` field.
+pub fn description_hash(description: &str) -> [u8; 32] {
+    <sha2::Sha256 as sha2::Digest>::digest(description.as_bytes()).into()
+}
+
+/// Routing policy for forwards over a channel.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelPolicy {
+    pub base_fee_msat: Msat,
+    pub fee_ppm: u32,
+    /// Required by LND. CLN only sets it node-wide (`cltv-delta`), so it
+    /// must be left out there.
+    #[serde(default)]
+    pub time_lock_delta: Option<u32>,
+    #[serde(default)]
+    pub min_htlc_msat: Option<Msat>,
+    #[serde(default)]
+    pub max_htlc_msat: Option<Msat>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ForwardQuery {
+    /// Unix seconds, inclusive.
+    #[serde(default)]
+    pub start: Option<u64>,
+    /// Unix seconds, exclusive.
+    #[serde(default)]
+    pub end: Option<u64>,
+    /// `next_offset` of the previous page; 0 for the first.
+    #[serde(default)]
+    pub offset: u64,
+    /// Defaults to 100.
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// A settled forward.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Forward {
+    /// Unix seconds.
+    pub timestamp: u64,
+    pub chan_in: Option<ShortChannelId>,
+    pub chan_out: Option<ShortChannelId>,
+    pub amount_in_msat: Msat,
+    pub amount_out_msat: Msat,
+    pub fee_msat: Msat,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ForwardPage {
+    /// Oldest first.
+    pub forwards: Vec<Forward>,
+    /// Pass as `offset` to get the next page; `None` on the last one. A page
+    /// may be short when a time range is set, so follow this rather than
+    /// stopping on a short page.
+    pub next_offset: Option<u64>,
+}
+
+/// A path through the graph to a destination, as the backend's pathfinding
+/// would use it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Route {
+    /// Amount delivered to the destination.
+    pub amount_msat: Msat,
+    /// Total routing fees on top of `amount_msat`.
+    pub fee_msat: Msat,
+    /// Blocks until the first hop's HTLC times out, i.e. for how long the
+    /// funds may be locked up.
+    pub total_delay: u32,
+    pub hops: Vec<RouteHop>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteHop {
+    /// Node the HTLC is forwarded to.
+    pub pubkey: PublicKey,
+    pub channel: ShortChannelId,
+    /// Amount forwarded to `pubkey`.
+    pub amount_msat: Msat,
+    /// Blocks until the HTLC to `pubkey` times out.
+    pub delay: u32,
+}
+
+/// A node from the public graph.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GraphNode {
+    pub pubkey: PublicKey,
+    /// Missing until the node has announced itself.
+    pub alias: Option<String>,
+    pub color: Option<String>,
+    /// `host:port` addresses from its announcement.
+    #[serde(default)]
+    pub addresses: Vec<String>,
+    /// Unix seconds of the latest announcement.
+    pub last_update: Option<u64>,
+}
+
+/// A public channel and the policy each side set for forwarding over it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GraphChannel {
+    pub scid: ShortChannelId,
+    pub node1: PublicKey,
+    pub node2: PublicKey,
+    pub capacity_sat: Option<Sat>,
+    /// One per direction that has been announced.
+    pub policies: Vec<EdgePolicy>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EdgePolicy {
+    /// Node that forwards in this direction and set the policy.
+    pub source: PublicKey,
+    pub base_fee_msat: Msat,
+    pub fee_ppm: u32,
+    pub time_lock_delta: u32,
+    pub min_htlc_msat: Msat,
+    pub max_htlc_msat: Option<Msat>,
+    pub disabled: bool,
+    /// Unix seconds.
+    pub last_update: Option<u64>,
+}
+
+/// Outcome of a probe: an HTLC sent along a route with a hash nobody can
+/// settle.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProbeResult {
+    /// The destination got the HTLC and only rejected it for the unknown
+    /// hash, so a real payment over `route` should go through.
+    pub reachable: bool,
+    /// Missing when no route was found at all.
+    pub route: Option<Route>,
+    /// Why the probe didn't make it, when it didn't.
+    pub failure: Option<String>,
 }
 
+/// Static channel backup: what peers need to force-close our channels and
+/// return the funds after the node's state is lost. It does not restore the
+/// node itself.
+#[derive(Debug, Clone)]
+pub struct ChannelBackup {
+    /// In the backend's own format, to be handed back to the same kind of
+    /// node: LND's multi-channel backup (already encrypted with the node's
+    /// seed), or CLN's `staticbackup` entries as a JSON array for
+    /// `recoverchannel`.
+    pub data: Vec<u8>,
+    /// Channels covered: funding outpoints for LND, channel ids for CLN.
+    pub channels: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupCheck {
+    pub valid: bool,
+    /// Channels the backup covers, when it is valid.
+    #[serde(default)]
+    pub channels: Vec<String>,
+    /// Why the backup was rejected.
+    pub error: Option<String>,
+}
+
+/// Opening a channel funded from an outside wallet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PsbtChannelOpen {
+    pub peer: PublicKey,
+    pub amount_sat: Sat,
+    #[serde(default)]
+    pub private: bool,
+    /// Inputs and change from the outside wallet, without the funding
+    /// output. CLN needs them up front; LND can take them later.
+    pub psbt: Option<Psbt>,
+}
+
+/// A channel open waiting for its funding transaction.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PsbtFunding {
+    /// Names the open in later steps: LND's pending channel id, CLN's
+    /// channel id.
+    pub id: String,
+    /// Where the funding output pays to, when the backend says (LND).
+    pub funding_address: Option<String>,
+    pub amount_sat: Sat,
+    /// The given PSBT, or an empty one, plus the funding output.
+    pub psbt: Psbt,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PsbtChannelOpened {
+    pub txid: String,
+    /// Funding outpoint for LND, channel id for CLN.
+    pub channel: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PsbtOutput {
+    pub address: String,
+    pub amount_sat: Sat,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FundPsbtRequest {
+    pub outputs: Vec<PsbtOutput>,
+    /// The backend's own estimate when unset.
+    pub sat_per_vbyte: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FundedPsbt {
+    pub psbt: Psbt,
+    /// Index of the change output, when LND added one.
+    pub change_index: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BroadcastTx {
+    pub txid: String,
+    /// The final transaction, hex encoded.
+    pub raw_tx: String,
+}
+
+/// Fee rate the node expects to confirm within `target_conf` blocks.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeeEstimate {
+    pub target_conf: u32,
+    /// Can be fractional; the minimum relay fee is about 1.01.
+    pub sat_per_vbyte: f64,
+}
+
+/// What an on-chain send from the node's wallet would cost now.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OnchainSendEstimate {
+    pub fee_sat: Sat,
+    pub sat_per_vbyte: f64,
+}
+
+/// Outcome of checking a signed message. LND and CLN only call a signature
+/// valid when the recovered key belongs to a node in their graph.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignatureCheck {
+    pub valid: bool,
+    /// Key recovered from the signature.
+    pub pubkey: Option<PublicKey>,
+}
+
+/// An operation or node feature a backend may or may not support.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    CreateInvoice,
+    DecodeInvoice,
+    PayInvoice,
+    ListInvoices,
+    SignMessage,
+    ChannelPolicy,
+    ForwardingHistory,
+    QueryRoutes,
+    NetworkGraph,
+    ProbePayment,
+    ChannelBackup,
+    Psbt,
+    FeeEstimates,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// `lnd`, `cln`, `ldk`, `nwc` or `pool`.
+    pub backend: String,
+    pub version: Option<String>,
+    /// `mainnet`, `testnet`, `signet` or `regtest`.
+    pub network: Option<String>,
+    pub supported: BTreeSet<Capability>,
+}
+
+impl Capabilities {
+    pub fn supports(&self, capability: Capability) -> bool {
+        self.supported.contains(&capability)
+    }
+}
+
+/// Maps the chain names backends report (`bitcoin`, `main`, ...) onto
+/// `mainnet`, `testnet`, `signet` and `regtest`.
+pub(crate) fn normalize_network(network: &str) -> String {
+    match network.trim().to_ascii_lowercase().as_str() {
+        "bitcoin" | "main" | "mainnet" => "mainnet".to_string(),
+        "testnet" | "testnet3" | "test" => "testnet".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// sat/kw (per 1000 weight units) as sat/vbyte.
+pub(crate) fn kw_to_vbyte(sat_per_kw: u64) -> f64 {
+    sat_per_kw as f64 * 4.0 / 1000.0
+}
+
+/// Backends are shared across request handlers, so every call takes `&self`
+/// and must be safe to run concurrently.
 #[async_trait]
-pub trait LightningClient {
-    async fn get_info(&mut self) -> Result<NodeInfo>;
+pub trait LightningClient: Send + Sync {
+    async fn get_info(&self) -> Result<NodeInfo>;
     async fn create_invoice(
-        &mut self,
-        msat: u64,
+        &self,
+        amount: Msat,
         label: Option<&str>,
         desc: Option<&str>,
-    ) -> Result<String>;
-    async fn get_balance(&mut self) -> Result<Balance>;
-    async fn list_invoices(&mut self, limit: Option<usize>) -> Result<Vec<Invoice>>;
-    async fn decode_invoice(&mut self, bolt11: &str) -> Result<DecodedInvoice>;
-    async fn pay_invoice(&mut self, bolt11: &str) -> Result<PaymentResult>;
+    ) -> Result<Bolt11>;
+    /// Like `create_invoice`, but the invoice commits to SHA-256 of
+    /// `description` (the `h` field) instead of carrying it, as LNURL-pay
+    /// and zaps require.
+    async fn create_invoice_hashed(
+        &self,
+        amount: Msat,
+        label: Option<&str>,
+        description: &str,
+    ) -> Result<Bolt11>;
+    async fn get_balance(&self) -> Result<Balance>;
+    async fn list_invoices(&self, limit: Option<usize>) -> Result<Vec<Invoice>>;
+    /// The invoice this node made with `hash`; `None` when it has none.
+    async fn lookup_invoice(&self, hash: &PaymentHash) -> Result<Option<Invoice>>;
+    async fn decode_invoice(&self, bolt11: &Bolt11) -> Result<DecodedInvoice>;
+    /// Pays `bolt11` and waits for the outcome. Fails with `PaymentPending`
+    /// when the outcome is not known yet; any other error means nothing
+    /// was sent.
+    async fn pay_invoice(&self, bolt11: &Bolt11) -> Result<PaymentResult>;
+    /// Where the outgoing payment with `hash` stands.
+    async fn lookup_payment(&self, hash: &PaymentHash) -> Result<PaymentState>;
+    /// Signs `message` with the node key; zbase32, as `lncli signmessage`.
+    async fn sign_message(&self, message: &str) -> Result<String>;
+    async fn verify_message(&self, message: &str, signature: &str) -> Result<SignatureCheck>;
+    async fn update_channel_policy(
+        &self,
+        channel: &ChannelSelector,
+        policy: &ChannelPolicy,
+    ) -> Result<()>;
+    async fn list_forwards(&self, query: &ForwardQuery) -> Result<ForwardPage>;
+    /// Finds a route for `amount` to `dest` without sending anything.
+    async fn query_routes(&self, dest: &PublicKey, amount: Msat) -> Result<Route>;
+    async fn get_node(&self, pubkey: &PublicKey) -> Result<GraphNode>;
+    async fn get_channel(&self, scid: ShortChannelId) -> Result<GraphChannel>;
+    /// Sends the invoice's amount to its payee with a random payment hash.
+    /// The HTLC always fails, so no funds move; how it fails tells whether
+    /// the payment would have arrived.
+    async fn probe_payment(&self, bolt11: &Bolt11) -> Result<ProbeResult>;
+    async fn export_channel_backup(&self) -> Result<ChannelBackup>;
+    /// Checks a blob from `export_channel_backup` of this kind of backend.
+    /// An unusable backup is `valid: false`, not an error.
+    async fn verify_channel_backup(&self, data: &[u8]) -> Result<BackupCheck>;
+    /// Starts a channel open that an outside wallet funds. Continue with
+    /// `update_channel_psbt` and `finalize_channel_psbt`, or give up with
+    /// `cancel_channel_psbt`.
+    async fn open_channel_psbt(&self, open: &PsbtChannelOpen) -> Result<PsbtFunding>;
+    /// Hands the funded, unsigned PSBT to the node. Returns the PSBT to
+    /// sign, which the peer may have added to.
+    async fn update_channel_psbt(&self, id: &str, psbt: &Psbt) -> Result<Psbt>;
+    /// Completes the open with the signed PSBT and publishes the funding
+    /// transaction.
+    async fn finalize_channel_psbt(&self, id: &str, signed: &Psbt) -> Result<PsbtChannelOpened>;
+    async fn cancel_channel_psbt(&self, id: &str) -> Result<()>;
+    /// Builds an unsigned PSBT paying `outputs` from the node's wallet. Its
+    /// inputs stay reserved until the transaction is broadcast or the
+    /// reservation runs out.
+    async fn fund_psbt(&self, request: &FundPsbtRequest) -> Result<FundedPsbt>;
+    /// Signs the inputs that belong to the node's wallet.
+    async fn sign_psbt(&self, psbt: &Psbt) -> Result<Psbt>;
+    /// Extracts the fully signed transaction and broadcasts it.
+    async fn finalize_psbt(&self, psbt: &Psbt) -> Result<BroadcastTx>;
+    async fn estimate_fee(&self, target_conf: u32) -> Result<FeeEstimate>;
+    /// Fee for sending `amount` to `address` with the wallet's own coin
+    /// selection, including change. Nothing is reserved or sent.
+    async fn estimate_onchain_send(
+        &self,
+        address: &str,
+        amount: Sat,
+        target_conf: u32,
+    ) -> Result<OnchainSendEstimate>;
+    /// What this backend can do, so callers can hide unsupported actions
+    /// instead of running into errors.
+    async fn capabilities(&self) -> Result<Capabilities>;
 }
 
-pub type LightningClientDyn = Arc<Mutex<Box<dyn LightningClient + Send + Sync>>>;
+pub type LightningClientDyn = Arc<dyn LightningClient>;
 
-pub use config::Settings;
-pub use factory::connect_from_config;
+pub use config::{NodeSettings, Settings};
+pub use factory::{connect_from_config, connect_node, connect_uri, connect_with_settings};
+pub use network::NetworkMismatch;
+pub use pool::LightningPool;
+pub use rates::{RateProvider, RateProviderDyn};
+pub use registry::NodeRegistry;
+pub use types::{
+    Bolt11, ChannelSelector, Msat, PaymentHash, Preimage, Psbt, PublicKey, Sat, ShortChannelId,
+};
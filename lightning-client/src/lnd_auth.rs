@@ -0,0 +1,197 @@
+// lightning-client/src/lnd_auth.rs
+use crate::config::LndConfig;
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose, Engine as _};
+use reqwest::Url;
+use std::fs;
+use std::path::Path;
+
+/// LND credentials resolved from config, shared by the gRPC and REST transports.
+#[derive(Clone)]
+pub struct LndCredentials {
+    /// `host:port`, without scheme.
+    pub host: String,
+    pub macaroon_hex: String,
+    /// PEM certificate to pin. Without one the system roots are trusted.
+    pub cert_pem: Option<Vec<u8>>,
+    /// Name the certificate must be valid for, when it isn't `host`.
+    pub tls_server_name: Option<String>,
+}
+
+impl LndCredentials {
+    pub fn from_config(cfg: &LndConfig) -> Result<Self> {
+        let uri = cfg
+            .lndconnect
+            .as_deref()
+            .map(parse_lndconnect)
+            .transpose()?;
+
+        let host = match cfg.host.trim() {
+            "" => uri
+                .as_ref()
+                .map(|u| u.host.clone())
+                .ok_or_else(|| anyhow!("LND host is required"))?,
+            host => strip_scheme(host).to_string(),
+        };
+
+        let macaroon = if let Some(value) = &cfg.macaroon {
+            decode_blob(value, "macaroon")?
+        } else if !cfg.macaroon_hex.trim().is_empty() {
+            hex::decode(cfg.macaroon_hex.trim())
+                .map_err(|e| anyhow!("Invalid macaroon hex: {}", e))?
+        } else if let Some(path) = &cfg.macaroon_path {
+            read_file(path, "macaroon")?
+        } else if let Some(mac) = uri.as_ref().and_then(|u| u.macaroon.clone()) {
+            mac
+        } else {
+            return Err(anyhow!(
+                "One of macaroon, macaroon_hex, macaroon_path or lndconnect is required"
+            ));
+        };
+
+        let cert = if let Some(value) = &cfg.cert {
+            Some(decode_blob(value, "cert")?)
+        } else if !cfg.cert_hex.trim().is_empty() {
+            Some(hex::decode(cfg.cert_hex.trim()).map_err(|e| anyhow!("Invalid cert hex: {}", e))?)
+        } else if let Some(path) = cfg.cert_path.as_deref().filter(|p| !p.trim().is_empty()) {
+            Some(read_file(path.trim(), "cert")?)
+        } else {
+            uri.and_then(|u| u.cert)
+        };
+
+        Ok(Self {
+            host,
+            macaroon_hex: hex::encode(macaroon),
+            cert_pem: cert.map(to_pem),
+            tls_server_name: cfg
+                .tls_server_name
+                .as_deref()
+                .map(str::trim)
+                .filter(|n| !n.is_empty())
+                .map(str::to_string),
+        })
+    }
+
+    /// Name the server certificate is checked against.
+    pub fn server_name(&self) -> &str {
+        if let Some(name) = &self.tls_server_name {
+            return name;
+        }
+        match self.host.rsplit_once(':') {
+            Some((host, port)) if port.parse::<u16>().is_ok() => {
+                host.trim_start_matches('[').trim_end_matches(']')
+            }
+            _ => &self.host,
+        }
+    }
+}
+
+struct LndConnectUri {
+    host: String,
+    cert: Option<Vec<u8>>,
+    macaroon: Option<Vec<u8>>,
+}
+
+/// `lndconnect://host:port?cert=<base64url DER>&macaroon=<base64url>`
+fn parse_lndconnect(uri: &str) -> Result<LndConnectUri> {
+    let url = Url::parse(uri.trim()).map_err(|e| anyhow!("Invalid lndconnect URI: {}", e))?;
+    if url.scheme() != "lndconnect" {
+        return Err(anyhow!("Expected an lndconnect:// URI"));
+    }
+    let host = url
+        .host_str()
+        .ok_or_else(|| anyhow!("lndconnect URI has no host"))?;
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => format!("{}:10009", host),
+    };
+
+    let mut cert = None;
+    let mut macaroon = None;
+    for (key, value) in url.query_pairs() {
+        match key.as_ref() {
+            "cert" => cert = Some(decode_base64(&value, "lndconnect cert")?),
+            "macaroon" => macaroon = Some(decode_base64(&value, "lndconnect macaroon")?),
+            _ => {}
+        }
+    }
+
+    Ok(LndConnectUri {
+        host,
+        cert,
+        macaroon,
+    })
+}
+
+fn strip_scheme(host: &str) -> &str {
+    let host = host.trim_end_matches('/');
+    host.split_once("://").map(|(_, rest)| rest).unwrap_or(host)
+}
+
+/// Accepts inline PEM, a file path, hex or (url-safe) base64.
+fn decode_blob(value: &str, what: &str) -> Result<Vec<u8>> {
+    let value = value.trim();
+    if value.starts_with("-----BEGIN") {
+        return Ok(value.as_bytes().to_vec());
+    }
+    if Path::new(value).is_file() {
+        return read_file(value, what);
+    }
+    if !value.is_empty()
+        && value.len().is_multiple_of(2)
+        && value.bytes().all(|b| b.is_ascii_hexdigit())
+    {
+        return hex::decode(value).map_err(|e| anyhow!("Invalid {} hex: {}", what, e));
+    }
+    decode_base64(value, what)
+        .map_err(|_| anyhow!("{} is neither a readable file, hex nor base64", what))
+}
+
+fn decode_base64(value: &str, what: &str) -> Result<Vec<u8>> {
+    [
+        general_purpose::STANDARD,
+        general_purpose::STANDARD_NO_PAD,
+        general_purpose::URL_SAFE,
+        general_purpose::URL_SAFE_NO_PAD,
+    ]
+    .iter()
+    .find_map(|engine| engine.decode(value).ok())
+    .ok_or_else(|| anyhow!("Invalid {} base64", what))
+}
+
+fn read_file(path: &str, what: &str) -> Result<Vec<u8>> {
+    fs::read(path).map_err(|e| anyhow!("Failed to read {} file '{}': {}", what, path, e))
+}
+
+/// DER bytes of the first certificate in a PEM bundle.
+pub fn pem_to_der(pem: &[u8]) -> Result<Vec<u8>> {
+    let pem = std::str::from_utf8(pem).map_err(|_| anyhow!("Certificate is not valid PEM"))?;
+    let body: String = pem
+        .lines()
+        .map(str::trim)
+        .skip_while(|l| *l != "-----BEGIN CERTIFICATE-----")
+        .skip(1)
+        .take_while(|l| !l.starts_with("-----END"))
+        .collect();
+    if body.is_empty() {
+        return Err(anyhow!("No certificate found in PEM"));
+    }
+    general_purpose::STANDARD
+        .decode(body)
+        .map_err(|e| anyhow!("Invalid PEM certificate: {}", e))
+}
+
+/// Wraps a DER certificate in PEM armour; PEM input is returned unchanged.
+fn to_pem(cert: Vec<u8>) -> Vec<u8> {
+    if cert.starts_with(b"-----BEGIN") {
+        return cert;
+    }
+    let body = general_purpose::STANDARD.encode(&cert);
+    let mut pem = String::from("-----BEGIN CERTIFICATE-----\n");
+    for line in body.as_bytes().chunks(64) {
+        pem.push_str(std::str::from_utf8(line).unwrap_or_default());
+        pem.push('\n');
+    }
+    pem.push_str("-----END CERTIFICATE-----\n");
+    pem.into_bytes()
+}
@@ -1,52 +1,355 @@
 // lightning-client/src/lnd_grpc.rs
 #[cfg(feature = "lnd-grpc")]
 use lnd_grpc_rust::lnrpc::{
-    AddInvoiceResponse, ChannelBalanceRequest, ChannelBalanceResponse, GetInfoRequest,
-    GetInfoResponse, Invoice as LndInvoice, ListInvoiceRequest, ListInvoiceResponse,
+    self, channel_point::FundingTxid, policy_update_request::Scope, AddInvoiceResponse,
+    ChanBackupExportRequest, ChanBackupSnapshot, ChanInfoRequest, ChannelBalanceRequest,
+    ChannelBalanceResponse, ChannelPoint, ForwardingHistoryRequest, GetInfoRequest,
+    GetInfoResponse, Invoice as LndInvoice, ListInvoiceRequest, ListInvoiceResponse, MppRecord,
+    MultiChanBackup, NodeInfoRequest, PayReqString, PolicyUpdateRequest, QueryRoutesRequest,
+    RoutingPolicy, SendRequest, SendToRouteRequest, SignMessageRequest, VerifyMessageRequest,
     WalletBalanceRequest, WalletBalanceResponse,
 };
 
 use super::*;
+#[cfg(feature = "lnd-grpc")]
+use crate::config::ConnectionConfig;
+#[cfg(feature = "lnd-grpc")]
+use crate::lnd_auth::LndCredentials;
+#[cfg(feature = "lnd-grpc")]
+use crate::psbt::{PendingOpens, FUND_TARGET_CONF};
 use anyhow::{anyhow, Result};
+#[cfg(feature = "lnd-grpc")]
+use hyper::client::connect::HttpConnector;
+#[cfg(feature = "lnd-grpc")]
+use hyper_openssl::HttpsConnector;
+#[cfg(feature = "lnd-grpc")]
+use lnd_grpc_rust::lnrpc::{
+    funding_shim::Shim, funding_transition_msg::Trigger, open_status_update::Update,
+    FundingPsbtFinalize, FundingPsbtVerify, FundingShim, FundingShimCancel, FundingTransitionMsg,
+    OpenChannelRequest, PsbtShim,
+};
+#[cfg(feature = "lnd-grpc")]
+use lnd_grpc_rust::routerrpc::TrackPaymentRequest;
+#[cfg(feature = "lnd-grpc")]
+use lnd_grpc_rust::walletrpc::{
+    fund_psbt_request::{Fees, Template},
+    FinalizePsbtRequest, FundPsbtRequest as LndFundPsbtRequest, SignPsbtRequest, Transaction,
+    TxTemplate,
+};
+#[cfg(feature = "lnd-grpc")]
+use openssl::{
+    ssl::{SslConnector, SslMethod},
+    x509::{store::X509StoreBuilder, X509},
+};
+#[cfg(feature = "lnd-grpc")]
+use std::{future::Future, pin::Pin, sync::RwLock, time::Duration};
+#[cfg(feature = "lnd-grpc")]
+use tonic::body::BoxBody;
+#[cfg(feature = "lnd-grpc")]
+use tonic::codegen::http::{Request, Response, Uri};
+#[cfg(feature = "lnd-grpc")]
+use tonic::codegen::{InterceptedService, Service};
+
+#[cfg(feature = "lnd-grpc")]
+type LightningRpc = lnd_grpc_rust::lnrpc::lightning_client::LightningClient<
+    InterceptedService<PinnedChannel, MacaroonAuth>,
+>;
+
+#[cfg(feature = "lnd-grpc")]
+type WalletKitRpc = lnd_grpc_rust::walletrpc::wallet_kit_client::WalletKitClient<
+    InterceptedService<PinnedChannel, MacaroonAuth>,
+>;
+
+#[cfg(feature = "lnd-grpc")]
+type RouterRpc = lnd_grpc_rust::routerrpc::router_client::RouterClient<
+    InterceptedService<PinnedChannel, MacaroonAuth>,
+>;
+
+/// PinnedChannel's message for a connection that was never made, so no
+/// request reached LND.
+#[cfg(feature = "lnd-grpc")]
+const CONNECT_FAILED: &str = "LND connect failed";
 
 #[cfg(feature = "lnd-grpc")]
 pub struct LndGrpcWrapper {
-    client: lnd_grpc_rust::LndClient,
+    creds: LndCredentials,
+    connection: ConnectionConfig,
+    // tonic clients share one channel; clone per call. The lock is only
+    // taken for writing when the channel is rebuilt.
+    lightning: RwLock<LightningRpc>,
+    wallet_kit: RwLock<WalletKitRpc>,
+    router: RwLock<RouterRpc>,
+    opens: PendingOpens,
 }
 
 #[cfg(feature = "lnd-grpc")]
 impl LndGrpcWrapper {
-    pub async fn connect(cert_hex: &str, macaroon_hex: &str, addr: &str) -> Result<Self> {
-        let mac = hex::decode(macaroon_hex).map_err(|e| anyhow!("Invalid macaroon hex: {}", e))?;
+    pub async fn connect(creds: &LndCredentials, connection: &ConnectionConfig) -> Result<Self> {
+        let (lightning, wallet_kit, router) = Self::build(creds, connection)?;
+        Ok(Self {
+            lightning: RwLock::new(lightning),
+            wallet_kit: RwLock::new(wallet_kit),
+            router: RwLock::new(router),
+            creds: creds.clone(),
+            connection: connection.clone(),
+            opens: PendingOpens::default(),
+        })
+    }
 
-        let cert_arg = if cert_hex.trim().is_empty() {
-            "".to_string()
-        } else {
-            hex::decode(cert_hex)?
-                .iter()
-                .map(|b| format!("{:02x}", b))
-                .collect()
+    fn build(
+        creds: &LndCredentials,
+        connection: &ConnectionConfig,
+    ) -> Result<(LightningRpc, WalletKitRpc, RouterRpc)> {
+        let channel = PinnedChannel::new(creds, connection)
+            .map_err(|e| anyhow!("LND gRPC connect failed: {}", e))?;
+        let auth = MacaroonAuth(
+            creds
+                .macaroon_hex
+                .parse()
+                .map_err(|_| anyhow!("Invalid macaroon"))?,
+        );
+        Ok((
+            lnd_grpc_rust::lnrpc::lightning_client::LightningClient::with_interceptor(
+                channel.clone(),
+                auth.clone(),
+            ),
+            lnd_grpc_rust::walletrpc::wallet_kit_client::WalletKitClient::with_interceptor(
+                channel.clone(),
+                auth.clone(),
+            ),
+            lnd_grpc_rust::routerrpc::router_client::RouterClient::with_interceptor(channel, auth),
+        ))
+    }
+
+    fn rpc(&self) -> LightningRpc {
+        self.lightning.read().unwrap().clone()
+    }
+
+    fn wallet_kit(&self) -> WalletKitRpc {
+        self.wallet_kit.read().unwrap().clone()
+    }
+
+    fn router(&self) -> RouterRpc {
+        self.router.read().unwrap().clone()
+    }
+
+    /// A request that sends HTLCs, with the payment timeout instead of the
+    /// request timeout.
+    fn payment_request<T>(&self, msg: T) -> tonic::Request<T> {
+        let mut req = tonic::Request::new(msg);
+        req.set_timeout(self.connection.payment_timeout());
+        req
+    }
+
+    /// Like `check`, for calls that send HTLCs under `hash`: once the
+    /// request may have reached LND, a lost connection or a timeout leaves
+    /// the payment in flight rather than failed.
+    fn check_payment<T>(
+        &self,
+        res: Result<tonic::Response<T>, tonic::Status>,
+        hash: Option<PaymentHash>,
+    ) -> Result<T> {
+        let pending = match &res {
+            Err(status) => match status.code() {
+                tonic::Code::DeadlineExceeded => true,
+                tonic::Code::Unavailable => !status.message().starts_with(CONNECT_FAILED),
+                // ErrPaymentInFlight: an earlier attempt is still running.
+                _ => status.message().contains("in transition"),
+            },
+            Ok(_) => false,
         };
+        match self.check(res) {
+            Err(e) if pending => Err(PaymentPending::error(hash, e)),
+            res => res,
+        }
+    }
 
-        let client = lnd_grpc_rust::connect(cert_arg, hex::encode(mac), addr.to_string())
-            .await
-            .map_err(|e| anyhow!("LND gRPC connect failed: {}", e))?;
+    async fn block_height(&self) -> Result<u32> {
+        let res = self.check(self.rpc().get_info(GetInfoRequest {}).await)?;
+        Ok(res.block_height)
+    }
+
+    /// Sends one FundingStateStep message for a pending PSBT open.
+    async fn funding_step(&self, trigger: Trigger) -> Result<()> {
+        let req = FundingTransitionMsg {
+            trigger: Some(trigger),
+        };
+        self.check(self.rpc().funding_state_step(req).await)?;
+        Ok(())
+    }
+
+    /// Unwraps a response. After a transport failure the channel is replaced,
+    /// so calls made after an LND restart dial a fresh connection instead of
+    /// reusing a dead one.
+    fn check<T>(&self, res: Result<tonic::Response<T>, tonic::Status>) -> Result<T> {
+        match res {
+            Ok(res) => Ok(res.into_inner()),
+            Err(status) => {
+                if matches!(
+                    status.code(),
+                    tonic::Code::Unavailable | tonic::Code::DeadlineExceeded
+                ) {
+                    match Self::build(&self.creds, &self.connection) {
+                        Ok((lightning, wallet_kit, router)) => {
+                            *self.lightning.write().unwrap() = lightning;
+                            *self.wallet_kit.write().unwrap() = wallet_kit;
+                            *self.router.write().unwrap() = router;
+                        }
+                        Err(e) => eprintln!("LND gRPC reconnect failed: {}", e),
+                    }
+                }
+                Err(status.into())
+            }
+        }
+    }
+}
+
+#[cfg(feature = "lnd-grpc")]
+#[derive(Clone)]
+struct MacaroonAuth(tonic::metadata::AsciiMetadataValue);
+
+#[cfg(feature = "lnd-grpc")]
+impl tonic::service::Interceptor for MacaroonAuth {
+    fn call(&mut self, mut req: tonic::Request<()>) -> Result<tonic::Request<()>, tonic::Status> {
+        req.metadata_mut().insert("macaroon", self.0.clone());
+        Ok(req)
+    }
+}
+
+/// HTTP/2 channel that only trusts the pinned LND certificate (the system
+/// store when none is given) and checks it against the configured server name.
+#[cfg(feature = "lnd-grpc")]
+#[derive(Clone)]
+struct PinnedChannel {
+    uri: Uri,
+    client: hyper::Client<HttpsConnector<HttpConnector>, BoxBody>,
+    timeout: Duration,
+}
+
+#[cfg(feature = "lnd-grpc")]
+impl PinnedChannel {
+    fn new(creds: &LndCredentials, connection: &ConnectionConfig) -> Result<Self> {
+        let mut http = HttpConnector::new();
+        http.enforce_http(false);
+        http.set_connect_timeout(Some(connection.connect_timeout()));
 
-        Ok(Self { client })
+        let mut tls = SslConnector::builder(SslMethod::tls())?;
+        if let Some(pem) = &creds.cert_pem {
+            let mut store = X509StoreBuilder::new()?;
+            store.add_cert(X509::from_pem(pem)?)?;
+            tls.set_cert_store(store.build());
+        }
+        tls.set_alpn_protos(b"\x02h2")?;
+
+        let server_name = creds.server_name().to_string();
+        let mut https = HttpsConnector::with_connector(http, tls)?;
+        https.set_callback(move |c, _| {
+            // Verify against the configured name, not the host we dial.
+            c.set_verify_hostname(false);
+            match server_name.parse::<std::net::IpAddr>() {
+                Ok(ip) => c.param_mut().set_ip(ip),
+                Err(_) => c.param_mut().set_host(&server_name),
+            }
+        });
+
+        Ok(Self {
+            uri: format!("https://{}", creds.host)
+                .parse()
+                .map_err(|e| anyhow!("Invalid LND host '{}': {}", creds.host, e))?,
+            client: hyper::Client::builder().http2_only(true).build(https),
+            timeout: connection.request_timeout(),
+        })
+    }
+}
+
+#[cfg(feature = "lnd-grpc")]
+impl Service<Request<BoxBody>> for PinnedChannel {
+    type Response = Response<hyper::Body>;
+    // A Status passes through tonic unchanged, so callers see
+    // Unavailable/DeadlineExceeded rather than a generic Unknown.
+    type Error = tonic::Status;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(
+        &mut self,
+        _: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, mut req: Request<BoxBody>) -> Self::Future {
+        let mut parts = req.uri().clone().into_parts();
+        parts.scheme = self.uri.scheme().cloned();
+        parts.authority = self.uri.authority().cloned();
+        if let Ok(uri) = Uri::from_parts(parts) {
+            *req.uri_mut() = uri;
+        }
+        // Calls that set their own deadline (payments) get it instead of
+        // the request timeout.
+        let timeout = grpc_timeout(&req).unwrap_or(self.timeout);
+        let res = self.client.request(req);
+        Box::pin(async move {
+            match tokio::time::timeout(timeout, res).await {
+                Ok(Ok(res)) => Ok(res),
+                Ok(Err(e)) if e.is_connect() => Err(tonic::Status::unavailable(format!(
+                    "{}: {}",
+                    CONNECT_FAILED, e
+                ))),
+                Ok(Err(e)) => Err(tonic::Status::unavailable(format!(
+                    "LND transport error: {}",
+                    e
+                ))),
+                Err(_) => Err(tonic::Status::deadline_exceeded(format!(
+                    "LND did not answer within {:?}",
+                    timeout
+                ))),
+            }
+        })
     }
 }
 
+/// The `grpc-timeout` header tonic sets for `Request::set_timeout`.
+#[cfg(feature = "lnd-grpc")]
+fn grpc_timeout<B>(req: &Request<B>) -> Option<Duration> {
+    let value = req.headers().get("grpc-timeout")?.to_str().ok()?;
+    let (n, unit) = value.split_at(value.len().checked_sub(1)?);
+    let n: u64 = n.parse().ok()?;
+    Some(match unit {
+        "H" => Duration::from_secs(n.saturating_mul(3600)),
+        "M" => Duration::from_secs(n.saturating_mul(60)),
+        "S" => Duration::from_secs(n),
+        "m" => Duration::from_millis(n),
+        "u" => Duration::from_micros(n),
+        "n" => Duration::from_nanos(n),
+        _ => return None,
+    })
+}
+
 #[cfg_attr(feature = "lnd-grpc", async_trait)]
 #[cfg_attr(not(feature = "lnd-grpc"), async_trait(?Send))]
 impl LightningClient for LndGrpcWrapper {
-    async fn get_info(&mut self) -> Result<NodeInfo> {
+    async fn get_info(&self) -> Result<NodeInfo> {
         #[cfg(feature = "lnd-grpc")]
         {
             let req = GetInfoRequest {};
-            let res: GetInfoResponse = self.client.lightning().get_info(req).await?.into_inner();
+            let res: GetInfoResponse = self.check(self.rpc().get_info(req).await)?;
+            let non_empty = |s: String| if s.is_empty() { None } else { Some(s) };
             Ok(NodeInfo {
                 alias: res.alias,
-                identity_pubkey: res.identity_pubkey,
+                identity_pubkey: res.identity_pubkey.parse().ok(),
+                implementation: "lnd".into(),
+                version: non_empty(res.version),
+                network: res.chains.first().map(|c| normalize_network(&c.network)),
+                block_height: Some(res.block_height),
+                block_hash: non_empty(res.block_hash),
+                synced_to_chain: Some(res.synced_to_chain),
+                synced_to_graph: Some(res.synced_to_graph),
+                num_active_channels: Some(res.num_active_channels),
+                num_pending_channels: Some(res.num_pending_channels),
+                num_inactive_channels: Some(res.num_inactive_channels),
+                num_peers: Some(res.num_peers),
+                uris: res.uris,
+                color: non_empty(res.color),
             })
         }
         #[cfg(not(feature = "lnd-grpc"))]
@@ -56,60 +359,68 @@ impl LightningClient for LndGrpcWrapper {
     }
 
     async fn create_invoice(
-        &mut self,
-        msat: u64,
+        &self,
+        amount: Msat,
         _label: Option<&str>,
         desc: Option<&str>,
-    ) -> Result<String> {
+    ) -> Result<Bolt11> {
         #[cfg(feature = "lnd-grpc")]
         {
             let req = LndInvoice {
-                value_msat: msat as i64,
+                value_msat: amount.to_i64()?,
                 memo: desc.unwrap_or("rust").to_string(),
                 ..Default::default()
             };
-            let res: AddInvoiceResponse =
-                self.client.lightning().add_invoice(req).await?.into_inner();
-            Ok(res.payment_request)
+            let res: AddInvoiceResponse = self.check(self.rpc().add_invoice(req).await)?;
+            res.payment_request.parse()
+        }
+        #[cfg(not(feature = "lnd-grpc"))]
+        {
+            Err(anyhow!("lnd-grpc feature not enabled"))
+        }
+    }
+
+    async fn create_invoice_hashed(
+        &self,
+        amount: Msat,
+        _label: Option<&str>,
+        description: &str,
+    ) -> Result<Bolt11> {
+        #[cfg(feature = "lnd-grpc")]
+        {
+            let req = LndInvoice {
+                value_msat: amount.to_i64()?,
+                description_hash: description_hash(description).to_vec(),
+                ..Default::default()
+            };
+            let res: AddInvoiceResponse = self.check(self.rpc().add_invoice(req).await)?;
+            res.payment_request.parse()
         }
         #[cfg(not(feature = "lnd-grpc"))]
         {
+            let _ = (amount, description);
             Err(anyhow!("lnd-grpc feature not enabled"))
         }
     }
 
-    async fn get_balance(&mut self) -> Result<Balance> {
+    async fn get_balance(&self) -> Result<Balance> {
         #[cfg(feature = "lnd-grpc")]
         {
             let wallet_req = WalletBalanceRequest {
                 account: "".to_string(),
                 min_confs: 1,
             };
-            let wallet_res: WalletBalanceResponse = self
-                .client
-                .lightning()
-                .wallet_balance(wallet_req)
-                .await?
-                .into_inner();
-            let onchain_sat = wallet_res.confirmed_balance as u64;
+            let wallet_res: WalletBalanceResponse =
+                self.check(self.rpc().wallet_balance(wallet_req).await)?;
+            let onchain_sat = Sat::try_from(wallet_res.confirmed_balance)?;
 
-            let chan_req = ChannelBalanceRequest {
-                ..Default::default()
-            };
-            let chan_res: ChannelBalanceResponse = self
-                .client
-                .lightning()
-                .channel_balance(chan_req)
-                .await?
-                .into_inner();
-            let channel_msat = if let Some(amount) = chan_res.local_balance {
-                if amount.msat > 0 {
-                    amount.msat as u64
-                } else {
-                    amount.sat as u64 * 1000
-                }
-            } else {
-                0u64
+            let chan_req = ChannelBalanceRequest {};
+            let chan_res: ChannelBalanceResponse =
+                self.check(self.rpc().channel_balance(chan_req).await)?;
+            let channel_msat = match chan_res.local_balance {
+                Some(amount) if amount.msat > 0 => Msat::from_msat(amount.msat),
+                Some(amount) => Sat::from_sat(amount.sat).to_msat()?,
+                None => Msat::ZERO,
             };
 
             Ok(Balance {
@@ -123,45 +434,522 @@ impl LightningClient for LndGrpcWrapper {
         }
     }
 
-    async fn list_invoices(&mut self, limit: Option<usize>) -> Result<Vec<Invoice>> {
+    async fn list_invoices(&self, limit: Option<usize>) -> Result<Vec<Invoice>> {
         #[cfg(feature = "lnd-grpc")]
         {
             let req = ListInvoiceRequest {
                 num_max_invoices: limit.unwrap_or(10) as u64,
                 ..Default::default()
             };
-            let res: ListInvoiceResponse = self
-                .client
-                .lightning()
-                .list_invoices(req)
-                .await?
-                .into_inner();
-            let invoices = res
-                .invoices
+            let res: ListInvoiceResponse = self.check(self.rpc().list_invoices(req).await)?;
+            res.invoices.into_iter().map(invoice_of).collect()
+        }
+        #[cfg(not(feature = "lnd-grpc"))]
+        {
+            Err(anyhow!("lnd-grpc feature not enabled"))
+        }
+    }
+
+    async fn lookup_invoice(&self, hash: &PaymentHash) -> Result<Option<Invoice>> {
+        #[cfg(feature = "lnd-grpc")]
+        {
+            let req = lnrpc::PaymentHash {
+                r_hash: hash.as_bytes().to_vec(),
+                ..Default::default()
+            };
+            match self.rpc().lookup_invoice(req).await {
+                // Unknown with this message before LND 0.18.
+                Err(status)
+                    if status.code() == tonic::Code::NotFound
+                        || status.message().contains("unable to locate invoice") =>
+                {
+                    Ok(None)
+                }
+                res => invoice_of(self.check(res)?).map(Some),
+            }
+        }
+        #[cfg(not(feature = "lnd-grpc"))]
+        {
+            let _ = hash;
+            Err(anyhow!("lnd-grpc feature not enabled"))
+        }
+    }
+
+    async fn decode_invoice(&self, bolt11: &Bolt11) -> Result<DecodedInvoice> {
+        #[cfg(feature = "lnd-grpc")]
+        {
+            let req = PayReqString {
+                pay_req: bolt11.to_string(),
+            };
+            let res = self.check(self.rpc().decode_pay_req(req).await)?;
+            let amount_msat = match res.num_msat {
+                0 => None,
+                msat => Some(Msat::from_msat(u64::try_from(msat)?)),
+            };
+            Ok(DecodedInvoice {
+                amount_msat,
+                desc: Some(res.description).filter(|d| !d.is_empty()),
+                payee: Some(res.destination.parse()?),
+            })
+        }
+        #[cfg(not(feature = "lnd-grpc"))]
+        {
+            let _ = bolt11;
+            Err(anyhow!("lnd-grpc feature not enabled"))
+        }
+    }
+
+    async fn pay_invoice(&self, bolt11: &Bolt11) -> Result<PaymentResult> {
+        #[cfg(feature = "lnd-grpc")]
+        {
+            let req = self.payment_request(SendRequest {
+                payment_request: bolt11.to_string(),
+                ..Default::default()
+            });
+            let res = self.check_payment(
+                self.rpc().send_payment_sync(req).await,
+                bolt11.payment_hash(),
+            )?;
+            if !res.payment_error.is_empty() {
+                // ErrPaymentInFlight: an earlier attempt is still running.
+                if res.payment_error.contains("in transition") {
+                    return Err(PaymentPending::error(
+                        bolt11.payment_hash(),
+                        res.payment_error,
+                    ));
+                }
+                return Err(anyhow!("Payment failed: {}", res.payment_error));
+            }
+
+            let route = res
+                .payment_route
+                .ok_or_else(|| anyhow!("LND returned no payment route"))?;
+            let fee_msat = Msat::try_from(route.total_fees_msat)?;
+            let total_msat = Msat::try_from(route.total_amt_msat)?;
+            Ok(PaymentResult {
+                hash: PaymentHash::from_slice(&res.payment_hash)?,
+                amount_msat: Some(Msat::from_msat(
+                    total_msat.as_msat().saturating_sub(fee_msat.as_msat()),
+                )),
+                fee_msat: Some(fee_msat),
+                preimage: Preimage::from_slice(&res.payment_preimage).ok(),
+            })
+        }
+        #[cfg(not(feature = "lnd-grpc"))]
+        {
+            let _ = bolt11;
+            Err(anyhow!("lnd-grpc feature not enabled"))
+        }
+    }
+
+    async fn lookup_payment(&self, hash: &PaymentHash) -> Result<PaymentState> {
+        #[cfg(feature = "lnd-grpc")]
+        {
+            use lnrpc::payment::PaymentStatus;
+
+            // The stream stays open while the payment is in flight; its
+            // first message is the current state.
+            let req = TrackPaymentRequest {
+                payment_hash: hash.as_bytes().to_vec(),
+                no_inflight_updates: false,
+            };
+            let mut stream = match self.router().track_payment_v2(req).await {
+                Err(status) if status.code() == tonic::Code::NotFound => {
+                    return Ok(PaymentState::Unknown)
+                }
+                res => self.check(res)?,
+            };
+            let payment = match stream.message().await {
+                Err(status) if status.code() == tonic::Code::NotFound => {
+                    return Ok(PaymentState::Unknown)
+                }
+                Err(status) => return Err(status.into()),
+                Ok(None) => return Err(anyhow!("TrackPaymentV2 returned no update")),
+                Ok(Some(payment)) => payment,
+            };
+            Ok(match payment.status() {
+                PaymentStatus::Succeeded => PaymentState::Succeeded(PaymentResult {
+                    hash: *hash,
+                    amount_msat: Some(Msat::try_from(payment.value_msat)?),
+                    fee_msat: Some(Msat::try_from(payment.fee_msat)?),
+                    preimage: payment.payment_preimage.parse().ok(),
+                }),
+                PaymentStatus::Failed => PaymentState::Failed,
+                PaymentStatus::InFlight | PaymentStatus::Initiated => PaymentState::Pending,
+                PaymentStatus::Unknown => PaymentState::Unknown,
+            })
+        }
+        #[cfg(not(feature = "lnd-grpc"))]
+        {
+            let _ = hash;
+            Err(anyhow!("lnd-grpc feature not enabled"))
+        }
+    }
+
+    async fn sign_message(&self, message: &str) -> Result<String> {
+        #[cfg(feature = "lnd-grpc")]
+        {
+            let req = SignMessageRequest {
+                msg: message.as_bytes().to_vec(),
+                single_hash: false,
+            };
+            let res = self.check(self.rpc().sign_message(req).await)?;
+            Ok(res.signature)
+        }
+        #[cfg(not(feature = "lnd-grpc"))]
+        {
+            Err(anyhow!("lnd-grpc feature not enabled"))
+        }
+    }
+
+    async fn verify_message(&self, message: &str, signature: &str) -> Result<SignatureCheck> {
+        #[cfg(feature = "lnd-grpc")]
+        {
+            let req = VerifyMessageRequest {
+                msg: message.as_bytes().to_vec(),
+                signature: signature.to_string(),
+            };
+            let res = self.check(self.rpc().verify_message(req).await)?;
+            Ok(SignatureCheck {
+                valid: res.valid,
+                pubkey: res.pubkey.parse().ok(),
+            })
+        }
+        #[cfg(not(feature = "lnd-grpc"))]
+        {
+            Err(anyhow!("lnd-grpc feature not enabled"))
+        }
+    }
+
+    async fn update_channel_policy(
+        &self,
+        channel: &ChannelSelector,
+        policy: &ChannelPolicy,
+    ) -> Result<()> {
+        #[cfg(feature = "lnd-grpc")]
+        {
+            let time_lock_delta = policy
+                .time_lock_delta
+                .ok_or_else(|| anyhow!("LND needs time_lock_delta for a policy update"))?;
+            let chan_point = |txid: &str, vout: u32| ChannelPoint {
+                funding_txid: Some(FundingTxid::FundingTxidStr(txid.to_string())),
+                output_index: vout,
+            };
+            let scope = match channel {
+                ChannelSelector::All => Scope::Global(true),
+                ChannelSelector::Outpoint { txid, vout } => {
+                    Scope::ChanPoint(chan_point(txid, *vout))
+                }
+                ChannelSelector::Short(scid) => {
+                    // LND addresses channels by funding outpoint only.
+                    let req = ChanInfoRequest {
+                        chan_id: scid.as_u64(),
+                        ..Default::default()
+                    };
+                    let edge = self.check(self.rpc().get_chan_info(req).await)?;
+                    let (txid, vout) = edge
+                        .chan_point
+                        .split_once(':')
+                        .ok_or_else(|| anyhow!("Channel {} has no funding outpoint", scid))?;
+                    Scope::ChanPoint(chan_point(txid, vout.parse()?))
+                }
+            };
+            let req = PolicyUpdateRequest {
+                base_fee_msat: policy.base_fee_msat.to_i64()?,
+                fee_rate_ppm: policy.fee_ppm,
+                time_lock_delta,
+                min_htlc_msat: policy.min_htlc_msat.map_or(0, Msat::as_msat),
+                min_htlc_msat_specified: policy.min_htlc_msat.is_some(),
+                max_htlc_msat: policy.max_htlc_msat.map_or(0, Msat::as_msat),
+                scope: Some(scope),
+                ..Default::default()
+            };
+            let res = self.check(self.rpc().update_channel_policy(req).await)?;
+            if res.failed_updates.is_empty() {
+                return Ok(());
+            }
+            let failed: Vec<String> = res
+                .failed_updates
+                .iter()
+                .map(|u| {
+                    let outpoint = u
+                        .outpoint
+                        .as_ref()
+                        .map(|o| format!("{}:{}", o.txid_str, o.output_index))
+                        .unwrap_or_default();
+                    format!("{}: {}", outpoint, u.update_error)
+                })
+                .collect();
+            Err(anyhow!("Policy update failed for {}", failed.join("; ")))
+        }
+        #[cfg(not(feature = "lnd-grpc"))]
+        {
+            Err(anyhow!("lnd-grpc feature not enabled"))
+        }
+    }
+
+    async fn list_forwards(&self, query: &ForwardQuery) -> Result<ForwardPage> {
+        #[cfg(feature = "lnd-grpc")]
+        {
+            let limit = query.limit.unwrap_or(100);
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)?
+                .as_secs();
+            let req = ForwardingHistoryRequest {
+                start_time: query.start.unwrap_or(0),
+                end_time: query.end.unwrap_or(now),
+                index_offset: u32::try_from(query.offset)?,
+                num_max_events: u32::try_from(limit)?,
+                ..Default::default()
+            };
+            let res = self.check(self.rpc().forwarding_history(req).await)?;
+            let forwards: Vec<Forward> = res
+                .forwarding_events
                 .into_iter()
-                .map(|inv| Invoice {
-                    hash: hex::encode(inv.r_hash),
-                    amount_msat: std::cmp::max(inv.value_msat, 0) as u64,
-                    state: match inv.state {
-                        0 => "open".to_string(),
-                        1 => "settled".to_string(),
-                        2 => "canceled".to_string(),
-                        3 => "accepted".to_string(),
-                        _ => format!("unknown: {}", inv.state),
-                    },
-                    bolt11: if inv.payment_request.is_empty() {
-                        None
-                    } else {
-                        Some(inv.payment_request)
-                    },
-                    desc: if inv.memo.is_empty() {
-                        None
-                    } else {
-                        Some(inv.memo)
-                    },
+                .map(|e| Forward {
+                    timestamp: e.timestamp_ns / 1_000_000_000,
+                    chan_in: Some(ShortChannelId::from_u64(e.chan_id_in)),
+                    chan_out: Some(ShortChannelId::from_u64(e.chan_id_out)),
+                    amount_in_msat: Msat::from_msat(e.amt_in_msat),
+                    amount_out_msat: Msat::from_msat(e.amt_out_msat),
+                    fee_msat: Msat::from_msat(e.fee_msat),
+                })
+                .collect();
+            let next_offset = (forwards.len() >= limit).then_some(u64::from(res.last_offset_index));
+            Ok(ForwardPage {
+                forwards,
+                next_offset,
+            })
+        }
+        #[cfg(not(feature = "lnd-grpc"))]
+        {
+            Err(anyhow!("lnd-grpc feature not enabled"))
+        }
+    }
+
+    async fn query_routes(&self, dest: &PublicKey, amount: Msat) -> Result<Route> {
+        #[cfg(feature = "lnd-grpc")]
+        {
+            let req = QueryRoutesRequest {
+                pub_key: dest.to_string(),
+                amt_msat: amount.to_i64()?,
+                ..Default::default()
+            };
+            let res = self.check(self.rpc().query_routes(req).await)?;
+            let raw = res
+                .routes
+                .first()
+                .ok_or_else(|| anyhow!("No route to {}", dest))?;
+            route(raw, self.block_height().await?)
+        }
+        #[cfg(not(feature = "lnd-grpc"))]
+        {
+            Err(anyhow!("lnd-grpc feature not enabled"))
+        }
+    }
+
+    async fn get_node(&self, pubkey: &PublicKey) -> Result<GraphNode> {
+        #[cfg(feature = "lnd-grpc")]
+        {
+            let req = NodeInfoRequest {
+                pub_key: pubkey.to_string(),
+                include_channels: false,
+            };
+            let res = self.check(self.rpc().get_node_info(req).await)?;
+            let node = res
+                .node
+                .ok_or_else(|| anyhow!("Node {} not found", pubkey))?;
+            let non_empty = |s: String| if s.is_empty() { None } else { Some(s) };
+            Ok(GraphNode {
+                pubkey: *pubkey,
+                alias: non_empty(node.alias),
+                color: non_empty(node.color),
+                addresses: node.addresses.into_iter().map(|a| a.addr).collect(),
+                // Zero until the node has announced itself.
+                last_update: (node.last_update > 0).then_some(u64::from(node.last_update)),
+            })
+        }
+        #[cfg(not(feature = "lnd-grpc"))]
+        {
+            Err(anyhow!("lnd-grpc feature not enabled"))
+        }
+    }
+
+    async fn get_channel(&self, scid: ShortChannelId) -> Result<GraphChannel> {
+        #[cfg(feature = "lnd-grpc")]
+        {
+            let req = ChanInfoRequest {
+                chan_id: scid.as_u64(),
+                ..Default::default()
+            };
+            let edge = self.check(self.rpc().get_chan_info(req).await)?;
+            let node1: PublicKey = edge.node1_pub.parse()?;
+            let node2: PublicKey = edge.node2_pub.parse()?;
+            let policy = |source: PublicKey, p: RoutingPolicy| -> Result<EdgePolicy> {
+                Ok(EdgePolicy {
+                    source,
+                    base_fee_msat: Msat::try_from(p.fee_base_msat)?,
+                    fee_ppm: u32::try_from(p.fee_rate_milli_msat)?,
+                    time_lock_delta: p.time_lock_delta,
+                    min_htlc_msat: Msat::try_from(p.min_htlc)?,
+                    max_htlc_msat: Some(Msat::from_msat(p.max_htlc_msat)),
+                    disabled: p.disabled,
+                    last_update: Some(u64::from(p.last_update)),
+                })
+            };
+            let policies = [(node1, edge.node1_policy), (node2, edge.node2_policy)]
+                .into_iter()
+                .filter_map(|(source, p)| p.map(|p| policy(source, p)))
+                .collect::<Result<_>>()?;
+
+            Ok(GraphChannel {
+                scid,
+                node1,
+                node2,
+                capacity_sat: Some(Sat::try_from(edge.capacity)?),
+                policies,
+            })
+        }
+        #[cfg(not(feature = "lnd-grpc"))]
+        {
+            Err(anyhow!("lnd-grpc feature not enabled"))
+        }
+    }
+
+    async fn probe_payment(&self, bolt11: &Bolt11) -> Result<ProbeResult> {
+        #[cfg(feature = "lnd-grpc")]
+        {
+            let amount = bolt11
+                .amount()
+                .ok_or_else(|| anyhow!("Cannot probe an invoice without an amount"))?;
+            let req = PayReqString {
+                pay_req: bolt11.to_string(),
+            };
+            let decoded = self.check(self.rpc().decode_pay_req(req).await)?;
+
+            let req = QueryRoutesRequest {
+                pub_key: decoded.destination,
+                amt_msat: amount.to_i64()?,
+                final_cltv_delta: i32::try_from(decoded.cltv_expiry)?,
+                ..Default::default()
+            };
+            let unreachable = |failure: String| ProbeResult {
+                reachable: false,
+                route: None,
+                failure: Some(failure),
+            };
+            let res = match self.rpc().query_routes(req).await {
+                // Anything LND itself answers means there is no usable route.
+                Err(status)
+                    if !matches!(
+                        status.code(),
+                        tonic::Code::Unavailable | tonic::Code::DeadlineExceeded
+                    ) =>
+                {
+                    return Ok(unreachable(status.message().to_string()))
+                }
+                res => self.check(res)?,
+            };
+            let Some(mut raw) = res.routes.into_iter().next() else {
+                return Ok(unreachable("no route found".to_string()));
+            };
+            let route = route(&raw, self.block_height().await?)?;
+            // The payee rejects payments without its payment secret outright.
+            if let Some(last) = raw.hops.last_mut() {
+                if !decoded.payment_addr.is_empty() {
+                    last.mpp_record = Some(MppRecord {
+                        payment_addr: decoded.payment_addr,
+                        total_amt_msat: amount.to_i64()?,
+                    });
+                }
+            }
+
+            let hash = PaymentHash::random();
+            let req = SendToRouteRequest {
+                payment_hash: hash.as_bytes().to_vec(),
+                route: Some(raw),
+                ..Default::default()
+            };
+            let req = self.payment_request(req);
+            let res = self.check_payment(self.rpc().send_to_route_sync(req).await, Some(hash))?;
+            let reachable = res
+                .payment_error
+                .starts_with("IncorrectOrUnknownPaymentDetails");
+            Ok(ProbeResult {
+                reachable,
+                route: Some(route),
+                failure: (!reachable).then_some(res.payment_error),
+            })
+        }
+        #[cfg(not(feature = "lnd-grpc"))]
+        {
+            Err(anyhow!("lnd-grpc feature not enabled"))
+        }
+    }
+
+    async fn export_channel_backup(&self) -> Result<ChannelBackup> {
+        #[cfg(feature = "lnd-grpc")]
+        {
+            let req = ChanBackupExportRequest {};
+            let res = self.check(self.rpc().export_all_channel_backups(req).await)?;
+            let multi = res
+                .multi_chan_backup
+                .ok_or_else(|| anyhow!("LND returned no multi-channel backup"))?;
+            let channels = multi
+                .chan_points
+                .iter()
+                .filter_map(|p| {
+                    let txid = match p.funding_txid.as_ref()? {
+                        FundingTxid::FundingTxidStr(txid) => txid.clone(),
+                        // Internal byte order.
+                        FundingTxid::FundingTxidBytes(bytes) => {
+                            hex::encode(bytes.iter().rev().copied().collect::<Vec<u8>>())
+                        }
+                    };
+                    Some(format!("{}:{}", txid, p.output_index))
                 })
                 .collect();
-            Ok(invoices)
+            Ok(ChannelBackup {
+                data: multi.multi_chan_backup,
+                channels,
+            })
+        }
+        #[cfg(not(feature = "lnd-grpc"))]
+        {
+            Err(anyhow!("lnd-grpc feature not enabled"))
+        }
+    }
+
+    async fn verify_channel_backup(&self, data: &[u8]) -> Result<BackupCheck> {
+        #[cfg(feature = "lnd-grpc")]
+        {
+            let req = ChanBackupSnapshot {
+                single_chan_backups: None,
+                multi_chan_backup: Some(MultiChanBackup {
+                    chan_points: Vec::new(),
+                    multi_chan_backup: data.to_vec(),
+                }),
+            };
+            match self.rpc().verify_chan_backup(req).await {
+                // Anything LND itself answers is a verdict on the backup.
+                Err(status)
+                    if !matches!(
+                        status.code(),
+                        tonic::Code::Unavailable | tonic::Code::DeadlineExceeded
+                    ) =>
+                {
+                    Ok(BackupCheck {
+                        valid: false,
+                        channels: Vec::new(),
+                        error: Some(status.message().to_string()),
+                    })
+                }
+                res => Ok(BackupCheck {
+                    valid: true,
+                    channels: self.check(res)?.chan_points,
+                    error: None,
+                }),
+            }
         }
         #[cfg(not(feature = "lnd-grpc"))]
         {
@@ -169,12 +957,70 @@ impl LightningClient for LndGrpcWrapper {
         }
     }
 
-    async fn decode_invoice(&mut self, _bolt11: &str) -> Result<DecodedInvoice> {
+    async fn open_channel_psbt(&self, open: &PsbtChannelOpen) -> Result<PsbtFunding> {
         #[cfg(feature = "lnd-grpc")]
         {
-            Err(anyhow!(
-                "decode_invoice not implemented in this gRPC binding version"
-            ))
+            let pending_id: [u8; 32] = rand::random();
+            let id = hex::encode(pending_id);
+            let req = OpenChannelRequest {
+                node_pubkey: open.peer.as_bytes().to_vec(),
+                local_funding_amount: i64::try_from(open.amount_sat.as_sat())?,
+                private: open.private,
+                funding_shim: Some(FundingShim {
+                    shim: Some(Shim::PsbtShim(PsbtShim {
+                        pending_chan_id: pending_id.to_vec(),
+                        base_psbt: open
+                            .psbt
+                            .as_ref()
+                            .map(|p| p.as_bytes().to_vec())
+                            .unwrap_or_default(),
+                        no_publish: false,
+                    })),
+                }),
+                ..Default::default()
+            };
+            let mut stream = self.check(self.rpc().open_channel(req).await)?;
+
+            let update = stream
+                .message()
+                .await?
+                .and_then(|u| u.update)
+                .ok_or_else(|| anyhow!("LND closed the channel open stream"))?;
+            let Update::PsbtFund(fund) = update else {
+                return Err(anyhow!("Expected a PSBT funding request, got {:?}", update));
+            };
+            let funding = PsbtFunding {
+                id: id.clone(),
+                funding_address: Some(fund.funding_address),
+                amount_sat: Sat::try_from(fund.funding_amount)?,
+                psbt: Psbt::from_bytes(fund.psbt)?,
+            };
+
+            let tx = self.opens.insert(&id);
+            let opens = self.opens.clone();
+            tokio::spawn(async move {
+                let outpoint = loop {
+                    match stream.message().await {
+                        Ok(Some(lnrpc::OpenStatusUpdate {
+                            update: Some(Update::ChanPending(pending)),
+                            ..
+                        })) => {
+                            // Internal byte order.
+                            let txid: Vec<u8> = pending.txid.iter().rev().copied().collect();
+                            break Ok(format!("{}:{}", hex::encode(txid), pending.output_index));
+                        }
+                        Ok(Some(_)) => continue,
+                        Ok(None) => break Err(anyhow!("LND closed the channel open stream")),
+                        Err(status) => break Err(anyhow!("LND: {}", status.message())),
+                    }
+                };
+                if let Err(e) = &outpoint {
+                    eprintln!("PSBT channel open {} ended: {}", id, e);
+                    opens.remove(&id);
+                }
+                let _ = tx.send(outpoint);
+            });
+            Ok(funding)
         }
         #[cfg(not(feature = "lnd-grpc"))]
         {
@@ -182,16 +1028,261 @@ impl LightningClient for LndGrpcWrapper {
         }
     }
 
-    async fn pay_invoice(&mut self, _bolt11: &str) -> Result<PaymentResult> {
+    async fn update_channel_psbt(&self, id: &str, psbt: &Psbt) -> Result<Psbt> {
         #[cfg(feature = "lnd-grpc")]
         {
-            Err(anyhow!(
-                "pay_invoice not implemented in this gRPC binding version"
-            ))
+            self.opens.check(id)?;
+            self.funding_step(Trigger::PsbtVerify(FundingPsbtVerify {
+                funded_psbt: psbt.as_bytes().to_vec(),
+                pending_chan_id: hex::decode(id)?,
+                skip_finalize: false,
+            }))
+            .await?;
+            Ok(psbt.clone())
         }
         #[cfg(not(feature = "lnd-grpc"))]
         {
             Err(anyhow!("lnd-grpc feature not enabled"))
         }
     }
+
+    async fn finalize_channel_psbt(&self, id: &str, signed: &Psbt) -> Result<PsbtChannelOpened> {
+        #[cfg(feature = "lnd-grpc")]
+        {
+            self.opens.check(id)?;
+            self.funding_step(Trigger::PsbtFinalize(FundingPsbtFinalize {
+                signed_psbt: signed.as_bytes().to_vec(),
+                pending_chan_id: hex::decode(id)?,
+                final_raw_tx: Vec::new(),
+            }))
+            .await?;
+            let channel = self.opens.published(id).await?;
+            Ok(PsbtChannelOpened {
+                txid: channel.split(':').next().unwrap_or_default().to_string(),
+                channel,
+            })
+        }
+        #[cfg(not(feature = "lnd-grpc"))]
+        {
+            Err(anyhow!("lnd-grpc feature not enabled"))
+        }
+    }
+
+    async fn cancel_channel_psbt(&self, id: &str) -> Result<()> {
+        #[cfg(feature = "lnd-grpc")]
+        {
+            self.funding_step(Trigger::ShimCancel(FundingShimCancel {
+                pending_chan_id: hex::decode(id)?,
+            }))
+            .await?;
+            self.opens.remove(id);
+            Ok(())
+        }
+        #[cfg(not(feature = "lnd-grpc"))]
+        {
+            Err(anyhow!("lnd-grpc feature not enabled"))
+        }
+    }
+
+    async fn fund_psbt(&self, request: &FundPsbtRequest) -> Result<FundedPsbt> {
+        #[cfg(feature = "lnd-grpc")]
+        {
+            let req = LndFundPsbtRequest {
+                template: Some(Template::Raw(TxTemplate {
+                    inputs: Vec::new(),
+                    outputs: request
+                        .outputs
+                        .iter()
+                        .map(|o| (o.address.clone(), o.amount_sat.as_sat()))
+                        .collect(),
+                })),
+                fees: Some(match request.sat_per_vbyte {
+                    Some(rate) => Fees::SatPerVbyte(rate),
+                    None => Fees::TargetConf(FUND_TARGET_CONF),
+                }),
+                ..Default::default()
+            };
+            let res = self.check(self.wallet_kit().fund_psbt(req).await)?;
+            Ok(FundedPsbt {
+                psbt: Psbt::from_bytes(res.funded_psbt)?,
+                change_index: u32::try_from(res.change_output_index).ok(),
+            })
+        }
+        #[cfg(not(feature = "lnd-grpc"))]
+        {
+            Err(anyhow!("lnd-grpc feature not enabled"))
+        }
+    }
+
+    async fn sign_psbt(&self, psbt: &Psbt) -> Result<Psbt> {
+        #[cfg(feature = "lnd-grpc")]
+        {
+            let req = SignPsbtRequest {
+                funded_psbt: psbt.as_bytes().to_vec(),
+            };
+            let res = self.check(self.wallet_kit().sign_psbt(req).await)?;
+            Psbt::from_bytes(res.signed_psbt)
+        }
+        #[cfg(not(feature = "lnd-grpc"))]
+        {
+            Err(anyhow!("lnd-grpc feature not enabled"))
+        }
+    }
+
+    async fn finalize_psbt(&self, psbt: &Psbt) -> Result<BroadcastTx> {
+        #[cfg(feature = "lnd-grpc")]
+        {
+            let req = FinalizePsbtRequest {
+                funded_psbt: psbt.as_bytes().to_vec(),
+                account: String::new(),
+            };
+            let res = self.check(self.wallet_kit().finalize_psbt(req).await)?;
+            let raw_tx = res.raw_final_tx;
+
+            let req = Transaction {
+                tx_hex: raw_tx.clone(),
+                label: String::new(),
+            };
+            let res = self.check(self.wallet_kit().publish_transaction(req).await)?;
+            if !res.publish_error.is_empty() {
+                return Err(anyhow!("PublishTransaction failed: {}", res.publish_error));
+            }
+            Ok(BroadcastTx {
+                txid: types::txid(&raw_tx)?,
+                raw_tx: hex::encode(raw_tx),
+            })
+        }
+        #[cfg(not(feature = "lnd-grpc"))]
+        {
+            Err(anyhow!("lnd-grpc feature not enabled"))
+        }
+    }
+
+    async fn estimate_fee(&self, target_conf: u32) -> Result<FeeEstimate> {
+        #[cfg(feature = "lnd-grpc")]
+        {
+            let req = lnd_grpc_rust::walletrpc::EstimateFeeRequest {
+                conf_target: i32::try_from(target_conf)?,
+            };
+            let res = self.check(self.wallet_kit().estimate_fee(req).await)?;
+            Ok(FeeEstimate {
+                target_conf,
+                sat_per_vbyte: kw_to_vbyte(u64::try_from(res.sat_per_kw)?),
+            })
+        }
+        #[cfg(not(feature = "lnd-grpc"))]
+        {
+            Err(anyhow!("lnd-grpc feature not enabled"))
+        }
+    }
+
+    async fn estimate_onchain_send(
+        &self,
+        address: &str,
+        amount: Sat,
+        target_conf: u32,
+    ) -> Result<OnchainSendEstimate> {
+        #[cfg(feature = "lnd-grpc")]
+        {
+            let req = lnrpc::EstimateFeeRequest {
+                addr_to_amount: [(address.to_string(), i64::try_from(amount.as_sat())?)].into(),
+                target_conf: i32::try_from(target_conf)?,
+                ..Default::default()
+            };
+            let res = self.check(self.rpc().estimate_fee(req).await)?;
+            Ok(OnchainSendEstimate {
+                fee_sat: Sat::try_from(res.fee_sat)?,
+                sat_per_vbyte: res.sat_per_vbyte as f64,
+            })
+        }
+        #[cfg(not(feature = "lnd-grpc"))]
+        {
+            Err(anyhow!("lnd-grpc feature not enabled"))
+        }
+    }
+
+    async fn capabilities(&self) -> Result<Capabilities> {
+        #[cfg(feature = "lnd-grpc")]
+        {
+            let info = self.get_info().await?;
+            Ok(Capabilities {
+                backend: "lnd".into(),
+                version: info.version,
+                network: info.network,
+                supported: [
+                    Capability::CreateInvoice,
+                    Capability::DecodeInvoice,
+                    Capability::PayInvoice,
+                    Capability::ListInvoices,
+                    Capability::SignMessage,
+                    Capability::ChannelPolicy,
+                    Capability::ForwardingHistory,
+                    Capability::QueryRoutes,
+                    Capability::NetworkGraph,
+                    Capability::ProbePayment,
+                    Capability::ChannelBackup,
+                    Capability::Psbt,
+                    Capability::FeeEstimates,
+                ]
+                .into(),
+            })
+        }
+        #[cfg(not(feature = "lnd-grpc"))]
+        {
+            Err(anyhow!("lnd-grpc feature not enabled"))
+        }
+    }
+}
+
+/// An entry of ListInvoices, or a LookupInvoice reply.
+#[cfg(feature = "lnd-grpc")]
+fn invoice_of(inv: LndInvoice) -> Result<Invoice> {
+    Ok(Invoice {
+        hash: PaymentHash::from_slice(&inv.r_hash)?,
+        amount_msat: Msat::try_from(inv.value_msat)?,
+        state: match inv.state {
+            0 => "open".to_string(),
+            1 => "settled".to_string(),
+            2 => "canceled".to_string(),
+            3 => "accepted".to_string(),
+            _ => format!("unknown: {}", inv.state),
+        },
+        bolt11: if inv.payment_request.is_empty() {
+            None
+        } else {
+            Some(inv.payment_request.parse()?)
+        },
+        desc: if inv.memo.is_empty() {
+            None
+        } else {
+            Some(inv.memo)
+        },
+    })
+}
+
+/// Converts a QueryRoutes route; LND gives absolute expiries, which are made
+/// relative to `height`.
+#[cfg(feature = "lnd-grpc")]
+fn route(raw: &lnrpc::Route, height: u32) -> Result<Route> {
+    let hops = raw
+        .hops
+        .iter()
+        .map(|h| {
+            Ok(RouteHop {
+                pubkey: h.pub_key.parse()?,
+                channel: ShortChannelId::from_u64(h.chan_id),
+                amount_msat: Msat::try_from(h.amt_to_forward_msat)?,
+                delay: h.expiry.saturating_sub(height),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let fee_msat = Msat::try_from(raw.total_fees_msat)?;
+    let total_msat = Msat::try_from(raw.total_amt_msat)?;
+
+    Ok(Route {
+        amount_msat: total_msat.checked_sub(fee_msat).unwrap_or(Msat::ZERO),
+        fee_msat,
+        total_delay: raw.total_time_lock.saturating_sub(height),
+        hops,
+    })
 }
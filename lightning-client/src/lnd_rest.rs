@@ -1,54 +1,216 @@
 // lightning-client/src/lnd_rest.rs
 use super::*;
+use crate::config::ConnectionConfig;
+use crate::lnd_auth::{pem_to_der, LndCredentials};
+use crate::psbt::{PendingOpens, FUND_TARGET_CONF};
 use anyhow::{anyhow, Result};
-use reqwest::{Certificate, ClientBuilder};
+use base64::{engine::general_purpose, Engine as _};
+use reqwest::ClientBuilder;
+use rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+use rustls::{Certificate, CertificateError, ClientConfig, OwnedTrustAnchor, RootCertStore};
 use serde_json::json;
 use serde_json::Value;
-use std::fs;
+use std::time::{Duration, SystemTime};
+
+/// How long a PSBT funded open may take from start to finalize.
+const PSBT_OPEN_TIMEOUT: Duration = Duration::from_secs(30 * 60);
 
 pub struct LndRestClient {
     url: String,
     client: reqwest::Client,
     macaroon: String,
+    payment_timeout: Duration,
+    opens: PendingOpens,
 }
 
 impl LndRestClient {
-    pub fn new(host: &str, macaroon_hex: &str, cert_path: &str) -> Result<Self> {
-        let cert_path = cert_path.trim();
-        if cert_path.is_empty() {
-            return Err(anyhow!("cert_path is required"));
+    pub fn new(creds: &LndCredentials, connection: &ConnectionConfig) -> Result<Self> {
+        let mut builder = ClientBuilder::new()
+            .connect_timeout(connection.connect_timeout())
+            .timeout(connection.request_timeout());
+        if creds.cert_pem.is_some() || creds.tls_server_name.is_some() {
+            let verifier = PinnedCertVerifier::new(creds)?;
+            let tls = ClientConfig::builder()
+                .with_safe_defaults()
+                .with_custom_certificate_verifier(Arc::new(verifier))
+                .with_no_client_auth();
+            builder = builder.use_preconfigured_tls(tls);
         }
+        let client = builder
+            .build()
+            .map_err(|e| anyhow!("Failed to build TLS client: {}", e))?;
 
-        // --- Macaroon ---
-        let macaroon = hex::encode(hex::decode(macaroon_hex)?);
+        Ok(Self {
+            url: format!("https://{}", creds.host),
+            client,
+            macaroon: creds.macaroon_hex.clone(),
+            payment_timeout: connection.payment_timeout(),
+            opens: PendingOpens::default(),
+        })
+    }
 
-        // --- Read PEM certificate ---
-        let cert_pem = fs::read_to_string(cert_path)
-            .map_err(|e| anyhow!("Failed to read cert file '{}': {}", cert_path, e))?;
+    /// First route QueryRoutes finds, as LND returns it.
+    async fn find_route(&self, dest: &str, amount: Msat, final_cltv: Option<u64>) -> Result<Value> {
+        let mut url = format!(
+            "{}/v1/graph/routes/{}/0?amt_msat={}",
+            self.url,
+            dest,
+            amount.as_msat()
+        );
+        if let Some(cltv) = final_cltv {
+            url.push_str(&format!("&final_cltv_delta={}", cltv));
+        }
+        let res: Value = self
+            .client
+            .get(url)
+            .header("Grpc-Metadata-macaroon", &self.macaroon)
+            .send()
+            .await?
+            .json()
+            .await?;
+        match res["routes"].get(0) {
+            Some(route) => Ok(route.clone()),
+            None => Err(anyhow!(
+                "No route to {}: {}",
+                dest,
+                res["message"].as_str().unwrap_or("none found")
+            )),
+        }
+    }
 
-        // --- Parse as PEM (your file is PEM!) ---
-        let cert = Certificate::from_pem(cert_pem.as_bytes())
-            .map_err(|e| anyhow!("Invalid PEM certificate in '{}': {}", cert_path, e))?;
+    async fn block_height(&self) -> Result<u32> {
+        self.get_info()
+            .await?
+            .block_height
+            .ok_or_else(|| anyhow!("LND did not report its block height"))
+    }
 
-        // --- Build client ---
-        let client = ClientBuilder::new()
-            .add_root_certificate(cert)
-            .danger_accept_invalid_certs(true)
-            .build()
-            .map_err(|e| anyhow!("Failed to build TLS client: {}", e))?;
+    /// Sends one FundingStateStep message for a pending PSBT open.
+    async fn funding_step(&self, step: Value) -> Result<()> {
+        let res: Value = self
+            .client
+            .post(format!("{}/v1/funding/step", self.url))
+            .header("Grpc-Metadata-macaroon", &self.macaroon)
+            .json(&step)
+            .send()
+            .await?
+            .json()
+            .await?;
+        match res["message"].as_str() {
+            Some(err) => Err(anyhow!("FundingStateStep failed: {}", err)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Newline-delimited JSON messages of a grpc-gateway server stream.
+struct JsonStream {
+    res: reqwest::Response,
+    buf: Vec<u8>,
+}
+
+impl JsonStream {
+    /// The next message's `result`; `None` once the stream ends.
+    async fn next(&mut self) -> Result<Option<Value>> {
+        loop {
+            if let Some(end) = self.buf.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = self.buf.drain(..=end).collect();
+                if line.iter().all(u8::is_ascii_whitespace) {
+                    continue;
+                }
+                let mut msg: Value = serde_json::from_slice(&line)?;
+                if let Some(result) = msg.get_mut("result") {
+                    return Ok(Some(result.take()));
+                }
+                let err = msg.get("error").unwrap_or(&msg);
+                return Err(anyhow!(
+                    "LND: {}",
+                    err["message"].as_str().unwrap_or("stream error")
+                ));
+            }
+            match self.res.chunk().await? {
+                Some(chunk) => self.buf.extend_from_slice(&chunk),
+                None if self.buf.iter().all(u8::is_ascii_whitespace) => return Ok(None),
+                None => self.buf.push(b'\n'),
+            }
+        }
+    }
+}
+
+/// Checks the server against the pinned LND certificate (or the public
+/// roots when none is pinned) under `tls_server_name` if one is set.
+struct PinnedCertVerifier {
+    pinned: Option<Vec<u8>>,
+    server_name: String,
+    webpki: WebPkiVerifier,
+}
+
+impl PinnedCertVerifier {
+    fn new(creds: &LndCredentials) -> Result<Self> {
+        let pinned = creds.cert_pem.as_deref().map(pem_to_der).transpose()?;
+
+        let mut roots = RootCertStore::empty();
+        match &pinned {
+            Some(der) => roots
+                .add(&Certificate(der.clone()))
+                .map_err(|e| anyhow!("Invalid LND certificate: {}", e))?,
+            None => roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+                OwnedTrustAnchor::from_subject_spki_name_constraints(
+                    ta.subject,
+                    ta.spki,
+                    ta.name_constraints,
+                )
+            })),
+        }
+
+        let server_name = creds.server_name().to_string();
+        rustls::ServerName::try_from(server_name.as_str())
+            .map_err(|_| anyhow!("Invalid TLS server name '{}'", server_name))?;
 
         Ok(Self {
-            url: host.trim_end_matches('/').to_string(),
-            client,
-            macaroon,
+            pinned,
+            server_name,
+            webpki: WebPkiVerifier::new(roots, None),
         })
     }
 }
 
+impl ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        _server_name: &rustls::ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        // LND's self-signed cert is flagged as a CA, which WebPKI refuses as
+        // a leaf. A byte-exact match with the pin only needs the name check.
+        if self.pinned.as_deref() == Some(end_entity.0.as_slice()) {
+            let cert = webpki::EndEntityCert::try_from(end_entity.0.as_slice())
+                .map_err(|_| rustls::Error::InvalidCertificate(CertificateError::BadEncoding))?;
+            let name =
+                webpki::SubjectNameRef::try_from_ascii_str(&self.server_name).map_err(|_| {
+                    rustls::Error::InvalidCertificate(CertificateError::NotValidForName)
+                })?;
+            cert.verify_is_valid_for_subject_name(name).map_err(|_| {
+                rustls::Error::InvalidCertificate(CertificateError::NotValidForName)
+            })?;
+            return Ok(ServerCertVerified::assertion());
+        }
+
+        let name = rustls::ServerName::try_from(self.server_name.as_str())
+            .map_err(|_| rustls::Error::InvalidCertificate(CertificateError::NotValidForName))?;
+        self.webpki
+            .verify_server_cert(end_entity, intermediates, &name, scts, ocsp_response, now)
+    }
+}
+
 // --- API calls (unchanged) ---
 #[async_trait]
 impl LightningClient for LndRestClient {
-    async fn get_info(&mut self) -> Result<NodeInfo> {
+    async fn get_info(&self) -> Result<NodeInfo> {
         let res: serde_json::Value = self
             .client
             .get(format!("{}/v1/getinfo", self.url))
@@ -58,23 +220,48 @@ impl LightningClient for LndRestClient {
             .json()
             .await?;
 
+        let text = |key: &str| {
+            res[key]
+                .as_str()
+                .filter(|s| !s.is_empty())
+                .map(ToString::to_string)
+        };
+        let count = |key: &str| res[key].as_u64().map(|n| n as u32);
+
         Ok(NodeInfo {
             alias: res["alias"].as_str().unwrap_or("LND").to_string(),
-            identity_pubkey: res["identity_pubkey"]
-                .as_str()
-                .unwrap_or("unknown")
-                .to_string(),
+            identity_pubkey: res["identity_pubkey"].as_str().and_then(|k| k.parse().ok()),
+            implementation: "lnd".into(),
+            version: text("version"),
+            network: res["chains"][0]["network"].as_str().map(normalize_network),
+            block_height: count("block_height"),
+            block_hash: text("block_hash"),
+            synced_to_chain: res["synced_to_chain"].as_bool(),
+            synced_to_graph: res["synced_to_graph"].as_bool(),
+            num_active_channels: count("num_active_channels"),
+            num_pending_channels: count("num_pending_channels"),
+            num_inactive_channels: count("num_inactive_channels"),
+            num_peers: count("num_peers"),
+            uris: res["uris"]
+                .as_array()
+                .map(|uris| {
+                    uris.iter()
+                        .filter_map(|u| u.as_str().map(ToString::to_string))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            color: text("color"),
         })
     }
 
     async fn create_invoice(
-        &mut self,
-        msat: u64,
+        &self,
+        amount: Msat,
         _label: Option<&str>,
         desc: Option<&str>,
-    ) -> Result<String> {
+    ) -> Result<Bolt11> {
         let payload = serde_json::json!({
-            "value_msat": msat,
+            "value_msat": amount.to_i64()?,
             "memo": desc.unwrap_or("rust")
         });
 
@@ -88,13 +275,40 @@ impl LightningClient for LndRestClient {
             .json()
             .await?;
 
-        Ok(res["payment_request"]
+        res["payment_request"]
             .as_str()
             .ok_or_else(|| anyhow!("no payment_request"))?
-            .to_string())
+            .parse()
     }
 
-    async fn get_balance(&mut self) -> Result<Balance> {
+    async fn create_invoice_hashed(
+        &self,
+        amount: Msat,
+        _label: Option<&str>,
+        description: &str,
+    ) -> Result<Bolt11> {
+        let payload = serde_json::json!({
+            "value_msat": amount.to_i64()?,
+            "description_hash": general_purpose::STANDARD.encode(description_hash(description)),
+        });
+
+        let res: serde_json::Value = self
+            .client
+            .post(format!("{}/v1/invoices", self.url))
+            .header("Grpc-Metadata-macaroon", &self.macaroon)
+            .json(&payload)
+            .send()
+            .await?
+            .json()
+            .await?;
+
+        res["payment_request"]
+            .as_str()
+            .ok_or_else(|| anyhow!("no payment_request"))?
+            .parse()
+    }
+
+    async fn get_balance(&self) -> Result<Balance> {
         let wallet_url = format!("{}/v1/balance/wallet", self.url);
         let wallet_res: Value = self
             .client
@@ -104,7 +318,7 @@ impl LightningClient for LndRestClient {
             .await?
             .json()
             .await?;
-        let onchain_sat = wallet_res["confirmed_balance"].as_u64().unwrap_or(0);
+        let onchain_sat = Sat::from_sat(wallet_res["confirmed_balance"].as_u64().unwrap_or(0));
 
         let chan_url = format!("{}/v1/balance/channels", self.url);
         let chan_res: Value = self
@@ -115,7 +329,7 @@ impl LightningClient for LndRestClient {
             .await?
             .json()
             .await?;
-        let channel_msat = chan_res["local_balance"].as_u64().unwrap_or(0);
+        let channel_msat = Msat::from_msat(chan_res["local_balance"].as_u64().unwrap_or(0));
 
         Ok(Balance {
             onchain_sat,
@@ -123,7 +337,7 @@ impl LightningClient for LndRestClient {
         })
     }
 
-    async fn list_invoices(&mut self, limit: Option<usize>) -> Result<Vec<Invoice>> {
+    async fn list_invoices(&self, limit: Option<usize>) -> Result<Vec<Invoice>> {
         let url = format!(
             "{}/v1/invoices?num_max_invoices={}",
             self.url,
@@ -138,27 +352,37 @@ impl LightningClient for LndRestClient {
             .json()
             .await?;
 
-        let mut invoices = vec![];
-        if let Some(inv_list) = res["invoices"].as_array() {
-            for inv in inv_list {
-                invoices.push(Invoice {
-                    hash: inv["r_hash"].as_str().unwrap_or("").to_string(),
-                    amount_msat: inv["value_msat"].as_u64().unwrap_or(0),
-                    state: if inv["settled"].as_bool().unwrap_or(false) {
-                        "paid".to_string()
-                    } else {
-                        "unpaid".to_string()
-                    },
-                    bolt11: inv["payment_request"].as_str().map(ToString::to_string),
-                    desc: inv["memo"].as_str().and_then(|s| if s.is_empty() { None } else { Some(s.to_string()) }),
-                });
-            }
-        }
+        let invoices = res["invoices"]
+            .as_array()
+            .map(|list| list.iter().filter_map(invoice_of).collect())
+            .unwrap_or_default();
         Ok(invoices)
     }
 
-    async fn decode_invoice(&mut self, bolt11: &str) -> Result<DecodedInvoice> {
-        let payload = json!({ "pay_req": bolt11 });
+    async fn lookup_invoice(&self, hash: &PaymentHash) -> Result<Option<Invoice>> {
+        let res: Value = self
+            .client
+            .get(format!("{}/v1/invoice/{}", self.url, hash))
+            .header("Grpc-Metadata-macaroon", &self.macaroon)
+            .send()
+            .await?
+            .json()
+            .await?;
+        // NotFound, or Unknown with this message before LND 0.18.
+        if res["code"].as_u64() == Some(5)
+            || res["message"]
+                .as_str()
+                .is_some_and(|m| m.contains("unable to locate invoice"))
+        {
+            return Ok(None);
+        }
+        invoice_of(&res)
+            .map(Some)
+            .ok_or_else(|| anyhow!("LookupInvoice failed: {}", res))
+    }
+
+    async fn decode_invoice(&self, bolt11: &Bolt11) -> Result<DecodedInvoice> {
+        let payload = json!({ "pay_req": bolt11.as_str() });
         let res: Value = self
             .client
             .post(format!("{}/v1/payreq", self.url))
@@ -170,9 +394,13 @@ impl LightningClient for LndRestClient {
             .await?;
 
         let num_sat = res["num_satoshis"].as_u64().unwrap_or(0);
-        let amount_msat = if num_sat == 0 { None } else { Some(num_sat * 1000) };
+        let amount_msat = if num_sat == 0 {
+            None
+        } else {
+            Some(Sat::from_sat(num_sat).to_msat()?)
+        };
         let desc = res["description"].as_str().map(ToString::to_string);
-        let payee = res["destination"].as_str().map(ToString::to_string);
+        let payee = res["destination"].as_str().map(str::parse).transpose()?;
 
         Ok(DecodedInvoice {
             amount_msat,
@@ -181,30 +409,836 @@ impl LightningClient for LndRestClient {
         })
     }
 
-    async fn pay_invoice(&mut self, bolt11: &str) -> Result<PaymentResult> {
-        let payload = json!({ "payment_request": bolt11 });
-        let res: Value = self
+    async fn pay_invoice(&self, bolt11: &Bolt11) -> Result<PaymentResult> {
+        let payload = json!({ "payment_request": bolt11.as_str() });
+        // Once the request may have reached LND, a lost connection or a
+        // timeout leaves the payment in flight rather than failed.
+        let pending = |e: reqwest::Error| PaymentPending::error(bolt11.payment_hash(), e);
+        let res = self
             .client
             .post(format!("{}/v1/sendpaymentsync", self.url))
             .header("Grpc-Metadata-macaroon", &self.macaroon)
+            .timeout(self.payment_timeout)
             .json(&payload)
             .send()
-            .await?
-            .json()
-            .await?;
+            .await;
+        let res: Value = match res {
+            Ok(res) => res.json().await.map_err(pending)?,
+            Err(e) if e.is_connect() => return Err(e.into()),
+            Err(e) => return Err(pending(e)),
+        };
 
-        if let Some(err) = res["payment_error"].as_str() {
+        if let Some(err) = res["payment_error"].as_str().filter(|e| !e.is_empty()) {
+            // ErrPaymentInFlight: an earlier attempt is still running.
+            if err.contains("in transition") {
+                return Err(PaymentPending::error(bolt11.payment_hash(), err));
+            }
+            return Err(anyhow!("Payment failed: {}", err));
+        }
+        if let Some(err) = res["message"].as_str() {
             return Err(anyhow!("Payment failed: {}", err));
         }
 
-        let hash = res["payment_hash"].as_str().ok_or_else(|| anyhow!("no payment_hash"))?.to_string();
-        let amount_msat = res["amount_msat"].as_u64().ok_or_else(|| anyhow!("no amount_msat"))?;
-        let fee_msat = res["fee_msat"].as_u64();
+        let hash = bytes_field(&res["payment_hash"]).ok_or_else(|| anyhow!("no payment_hash"))?;
+        let route = &res["payment_route"];
+        let fee_msat = u64_field(&route["total_fees_msat"]);
+        let amount_msat = u64_field(&route["total_amt_msat"])
+            .map(|total| Msat::from_msat(total.saturating_sub(fee_msat.unwrap_or(0))));
+        let fee_msat = fee_msat.map(Msat::from_msat);
+        let preimage = bytes_field(&res["payment_preimage"]);
 
         Ok(PaymentResult {
             hash,
             amount_msat,
             fee_msat,
+            preimage,
+        })
+    }
+
+    async fn lookup_payment(&self, hash: &PaymentHash) -> Result<PaymentState> {
+        // TrackPaymentV2 streams updates and stays open while the payment
+        // is in flight; the first message is its current state.
+        let mut res = self
+            .client
+            .get(format!(
+                "{}/v2/router/track/{}",
+                self.url,
+                general_purpose::URL_SAFE.encode(hash.as_bytes())
+            ))
+            .header("Grpc-Metadata-macaroon", &self.macaroon)
+            .send()
+            .await?;
+        let mut line = Vec::new();
+        while let Some(chunk) = res.chunk().await? {
+            line.extend_from_slice(&chunk);
+            if let Some(end) = line.iter().position(|b| *b == b'\n') {
+                line.truncate(end);
+                break;
+            }
+        }
+        let msg: Value = serde_json::from_slice(&line)?;
+
+        // NotFound: LND never saw a payment with this hash.
+        if msg["error"]["code"].as_u64() == Some(5) {
+            return Ok(PaymentState::Unknown);
+        }
+        let payment = &msg["result"];
+        Ok(match payment["status"].as_str() {
+            Some("SUCCEEDED") => PaymentState::Succeeded(PaymentResult {
+                hash: *hash,
+                amount_msat: u64_field(&payment["value_msat"]).map(Msat::from_msat),
+                fee_msat: u64_field(&payment["fee_msat"]).map(Msat::from_msat),
+                preimage: bytes_field(&payment["payment_preimage"]),
+            }),
+            Some("FAILED") => PaymentState::Failed,
+            Some("IN_FLIGHT" | "INITIATED") => PaymentState::Pending,
+            _ => return Err(anyhow!("TrackPaymentV2 failed: {}", msg)),
         })
     }
-}
\ No newline at end of file
+
+    async fn sign_message(&self, message: &str) -> Result<String> {
+        let payload = json!({ "msg": general_purpose::STANDARD.encode(message) });
+        let res: Value = self
+            .client
+            .post(format!("{}/v1/signmessage", self.url))
+            .header("Grpc-Metadata-macaroon", &self.macaroon)
+            .json(&payload)
+            .send()
+            .await?
+            .json()
+            .await?;
+
+        res["signature"]
+            .as_str()
+            .map(ToString::to_string)
+            .ok_or_else(|| anyhow!("signmessage failed: {}", res))
+    }
+
+    async fn verify_message(&self, message: &str, signature: &str) -> Result<SignatureCheck> {
+        let payload = json!({
+            "msg": general_purpose::STANDARD.encode(message),
+            "signature": signature,
+        });
+        let res: Value = self
+            .client
+            .post(format!("{}/v1/verifymessage", self.url))
+            .header("Grpc-Metadata-macaroon", &self.macaroon)
+            .json(&payload)
+            .send()
+            .await?
+            .json()
+            .await?;
+
+        if let Some(err) = res["message"].as_str() {
+            return Err(anyhow!("verifymessage failed: {}", err));
+        }
+        Ok(SignatureCheck {
+            valid: res["valid"].as_bool().unwrap_or(false),
+            pubkey: res["pubkey"].as_str().and_then(|k| k.parse().ok()),
+        })
+    }
+
+    async fn update_channel_policy(
+        &self,
+        channel: &ChannelSelector,
+        policy: &ChannelPolicy,
+    ) -> Result<()> {
+        let time_lock_delta = policy
+            .time_lock_delta
+            .ok_or_else(|| anyhow!("LND needs time_lock_delta for a policy update"))?;
+        let mut payload = json!({
+            "base_fee_msat": policy.base_fee_msat.to_i64()?.to_string(),
+            "fee_rate_ppm": policy.fee_ppm,
+            "time_lock_delta": time_lock_delta,
+        });
+        match channel {
+            ChannelSelector::All => payload["global"] = json!(true),
+            ChannelSelector::Outpoint { txid, vout } => {
+                payload["chan_point"] = json!({ "funding_txid_str": txid, "output_index": vout })
+            }
+            ChannelSelector::Short(scid) => {
+                // LND addresses channels by funding outpoint only.
+                let edge: Value = self
+                    .client
+                    .get(format!("{}/v1/graph/edge/{}", self.url, scid.as_u64()))
+                    .header("Grpc-Metadata-macaroon", &self.macaroon)
+                    .send()
+                    .await?
+                    .json()
+                    .await?;
+                let (txid, vout) = edge["chan_point"]
+                    .as_str()
+                    .and_then(|p| p.split_once(':'))
+                    .ok_or_else(|| anyhow!("Channel {} not found: {}", scid, edge))?;
+                payload["chan_point"] = json!({
+                    "funding_txid_str": txid,
+                    "output_index": vout.parse::<u32>()?,
+                });
+            }
+        }
+        if let Some(min) = policy.min_htlc_msat {
+            payload["min_htlc_msat"] = json!(min.as_msat().to_string());
+            payload["min_htlc_msat_specified"] = json!(true);
+        }
+        if let Some(max) = policy.max_htlc_msat {
+            payload["max_htlc_msat"] = json!(max.as_msat().to_string());
+        }
+
+        let res: Value = self
+            .client
+            .post(format!("{}/v1/chanpolicy", self.url))
+            .header("Grpc-Metadata-macaroon", &self.macaroon)
+            .json(&payload)
+            .send()
+            .await?
+            .json()
+            .await?;
+        if let Some(err) = res["message"].as_str() {
+            return Err(anyhow!("Policy update failed: {}", err));
+        }
+        let failed: Vec<String> = res["failed_updates"]
+            .as_array()
+            .map(|f| {
+                f.iter()
+                    .map(|u| format!("{}: {}", u["outpoint"], u["update_error"]))
+                    .collect()
+            })
+            .unwrap_or_default();
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("Policy update failed for {}", failed.join("; ")))
+        }
+    }
+
+    async fn list_forwards(&self, query: &ForwardQuery) -> Result<ForwardPage> {
+        let limit = query.limit.unwrap_or(100);
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_secs();
+        let payload = json!({
+            "start_time": query.start.unwrap_or(0).to_string(),
+            "end_time": query.end.unwrap_or(now).to_string(),
+            "index_offset": u32::try_from(query.offset)?,
+            "num_max_events": u32::try_from(limit)?,
+        });
+        let res: Value = self
+            .client
+            .post(format!("{}/v1/switch", self.url))
+            .header("Grpc-Metadata-macaroon", &self.macaroon)
+            .json(&payload)
+            .send()
+            .await?
+            .json()
+            .await?;
+        let events = res["forwarding_events"]
+            .as_array()
+            .ok_or_else(|| anyhow!("ForwardingHistory failed: {}", res))?;
+
+        let forwards: Vec<Forward> = events
+            .iter()
+            .map(|e| {
+                let msat = |key: &str| Msat::from_msat(u64_field(&e[key]).unwrap_or(0));
+                let chan = |key: &str| u64_field(&e[key]).map(ShortChannelId::from_u64);
+                Forward {
+                    timestamp: u64_field(&e["timestamp_ns"]).unwrap_or(0) / 1_000_000_000,
+                    chan_in: chan("chan_id_in"),
+                    chan_out: chan("chan_id_out"),
+                    amount_in_msat: msat("amt_in_msat"),
+                    amount_out_msat: msat("amt_out_msat"),
+                    fee_msat: msat("fee_msat"),
+                }
+            })
+            .collect();
+        let next_offset = (forwards.len() >= limit)
+            .then(|| u64_field(&res["last_offset_index"]))
+            .flatten();
+        Ok(ForwardPage {
+            forwards,
+            next_offset,
+        })
+    }
+
+    async fn query_routes(&self, dest: &PublicKey, amount: Msat) -> Result<Route> {
+        let raw = self.find_route(&dest.to_string(), amount, None).await?;
+        route(&raw, self.block_height().await?)
+    }
+
+    async fn get_node(&self, pubkey: &PublicKey) -> Result<GraphNode> {
+        let res: Value = self
+            .client
+            .get(format!("{}/v1/graph/node/{}", self.url, pubkey))
+            .header("Grpc-Metadata-macaroon", &self.macaroon)
+            .send()
+            .await?
+            .json()
+            .await?;
+        let node = res
+            .get("node")
+            .ok_or_else(|| anyhow!("Node {} not found: {}", pubkey, res))?;
+        let text = |key: &str| {
+            node[key]
+                .as_str()
+                .filter(|s| !s.is_empty())
+                .map(ToString::to_string)
+        };
+
+        Ok(GraphNode {
+            pubkey: *pubkey,
+            alias: text("alias"),
+            color: text("color"),
+            addresses: node["addresses"]
+                .as_array()
+                .map(|addrs| {
+                    addrs
+                        .iter()
+                        .filter_map(|a| a["addr"].as_str().map(ToString::to_string))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            // Zero until the node has announced itself.
+            last_update: node["last_update"].as_u64().filter(|&t| t > 0),
+        })
+    }
+
+    async fn get_channel(&self, scid: ShortChannelId) -> Result<GraphChannel> {
+        let res: Value = self
+            .client
+            .get(format!("{}/v1/graph/edge/{}", self.url, scid.as_u64()))
+            .header("Grpc-Metadata-macaroon", &self.macaroon)
+            .send()
+            .await?
+            .json()
+            .await?;
+        let key = |field: &str| -> Result<PublicKey> {
+            res[field]
+                .as_str()
+                .ok_or_else(|| anyhow!("Channel {} not found: {}", scid, res))?
+                .parse()
+        };
+        let (node1, node2) = (key("node1_pub")?, key("node2_pub")?);
+
+        let policies = [(node1, "node1_policy"), (node2, "node2_policy")]
+            .into_iter()
+            .filter(|(_, field)| res[*field].is_object())
+            .map(|(source, field)| {
+                let p = &res[field];
+                let msat = |key: &str| Msat::from_msat(u64_field(&p[key]).unwrap_or(0));
+                EdgePolicy {
+                    source,
+                    base_fee_msat: msat("fee_base_msat"),
+                    fee_ppm: u64_field(&p["fee_rate_milli_msat"]).unwrap_or(0) as u32,
+                    time_lock_delta: p["time_lock_delta"].as_u64().unwrap_or(0) as u32,
+                    min_htlc_msat: msat("min_htlc"),
+                    max_htlc_msat: u64_field(&p["max_htlc_msat"]).map(Msat::from_msat),
+                    disabled: p["disabled"].as_bool().unwrap_or(false),
+                    last_update: p["last_update"].as_u64(),
+                }
+            })
+            .collect();
+
+        Ok(GraphChannel {
+            scid,
+            node1,
+            node2,
+            capacity_sat: u64_field(&res["capacity"]).map(Sat::from_sat),
+            policies,
+        })
+    }
+
+    async fn probe_payment(&self, bolt11: &Bolt11) -> Result<ProbeResult> {
+        let decoded: Value = self
+            .client
+            .post(format!("{}/v1/payreq", self.url))
+            .header("Grpc-Metadata-macaroon", &self.macaroon)
+            .json(&json!({ "pay_req": bolt11.as_str() }))
+            .send()
+            .await?
+            .json()
+            .await?;
+        let dest = decoded["destination"]
+            .as_str()
+            .ok_or_else(|| anyhow!("DecodePayReq failed: {}", decoded))?;
+        let amount = bolt11
+            .amount()
+            .ok_or_else(|| anyhow!("Cannot probe an invoice without an amount"))?;
+
+        let final_cltv = u64_field(&decoded["cltv_expiry"]);
+        let mut raw = match self.find_route(dest, amount, final_cltv).await {
+            Ok(raw) => raw,
+            // Anything LND itself answers means there is no usable route.
+            Err(e) if e.downcast_ref::<reqwest::Error>().is_none() => {
+                return Ok(ProbeResult {
+                    reachable: false,
+                    route: None,
+                    failure: Some(e.to_string()),
+                })
+            }
+            Err(e) => return Err(e),
+        };
+        let route = route(&raw, self.block_height().await?)?;
+        // The payee rejects payments without its payment secret outright.
+        if let (Some(addr), Some(last)) = (
+            decoded["payment_addr"].as_str(),
+            raw["hops"].as_array_mut().and_then(|h| h.last_mut()),
+        ) {
+            last["mpp_record"] = json!({
+                "payment_addr": addr,
+                "total_amt_msat": amount.as_msat().to_string(),
+            });
+        }
+
+        let hash = PaymentHash::random();
+        let payload = json!({
+            "payment_hash": general_purpose::STANDARD.encode(hash.as_bytes()),
+            "route": raw,
+        });
+        let pending = |e: reqwest::Error| PaymentPending::error(Some(hash), e);
+        let res = self
+            .client
+            .post(format!("{}/v1/channels/transactions/route", self.url))
+            .header("Grpc-Metadata-macaroon", &self.macaroon)
+            .timeout(self.payment_timeout)
+            .json(&payload)
+            .send()
+            .await;
+        let res: Value = match res {
+            Ok(res) => res.json().await.map_err(pending)?,
+            Err(e) if e.is_connect() => return Err(e.into()),
+            Err(e) => return Err(pending(e)),
+        };
+        if let Some(err) = res["message"].as_str() {
+            return Err(anyhow!("SendToRoute failed: {}", err));
+        }
+
+        let error = res["payment_error"].as_str().unwrap_or_default();
+        let reachable = error.starts_with("IncorrectOrUnknownPaymentDetails");
+        Ok(ProbeResult {
+            reachable,
+            route: Some(route),
+            failure: (!reachable).then(|| error.to_string()),
+        })
+    }
+
+    async fn export_channel_backup(&self) -> Result<ChannelBackup> {
+        let res: Value = self
+            .client
+            .get(format!("{}/v1/channels/backup", self.url))
+            .header("Grpc-Metadata-macaroon", &self.macaroon)
+            .send()
+            .await?
+            .json()
+            .await?;
+        let multi = &res["multi_chan_backup"];
+        let data = multi["multi_chan_backup"]
+            .as_str()
+            .and_then(|b| general_purpose::STANDARD.decode(b).ok())
+            .ok_or_else(|| anyhow!("ExportAllChannelBackups failed: {}", res))?;
+        let channels = multi["chan_points"]
+            .as_array()
+            .map(|points| points.iter().filter_map(chan_point).collect())
+            .unwrap_or_default();
+        Ok(ChannelBackup { data, channels })
+    }
+
+    async fn verify_channel_backup(&self, data: &[u8]) -> Result<BackupCheck> {
+        let payload = json!({
+            "multi_chan_backup": {
+                "multi_chan_backup": general_purpose::STANDARD.encode(data),
+            },
+        });
+        let res: Value = self
+            .client
+            .post(format!("{}/v1/channels/backup/verify", self.url))
+            .header("Grpc-Metadata-macaroon", &self.macaroon)
+            .json(&payload)
+            .send()
+            .await?
+            .json()
+            .await?;
+        if let Some(err) = res["message"].as_str() {
+            return Ok(BackupCheck {
+                valid: false,
+                channels: Vec::new(),
+                error: Some(err.to_string()),
+            });
+        }
+        // Older LND versions answer with an empty object.
+        let channels = res["chan_points"]
+            .as_array()
+            .map(|points| {
+                points
+                    .iter()
+                    .filter_map(|p| p.as_str().map(ToString::to_string))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(BackupCheck {
+            valid: true,
+            channels,
+            error: None,
+        })
+    }
+
+    async fn open_channel_psbt(&self, open: &PsbtChannelOpen) -> Result<PsbtFunding> {
+        let pending_id: [u8; 32] = rand::random();
+        let id = hex::encode(pending_id);
+        let mut shim = json!({ "pending_chan_id": general_purpose::STANDARD.encode(pending_id) });
+        if let Some(psbt) = &open.psbt {
+            shim["base_psbt"] = json!(general_purpose::STANDARD.encode(psbt.as_bytes()));
+        }
+        let payload = json!({
+            "node_pubkey": general_purpose::STANDARD.encode(open.peer.as_bytes()),
+            "local_funding_amount": open.amount_sat,
+            "private": open.private,
+            "funding_shim": { "psbt_shim": shim },
+        });
+        let res = self
+            .client
+            .post(format!("{}/v1/channels/stream", self.url))
+            .header("Grpc-Metadata-macaroon", &self.macaroon)
+            .timeout(PSBT_OPEN_TIMEOUT)
+            .json(&payload)
+            .send()
+            .await?;
+        let mut stream = JsonStream {
+            res,
+            buf: Vec::new(),
+        };
+
+        let update = stream
+            .next()
+            .await?
+            .ok_or_else(|| anyhow!("LND closed the channel open stream"))?;
+        let fund = &update["psbt_fund"];
+        let psbt = fund["psbt"]
+            .as_str()
+            .ok_or_else(|| anyhow!("Expected a PSBT funding request, got {}", update))?
+            .parse()?;
+        let funding = PsbtFunding {
+            id: id.clone(),
+            funding_address: fund["funding_address"].as_str().map(ToString::to_string),
+            amount_sat: Sat::from_sat(u64_field(&fund["funding_amount"]).unwrap_or(0)),
+            psbt,
+        };
+
+        let tx = self.opens.insert(&id);
+        let opens = self.opens.clone();
+        tokio::spawn(async move {
+            let outpoint = loop {
+                match stream.next().await {
+                    Ok(Some(update)) => {
+                        let pending = &update["chan_pending"];
+                        if pending.is_object() {
+                            let point = json!({
+                                "funding_txid_bytes": pending["txid"],
+                                "output_index": pending["output_index"],
+                            });
+                            break chan_point(&point)
+                                .ok_or_else(|| anyhow!("Bad chan_pending update: {}", update));
+                        }
+                    }
+                    Ok(None) => break Err(anyhow!("LND closed the channel open stream")),
+                    Err(e) => break Err(e),
+                }
+            };
+            if let Err(e) = &outpoint {
+                eprintln!("PSBT channel open {} ended: {}", id, e);
+                opens.remove(&id);
+            }
+            let _ = tx.send(outpoint);
+        });
+        Ok(funding)
+    }
+
+    async fn update_channel_psbt(&self, id: &str, psbt: &Psbt) -> Result<Psbt> {
+        self.opens.check(id)?;
+        let pending_id = hex::decode(id)?;
+        self.funding_step(json!({
+            "psbt_verify": {
+                "funded_psbt": general_purpose::STANDARD.encode(psbt.as_bytes()),
+                "pending_chan_id": general_purpose::STANDARD.encode(pending_id),
+            },
+        }))
+        .await?;
+        Ok(psbt.clone())
+    }
+
+    async fn finalize_channel_psbt(&self, id: &str, signed: &Psbt) -> Result<PsbtChannelOpened> {
+        self.opens.check(id)?;
+        let pending_id = hex::decode(id)?;
+        self.funding_step(json!({
+            "psbt_finalize": {
+                "signed_psbt": general_purpose::STANDARD.encode(signed.as_bytes()),
+                "pending_chan_id": general_purpose::STANDARD.encode(pending_id),
+            },
+        }))
+        .await?;
+        let channel = self.opens.published(id).await?;
+        Ok(PsbtChannelOpened {
+            txid: channel.split(':').next().unwrap_or_default().to_string(),
+            channel,
+        })
+    }
+
+    async fn cancel_channel_psbt(&self, id: &str) -> Result<()> {
+        let pending_id = hex::decode(id)?;
+        self.funding_step(json!({
+            "shim_cancel": {
+                "pending_chan_id": general_purpose::STANDARD.encode(pending_id),
+            },
+        }))
+        .await?;
+        self.opens.remove(id);
+        Ok(())
+    }
+
+    async fn fund_psbt(&self, request: &FundPsbtRequest) -> Result<FundedPsbt> {
+        let outputs: serde_json::Map<String, Value> = request
+            .outputs
+            .iter()
+            .map(|o| (o.address.clone(), json!(o.amount_sat)))
+            .collect();
+        let mut payload = json!({ "raw": { "outputs": outputs } });
+        match request.sat_per_vbyte {
+            Some(rate) => payload["sat_per_vbyte"] = json!(rate),
+            None => payload["target_conf"] = json!(FUND_TARGET_CONF),
+        }
+        let res: Value = self
+            .client
+            .post(format!("{}/v2/wallet/psbt/fund", self.url))
+            .header("Grpc-Metadata-macaroon", &self.macaroon)
+            .json(&payload)
+            .send()
+            .await?
+            .json()
+            .await?;
+        let psbt = res["funded_psbt"]
+            .as_str()
+            .ok_or_else(|| anyhow!("FundPsbt failed: {}", res))?
+            .parse()?;
+        Ok(FundedPsbt {
+            psbt,
+            change_index: res["change_output_index"]
+                .as_i64()
+                .and_then(|i| u32::try_from(i).ok()),
+        })
+    }
+
+    async fn sign_psbt(&self, psbt: &Psbt) -> Result<Psbt> {
+        let res: Value = self
+            .client
+            .post(format!("{}/v2/wallet/psbt/sign", self.url))
+            .header("Grpc-Metadata-macaroon", &self.macaroon)
+            .json(&json!({ "funded_psbt": general_purpose::STANDARD.encode(psbt.as_bytes()) }))
+            .send()
+            .await?
+            .json()
+            .await?;
+        res["signed_psbt"]
+            .as_str()
+            .ok_or_else(|| anyhow!("SignPsbt failed: {}", res))?
+            .parse()
+    }
+
+    async fn finalize_psbt(&self, psbt: &Psbt) -> Result<BroadcastTx> {
+        let res: Value = self
+            .client
+            .post(format!("{}/v2/wallet/psbt/finalize", self.url))
+            .header("Grpc-Metadata-macaroon", &self.macaroon)
+            .json(&json!({ "funded_psbt": general_purpose::STANDARD.encode(psbt.as_bytes()) }))
+            .send()
+            .await?
+            .json()
+            .await?;
+        let raw_tx = res["raw_final_tx"]
+            .as_str()
+            .and_then(|tx| general_purpose::STANDARD.decode(tx).ok())
+            .ok_or_else(|| anyhow!("FinalizePsbt failed: {}", res))?;
+
+        let res: Value = self
+            .client
+            .post(format!("{}/v2/wallet/tx", self.url))
+            .header("Grpc-Metadata-macaroon", &self.macaroon)
+            .json(&json!({ "tx_hex": general_purpose::STANDARD.encode(&raw_tx) }))
+            .send()
+            .await?
+            .json()
+            .await?;
+        if let Some(err) = res["message"]
+            .as_str()
+            .or(res["publish_error"].as_str())
+            .filter(|e| !e.is_empty())
+        {
+            return Err(anyhow!("PublishTransaction failed: {}", err));
+        }
+        Ok(BroadcastTx {
+            txid: types::txid(&raw_tx)?,
+            raw_tx: hex::encode(raw_tx),
+        })
+    }
+
+    async fn estimate_fee(&self, target_conf: u32) -> Result<FeeEstimate> {
+        let res: Value = self
+            .client
+            .get(format!(
+                "{}/v2/wallet/estimatefee/{}",
+                self.url, target_conf
+            ))
+            .header("Grpc-Metadata-macaroon", &self.macaroon)
+            .send()
+            .await?
+            .json()
+            .await?;
+        let sat_per_kw =
+            u64_field(&res["sat_per_kw"]).ok_or_else(|| anyhow!("EstimateFee failed: {}", res))?;
+        Ok(FeeEstimate {
+            target_conf,
+            sat_per_vbyte: kw_to_vbyte(sat_per_kw),
+        })
+    }
+
+    async fn estimate_onchain_send(
+        &self,
+        address: &str,
+        amount: Sat,
+        target_conf: u32,
+    ) -> Result<OnchainSendEstimate> {
+        let res: Value = self
+            .client
+            .get(format!("{}/v1/transactions/fee", self.url))
+            .header("Grpc-Metadata-macaroon", &self.macaroon)
+            .query(&[
+                (
+                    format!("addr_to_amount[{}]", address),
+                    amount.as_sat().to_string(),
+                ),
+                ("target_conf".to_string(), target_conf.to_string()),
+            ])
+            .send()
+            .await?
+            .json()
+            .await?;
+        let fee_sat =
+            u64_field(&res["fee_sat"]).ok_or_else(|| anyhow!("EstimateFee failed: {}", res))?;
+        Ok(OnchainSendEstimate {
+            fee_sat: Sat::from_sat(fee_sat),
+            sat_per_vbyte: u64_field(&res["sat_per_vbyte"]).unwrap_or(0) as f64,
+        })
+    }
+
+    async fn capabilities(&self) -> Result<Capabilities> {
+        let info = self.get_info().await?;
+        Ok(Capabilities {
+            backend: "lnd".into(),
+            version: info.version,
+            network: info.network,
+            supported: [
+                Capability::CreateInvoice,
+                Capability::DecodeInvoice,
+                Capability::PayInvoice,
+                Capability::ListInvoices,
+                Capability::SignMessage,
+                Capability::ChannelPolicy,
+                Capability::ForwardingHistory,
+                Capability::QueryRoutes,
+                Capability::NetworkGraph,
+                Capability::ProbePayment,
+                Capability::ChannelBackup,
+                Capability::Psbt,
+                Capability::FeeEstimates,
+            ]
+            .into(),
+        })
+    }
+}
+
+/// REST encodes bytes fields as base64; accept hex too.
+fn bytes_field<T>(value: &Value) -> Option<T>
+where
+    T: std::str::FromStr + for<'a> TryFrom<&'a [u8]>,
+{
+    let s = value.as_str()?;
+    s.parse().ok().or_else(|| {
+        let bytes = general_purpose::STANDARD.decode(s).ok()?;
+        T::try_from(bytes.as_slice()).ok()
+    })
+}
+
+/// An entry of ListInvoices, or a LookupInvoice reply.
+fn invoice_of(inv: &Value) -> Option<Invoice> {
+    Some(Invoice {
+        hash: bytes_field(&inv["r_hash"])?,
+        amount_msat: Msat::from_msat(u64_field(&inv["value_msat"]).unwrap_or(0)),
+        state: if inv["settled"].as_bool().unwrap_or(false) {
+            "paid".to_string()
+        } else {
+            "unpaid".to_string()
+        },
+        bolt11: inv["payment_request"].as_str().and_then(|p| p.parse().ok()),
+        desc: inv["memo"]
+            .as_str()
+            .filter(|s| !s.is_empty())
+            .map(str::to_string),
+    })
+}
+
+/// Converts a QueryRoutes route; LND gives absolute expiries, which are made
+/// relative to `height`.
+fn route(raw: &Value, height: u32) -> Result<Route> {
+    let delay = |v: &Value| (v.as_u64().unwrap_or(0) as u32).saturating_sub(height);
+    let hops = raw["hops"]
+        .as_array()
+        .ok_or_else(|| anyhow!("Route without hops: {}", raw))?
+        .iter()
+        .map(|h| {
+            Ok(RouteHop {
+                pubkey: h["pub_key"]
+                    .as_str()
+                    .ok_or_else(|| anyhow!("Hop without pub_key"))?
+                    .parse()?,
+                channel: ShortChannelId::from_u64(
+                    u64_field(&h["chan_id"]).ok_or_else(|| anyhow!("Hop without chan_id"))?,
+                ),
+                amount_msat: Msat::from_msat(u64_field(&h["amt_to_forward_msat"]).unwrap_or(0)),
+                delay: delay(&h["expiry"]),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let fee_msat = Msat::from_msat(u64_field(&raw["total_fees_msat"]).unwrap_or(0));
+    let total_msat = Msat::from_msat(u64_field(&raw["total_amt_msat"]).unwrap_or(0));
+
+    Ok(Route {
+        amount_msat: total_msat.checked_sub(fee_msat).unwrap_or(Msat::ZERO),
+        fee_msat,
+        total_delay: delay(&raw["total_time_lock"]),
+        hops,
+    })
+}
+
+/// `txid:vout` of a ChannelPoint, whose txid may come as bytes in internal
+/// order.
+fn chan_point(point: &Value) -> Option<String> {
+    let txid = match point["funding_txid_str"].as_str() {
+        Some(txid) => txid.to_string(),
+        None => {
+            let mut bytes = general_purpose::STANDARD
+                .decode(point["funding_txid_bytes"].as_str()?)
+                .ok()?;
+            bytes.reverse();
+            hex::encode(bytes)
+        }
+    };
+    Some(format!(
+        "{}:{}",
+        txid,
+        point["output_index"].as_u64().unwrap_or(0)
+    ))
+}
+
+/// REST encodes 64-bit integers as strings.
+fn u64_field(value: &Value) -> Option<u64> {
+    value
+        .as_u64()
+        .or_else(|| value.as_str().and_then(|s| s.parse().ok()))
+}
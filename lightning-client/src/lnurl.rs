@@ -0,0 +1,502 @@
+// lightning-client/src/lnurl.rs
+use super::*;
+use crate::types::{decode_bech32, encode_bech32};
+use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, KeyIvInit};
+use anyhow::anyhow;
+use base64::{engine::general_purpose, Engine as _};
+use reqwest::Url;
+use serde_json::Value;
+use std::time::Duration;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// LUD-09 limits on what a service may ask the wallet to show.
+const MAX_MESSAGE_LEN: usize = 144;
+const MAX_CIPHERTEXT_LEN: usize = 4096;
+
+// ---------------------------------------------------------------------
+// Targets
+// ---------------------------------------------------------------------
+
+/// Resolves a Lightning Address (LUD-16), a bech32 `lnurl1...` string
+/// (LUD-01) or an `lnurlp://` / `lnurlw://` URL (LUD-17) to the URL to
+/// query.
+pub fn lnurl_url(target: &str) -> Result<Url> {
+    let target = target.trim();
+    let target = strip_prefix_ci(target, "lightning:").unwrap_or(target);
+
+    let url = if let Some((user, domain)) = target.split_once('@') {
+        let user = user.to_ascii_lowercase();
+        if user.is_empty()
+            || !user
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b"-_.+".contains(&b))
+        {
+            return Err(anyhow!("Invalid Lightning Address '{}'", target));
+        }
+        with_default_scheme(&format!("{}/.well-known/lnurlp/{}", domain, user))?
+    } else if let Some(rest) = ["lnurlp://", "lnurlw://"]
+        .iter()
+        .find_map(|scheme| strip_prefix_ci(target, scheme))
+    {
+        with_default_scheme(rest)?
+    } else if strip_prefix_ci(target, "lnurl1").is_some() {
+        let (hrp, bytes) = decode_bech32(target)?;
+        if hrp != "lnurl" {
+            return Err(anyhow!("Invalid LNURL: unexpected prefix '{}'", hrp));
+        }
+        let url = String::from_utf8(bytes).map_err(|_| anyhow!("Invalid LNURL: not a URL"))?;
+        Url::parse(&url).map_err(|e| anyhow!("Invalid LNURL '{}': {}", url, e))?
+    } else {
+        return Err(anyhow!(
+            "Expected a Lightning Address, an lnurl1... string or an lnurlp:// or lnurlw:// URL"
+        ));
+    };
+
+    check_scheme(&url)?;
+    Ok(url)
+}
+
+/// Bech32 form of `url`, upper case as LUD-01 recommends for QR codes.
+pub fn encode_lnurl(url: &str) -> String {
+    encode_bech32("lnurl", url.as_bytes()).to_ascii_uppercase()
+}
+
+fn strip_prefix_ci<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+    s.get(..prefix.len())
+        .filter(|p| p.eq_ignore_ascii_case(prefix))
+        .map(|_| &s[prefix.len()..])
+}
+
+/// `https://`, or `http://` for hosts where TLS adds nothing.
+fn with_default_scheme(rest: &str) -> Result<Url> {
+    let mut url = Url::parse(&format!("https://{}", rest))
+        .map_err(|e| anyhow!("Invalid LNURL host in '{}': {}", rest, e))?;
+    if plain_http_ok(&url) {
+        let _ = url.set_scheme("http");
+    }
+    Ok(url)
+}
+
+/// Onion services are authenticated by their address; loopback is for
+/// local development.
+fn plain_http_ok(url: &Url) -> bool {
+    match url.host() {
+        Some(url::Host::Domain(d)) => d.ends_with(".onion") || d == "localhost",
+        Some(url::Host::Ipv4(ip)) => ip.is_loopback(),
+        Some(url::Host::Ipv6(ip)) => ip.is_loopback(),
+        None => false,
+    }
+}
+
+fn check_scheme(url: &Url) -> Result<()> {
+    match url.scheme() {
+        "https" => Ok(()),
+        "http" if plain_http_ok(url) => Ok(()),
+        _ => Err(anyhow!("LNURL endpoints must use https: {}", url)),
+    }
+}
+
+// ---------------------------------------------------------------------
+// Types
+// ---------------------------------------------------------------------
+
+/// First response of an LNURL-pay endpoint (LUD-06).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PayRequest {
+    pub callback: String,
+    pub min_sendable: Msat,
+    pub max_sendable: Msat,
+    /// JSON-encoded `[[mime, content], ...]`, hashed as sent into the
+    /// invoice's description hash.
+    pub metadata: String,
+    /// Longest comment the service accepts (LUD-12); 0 for none.
+    #[serde(default)]
+    pub comment_allowed: usize,
+}
+
+impl PayRequest {
+    /// The `text/plain` metadata entry shown to the payer.
+    pub fn description(&self) -> Option<String> {
+        self.metadata_entry("text/plain")
+    }
+
+    /// The address the service says this is (LUD-16).
+    pub fn identifier(&self) -> Option<String> {
+        self.metadata_entry("text/identifier")
+            .or_else(|| self.metadata_entry("text/email"))
+    }
+
+    fn metadata_entry(&self, mime: &str) -> Option<String> {
+        let entries: Vec<Vec<Value>> = serde_json::from_str(&self.metadata).ok()?;
+        entries
+            .into_iter()
+            .find_map(|entry| match entry.as_slice() {
+                [Value::String(m), Value::String(content), ..] if m == mime => {
+                    Some(content.clone())
+                }
+                _ => None,
+            })
+    }
+}
+
+/// First response of an LNURL-withdraw endpoint (LUD-03).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WithdrawRequest {
+    pub callback: String,
+    /// Secret identifying the withdrawal, echoed to the callback.
+    pub k1: String,
+    #[serde(default)]
+    pub default_description: String,
+    pub min_withdrawable: Msat,
+    pub max_withdrawable: Msat,
+}
+
+/// LUD-09 action for the wallet to show once the payment settles.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "tag", rename_all = "lowercase")]
+pub enum SuccessAction {
+    Message {
+        message: String,
+    },
+    Url {
+        description: String,
+        url: String,
+    },
+    /// Encrypted with the payment preimage (LUD-10).
+    Aes {
+        description: String,
+        ciphertext: String,
+        iv: String,
+    },
+}
+
+impl SuccessAction {
+    fn validate(&self, callback: &Url) -> Result<()> {
+        let too_long = |s: &str| s.chars().count() > MAX_MESSAGE_LEN;
+        match self {
+            SuccessAction::Message { message } if too_long(message) => Err(anyhow!(
+                "Success message is over {} characters",
+                MAX_MESSAGE_LEN
+            )),
+            SuccessAction::Url { description, url } => {
+                if too_long(description) {
+                    return Err(anyhow!(
+                        "Success description is over {} characters",
+                        MAX_MESSAGE_LEN
+                    ));
+                }
+                let url = Url::parse(url).map_err(|e| anyhow!("Invalid success URL: {}", e))?;
+                if url.host() != callback.host() {
+                    return Err(anyhow!(
+                        "Success URL {} is not on the callback's domain",
+                        url
+                    ));
+                }
+                Ok(())
+            }
+            SuccessAction::Aes {
+                description,
+                ciphertext,
+                iv,
+            } => {
+                if too_long(description) {
+                    return Err(anyhow!(
+                        "Success description is over {} characters",
+                        MAX_MESSAGE_LEN
+                    ));
+                }
+                if ciphertext.len() > MAX_CIPHERTEXT_LEN || iv.len() != 24 {
+                    return Err(anyhow!("Malformed aes success action"));
+                }
+                Ok(())
+            }
+            SuccessAction::Message { .. } => Ok(()),
+        }
+    }
+
+    /// Plaintext of an `aes` action, decrypted with the preimage the
+    /// payment revealed.
+    pub fn decrypt(&self, preimage: &Preimage) -> Result<String> {
+        let SuccessAction::Aes { ciphertext, iv, .. } = self else {
+            return Err(anyhow!("Only aes success actions are encrypted"));
+        };
+        let iv = general_purpose::STANDARD
+            .decode(iv)
+            .map_err(|e| anyhow!("Invalid success action IV: {}", e))?;
+        let mut buf = general_purpose::STANDARD
+            .decode(ciphertext)
+            .map_err(|e| anyhow!("Invalid success action ciphertext: {}", e))?;
+
+        let plaintext = cbc::Decryptor::<aes::Aes256>::new_from_slices(preimage.as_bytes(), &iv)
+            .map_err(|_| anyhow!("Success action IV must be 16 bytes"))?
+            .decrypt_padded_mut::<Pkcs7>(&mut buf)
+            .map_err(|_| anyhow!("Success action does not decrypt with this preimage"))?;
+        String::from_utf8(plaintext.to_vec())
+            .map_err(|_| anyhow!("Decrypted success action is not UTF-8"))
+    }
+}
+
+/// Invoice from a pay callback, checked against the request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LnurlInvoice {
+    pub bolt11: Bolt11,
+    pub success_action: Option<SuccessAction>,
+}
+
+impl LnurlInvoice {
+    /// Pays through `node` and decrypts an `aes` success action.
+    pub async fn pay(self, node: &dyn LightningClient) -> Result<LnurlPayment> {
+        let payment = node.pay_invoice(&self.bolt11).await?;
+
+        // The payment went through either way; a bad ciphertext only loses
+        // the message, so the error goes back alongside the payment.
+        let (decrypted, decrypt_error) = match (&self.success_action, &payment.preimage) {
+            (Some(action @ SuccessAction::Aes { .. }), Some(preimage)) => {
+                match action.decrypt(preimage) {
+                    Ok(text) => (Some(text), None),
+                    Err(e) => (None, Some(e.to_string())),
+                }
+            }
+            _ => (None, None),
+        };
+
+        Ok(LnurlPayment {
+            payment,
+            success_action: self.success_action,
+            decrypted,
+            decrypt_error,
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct LnurlPayment {
+    pub payment: PaymentResult,
+    pub success_action: Option<SuccessAction>,
+    /// Plaintext of an `aes` success action.
+    pub decrypted: Option<String>,
+    /// Why an `aes` success action could not be decrypted.
+    pub decrypt_error: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CallbackResponse {
+    pr: Bolt11,
+    #[serde(default)]
+    success_action: Option<SuccessAction>,
+}
+
+// ---------------------------------------------------------------------
+// Client
+// ---------------------------------------------------------------------
+
+/// Talks to LNURL services; payments go through any `LightningClient`.
+pub struct LnurlClient {
+    http: reqwest::Client,
+}
+
+impl Default for LnurlClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LnurlClient {
+    pub fn new() -> Self {
+        Self {
+            http: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+
+    /// GETs `url`, turning `{"status": "ERROR"}` replies into errors.
+    async fn get_json(&self, url: Url) -> Result<Value> {
+        let res = self.http.get(url.clone()).send().await?;
+        let status = res.status();
+        // Services send their reason with 4xx statuses too.
+        let body: Option<Value> = res.json().await.ok();
+
+        match body {
+            Some(body) if body["status"].as_str() == Some("ERROR") => Err(anyhow!(
+                "LNURL service error: {}",
+                body["reason"].as_str().unwrap_or("no reason given")
+            )),
+            _ if !status.is_success() => {
+                Err(anyhow!("LNURL service at {} returned {}", url, status))
+            }
+            Some(body) => Ok(body),
+            None => Err(anyhow!("LNURL service at {} sent invalid JSON", url)),
+        }
+    }
+
+    /// GETs the LNURL behind `target` and checks it is a `tag` request.
+    async fn get_request(&self, target: &str, tag: &str) -> Result<Value> {
+        let body = self.get_json(lnurl_url(target)?).await?;
+        match body["tag"].as_str() {
+            Some(t) if t == tag => Ok(body),
+            other => Err(anyhow!(
+                "Expected an LNURL {}, got tag {}",
+                tag,
+                other.unwrap_or("missing")
+            )),
+        }
+    }
+
+    /// Fetches the pay request behind a Lightning Address or LNURL.
+    pub async fn resolve(&self, target: &str) -> Result<PayRequest> {
+        let body = self.get_request(target, "payRequest").await?;
+        let pay: PayRequest =
+            serde_json::from_value(body).map_err(|e| anyhow!("Invalid pay request: {}", e))?;
+        check_scheme(&callback_url(&pay)?)?;
+        if pay.min_sendable > pay.max_sendable || pay.max_sendable == Msat::ZERO {
+            return Err(anyhow!(
+                "Pay request has an empty amount range {}..{}",
+                pay.min_sendable,
+                pay.max_sendable
+            ));
+        }
+        if pay.description().is_none() {
+            return Err(anyhow!("Pay request metadata has no text/plain entry"));
+        }
+        Ok(pay)
+    }
+
+    /// Asks the callback for an invoice and checks its amount and
+    /// description hash before anything is paid.
+    pub async fn fetch_invoice(
+        &self,
+        pay: &PayRequest,
+        amount: Msat,
+        comment: Option<&str>,
+    ) -> Result<LnurlInvoice> {
+        if amount < pay.min_sendable || amount > pay.max_sendable {
+            return Err(anyhow!(
+                "Amount {} is outside the {}..{} this service accepts",
+                amount,
+                pay.min_sendable,
+                pay.max_sendable
+            ));
+        }
+        let comment = comment.map(str::trim).filter(|c| !c.is_empty());
+        if let Some(comment) = comment {
+            if comment.chars().count() > pay.comment_allowed {
+                return Err(anyhow!(
+                    "Comment is over the {} characters this service accepts",
+                    pay.comment_allowed
+                ));
+            }
+        }
+
+        let callback = callback_url(pay)?;
+        let mut url = callback.clone();
+        url.query_pairs_mut()
+            .append_pair("amount", &amount.as_msat().to_string());
+        if let Some(comment) = comment {
+            url.query_pairs_mut().append_pair("comment", comment);
+        }
+        let res: CallbackResponse = serde_json::from_value(self.get_json(url).await?)
+            .map_err(|e| anyhow!("Invalid pay callback response: {}", e))?;
+
+        if res.pr.amount() != Some(amount) {
+            return Err(anyhow!(
+                "Service returned an invoice for {} instead of {}",
+                res.pr
+                    .amount()
+                    .map_or("any amount".to_string(), |a| a.to_string()),
+                amount
+            ));
+        }
+        if res.pr.description_hash() != Some(description_hash(&pay.metadata)) {
+            return Err(anyhow!(
+                "Invoice description hash does not match the pay request metadata"
+            ));
+        }
+        if let Some(action) = &res.success_action {
+            action.validate(&callback)?;
+        }
+
+        Ok(LnurlInvoice {
+            bolt11: res.pr,
+            success_action: res.success_action,
+        })
+    }
+
+    /// Fetches the withdraw request behind an LNURL (LUD-03).
+    pub async fn resolve_withdraw(&self, target: &str) -> Result<WithdrawRequest> {
+        let body = self.get_request(target, "withdrawRequest").await?;
+        let withdraw: WithdrawRequest =
+            serde_json::from_value(body).map_err(|e| anyhow!("Invalid withdraw request: {}", e))?;
+        check_scheme(
+            &Url::parse(&withdraw.callback)
+                .map_err(|e| anyhow!("Invalid callback '{}': {}", withdraw.callback, e))?,
+        )?;
+        if withdraw.min_withdrawable > withdraw.max_withdrawable
+            || withdraw.max_withdrawable == Msat::ZERO
+        {
+            return Err(anyhow!(
+                "Withdraw request has an empty amount range {}..{}",
+                withdraw.min_withdrawable,
+                withdraw.max_withdrawable
+            ));
+        }
+        Ok(withdraw)
+    }
+
+    /// Creates an invoice for `amount` on `node` and hands it to the
+    /// service, which pays it asynchronously. Returns the invoice so the
+    /// caller can watch for it to settle.
+    pub async fn withdraw(
+        &self,
+        node: &dyn LightningClient,
+        withdraw: &WithdrawRequest,
+        amount: Msat,
+        description: Option<&str>,
+    ) -> Result<Bolt11> {
+        if amount < withdraw.min_withdrawable || amount > withdraw.max_withdrawable {
+            return Err(anyhow!(
+                "Amount {} is outside the {}..{} this service allows",
+                amount,
+                withdraw.min_withdrawable,
+                withdraw.max_withdrawable
+            ));
+        }
+        let desc = description.unwrap_or(&withdraw.default_description);
+        let bolt11 = node.create_invoice(amount, None, Some(desc)).await?;
+
+        let mut url = Url::parse(&withdraw.callback)
+            .map_err(|e| anyhow!("Invalid callback '{}': {}", withdraw.callback, e))?;
+        url.query_pairs_mut()
+            .append_pair("k1", &withdraw.k1)
+            .append_pair("pr", bolt11.as_str());
+        let body = self.get_json(url).await?;
+        if body["status"].as_str() != Some("OK") {
+            return Err(anyhow!("Withdraw callback did not confirm: {}", body));
+        }
+        Ok(bolt11)
+    }
+
+    /// Resolves `target`, fetches an invoice for `amount` and pays it
+    /// through `node`.
+    pub async fn pay(
+        &self,
+        node: &dyn LightningClient,
+        target: &str,
+        amount: Msat,
+        comment: Option<&str>,
+    ) -> Result<LnurlPayment> {
+        let pay = self.resolve(target).await?;
+        self.fetch_invoice(&pay, amount, comment)
+            .await?
+            .pay(node)
+            .await
+    }
+}
+
+fn callback_url(pay: &PayRequest) -> Result<Url> {
+    Url::parse(&pay.callback).map_err(|e| anyhow!("Invalid callback '{}': {}", pay.callback, e))
+}
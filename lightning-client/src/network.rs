@@ -0,0 +1,228 @@
+// lightning-client/src/network.rs
+use super::*;
+use anyhow::anyhow;
+use std::fmt;
+
+const NETWORKS: [&str; 4] = ["mainnet", "testnet", "signet", "regtest"];
+
+/// The node or an invoice is on a different chain than `expected_network`.
+#[derive(Debug)]
+pub struct NetworkMismatch {
+    pub expected: String,
+    pub actual: String,
+    /// `node` or `invoice`.
+    pub subject: &'static str,
+}
+
+impl fmt::Display for NetworkMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Refusing to operate: {} is on {}, expected {}",
+            self.subject, self.actual, self.expected
+        )
+    }
+}
+
+impl std::error::Error for NetworkMismatch {}
+
+/// Refuses to run against a node, or pay an invoice, on another chain than
+/// `expected_network`.
+pub struct NetworkGuard {
+    inner: LightningClientDyn,
+    expected: String,
+}
+
+impl NetworkGuard {
+    /// Checks the node's chain once before handing it out. A backend that
+    /// doesn't report its chain is accepted; invoices are still checked.
+    pub async fn connect(inner: LightningClientDyn, expected: &str) -> Result<Self> {
+        let expected = normalize_network(expected);
+        if !NETWORKS.contains(&expected.as_str()) {
+            return Err(anyhow!(
+                "Invalid expected_network '{}'; use one of {}",
+                expected,
+                NETWORKS.join(", ")
+            ));
+        }
+
+        let info = inner
+            .get_info()
+            .await
+            .map_err(|e| anyhow!("Cannot verify the node's network: {}", e))?;
+        match info.network {
+            Some(actual) if actual != expected => {
+                return Err(NetworkMismatch {
+                    expected,
+                    actual,
+                    subject: "node",
+                }
+                .into())
+            }
+            Some(_) => {}
+            None => eprintln!(
+                "WARNING: {} node does not report its network; only invoices are checked against {}",
+                info.implementation, expected
+            ),
+        }
+
+        Ok(Self { inner, expected })
+    }
+
+    fn check_invoice(&self, bolt11: &Bolt11) -> Result<()> {
+        let actual = bolt11.network();
+        if actual != self.expected {
+            return Err(NetworkMismatch {
+                expected: self.expected.clone(),
+                actual: actual.to_string(),
+                subject: "invoice",
+            }
+            .into());
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl LightningClient for NetworkGuard {
+    async fn get_info(&self) -> Result<NodeInfo> {
+        self.inner.get_info().await
+    }
+
+    async fn create_invoice(
+        &self,
+        amount: Msat,
+        label: Option<&str>,
+        desc: Option<&str>,
+    ) -> Result<Bolt11> {
+        self.inner.create_invoice(amount, label, desc).await
+    }
+
+    async fn create_invoice_hashed(
+        &self,
+        amount: Msat,
+        label: Option<&str>,
+        description: &str,
+    ) -> Result<Bolt11> {
+        self.inner
+            .create_invoice_hashed(amount, label, description)
+            .await
+    }
+
+    async fn get_balance(&self) -> Result<Balance> {
+        self.inner.get_balance().await
+    }
+
+    async fn list_invoices(&self, limit: Option<usize>) -> Result<Vec<Invoice>> {
+        self.inner.list_invoices(limit).await
+    }
+
+    async fn lookup_invoice(&self, hash: &PaymentHash) -> Result<Option<Invoice>> {
+        self.inner.lookup_invoice(hash).await
+    }
+
+    async fn decode_invoice(&self, bolt11: &Bolt11) -> Result<DecodedInvoice> {
+        self.inner.decode_invoice(bolt11).await
+    }
+
+    async fn pay_invoice(&self, bolt11: &Bolt11) -> Result<PaymentResult> {
+        self.check_invoice(bolt11)?;
+        self.inner.pay_invoice(bolt11).await
+    }
+
+    async fn lookup_payment(&self, hash: &PaymentHash) -> Result<PaymentState> {
+        self.inner.lookup_payment(hash).await
+    }
+
+    async fn sign_message(&self, message: &str) -> Result<String> {
+        self.inner.sign_message(message).await
+    }
+
+    async fn verify_message(&self, message: &str, signature: &str) -> Result<SignatureCheck> {
+        self.inner.verify_message(message, signature).await
+    }
+
+    async fn update_channel_policy(
+        &self,
+        channel: &ChannelSelector,
+        policy: &ChannelPolicy,
+    ) -> Result<()> {
+        self.inner.update_channel_policy(channel, policy).await
+    }
+
+    async fn list_forwards(&self, query: &ForwardQuery) -> Result<ForwardPage> {
+        self.inner.list_forwards(query).await
+    }
+
+    async fn query_routes(&self, dest: &PublicKey, amount: Msat) -> Result<Route> {
+        self.inner.query_routes(dest, amount).await
+    }
+
+    async fn get_node(&self, pubkey: &PublicKey) -> Result<GraphNode> {
+        self.inner.get_node(pubkey).await
+    }
+
+    async fn get_channel(&self, scid: ShortChannelId) -> Result<GraphChannel> {
+        self.inner.get_channel(scid).await
+    }
+
+    async fn probe_payment(&self, bolt11: &Bolt11) -> Result<ProbeResult> {
+        self.check_invoice(bolt11)?;
+        self.inner.probe_payment(bolt11).await
+    }
+
+    async fn export_channel_backup(&self) -> Result<ChannelBackup> {
+        self.inner.export_channel_backup().await
+    }
+
+    async fn verify_channel_backup(&self, data: &[u8]) -> Result<BackupCheck> {
+        self.inner.verify_channel_backup(data).await
+    }
+
+    async fn open_channel_psbt(&self, open: &PsbtChannelOpen) -> Result<PsbtFunding> {
+        self.inner.open_channel_psbt(open).await
+    }
+
+    async fn update_channel_psbt(&self, id: &str, psbt: &Psbt) -> Result<Psbt> {
+        self.inner.update_channel_psbt(id, psbt).await
+    }
+
+    async fn finalize_channel_psbt(&self, id: &str, signed: &Psbt) -> Result<PsbtChannelOpened> {
+        self.inner.finalize_channel_psbt(id, signed).await
+    }
+
+    async fn cancel_channel_psbt(&self, id: &str) -> Result<()> {
+        self.inner.cancel_channel_psbt(id).await
+    }
+
+    async fn fund_psbt(&self, request: &FundPsbtRequest) -> Result<FundedPsbt> {
+        self.inner.fund_psbt(request).await
+    }
+
+    async fn sign_psbt(&self, psbt: &Psbt) -> Result<Psbt> {
+        self.inner.sign_psbt(psbt).await
+    }
+
+    async fn finalize_psbt(&self, psbt: &Psbt) -> Result<BroadcastTx> {
+        self.inner.finalize_psbt(psbt).await
+    }
+
+    async fn estimate_fee(&self, target_conf: u32) -> Result<FeeEstimate> {
+        self.inner.estimate_fee(target_conf).await
+    }
+
+    async fn estimate_onchain_send(
+        &self,
+        address: &str,
+        amount: Sat,
+        target_conf: u32,
+    ) -> Result<OnchainSendEstimate> {
+        self.inner
+            .estimate_onchain_send(address, amount, target_conf)
+            .await
+    }
+
+    async fn capabilities(&self) -> Result<Capabilities> {
+        self.inner.capabilities().await
+    }
+}
@@ -0,0 +1,185 @@
+// lightning-client/src/nostr_relay.rs
+use anyhow::{anyhow, Result};
+use futures_util::{SinkExt, StreamExt};
+use nostr::prelude::{ClientMessage, Event, Filter, RelayMessage, SubscriptionId};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+const SEEN_CAPACITY: usize = 1024;
+
+/// Minimal NIP-01 client: a set of relays sharing the same subscriptions,
+/// with incoming events verified, deduplicated and fanned out to listeners.
+///
+/// Relays reconnect on their own and replay open subscriptions. Dropping the
+/// last clone of the pool closes every connection.
+#[derive(Clone)]
+pub struct RelayPool {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    senders: Vec<mpsc::UnboundedSender<String>>,
+    subscriptions: Mutex<HashMap<String, String>>,
+    seen: Mutex<(HashSet<String>, VecDeque<String>)>,
+    events: broadcast::Sender<Event>,
+}
+
+impl RelayPool {
+    pub fn new(urls: &[String]) -> Result<Self> {
+        if urls.is_empty() {
+            return Err(anyhow!("At least one relay is required"));
+        }
+
+        let mut receivers = Vec::with_capacity(urls.len());
+        let mut senders = Vec::with_capacity(urls.len());
+        for url in urls {
+            if !url.starts_with("ws://") && !url.starts_with("wss://") {
+                return Err(anyhow!("Invalid relay URL '{}'", url));
+            }
+            let (tx, rx) = mpsc::unbounded_channel();
+            senders.push(tx);
+            receivers.push((url.clone(), rx));
+        }
+
+        let inner = Arc::new(Inner {
+            senders,
+            subscriptions: Mutex::new(HashMap::new()),
+            seen: Mutex::new((HashSet::new(), VecDeque::new())),
+            events: broadcast::channel(256).0,
+        });
+        for (url, rx) in receivers {
+            tokio::spawn(run_relay(url, rx, Arc::downgrade(&inner)));
+        }
+
+        Ok(Self { inner })
+    }
+
+    /// Stream of verified events matching any open subscription.
+    pub fn events(&self) -> broadcast::Receiver<Event> {
+        self.inner.events.subscribe()
+    }
+
+    pub fn subscribe(&self, id: &str, filter: Filter) {
+        let req = ClientMessage::req(SubscriptionId::new(id), vec![filter]).as_json();
+        self.inner
+            .subscriptions
+            .lock()
+            .unwrap()
+            .insert(id.to_string(), req.clone());
+        self.inner.send(req);
+    }
+
+    pub fn unsubscribe(&self, id: &str) {
+        if self
+            .inner
+            .subscriptions
+            .lock()
+            .unwrap()
+            .remove(id)
+            .is_some()
+        {
+            self.inner
+                .send(ClientMessage::close(SubscriptionId::new(id)).as_json());
+        }
+    }
+
+    pub fn publish(&self, event: Event) {
+        self.inner.send(ClientMessage::event(event).as_json());
+    }
+}
+
+impl Inner {
+    fn send(&self, msg: String) {
+        for tx in &self.senders {
+            let _ = tx.send(msg.clone());
+        }
+    }
+
+    fn dispatch(&self, text: &str) {
+        let event = match RelayMessage::from_json(text) {
+            Ok(RelayMessage::Event { event, .. }) => event.into_owned(),
+            _ => return,
+        };
+        if event.verify().is_err() {
+            return;
+        }
+
+        // Every relay delivers its own copy.
+        {
+            let mut seen = self.seen.lock().unwrap();
+            let id = event.id.to_hex();
+            if !seen.0.insert(id.clone()) {
+                return;
+            }
+            seen.1.push_back(id);
+            if seen.1.len() > SEEN_CAPACITY {
+                if let Some(old) = seen.1.pop_front() {
+                    seen.0.remove(&old);
+                }
+            }
+        }
+
+        let _ = self.events.send(event);
+    }
+}
+
+async fn run_relay(url: String, mut outgoing: mpsc::UnboundedReceiver<String>, inner: Weak<Inner>) {
+    loop {
+        match connect_async(url.as_str()).await {
+            Ok((ws, _)) => {
+                let (mut write, mut read) = ws.split();
+
+                let replay: Vec<String> = match inner.upgrade() {
+                    Some(inner) => inner
+                        .subscriptions
+                        .lock()
+                        .unwrap()
+                        .values()
+                        .cloned()
+                        .collect(),
+                    None => return,
+                };
+                for req in replay {
+                    if write.send(Message::Text(req)).await.is_err() {
+                        break;
+                    }
+                }
+
+                loop {
+                    tokio::select! {
+                        msg = outgoing.recv() => match msg {
+                            Some(text) => {
+                                if write.send(Message::Text(text)).await.is_err() {
+                                    break;
+                                }
+                            }
+                            None => return,
+                        },
+                        frame = read.next() => match frame {
+                            Some(Ok(Message::Text(text))) => match inner.upgrade() {
+                                Some(inner) => inner.dispatch(&text),
+                                None => return,
+                            },
+                            Some(Ok(Message::Ping(payload))) => {
+                                let _ = write.send(Message::Pong(payload)).await;
+                            }
+                            Some(Ok(_)) => {}
+                            Some(Err(_)) | None => break,
+                        },
+                    }
+                }
+                eprintln!("Relay {} disconnected, reconnecting", url);
+            }
+            Err(e) => eprintln!("Relay {} connect failed: {}", url, e),
+        }
+
+        if inner.strong_count() == 0 {
+            return;
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
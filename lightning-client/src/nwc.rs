@@ -0,0 +1,453 @@
+// lightning-client/src/nwc.rs
+use super::*;
+use crate::nostr_relay::RelayPool;
+use anyhow::{anyhow, Result};
+use nostr::nips::nip47::{
+    ErrorCode, ListTransactionsRequest, LookupInvoiceRequest, LookupInvoiceResponse,
+    MakeInvoiceRequest, Method, Nip47Ciphers, Nip47Tag, NostrWalletConnectUri, PayInvoiceRequest,
+    Request, Response, TransactionState, TransactionType,
+};
+use nostr::prelude::{Event, Filter, Kind};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::timeout;
+
+const INFO_TIMEOUT: Duration = Duration::from_secs(5);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+/// How long `pay_invoice` waits for the wallet before reporting the payment
+/// as pending.
+const PAYMENT_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Wallet reached over Nostr Wallet Connect (NIP-47).
+pub struct NwcClient {
+    uri: NostrWalletConnectUri,
+    pool: RelayPool,
+    cipher: Nip47Ciphers,
+}
+
+impl NwcClient {
+    /// `uri` is a `nostr+walletconnect://<wallet pubkey>?relay=...&secret=...` string.
+    pub async fn connect(uri: &str) -> Result<Self> {
+        let uri = NostrWalletConnectUri::parse(uri.trim())
+            .map_err(|e| anyhow!("Invalid NWC URI: {}", e))?;
+        let relays: Vec<String> = uri.relays.iter().map(|r| r.to_string()).collect();
+        let pool = RelayPool::new(&relays)?;
+
+        // The wallet's info event lists its ciphers; without one NIP-04 is implied.
+        let mut events = pool.events();
+        let filter = Filter::new()
+            .kind(Kind::WalletConnectInfo)
+            .author(uri.public_key)
+            .limit(1);
+        pool.subscribe("nwc-info", filter);
+        let info = timeout(
+            INFO_TIMEOUT,
+            next_matching(&mut events, |ev| {
+                ev.kind == Kind::WalletConnectInfo && ev.pubkey == uri.public_key
+            }),
+        )
+        .await;
+        pool.unsubscribe("nwc-info");
+
+        let cipher = info
+            .ok()
+            .and_then(|ev| ev.ok())
+            .and_then(|ev| {
+                ev.tags
+                    .iter()
+                    .find_map(|t| match Nip47Tag::try_from(t).ok()? {
+                        Nip47Tag::Encryption(c) => Some(c.latest()),
+                    })
+            })
+            .unwrap_or(Nip47Ciphers::NIP04);
+
+        Ok(Self { uri, pool, cipher })
+    }
+
+    async fn request(&self, req: Request) -> Result<Response> {
+        let res = self
+            .exchange(req, REQUEST_TIMEOUT)
+            .await?
+            .ok_or_else(|| anyhow!("NWC request timed out"))?;
+        if let Some(err) = &res.error {
+            return Err(anyhow!("NWC wallet error: {}", err));
+        }
+        Ok(res)
+    }
+
+    /// Sends `req` and waits up to `wait` for the wallet's reply, `None`
+    /// when it doesn't come in time. Error replies are returned as is.
+    async fn exchange(&self, req: Request, wait: Duration) -> Result<Option<Response>> {
+        let event = req.to_event(&self.uri, self.cipher)?;
+        let request_id = event.id;
+        let sub_id = request_id.to_hex();
+
+        let mut events = self.pool.events();
+        let filter = Filter::new()
+            .kind(Kind::WalletConnectResponse)
+            .author(self.uri.public_key)
+            .event(request_id);
+        self.pool.subscribe(&sub_id, filter);
+        self.pool.publish(event);
+
+        let wallet = self.uri.public_key;
+        let reply = timeout(
+            wait,
+            next_matching(&mut events, |ev| {
+                ev.kind == Kind::WalletConnectResponse
+                    && ev.pubkey == wallet
+                    && ev.tags.event_ids().any(|id| id == request_id)
+            }),
+        )
+        .await;
+        self.pool.unsubscribe(&sub_id);
+
+        let Ok(reply) = reply else {
+            return Ok(None);
+        };
+        Ok(Some(Response::from_event(&self.uri, &reply?, self.cipher)?))
+    }
+}
+
+/// An incoming transaction as an invoice.
+fn invoice_of(tx: LookupInvoiceResponse) -> Option<Invoice> {
+    Some(Invoice {
+        hash: tx.payment_hash.parse().ok()?,
+        amount_msat: Msat::from_msat(tx.amount),
+        state: match tx.state {
+            Some(TransactionState::Settled) => "paid",
+            Some(TransactionState::Expired) => "expired",
+            Some(TransactionState::Failed) => "failed",
+            Some(TransactionState::Accepted) => "accepted",
+            Some(TransactionState::Pending) | None => "unpaid",
+        }
+        .to_string(),
+        bolt11: tx.invoice.and_then(|i| i.parse().ok()),
+        desc: tx.description.filter(|d| !d.is_empty()),
+    })
+}
+
+async fn next_matching<F>(
+    events: &mut tokio::sync::broadcast::Receiver<Event>,
+    matches: F,
+) -> Result<Event>
+where
+    F: Fn(&Event) -> bool,
+{
+    loop {
+        match events.recv().await {
+            Ok(ev) if matches(&ev) => return Ok(ev),
+            Ok(_) | Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return Err(anyhow!("Relay pool closed")),
+        }
+    }
+}
+
+#[async_trait]
+impl LightningClient for NwcClient {
+    async fn get_info(&self) -> Result<NodeInfo> {
+        let res = self.request(Request::get_info()).await?.to_get_info()?;
+        // Wallets only share what NIP-47 get_info carries.
+        Ok(NodeInfo {
+            alias: res.alias.unwrap_or_else(|| "NWC".to_string()),
+            identity_pubkey: res.pubkey.and_then(|k| k.parse().ok()),
+            implementation: "nwc".into(),
+            network: res.network.as_deref().map(normalize_network),
+            block_height: res.block_height,
+            block_hash: res.block_hash,
+            color: res.color,
+            ..Default::default()
+        })
+    }
+
+    async fn create_invoice(
+        &self,
+        amount: Msat,
+        _label: Option<&str>,
+        desc: Option<&str>,
+    ) -> Result<Bolt11> {
+        let req = MakeInvoiceRequest {
+            amount: amount.as_msat(),
+            description: Some(desc.unwrap_or("rust").to_string()),
+            description_hash: None,
+            expiry: None,
+        };
+        let res = self
+            .request(Request::make_invoice(req))
+            .await?
+            .to_make_invoice()?;
+        res.invoice.parse()
+    }
+
+    async fn create_invoice_hashed(
+        &self,
+        amount: Msat,
+        _label: Option<&str>,
+        description: &str,
+    ) -> Result<Bolt11> {
+        let req = MakeInvoiceRequest {
+            amount: amount.as_msat(),
+            description: None,
+            description_hash: Some(hex::encode(description_hash(description))),
+            expiry: None,
+        };
+        let res = self
+            .request(Request::make_invoice(req))
+            .await?
+            .to_make_invoice()?;
+        res.invoice.parse()
+    }
+
+    async fn get_balance(&self) -> Result<Balance> {
+        let res = self
+            .request(Request::get_balance())
+            .await?
+            .to_get_balance()?;
+        // NWC only exposes the spendable Lightning balance.
+        Ok(Balance {
+            onchain_sat: Sat::ZERO,
+            channel_msat: Msat::from_msat(res.balance),
+        })
+    }
+
+    async fn list_invoices(&self, limit: Option<usize>) -> Result<Vec<Invoice>> {
+        let req = ListTransactionsRequest {
+            limit: Some(limit.unwrap_or(10) as u64),
+            unpaid: Some(true),
+            transaction_type: Some(TransactionType::Incoming),
+            ..Default::default()
+        };
+        let res = self
+            .request(Request::list_transactions(req))
+            .await?
+            .to_list_transactions()?;
+        Ok(res.into_iter().filter_map(invoice_of).collect())
+    }
+
+    async fn lookup_invoice(&self, hash: &PaymentHash) -> Result<Option<Invoice>> {
+        let req = LookupInvoiceRequest {
+            payment_hash: Some(hash.to_string()),
+            invoice: None,
+        };
+        let res = self
+            .exchange(Request::lookup_invoice(req), REQUEST_TIMEOUT)
+            .await?
+            .ok_or_else(|| anyhow!("NWC request timed out"))?;
+        match &res.error {
+            Some(err) if err.code == ErrorCode::NotFound => return Ok(None),
+            Some(err) => return Err(anyhow!("NWC wallet error: {}", err)),
+            None => {}
+        }
+        let res = res.to_lookup_invoice()?;
+        if res.transaction_type == Some(TransactionType::Outgoing) {
+            return Ok(None);
+        }
+        Ok(invoice_of(res))
+    }
+
+    async fn decode_invoice(&self, bolt11: &Bolt11) -> Result<DecodedInvoice> {
+        // NIP-47 has no decode method; this only works for invoices the
+        // wallet knows about.
+        let req = LookupInvoiceRequest {
+            payment_hash: None,
+            invoice: Some(bolt11.to_string()),
+        };
+        let res = self
+            .request(Request::lookup_invoice(req))
+            .await?
+            .to_lookup_invoice()?;
+        Ok(DecodedInvoice {
+            amount_msat: if res.amount == 0 {
+                None
+            } else {
+                Some(Msat::from_msat(res.amount))
+            },
+            desc: res.description.filter(|d| !d.is_empty()),
+            payee: None,
+        })
+    }
+
+    async fn pay_invoice(&self, bolt11: &Bolt11) -> Result<PaymentResult> {
+        let req = Request::pay_invoice(PayInvoiceRequest::new(bolt11.as_str()));
+        // Once published, the wallet may pay even if its reply never
+        // arrives.
+        let res = match self.exchange(req, PAYMENT_TIMEOUT).await {
+            Ok(Some(res)) => res,
+            Ok(None) => {
+                return Err(PaymentPending::error(
+                    bolt11.payment_hash(),
+                    format!("no reply from the wallet within {:?}", PAYMENT_TIMEOUT),
+                ))
+            }
+            Err(e) => return Err(PaymentPending::error(bolt11.payment_hash(), e)),
+        };
+        if let Some(err) = &res.error {
+            return Err(anyhow!("Payment failed: {}", err));
+        }
+        // From here on the wallet reports success, so nothing may fail.
+        let res = res.to_pay_invoice().map_err(|e| {
+            PaymentPending::error(bolt11.payment_hash(), format!("unreadable reply: {}", e))
+        })?;
+        let preimage: Option<Preimage> = res.preimage.parse().ok();
+        let hash = preimage
+            .map(|p| p.payment_hash())
+            .or_else(|| bolt11.payment_hash())
+            .ok_or_else(|| anyhow!("Paid, but neither the reply nor the invoice has a hash"))?;
+
+        // The reply has no amount. For an invoice without one, ask the
+        // wallet what was sent.
+        let amount_msat = match bolt11.amount() {
+            Some(amount) => Some(amount),
+            None => match self.lookup_payment(&hash).await {
+                Ok(PaymentState::Succeeded(payment)) => payment.amount_msat,
+                _ => None,
+            },
+        };
+
+        Ok(PaymentResult {
+            hash,
+            amount_msat,
+            fee_msat: res.fees_paid.map(Msat::from_msat),
+            preimage,
+        })
+    }
+    async fn lookup_payment(&self, hash: &PaymentHash) -> Result<PaymentState> {
+        let req = LookupInvoiceRequest {
+            payment_hash: Some(hash.to_string()),
+            invoice: None,
+        };
+        let res = self
+            .exchange(Request::lookup_invoice(req), REQUEST_TIMEOUT)
+            .await?
+            .ok_or_else(|| anyhow!("NWC request timed out"))?;
+        match &res.error {
+            Some(err) if err.code == ErrorCode::NotFound => return Ok(PaymentState::Unknown),
+            Some(err) => return Err(anyhow!("NWC wallet error: {}", err)),
+            None => {}
+        }
+        let res = res.to_lookup_invoice()?;
+        if res.transaction_type == Some(TransactionType::Incoming) {
+            return Ok(PaymentState::Unknown);
+        }
+        Ok(match res.state {
+            Some(TransactionState::Settled) => PaymentState::Succeeded(PaymentResult {
+                hash: *hash,
+                amount_msat: Some(Msat::from_msat(res.amount)),
+                fee_msat: Some(Msat::from_msat(res.fees_paid)),
+                preimage: res.preimage.and_then(|p| p.parse().ok()),
+            }),
+            Some(TransactionState::Failed | TransactionState::Expired) => PaymentState::Failed,
+            Some(TransactionState::Pending | TransactionState::Accepted) | None => {
+                PaymentState::Pending
+            }
+        })
+    }
+
+    async fn sign_message(&self, _message: &str) -> Result<String> {
+        Err(anyhow!("NIP-47 has no message signing"))
+    }
+
+    async fn verify_message(&self, _message: &str, _signature: &str) -> Result<SignatureCheck> {
+        Err(anyhow!("NIP-47 has no message verification"))
+    }
+
+    async fn update_channel_policy(
+        &self,
+        _channel: &ChannelSelector,
+        _policy: &ChannelPolicy,
+    ) -> Result<()> {
+        Err(anyhow!("NIP-47 has no channel management"))
+    }
+
+    async fn list_forwards(&self, _query: &ForwardQuery) -> Result<ForwardPage> {
+        Err(anyhow!("NIP-47 has no forwarding history"))
+    }
+
+    async fn query_routes(&self, _dest: &PublicKey, _amount: Msat) -> Result<Route> {
+        Err(anyhow!("NIP-47 has no route queries"))
+    }
+
+    async fn get_node(&self, _pubkey: &PublicKey) -> Result<GraphNode> {
+        Err(anyhow!("NIP-47 has no network graph"))
+    }
+
+    async fn get_channel(&self, _scid: ShortChannelId) -> Result<GraphChannel> {
+        Err(anyhow!("NIP-47 has no network graph"))
+    }
+
+    async fn probe_payment(&self, _bolt11: &Bolt11) -> Result<ProbeResult> {
+        Err(anyhow!("NIP-47 has no payment probing"))
+    }
+
+    async fn export_channel_backup(&self) -> Result<ChannelBackup> {
+        Err(anyhow!("NIP-47 has no channel backups"))
+    }
+
+    async fn verify_channel_backup(&self, _data: &[u8]) -> Result<BackupCheck> {
+        Err(anyhow!("NIP-47 has no channel backups"))
+    }
+
+    async fn open_channel_psbt(&self, _open: &PsbtChannelOpen) -> Result<PsbtFunding> {
+        Err(anyhow!("NIP-47 has no channel management"))
+    }
+
+    async fn update_channel_psbt(&self, _id: &str, _psbt: &Psbt) -> Result<Psbt> {
+        Err(anyhow!("NIP-47 has no channel management"))
+    }
+
+    async fn finalize_channel_psbt(&self, _id: &str, _signed: &Psbt) -> Result<PsbtChannelOpened> {
+        Err(anyhow!("NIP-47 has no channel management"))
+    }
+
+    async fn cancel_channel_psbt(&self, _id: &str) -> Result<()> {
+        Err(anyhow!("NIP-47 has no channel management"))
+    }
+
+    async fn fund_psbt(&self, _request: &FundPsbtRequest) -> Result<FundedPsbt> {
+        Err(anyhow!("NIP-47 has no on-chain wallet"))
+    }
+
+    async fn sign_psbt(&self, _psbt: &Psbt) -> Result<Psbt> {
+        Err(anyhow!("NIP-47 has no on-chain wallet"))
+    }
+
+    async fn finalize_psbt(&self, _psbt: &Psbt) -> Result<BroadcastTx> {
+        Err(anyhow!("NIP-47 has no on-chain wallet"))
+    }
+
+    async fn estimate_fee(&self, _target_conf: u32) -> Result<FeeEstimate> {
+        Err(anyhow!("NIP-47 has no on-chain wallet"))
+    }
+
+    async fn estimate_onchain_send(
+        &self,
+        _address: &str,
+        _amount: Sat,
+        _target_conf: u32,
+    ) -> Result<OnchainSendEstimate> {
+        Err(anyhow!("NIP-47 has no on-chain wallet"))
+    }
+
+    async fn capabilities(&self) -> Result<Capabilities> {
+        let res = self.request(Request::get_info()).await?.to_get_info()?;
+        let supported = res
+            .methods
+            .iter()
+            .filter_map(|method| match method {
+                Method::MakeInvoice => Some(Capability::CreateInvoice),
+                // Served by lookup_invoice, so only for the wallet's own invoices.
+                Method::LookupInvoice => Some(Capability::DecodeInvoice),
+                Method::PayInvoice => Some(Capability::PayInvoice),
+                Method::ListTransactions => Some(Capability::ListInvoices),
+                _ => None,
+            })
+            .collect();
+
+        Ok(Capabilities {
+            backend: "nwc".into(),
+            version: None,
+            network: res.network.as_deref().map(normalize_network),
+            supported,
+        })
+    }
+}
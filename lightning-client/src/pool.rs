@@ -0,0 +1,582 @@
+// lightning-client/src/pool.rs
+use super::*;
+use crate::config::{InvoiceStrategy, PaymentStrategy, PoolConfig};
+use anyhow::anyhow;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long a failed member is skipped before it gets another chance.
+const RETRY_DOWN_AFTER: Duration = Duration::from_secs(30);
+const PINNED_CAPACITY: usize = 4096;
+
+/// Several backends behind one `LightningClient`.
+///
+/// Reads and invoice creation fail over to the next healthy member; balances
+/// and invoice lists are merged. A payment never moves: an invoice is bound
+/// to the member that first took it, even if that member errors, because it
+/// may still have sent HTLCs. An invoice the pool has no pin for goes to
+/// whichever member already knows its payment, and to nobody while a member
+/// can't be asked.
+pub struct LightningPool {
+    members: Vec<Member>,
+    invoice_strategy: InvoiceStrategy,
+    payment_strategy: PaymentStrategy,
+    next_invoice: AtomicUsize,
+    pinned: Mutex<Pinned>,
+}
+
+struct Member {
+    name: String,
+    client: LightningClientDyn,
+    down_since: Mutex<Option<Instant>>,
+}
+
+#[derive(Default)]
+struct Pinned {
+    by_invoice: HashMap<String, usize>,
+    order: VecDeque<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MemberHealth {
+    pub name: String,
+    pub healthy: bool,
+}
+
+impl Member {
+    fn available(&self) -> bool {
+        match *self.down_since.lock().unwrap() {
+            Some(since) => since.elapsed() >= RETRY_DOWN_AFTER,
+            None => true,
+        }
+    }
+
+    fn mark(&self, healthy: bool) {
+        let mut down = self.down_since.lock().unwrap();
+        if healthy {
+            *down = None;
+        } else if down.is_none() {
+            eprintln!("Pool member '{}' is down", self.name);
+            *down = Some(Instant::now());
+        } else if down.is_some_and(|since| since.elapsed() >= RETRY_DOWN_AFTER) {
+            // Failed its retry; skip it for another window.
+            *down = Some(Instant::now());
+        }
+    }
+
+    /// Only a transport error says the member is down; a refused request
+    /// means it answered.
+    fn mark_err(&self, err: &anyhow::Error) {
+        self.mark(!retry::is_transient(err));
+    }
+}
+
+impl LightningPool {
+    /// `members` are (name, client) pairs in priority order.
+    pub fn new(members: Vec<(String, LightningClientDyn)>, cfg: &PoolConfig) -> Result<Self> {
+        if members.is_empty() {
+            return Err(anyhow!("A pool needs at least one member"));
+        }
+        Ok(Self {
+            members: members
+                .into_iter()
+                .map(|(name, client)| Member {
+                    name,
+                    client,
+                    down_since: Mutex::new(None),
+                })
+                .collect(),
+            invoice_strategy: cfg.invoice_strategy,
+            payment_strategy: cfg.payment_strategy,
+            next_invoice: AtomicUsize::new(0),
+            pinned: Mutex::new(Pinned::default()),
+        })
+    }
+
+    /// Probes every member with `get_info` and records the result.
+    pub async fn check_health(&self) -> Vec<MemberHealth> {
+        let mut report = Vec::with_capacity(self.members.len());
+        for member in &self.members {
+            let healthy = member.client.get_info().await.is_ok();
+            member.mark(healthy);
+            report.push(MemberHealth {
+                name: member.name.clone(),
+                healthy,
+            });
+        }
+        report
+    }
+
+    /// Last known state, without probing.
+    pub fn health(&self) -> Vec<MemberHealth> {
+        self.members
+            .iter()
+            .map(|m| MemberHealth {
+                name: m.name.clone(),
+                healthy: m.down_since.lock().unwrap().is_none(),
+            })
+            .collect()
+    }
+
+    /// Available members in priority order, rotated to start at `first`.
+    /// Falls back to every member when all of them look down.
+    fn order(&self, first: usize) -> Vec<usize> {
+        let n = self.members.len();
+        let all: Vec<usize> = (0..n).map(|i| (first + i) % n).collect();
+        let up: Vec<usize> = all
+            .iter()
+            .copied()
+            .filter(|&i| self.members[i].available())
+            .collect();
+        if up.is_empty() {
+            all
+        } else {
+            up
+        }
+    }
+
+    /// Where new invoices go first, per the pool's invoice strategy.
+    fn invoice_order(&self) -> Vec<usize> {
+        let first = match self.invoice_strategy {
+            InvoiceStrategy::Failover => 0,
+            InvoiceStrategy::RoundRobin => {
+                self.next_invoice.fetch_add(1, Ordering::Relaxed) % self.members.len()
+            }
+        };
+        self.order(first)
+    }
+
+    /// Runs `op` on each member in `order` until one succeeds.
+    async fn failover<T, F, Fut>(&self, order: Vec<usize>, op: F) -> Result<T>
+    where
+        F: Fn(LightningClientDyn) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut errors = Vec::new();
+        for i in order {
+            let member = &self.members[i];
+            match op(Arc::clone(&member.client)).await {
+                Ok(v) => {
+                    member.mark(true);
+                    return Ok(v);
+                }
+                Err(e) => {
+                    member.mark_err(&e);
+                    errors.push(format!("{}: {}", member.name, e));
+                }
+            }
+        }
+        Err(anyhow!("All pool members failed: {}", errors.join("; ")))
+    }
+
+    async fn pick_payer(&self, bolt11: &Bolt11) -> Result<usize> {
+        let order = self.order(0);
+        if self.payment_strategy == PaymentStrategy::Failover {
+            return Ok(order[0]);
+        }
+
+        let amount = self
+            .decode_invoice(bolt11)
+            .await?
+            .amount_msat
+            .unwrap_or(Msat::ZERO);
+        for i in order {
+            let member = &self.members[i];
+            match member.client.get_balance().await {
+                Ok(balance) => {
+                    member.mark(true);
+                    if balance.channel_msat >= amount {
+                        return Ok(i);
+                    }
+                }
+                Err(e) => member.mark_err(&e),
+            }
+        }
+        Err(anyhow!(
+            "No pool member has {} of outbound liquidity",
+            amount
+        ))
+    }
+
+    /// The member that knows the payment with `hash`, and what it knows.
+    /// `None` only when every member said it has never seen it.
+    async fn find_payment(&self, hash: &PaymentHash) -> Result<Option<(usize, PaymentState)>> {
+        let mut errors = Vec::new();
+        for (i, member) in self.members.iter().enumerate() {
+            match member.client.lookup_payment(hash).await {
+                Ok(PaymentState::Unknown) => {}
+                Ok(state) => return Ok(Some((i, state))),
+                Err(e) => errors.push(format!("{}: {}", member.name, e)),
+            }
+        }
+        if errors.is_empty() {
+            Ok(None)
+        } else {
+            Err(anyhow!(
+                "Cannot look up payment {}: {}",
+                hash,
+                errors.join("; ")
+            ))
+        }
+    }
+}
+
+#[async_trait]
+impl LightningClient for LightningPool {
+    async fn get_info(&self) -> Result<NodeInfo> {
+        self.failover(self.order(0), |c| async move { c.get_info().await })
+            .await
+    }
+
+    async fn create_invoice(
+        &self,
+        amount: Msat,
+        label: Option<&str>,
+        desc: Option<&str>,
+    ) -> Result<Bolt11> {
+        self.failover(self.invoice_order(), |c| async move {
+            c.create_invoice(amount, label, desc).await
+        })
+        .await
+    }
+
+    async fn create_invoice_hashed(
+        &self,
+        amount: Msat,
+        label: Option<&str>,
+        description: &str,
+    ) -> Result<Bolt11> {
+        self.failover(self.invoice_order(), |c| async move {
+            c.create_invoice_hashed(amount, label, description).await
+        })
+        .await
+    }
+
+    async fn get_balance(&self) -> Result<Balance> {
+        let mut total = Balance {
+            onchain_sat: Sat::ZERO,
+            channel_msat: Msat::ZERO,
+        };
+        let mut reached = 0;
+        for i in self.order(0) {
+            let member = &self.members[i];
+            match member.client.get_balance().await {
+                Ok(b) => {
+                    member.mark(true);
+                    total.onchain_sat = total
+                        .onchain_sat
+                        .checked_add(b.onchain_sat)
+                        .ok_or_else(|| anyhow!("On-chain balance overflow"))?;
+                    total.channel_msat = total
+                        .channel_msat
+                        .checked_add(b.channel_msat)
+                        .ok_or_else(|| anyhow!("Channel balance overflow"))?;
+                    reached += 1;
+                }
+                Err(e) => member.mark_err(&e),
+            }
+        }
+        if reached == 0 {
+            return Err(anyhow!("No pool member reachable"));
+        }
+        Ok(total)
+    }
+
+    async fn list_invoices(&self, limit: Option<usize>) -> Result<Vec<Invoice>> {
+        let mut lists = Vec::new();
+        for i in self.order(0) {
+            let member = &self.members[i];
+            match member.client.list_invoices(limit).await {
+                Ok(list) => {
+                    member.mark(true);
+                    lists.push(list.into_iter());
+                }
+                Err(e) => member.mark_err(&e),
+            }
+        }
+        if lists.is_empty() {
+            return Err(anyhow!("No pool member reachable"));
+        }
+
+        // Interleave so no single member crowds out the others.
+        let limit = limit.unwrap_or(10);
+        let mut merged = Vec::new();
+        while merged.len() < limit {
+            let before = merged.len();
+            for list in lists.iter_mut() {
+                if let Some(inv) = list.next() {
+                    merged.push(inv);
+                }
+            }
+            if merged.len() == before {
+                break;
+            }
+        }
+        merged.truncate(limit);
+        Ok(merged)
+    }
+
+    /// Any member may have made the invoice, so ask each until one has it.
+    async fn lookup_invoice(&self, hash: &PaymentHash) -> Result<Option<Invoice>> {
+        let mut errors = Vec::new();
+        for member in &self.members {
+            match member.client.lookup_invoice(hash).await {
+                Ok(None) => {}
+                Ok(found) => return Ok(found),
+                Err(e) => errors.push(format!("{}: {}", member.name, e)),
+            }
+        }
+        if errors.is_empty() {
+            Ok(None)
+        } else {
+            Err(anyhow!(
+                "Cannot look up invoice {}: {}",
+                hash,
+                errors.join("; ")
+            ))
+        }
+    }
+
+    async fn decode_invoice(&self, bolt11: &Bolt11) -> Result<DecodedInvoice> {
+        self.failover(
+            self.order(0),
+            |c| async move { c.decode_invoice(bolt11).await },
+        )
+        .await
+    }
+
+    async fn pay_invoice(&self, bolt11: &Bolt11) -> Result<PaymentResult> {
+        // Bolt11 is already normalized, so equal invoices give equal keys.
+        let key = bolt11.to_string();
+
+        let pinned = self.pinned.lock().unwrap().by_invoice.get(&key).copied();
+        let idx = match pinned {
+            Some(i) => i,
+            None => {
+                // The pins are lost on restart and evicted when full, so ask
+                // the members before trusting that nobody took the invoice.
+                let hash = bolt11
+                    .payment_hash()
+                    .ok_or_else(|| anyhow!("Invoice has no payment hash"))?;
+                let chosen = match self.find_payment(&hash).await {
+                    Ok(Some((i, _))) => i,
+                    Ok(None) => self.pick_payer(bolt11).await?,
+                    Err(e) => return Err(e.context("Not paying until every member has answered")),
+                };
+                let mut pinned = self.pinned.lock().unwrap();
+                // A concurrent call for the same invoice may have won the race.
+                match pinned.by_invoice.get(&key) {
+                    Some(&i) => i,
+                    None => {
+                        pinned.by_invoice.insert(key.clone(), chosen);
+                        pinned.order.push_back(key);
+                        if pinned.order.len() > PINNED_CAPACITY {
+                            if let Some(old) = pinned.order.pop_front() {
+                                pinned.by_invoice.remove(&old);
+                            }
+                        }
+                        chosen
+                    }
+                }
+            }
+        };
+
+        self.members[idx].client.pay_invoice(bolt11).await
+    }
+
+    /// Any member may have sent the payment, so ask each until one knows it.
+    async fn lookup_payment(&self, hash: &PaymentHash) -> Result<PaymentState> {
+        Ok(self
+            .find_payment(hash)
+            .await?
+            .map_or(PaymentState::Unknown, |(_, state)| state))
+    }
+
+    /// Signs with the first healthy member's key; members don't share keys,
+    /// so name the node directly to prove ownership of a specific one.
+    async fn sign_message(&self, message: &str) -> Result<String> {
+        self.failover(
+            self.order(0),
+            |c| async move { c.sign_message(message).await },
+        )
+        .await
+    }
+
+    async fn verify_message(&self, message: &str, signature: &str) -> Result<SignatureCheck> {
+        self.failover(self.order(0), |c| async move {
+            c.verify_message(message, signature).await
+        })
+        .await
+    }
+
+    /// `all` is applied on every member; a single channel on whichever
+    /// member has it.
+    async fn update_channel_policy(
+        &self,
+        channel: &ChannelSelector,
+        policy: &ChannelPolicy,
+    ) -> Result<()> {
+        let mut errors = Vec::new();
+        for member in &self.members {
+            match member.client.update_channel_policy(channel, policy).await {
+                Ok(()) if *channel != ChannelSelector::All => return Ok(()),
+                Ok(()) => {}
+                Err(e) => errors.push(format!("{}: {}", member.name, e)),
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("Policy update failed: {}", errors.join("; ")))
+        }
+    }
+
+    /// Offsets are per node, so one cursor can't page through several.
+    async fn list_forwards(&self, _query: &ForwardQuery) -> Result<ForwardPage> {
+        Err(anyhow!(
+            "Forwarding history is kept per node; query the pool members by name"
+        ))
+    }
+
+    async fn query_routes(&self, dest: &PublicKey, amount: Msat) -> Result<Route> {
+        self.failover(self.order(0), |c| async move {
+            c.query_routes(dest, amount).await
+        })
+        .await
+    }
+
+    async fn get_node(&self, pubkey: &PublicKey) -> Result<GraphNode> {
+        self.failover(self.order(0), |c| async move { c.get_node(pubkey).await })
+            .await
+    }
+
+    async fn get_channel(&self, scid: ShortChannelId) -> Result<GraphChannel> {
+        self.failover(self.order(0), |c| async move { c.get_channel(scid).await })
+            .await
+    }
+
+    /// Probes from the member `pay_invoice` would pick, without pinning the
+    /// invoice to it.
+    async fn probe_payment(&self, bolt11: &Bolt11) -> Result<ProbeResult> {
+        let idx = self.pick_payer(bolt11).await?;
+        self.members[idx].client.probe_payment(bolt11).await
+    }
+
+    /// Each member has its own channels, so a pool has no single backup.
+    async fn export_channel_backup(&self) -> Result<ChannelBackup> {
+        Err(anyhow!(
+            "Channel backups are per node; back up the pool members by name"
+        ))
+    }
+
+    async fn verify_channel_backup(&self, _data: &[u8]) -> Result<BackupCheck> {
+        Err(anyhow!(
+            "Channel backups are per node; verify against the pool member it came from"
+        ))
+    }
+
+    /// The later steps of an open must reach the member that started it.
+    async fn open_channel_psbt(&self, _open: &PsbtChannelOpen) -> Result<PsbtFunding> {
+        Err(anyhow!(
+            "Channel opens are per node; open through the pool member by name"
+        ))
+    }
+
+    async fn update_channel_psbt(&self, _id: &str, _psbt: &Psbt) -> Result<Psbt> {
+        Err(anyhow!(
+            "Channel opens are per node; open through the pool member by name"
+        ))
+    }
+
+    async fn finalize_channel_psbt(&self, _id: &str, _signed: &Psbt) -> Result<PsbtChannelOpened> {
+        Err(anyhow!(
+            "Channel opens are per node; open through the pool member by name"
+        ))
+    }
+
+    async fn cancel_channel_psbt(&self, _id: &str) -> Result<()> {
+        Err(anyhow!(
+            "Channel opens are per node; open through the pool member by name"
+        ))
+    }
+
+    /// Each member has its own wallet, whose reserved inputs only it can sign.
+    async fn fund_psbt(&self, _request: &FundPsbtRequest) -> Result<FundedPsbt> {
+        Err(anyhow!(
+            "On-chain wallets are per node; use the pool member by name"
+        ))
+    }
+
+    async fn sign_psbt(&self, _psbt: &Psbt) -> Result<Psbt> {
+        Err(anyhow!(
+            "On-chain wallets are per node; use the pool member by name"
+        ))
+    }
+
+    async fn finalize_psbt(&self, _psbt: &Psbt) -> Result<BroadcastTx> {
+        Err(anyhow!(
+            "On-chain wallets are per node; use the pool member by name"
+        ))
+    }
+
+    /// Fee rates come from the shared chain, so any member can answer.
+    async fn estimate_fee(&self, target_conf: u32) -> Result<FeeEstimate> {
+        self.failover(self.order(0), |c| async move {
+            c.estimate_fee(target_conf).await
+        })
+        .await
+    }
+
+    /// Coin selection is per wallet, so this is what a send from the first
+    /// healthy member would cost; ask a member by name for its own wallet.
+    async fn estimate_onchain_send(
+        &self,
+        address: &str,
+        amount: Sat,
+        target_conf: u32,
+    ) -> Result<OnchainSendEstimate> {
+        self.failover(self.order(0), |c| async move {
+            c.estimate_onchain_send(address, amount, target_conf).await
+        })
+        .await
+    }
+
+    /// What every reachable member supports, since any of them may serve a
+    /// call.
+    async fn capabilities(&self) -> Result<Capabilities> {
+        let mut merged: Option<Capabilities> = None;
+        for i in self.order(0) {
+            let member = &self.members[i];
+            let caps = match member.client.capabilities().await {
+                Ok(caps) => caps,
+                Err(e) => {
+                    member.mark_err(&e);
+                    continue;
+                }
+            };
+            member.mark(true);
+            merged = Some(match merged {
+                None => Capabilities {
+                    backend: "pool".into(),
+                    version: None,
+                    ..caps
+                },
+                Some(mut acc) => {
+                    acc.supported.retain(|c| caps.supported.contains(c));
+                    if acc.network != caps.network {
+                        acc.network = None;
+                    }
+                    acc
+                }
+            });
+        }
+        let mut merged = merged.ok_or_else(|| anyhow!("No pool member reachable"))?;
+        merged.supported.remove(&Capability::ForwardingHistory);
+        merged.supported.remove(&Capability::ChannelBackup);
+        merged.supported.remove(&Capability::Psbt);
+        Ok(merged)
+    }
+}
@@ -0,0 +1,55 @@
+// lightning-client/src/psbt.rs
+//
+// State shared by the LND backends for PSBT funded channel opens.
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// How long LND gets to publish the funding transaction once finalized.
+const PUBLISH_TIMEOUT: Duration = Duration::from_secs(60);
+/// Confirmation target for fund_psbt when no fee rate is given.
+pub(crate) const FUND_TARGET_CONF: u32 = 6;
+
+/// PSBT funded opens between `open_channel_psbt` and
+/// `finalize_channel_psbt`, by pending channel id. LND reports the funding
+/// outpoint on the stream that started the open, so that stream is read in
+/// the background until then.
+#[derive(Clone, Default)]
+pub(crate) struct PendingOpens(Arc<Mutex<HashMap<String, oneshot::Receiver<Result<String>>>>>);
+
+impl PendingOpens {
+    pub(crate) fn insert(&self, id: &str) -> oneshot::Sender<Result<String>> {
+        let (tx, rx) = oneshot::channel();
+        self.0.lock().unwrap().insert(id.to_string(), rx);
+        tx
+    }
+
+    pub(crate) fn check(&self, id: &str) -> Result<()> {
+        if self.0.lock().unwrap().contains_key(id) {
+            Ok(())
+        } else {
+            Err(anyhow!("No PSBT channel open '{}' in progress", id))
+        }
+    }
+
+    pub(crate) fn remove(&self, id: &str) -> Option<oneshot::Receiver<Result<String>>> {
+        self.0.lock().unwrap().remove(id)
+    }
+
+    /// Waits for the funding outpoint of a finalized open.
+    pub(crate) async fn published(&self, id: &str) -> Result<String> {
+        let rx = self
+            .remove(id)
+            .ok_or_else(|| anyhow!("No PSBT channel open '{}' in progress", id))?;
+        match tokio::time::timeout(PUBLISH_TIMEOUT, rx).await {
+            Ok(Ok(res)) => res,
+            Ok(Err(_)) => Err(anyhow!("LND closed the channel open stream")),
+            Err(_) => Err(anyhow!(
+                "LND did not publish the funding transaction within {:?}",
+                PUBLISH_TIMEOUT
+            )),
+        }
+    }
+}
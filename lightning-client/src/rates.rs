@@ -0,0 +1,184 @@
+// lightning-client/src/rates.rs
+use super::*;
+use crate::config::RatesConfig;
+use anyhow::anyhow;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const MSAT_PER_BTC: f64 = 100_000_000_000.0;
+
+/// Source of BTC exchange rates for fiat-denominated invoices.
+#[async_trait]
+pub trait RateProvider: Send + Sync {
+    /// Price of one BTC in `currency`, an ISO 4217 code such as `USD`.
+    async fn btc_price(&self, currency: &str) -> Result<f64>;
+}
+
+pub type RateProviderDyn = Arc<dyn RateProvider>;
+
+pub fn rate_provider(cfg: &RatesConfig) -> Result<RateProviderDyn> {
+    match cfg.provider.as_str() {
+        "static" => Ok(Arc::new(StaticRates::new(&cfg.prices)?)),
+        "http" => {
+            let url = cfg
+                .url
+                .clone()
+                .ok_or_else(|| anyhow!("[rates] provider \"http\" needs a url"))?;
+            Ok(Arc::new(HttpRates::new(
+                url,
+                cfg.json_pointer.clone(),
+                Duration::from_secs(cfg.cache_secs),
+            )))
+        }
+        other => Err(anyhow!(
+            "Unknown rate provider '{}'; use \"static\" or \"http\"",
+            other
+        )),
+    }
+}
+
+/// `USD`, `usd` -> `USD`; rejects anything but three letters.
+pub fn currency_code(currency: &str) -> Result<String> {
+    let code = currency.trim().to_ascii_uppercase();
+    if code.len() != 3 || !code.bytes().all(|b| b.is_ascii_alphabetic()) {
+        return Err(anyhow!("Invalid currency code '{}'", currency));
+    }
+    Ok(code)
+}
+
+/// Converts a fiat amount at `btc_price` (fiat per BTC), rounding to the
+/// nearest msat.
+pub fn fiat_to_msat(amount: f64, btc_price: f64) -> Result<Msat> {
+    if !amount.is_finite() || amount <= 0.0 {
+        return Err(anyhow!("Fiat amount must be positive"));
+    }
+    if !btc_price.is_finite() || btc_price <= 0.0 {
+        return Err(anyhow!("Invalid BTC price {}", btc_price));
+    }
+    let msat = (amount / btc_price * MSAT_PER_BTC).round();
+    // Amounts beyond int64 are refused by LND anyway.
+    if msat < 1.0 || msat >= i64::MAX as f64 {
+        return Err(anyhow!(
+            "{} at {} per BTC is out of range",
+            amount,
+            btc_price
+        ));
+    }
+    Ok(Msat::from_msat(msat as u64))
+}
+
+// ---------------------------------------------------------------------
+// Static
+// ---------------------------------------------------------------------
+
+/// Fixed prices from the config, e.g. for tests or a shop that sets its own.
+pub struct StaticRates {
+    prices: HashMap<String, f64>,
+}
+
+impl StaticRates {
+    pub fn new<'a>(prices: impl IntoIterator<Item = (&'a String, &'a f64)>) -> Result<Self> {
+        let prices = prices
+            .into_iter()
+            .map(|(currency, &price)| {
+                if !price.is_finite() || price <= 0.0 {
+                    return Err(anyhow!("Invalid price {} for {}", price, currency));
+                }
+                Ok((currency_code(currency)?, price))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { prices })
+    }
+}
+
+#[async_trait]
+impl RateProvider for StaticRates {
+    async fn btc_price(&self, currency: &str) -> Result<f64> {
+        let code = currency_code(currency)?;
+        self.prices
+            .get(&code)
+            .copied()
+            .ok_or_else(|| anyhow!("No rate configured for {}", code))
+    }
+}
+
+// ---------------------------------------------------------------------
+// HTTP
+// ---------------------------------------------------------------------
+
+/// Fetches prices from a JSON endpoint and caches them for `cache_for`.
+pub struct HttpRates {
+    client: reqwest::Client,
+    url: String,
+    json_pointer: Option<String>,
+    cache_for: Duration,
+    cache: Mutex<HashMap<String, (Instant, f64)>>,
+}
+
+impl HttpRates {
+    pub fn new(url: String, json_pointer: Option<String>, cache_for: Duration) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap_or_default(),
+            url,
+            json_pointer,
+            cache_for,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn fetch(&self, code: &str) -> Result<f64> {
+        let fill = |template: &str| {
+            template
+                .replace("{currency_lower}", &code.to_ascii_lowercase())
+                .replace("{currency}", code)
+        };
+        let body: Value = self
+            .client
+            .get(fill(&self.url))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let value = match &self.json_pointer {
+            Some(pointer) => body
+                .pointer(&fill(pointer))
+                .ok_or_else(|| anyhow!("Rate response has no {}", fill(pointer)))?,
+            None => &body,
+        };
+        // Some APIs quote prices as strings to keep their precision.
+        let price = match value {
+            Value::Number(n) => n.as_f64(),
+            Value::String(s) => s.trim().parse().ok(),
+            _ => None,
+        }
+        .filter(|p: &f64| p.is_finite() && *p > 0.0)
+        .ok_or_else(|| anyhow!("Rate response has no valid {} price: {}", code, value))?;
+        Ok(price)
+    }
+}
+
+#[async_trait]
+impl RateProvider for HttpRates {
+    async fn btc_price(&self, currency: &str) -> Result<f64> {
+        let code = currency_code(currency)?;
+        if let Some(&(at, price)) = self.cache.lock().unwrap().get(&code) {
+            if at.elapsed() < self.cache_for {
+                return Ok(price);
+            }
+        }
+
+        let price = self.fetch(&code).await?;
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(code, (Instant::now(), price));
+        Ok(price)
+    }
+}
@@ -0,0 +1,83 @@
+// lightning-client/src/registry.rs
+use super::*;
+use crate::factory::connect_node;
+use crate::pool::LightningPool;
+use anyhow::anyhow;
+use std::collections::BTreeMap;
+
+/// Every configured node, connected and addressable by name.
+pub struct NodeRegistry {
+    nodes: BTreeMap<String, LightningClientDyn>,
+    pools: BTreeMap<String, Arc<LightningPool>>,
+    default: String,
+}
+
+impl NodeRegistry {
+    pub async fn connect(settings: Settings) -> Result<Self> {
+        let (configs, default) = settings.into_nodes()?;
+
+        // Pools refer to other nodes by name, so connect those first.
+        let mut nodes = BTreeMap::new();
+        let mut pool_configs = Vec::new();
+        for (name, node) in configs {
+            if node.node_type == "pool" {
+                let cfg = node
+                    .pool
+                    .ok_or_else(|| anyhow!("Node '{}': Missing [pool] config", name))?;
+                pool_configs.push((name, cfg));
+                continue;
+            }
+            let client = connect_node(node)
+                .await
+                .map_err(|e| anyhow!("Node '{}': {}", name, e))?;
+            nodes.insert(name, client);
+        }
+
+        let mut pools = BTreeMap::new();
+        for (name, cfg) in pool_configs {
+            let members = cfg
+                .members
+                .iter()
+                .map(|member| {
+                    nodes
+                        .get(member)
+                        .map(|client| (member.clone(), Arc::clone(client)))
+                        .ok_or_else(|| anyhow!("Node '{}': Unknown pool member '{}'", name, member))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let pool = Arc::new(
+                LightningPool::new(members, &cfg).map_err(|e| anyhow!("Node '{}': {}", name, e))?,
+            );
+            pools.insert(name, pool);
+        }
+        for (name, pool) in &pools {
+            nodes.insert(name.clone(), Arc::clone(pool) as LightningClientDyn);
+        }
+
+        Ok(Self {
+            nodes,
+            pools,
+            default,
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Option<LightningClientDyn> {
+        self.nodes.get(name).cloned()
+    }
+
+    pub fn default_node(&self) -> LightningClientDyn {
+        Arc::clone(&self.nodes[&self.default])
+    }
+
+    pub fn default_name(&self) -> &str {
+        &self.default
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.nodes.keys().map(String::as_str)
+    }
+
+    pub fn pools(&self) -> impl Iterator<Item = (&str, &Arc<LightningPool>)> {
+        self.pools.iter().map(|(name, pool)| (name.as_str(), pool))
+    }
+}
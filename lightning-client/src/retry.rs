@@ -0,0 +1,246 @@
+// lightning-client/src/retry.rs
+use super::*;
+use crate::config::ConnectionConfig;
+use std::future::Future;
+use std::io::ErrorKind;
+use std::time::Duration;
+
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+/// Retries read-only calls on transport errors with exponential backoff.
+///
+/// `create_invoice` and `pay_invoice` are passed through once: a timed-out
+/// request may still have reached the node, and repeating it could create a
+/// second invoice or send a payment twice. Probes are passed through too, as
+/// each attempt puts another HTLC on the wire.
+pub struct RetryingClient {
+    inner: LightningClientDyn,
+    max_retries: u32,
+    backoff: Duration,
+}
+
+impl RetryingClient {
+    pub fn new(inner: LightningClientDyn, cfg: &ConnectionConfig) -> Self {
+        Self {
+            inner,
+            max_retries: cfg.max_retries,
+            backoff: cfg.retry_backoff(),
+        }
+    }
+
+    async fn retry<T, F, Fut>(&self, what: &str, op: F) -> Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut delay = self.backoff;
+        let mut attempt = 0;
+        loop {
+            match op().await {
+                Err(e) if attempt < self.max_retries && is_transient(&e) => {
+                    attempt += 1;
+                    eprintln!(
+                        "{} failed: {}; retry {}/{} in {:?}",
+                        what, e, attempt, self.max_retries, delay
+                    );
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(MAX_BACKOFF);
+                }
+                res => return res,
+            }
+        }
+    }
+}
+
+/// Whether `err` comes from the connection rather than the node refusing
+/// the request, i.e. whether trying again can help.
+pub fn is_transient(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+            return e.is_timeout()
+                || e.is_connect()
+                || matches!(e.status().map(|s| s.as_u16()), Some(502..=504));
+        }
+        #[cfg(feature = "lnd-grpc")]
+        if let Some(status) = cause.downcast_ref::<tonic::Status>() {
+            return matches!(
+                status.code(),
+                tonic::Code::Unavailable | tonic::Code::DeadlineExceeded
+            );
+        }
+        if let Some(e) = cause.downcast_ref::<std::io::Error>() {
+            return matches!(
+                e.kind(),
+                ErrorKind::ConnectionRefused
+                    | ErrorKind::ConnectionReset
+                    | ErrorKind::ConnectionAborted
+                    | ErrorKind::TimedOut
+                    | ErrorKind::BrokenPipe
+            );
+        }
+        false
+    })
+}
+
+#[async_trait]
+impl LightningClient for RetryingClient {
+    async fn get_info(&self) -> Result<NodeInfo> {
+        self.retry("get_info", || self.inner.get_info()).await
+    }
+
+    async fn create_invoice(
+        &self,
+        amount: Msat,
+        label: Option<&str>,
+        desc: Option<&str>,
+    ) -> Result<Bolt11> {
+        self.inner.create_invoice(amount, label, desc).await
+    }
+
+    async fn create_invoice_hashed(
+        &self,
+        amount: Msat,
+        label: Option<&str>,
+        description: &str,
+    ) -> Result<Bolt11> {
+        self.inner
+            .create_invoice_hashed(amount, label, description)
+            .await
+    }
+
+    async fn get_balance(&self) -> Result<Balance> {
+        self.retry("get_balance", || self.inner.get_balance()).await
+    }
+
+    async fn list_invoices(&self, limit: Option<usize>) -> Result<Vec<Invoice>> {
+        self.retry("list_invoices", || self.inner.list_invoices(limit))
+            .await
+    }
+
+    async fn lookup_invoice(&self, hash: &PaymentHash) -> Result<Option<Invoice>> {
+        self.retry("lookup_invoice", || self.inner.lookup_invoice(hash))
+            .await
+    }
+
+    async fn decode_invoice(&self, bolt11: &Bolt11) -> Result<DecodedInvoice> {
+        self.retry("decode_invoice", || self.inner.decode_invoice(bolt11))
+            .await
+    }
+
+    async fn pay_invoice(&self, bolt11: &Bolt11) -> Result<PaymentResult> {
+        self.inner.pay_invoice(bolt11).await
+    }
+
+    async fn lookup_payment(&self, hash: &PaymentHash) -> Result<PaymentState> {
+        self.retry("lookup_payment", || self.inner.lookup_payment(hash))
+            .await
+    }
+
+    async fn sign_message(&self, message: &str) -> Result<String> {
+        self.retry("sign_message", || self.inner.sign_message(message))
+            .await
+    }
+
+    async fn verify_message(&self, message: &str, signature: &str) -> Result<SignatureCheck> {
+        self.retry("verify_message", || {
+            self.inner.verify_message(message, signature)
+        })
+        .await
+    }
+
+    async fn update_channel_policy(
+        &self,
+        channel: &ChannelSelector,
+        policy: &ChannelPolicy,
+    ) -> Result<()> {
+        self.inner.update_channel_policy(channel, policy).await
+    }
+
+    async fn list_forwards(&self, query: &ForwardQuery) -> Result<ForwardPage> {
+        self.retry("list_forwards", || self.inner.list_forwards(query))
+            .await
+    }
+
+    async fn query_routes(&self, dest: &PublicKey, amount: Msat) -> Result<Route> {
+        self.retry("query_routes", || self.inner.query_routes(dest, amount))
+            .await
+    }
+
+    async fn get_node(&self, pubkey: &PublicKey) -> Result<GraphNode> {
+        self.retry("get_node", || self.inner.get_node(pubkey)).await
+    }
+
+    async fn get_channel(&self, scid: ShortChannelId) -> Result<GraphChannel> {
+        self.retry("get_channel", || self.inner.get_channel(scid))
+            .await
+    }
+
+    async fn probe_payment(&self, bolt11: &Bolt11) -> Result<ProbeResult> {
+        self.inner.probe_payment(bolt11).await
+    }
+
+    async fn export_channel_backup(&self) -> Result<ChannelBackup> {
+        self.retry("export_channel_backup", || {
+            self.inner.export_channel_backup()
+        })
+        .await
+    }
+
+    async fn verify_channel_backup(&self, data: &[u8]) -> Result<BackupCheck> {
+        self.retry("verify_channel_backup", || {
+            self.inner.verify_channel_backup(data)
+        })
+        .await
+    }
+
+    async fn open_channel_psbt(&self, open: &PsbtChannelOpen) -> Result<PsbtFunding> {
+        self.inner.open_channel_psbt(open).await
+    }
+
+    async fn update_channel_psbt(&self, id: &str, psbt: &Psbt) -> Result<Psbt> {
+        self.inner.update_channel_psbt(id, psbt).await
+    }
+
+    async fn finalize_channel_psbt(&self, id: &str, signed: &Psbt) -> Result<PsbtChannelOpened> {
+        self.inner.finalize_channel_psbt(id, signed).await
+    }
+
+    async fn cancel_channel_psbt(&self, id: &str) -> Result<()> {
+        self.inner.cancel_channel_psbt(id).await
+    }
+
+    async fn fund_psbt(&self, request: &FundPsbtRequest) -> Result<FundedPsbt> {
+        self.inner.fund_psbt(request).await
+    }
+
+    async fn sign_psbt(&self, psbt: &Psbt) -> Result<Psbt> {
+        self.retry("sign_psbt", || self.inner.sign_psbt(psbt)).await
+    }
+
+    async fn finalize_psbt(&self, psbt: &Psbt) -> Result<BroadcastTx> {
+        self.inner.finalize_psbt(psbt).await
+    }
+
+    async fn estimate_fee(&self, target_conf: u32) -> Result<FeeEstimate> {
+        self.retry("estimate_fee", || self.inner.estimate_fee(target_conf))
+            .await
+    }
+
+    async fn estimate_onchain_send(
+        &self,
+        address: &str,
+        amount: Sat,
+        target_conf: u32,
+    ) -> Result<OnchainSendEstimate> {
+        self.retry("estimate_onchain_send", || {
+            self.inner
+                .estimate_onchain_send(address, amount, target_conf)
+        })
+        .await
+    }
+
+    async fn capabilities(&self) -> Result<Capabilities> {
+        self.retry("capabilities", || self.inner.capabilities())
+            .await
+    }
+}
@@ -0,0 +1,886 @@
+// lightning-client/src/types.rs
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::str::FromStr;
+
+// ---------------------------------------------------------------------
+// Amounts
+// ---------------------------------------------------------------------
+
+/// Millisatoshis. Serialized as a plain number.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct Msat(u64);
+
+/// Satoshis. Serialized as a plain number.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct Sat(u64);
+
+impl Msat {
+    pub const ZERO: Msat = Msat(0);
+
+    pub const fn from_msat(msat: u64) -> Self {
+        Msat(msat)
+    }
+
+    pub const fn as_msat(self) -> u64 {
+        self.0
+    }
+
+    pub fn checked_add(self, other: Msat) -> Option<Msat> {
+        self.0.checked_add(other.0).map(Msat)
+    }
+
+    pub fn checked_sub(self, other: Msat) -> Option<Msat> {
+        self.0.checked_sub(other.0).map(Msat)
+    }
+
+    /// Whole satoshis, rounding down.
+    pub const fn to_sat_floor(self) -> Sat {
+        Sat(self.0 / 1000)
+    }
+
+    /// For APIs that take a signed 64-bit amount (LND's `int64` fields).
+    pub fn to_i64(self) -> Result<i64> {
+        i64::try_from(self.0).map_err(|_| anyhow!("{} does not fit in int64", self))
+    }
+}
+
+impl TryFrom<i64> for Msat {
+    type Error = anyhow::Error;
+
+    fn try_from(msat: i64) -> Result<Self> {
+        u64::try_from(msat)
+            .map(Msat)
+            .map_err(|_| anyhow!("Negative amount: {} msat", msat))
+    }
+}
+
+impl Sat {
+    pub const ZERO: Sat = Sat(0);
+
+    pub const fn from_sat(sat: u64) -> Self {
+        Sat(sat)
+    }
+
+    pub const fn as_sat(self) -> u64 {
+        self.0
+    }
+
+    pub fn checked_add(self, other: Sat) -> Option<Sat> {
+        self.0.checked_add(other.0).map(Sat)
+    }
+
+    pub fn to_msat(self) -> Result<Msat> {
+        self.0
+            .checked_mul(1000)
+            .map(Msat)
+            .ok_or_else(|| anyhow!("{} overflows msat", self))
+    }
+}
+
+impl TryFrom<i64> for Sat {
+    type Error = anyhow::Error;
+
+    fn try_from(sat: i64) -> Result<Self> {
+        u64::try_from(sat)
+            .map(Sat)
+            .map_err(|_| anyhow!("Negative amount: {} sat", sat))
+    }
+}
+
+impl fmt::Display for Msat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} msat", self.0)
+    }
+}
+
+impl fmt::Display for Sat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} sat", self.0)
+    }
+}
+
+// ---------------------------------------------------------------------
+// Identifiers
+// ---------------------------------------------------------------------
+
+/// Fixed-size byte string shown and serialized as lowercase hex.
+macro_rules! hex_id {
+    ($(#[$meta:meta])* $name:ident, $len:expr, $valid:expr) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub struct $name([u8; $len]);
+
+        impl $name {
+            pub fn from_slice(bytes: &[u8]) -> Result<Self> {
+                let bytes = <[u8; $len]>::try_from(bytes).map_err(|_| {
+                    anyhow!(
+                        "{} must be {} bytes, got {}",
+                        stringify!($name),
+                        $len,
+                        bytes.len()
+                    )
+                })?;
+                let valid: fn(&[u8; $len]) -> bool = $valid;
+                if !valid(&bytes) {
+                    return Err(anyhow!("Invalid {}", stringify!($name)));
+                }
+                Ok(Self(bytes))
+            }
+
+            pub fn as_bytes(&self) -> &[u8; $len] {
+                &self.0
+            }
+        }
+
+        impl TryFrom<&[u8]> for $name {
+            type Error = anyhow::Error;
+
+            fn try_from(bytes: &[u8]) -> Result<Self> {
+                Self::from_slice(bytes)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = anyhow::Error;
+
+            fn from_str(s: &str) -> Result<Self> {
+                let bytes = hex::decode(s.trim())
+                    .map_err(|e| anyhow!("Invalid {} hex: {}", stringify!($name), e))?;
+                Self::from_slice(&bytes)
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(&hex::encode(self.0))
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}({})", stringify!($name), self)
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+                s.collect_str(self)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+                String::deserialize(d)?
+                    .parse()
+                    .map_err(serde::de::Error::custom)
+            }
+        }
+    };
+}
+
+hex_id!(
+    /// SHA-256 of a payment's preimage.
+    PaymentHash,
+    32,
+    |_| true
+);
+
+hex_id!(
+    /// Secret revealed when a payment settles.
+    Preimage,
+    32,
+    |_| true
+);
+
+hex_id!(
+    /// Compressed secp256k1 node key. Only the length and the 02/03
+    /// prefix are checked, not that the point lies on the curve: the node
+    /// that receives the key rejects an invalid one.
+    PublicKey,
+    33,
+    |bytes| matches!(bytes[0], 0x02 | 0x03)
+);
+
+impl Preimage {
+    pub fn payment_hash(&self) -> PaymentHash {
+        PaymentHash(Sha256::digest(self.0).into())
+    }
+}
+
+impl PaymentHash {
+    /// Hash of a random preimage that is thrown away, so an HTLC locked to it
+    /// can never be settled. Used for probing routes.
+    pub fn random() -> Self {
+        Preimage(rand::random()).payment_hash()
+    }
+}
+
+// ---------------------------------------------------------------------
+// BOLT11
+// ---------------------------------------------------------------------
+
+const BECH32_CHARSET: &str = "qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// A BOLT11 payment request with a valid bech32 checksum and a known
+/// network prefix. Stored lowercase, without any `lightning:` prefix.
+#[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Bolt11(String);
+
+impl Bolt11 {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// `mainnet`, `testnet`, `signet` or `regtest`.
+    pub fn network(&self) -> &'static str {
+        network_of(&self.0).unwrap_or("mainnet")
+    }
+
+    /// Amount from the human-readable part; `None` for "any amount".
+    pub fn amount(&self) -> Option<Msat> {
+        let hrp = &self.0[..self.0.rfind('1')?];
+        let rest = hrp
+            .strip_prefix("ln")?
+            .trim_start_matches(|c: char| c.is_ascii_alphabetic());
+
+        let (digits, multiplier) = match rest.chars().last()? {
+            'm' | 'u' | 'n' | 'p' => rest.split_at(rest.len() - 1),
+            _ => (rest, ""),
+        };
+        let amount: u64 = digits.parse().ok()?;
+
+        // msat per unit of BTC / mBTC / uBTC / nBTC; pico-BTC is a tenth of a msat.
+        match multiplier {
+            "" => amount.checked_mul(100_000_000_000),
+            "m" => amount.checked_mul(100_000_000),
+            "u" => amount.checked_mul(100_000),
+            "n" => amount.checked_mul(100),
+            "p" if amount.is_multiple_of(10) => Some(amount / 10),
+            _ => None,
+        }
+        .map(Msat)
+    }
+
+    /// The `p` field.
+    pub fn payment_hash(&self) -> Option<PaymentHash> {
+        let bytes = from_base32(&self.tagged_field(1)?);
+        PaymentHash::from_slice(bytes.get(..32)?).ok()
+    }
+
+    /// The `h` field: SHA-256 of a description too long to embed, as
+    /// LNURL-pay invoices carry.
+    pub fn description_hash(&self) -> Option<[u8; 32]> {
+        let bytes = from_base32(&self.tagged_field(23)?);
+        bytes.get(..32)?.try_into().ok()
+    }
+
+    /// Raw 5-bit groups of the first tagged field of type `tag`.
+    fn tagged_field(&self, tag: u8) -> Option<Vec<u8>> {
+        let data = self.0[self.0.rfind('1')? + 1..]
+            .chars()
+            .map(|c| BECH32_CHARSET.find(c).map(|i| i as u8))
+            .collect::<Option<Vec<u8>>>()?;
+        // Timestamp first; signature and checksum last.
+        let mut fields = data.get(7..data.len().checked_sub(104 + 6)?)?;
+        while let [kind, hi, lo, rest @ ..] = fields {
+            let len = usize::from(*hi) * 32 + usize::from(*lo);
+            let value = rest.get(..len)?;
+            if *kind == tag {
+                return Some(value.to_vec());
+            }
+            fields = &rest[len..];
+        }
+        None
+    }
+}
+
+fn network_of(bolt11: &str) -> Option<&'static str> {
+    // Longer prefixes first: lnbc is a prefix of lnbcrt, lntb of lntbs.
+    [
+        ("lnbcrt", "regtest"),
+        ("lntbs", "signet"),
+        ("lntb", "testnet"),
+        ("lnbc", "mainnet"),
+    ]
+    .into_iter()
+    .find(|(prefix, _)| bolt11.starts_with(prefix))
+    .map(|(_, network)| network)
+}
+
+fn bech32_checksum_ok(hrp: &str, data: &[u8]) -> bool {
+    bech32_polymod(hrp, data) == 1
+}
+
+fn bech32_polymod(hrp: &str, data: &[u8]) -> u32 {
+    const GEN: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+    let values = hrp
+        .bytes()
+        .map(|b| b >> 5)
+        .chain([0])
+        .chain(hrp.bytes().map(|b| b & 31))
+        .chain(data.iter().copied());
+
+    let mut chk = 1u32;
+    for v in values {
+        let top = chk >> 25;
+        chk = ((chk & 0x1ff_ffff) << 5) ^ u32::from(v);
+        for (i, g) in GEN.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                chk ^= g;
+            }
+        }
+    }
+    chk
+}
+
+/// Packs 5-bit groups into bytes, dropping the trailing padding bits.
+fn from_base32(groups: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(groups.len() * 5 / 8);
+    let (mut acc, mut bits) = (0u32, 0);
+    for g in groups {
+        acc = (acc << 5) | u32::from(*g);
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+    bytes
+}
+
+/// Encodes `bytes` as lowercase bech32 without a length limit.
+pub(crate) fn encode_bech32(hrp: &str, bytes: &[u8]) -> String {
+    let mut data = Vec::with_capacity(bytes.len() * 8 / 5 + 7);
+    let (mut acc, mut bits) = (0u32, 0);
+    for b in bytes {
+        acc = (acc << 8) | u32::from(*b);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            data.push(((acc >> bits) & 31) as u8);
+        }
+    }
+    if bits > 0 {
+        data.push(((acc << (5 - bits)) & 31) as u8);
+    }
+    let chk = bech32_polymod(hrp, &[data.as_slice(), &[0; 6]].concat()) ^ 1;
+    data.extend((0..6).map(|i| ((chk >> (5 * (5 - i))) & 31) as u8));
+
+    let charset = BECH32_CHARSET.as_bytes();
+    let mut s = format!("{}1", hrp);
+    s.extend(data.iter().map(|&g| charset[usize::from(g)] as char));
+    s
+}
+
+/// Decodes a bech32 string of any length (LNURLs exceed the usual 90
+/// characters) into its human-readable part and payload bytes.
+pub(crate) fn decode_bech32(s: &str) -> Result<(String, Vec<u8>)> {
+    if s.bytes().any(|b| b.is_ascii_uppercase()) && s.bytes().any(|b| b.is_ascii_lowercase()) {
+        return Err(anyhow!("Invalid bech32: mixed case"));
+    }
+    let lower = s.to_ascii_lowercase();
+    let sep = lower
+        .rfind('1')
+        .filter(|&sep| sep > 0)
+        .ok_or_else(|| anyhow!("Invalid bech32: no separator"))?;
+    let (hrp, data) = (&lower[..sep], &lower[sep + 1..]);
+    let data = data
+        .chars()
+        .map(|c| BECH32_CHARSET.find(c).map(|i| i as u8))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(|| anyhow!("Invalid bech32: bad character"))?;
+    if data.len() < 6 || !bech32_checksum_ok(hrp, &data) {
+        return Err(anyhow!("Invalid bech32: bad checksum"));
+    }
+
+    Ok((hrp.to_string(), from_base32(&data[..data.len() - 6])))
+}
+
+impl FromStr for Bolt11 {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let s = s
+            .get(..10)
+            .filter(|p| p.eq_ignore_ascii_case("lightning:"))
+            .map_or(s, |_| &s[10..]);
+        if s.bytes().any(|b| b.is_ascii_uppercase()) && s.bytes().any(|b| b.is_ascii_lowercase()) {
+            return Err(anyhow!("Invalid bolt11: mixed case"));
+        }
+        let lower = s.to_ascii_lowercase();
+
+        if network_of(&lower).is_none() {
+            return Err(anyhow!(
+                "Invalid bolt11: expected an lnbc, lntb, lntbs or lnbcrt prefix"
+            ));
+        }
+        let sep = lower
+            .rfind('1')
+            .ok_or_else(|| anyhow!("Invalid bolt11: no separator"))?;
+        let (hrp, data) = (&lower[..sep], &lower[sep + 1..]);
+        let data = data
+            .chars()
+            .map(|c| BECH32_CHARSET.find(c).map(|i| i as u8))
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(|| anyhow!("Invalid bolt11: bad character"))?;
+        // Timestamp, signature and checksum alone are 7 + 104 + 6 characters.
+        if data.len() < 117 || !bech32_checksum_ok(hrp, &data) {
+            return Err(anyhow!("Invalid bolt11: bad checksum"));
+        }
+
+        Ok(Bolt11(lower))
+    }
+}
+
+impl TryFrom<String> for Bolt11 {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<Bolt11> for String {
+    fn from(b: Bolt11) -> String {
+        b.0
+    }
+}
+
+impl fmt::Display for Bolt11 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl fmt::Debug for Bolt11 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Bolt11({})", self.0)
+    }
+}
+
+// ---------------------------------------------------------------------
+// Channels
+// ---------------------------------------------------------------------
+
+/// BOLT 7 short channel id, shown as `block x tx x output`.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ShortChannelId(u64);
+
+impl ShortChannelId {
+    /// LND's numeric `chan_id`.
+    pub const fn from_u64(id: u64) -> Self {
+        ShortChannelId(id)
+    }
+
+    pub const fn as_u64(self) -> u64 {
+        self.0
+    }
+}
+
+impl FromStr for ShortChannelId {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || anyhow!("Invalid short channel id '{}'", s);
+        let parts = s
+            .trim()
+            .split('x')
+            .map(|p| p.parse::<u64>().map_err(|_| invalid()))
+            .collect::<Result<Vec<_>>>()?;
+        match parts[..] {
+            [block, tx, out] if block < 1 << 24 && tx < 1 << 24 && out < 1 << 16 => {
+                Ok(ShortChannelId(block << 40 | tx << 16 | out))
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl TryFrom<String> for ShortChannelId {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<ShortChannelId> for String {
+    fn from(id: ShortChannelId) -> String {
+        id.to_string()
+    }
+}
+
+impl fmt::Display for ShortChannelId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}x{}x{}",
+            self.0 >> 40,
+            (self.0 >> 16) & 0xff_ffff,
+            self.0 & 0xffff
+        )
+    }
+}
+
+impl fmt::Debug for ShortChannelId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ShortChannelId({})", self)
+    }
+}
+
+/// One channel, by short id or funding outpoint, or every channel.
+/// Parsed from `all`, `812345x1x0` or `<txid>:<vout>`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum ChannelSelector {
+    All,
+    Short(ShortChannelId),
+    Outpoint { txid: String, vout: u32 },
+}
+
+impl FromStr for ChannelSelector {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("all") {
+            return Ok(ChannelSelector::All);
+        }
+        if let Some((txid, vout)) = s.split_once(':') {
+            let valid_txid = txid.len() == 64 && txid.bytes().all(|b| b.is_ascii_hexdigit());
+            return match vout.parse() {
+                Ok(vout) if valid_txid => Ok(ChannelSelector::Outpoint {
+                    txid: txid.to_ascii_lowercase(),
+                    vout,
+                }),
+                _ => Err(anyhow!("Invalid funding outpoint '{}'", s)),
+            };
+        }
+        s.parse().map(ChannelSelector::Short).map_err(|_| {
+            anyhow!(
+                "Invalid channel '{}'; use all, a short channel id or txid:vout",
+                s
+            )
+        })
+    }
+}
+
+impl TryFrom<String> for ChannelSelector {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<ChannelSelector> for String {
+    fn from(c: ChannelSelector) -> String {
+        c.to_string()
+    }
+}
+
+impl fmt::Display for ChannelSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChannelSelector::All => f.write_str("all"),
+            ChannelSelector::Short(id) => write!(f, "{}", id),
+            ChannelSelector::Outpoint { txid, vout } => write!(f, "{}:{}", txid, vout),
+        }
+    }
+}
+
+// ---------------------------------------------------------------------
+// On-chain
+// ---------------------------------------------------------------------
+
+const PSBT_MAGIC: &[u8] = b"psbt\xff";
+
+/// A BIP 174 partially signed transaction. Only the magic is checked; the
+/// node parses the rest. Shown and serialized as base64.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Psbt(Vec<u8>);
+
+impl Psbt {
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self> {
+        if !bytes.starts_with(PSBT_MAGIC) {
+            return Err(anyhow!("Invalid PSBT: missing magic bytes"));
+        }
+        Ok(Psbt(bytes))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl FromStr for Psbt {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let bytes = general_purpose::STANDARD
+            .decode(s.trim())
+            .map_err(|e| anyhow!("Invalid PSBT base64: {}", e))?;
+        Psbt::from_bytes(bytes)
+    }
+}
+
+impl TryFrom<String> for Psbt {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<Psbt> for String {
+    fn from(p: Psbt) -> String {
+        p.to_string()
+    }
+}
+
+impl fmt::Display for Psbt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&general_purpose::STANDARD.encode(&self.0))
+    }
+}
+
+impl fmt::Debug for Psbt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Psbt({} bytes)", self.0.len())
+    }
+}
+
+/// Txid of a serialized transaction, in the usual reversed hex. Witness
+/// data is walked but skipped, as it is not committed to by the txid.
+pub(crate) fn txid(raw_tx: &[u8]) -> Result<String> {
+    let truncated = || anyhow!("Truncated transaction");
+    let mut pos = 4;
+    let segwit = raw_tx.get(4..6) == Some(&[0, 1]);
+    if segwit {
+        pos += 2;
+    }
+    let body_start = pos;
+
+    let inputs = read_varint(raw_tx, &mut pos).ok_or_else(truncated)?;
+    for _ in 0..inputs {
+        // Previous outpoint, then the script, then the sequence.
+        pos = pos.saturating_add(36);
+        let script_len = read_varint(raw_tx, &mut pos).ok_or_else(truncated)?;
+        pos = pos.saturating_add(script_len as usize).saturating_add(4);
+    }
+    let outputs = read_varint(raw_tx, &mut pos).ok_or_else(truncated)?;
+    for _ in 0..outputs {
+        pos = pos.saturating_add(8);
+        let script_len = read_varint(raw_tx, &mut pos).ok_or_else(truncated)?;
+        pos = pos.saturating_add(script_len as usize);
+    }
+    let body = raw_tx.get(body_start..pos).ok_or_else(truncated)?;
+    if segwit {
+        // One stack of length-prefixed items per input.
+        for _ in 0..inputs {
+            let items = read_varint(raw_tx, &mut pos).ok_or_else(truncated)?;
+            for _ in 0..items {
+                let len = read_varint(raw_tx, &mut pos).ok_or_else(truncated)?;
+                pos = pos.saturating_add(len as usize);
+            }
+        }
+    }
+    let lock_time = raw_tx
+        .get(pos..)
+        .filter(|rest| rest.len() == 4)
+        .ok_or_else(|| anyhow!("Malformed transaction"))?;
+
+    let stripped = [&raw_tx[..4], body, lock_time].concat();
+    let mut hash: [u8; 32] = Sha256::digest(Sha256::digest(stripped)).into();
+    hash.reverse();
+    Ok(hex::encode(hash))
+}
+
+/// Bitcoin's CompactSize integer.
+fn read_varint(bytes: &[u8], pos: &mut usize) -> Option<u64> {
+    let first = *bytes.get(*pos)?;
+    let len = match first {
+        0xfd => 2,
+        0xfe => 4,
+        0xff => 8,
+        _ => {
+            *pos += 1;
+            return Some(u64::from(first));
+        }
+    };
+    let raw = bytes.get(*pos + 1..*pos + 1 + len)?;
+    *pos += 1 + len;
+    Some(
+        raw.iter()
+            .rev()
+            .fold(0u64, |acc, &b| (acc << 8) | u64::from(b)),
+    )
+}
+
+// ---------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // BOLT11 spec vectors.
+    const DONATION: &str = "lnbc2500u1pvjluezsp5zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zygspp5qqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqypqdq5xysxxatsyp3k7enxv4jsxqzpu9qrsgquk0rl77nj30yxdy8j9vdx85fkpmdla2087ne0xh8nhedh8w27kyke0lp53ut353s06fv3qfegext0eh0ymjpf39tuven09sam30g4vgpfna3rh";
+    const CHOCOLATE: &str = "lnbc20m1pvjluezsp5zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zygspp5qqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqypqhp58yjmdan79s6qqdhdzgynm4zwqd5d7xmw5fk98klysy043l2ahrqs9qrsgq7ea976txfraylvgzuxs8kgcw23ezlrszfnh8r6qtfpr6cxga50aj6txm9rxrydzd06dfeawfk6swupvz4erwnyutnjq7x39ymw6j38gp7ynn44";
+    const TESTNET: &str = "lntb20m1pvjluezsp5zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zygshp58yjmdan79s6qqdhdzgynm4zwqd5d7xmw5fk98klysy043l2ahrqspp5qqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqypqfpp3x9et2e20v6pu37c5d9vax37wxq72un989qrsgqdj545axuxtnfemtpwkc45hx9d2ft7x04mt8q7y6t0k2dge9e7h8kpy9p34ytyslj3yu569aalz2xdk8xkd7ltxqld94u8h2esmsmacgpghe9k8";
+    const BAD_CHECKSUM: &str = "lnbc2500u1pvjluezpp5qqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqypqdpquwpc4curk03c9wlrswe78q4eyqc7d8d0xqzpuyk0sg5g70me25alkluzd2x62aysf2pyy8edtjeevuv4p2d5p76r4zkmneet7uvyakky2zr4cusd45tftc9c5fh0nnqpnl2jfll544esqchsrnt";
+    const MIXED_CASE: &str = "LNBC2500u1pvjluezpp5qqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqypqdpquwpc4curk03c9wlrswe78q4eyqc7d8d0xqzpuyk0sg5g70me25alkluzd2x62aysf2pyy8edtjeevuv4p2d5p76r4zkmneet7uvyakky2zr4cusd45tftc9c5fh0nnqpnl2jfll544esqchsrny";
+    const TOO_SHORT: &str = "lnbc1pvjluezpp5qqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqypqdpl2pkx2ctnv5sxxmmwwd5kgetjypeh2ursdae8g6na6hlh";
+    const UNKNOWN_MULTIPLIER: &str = "lnbc2500x1pvjluezpp5qqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqypqdq5xysxxatsyp3k7enxv4jsxqzpusp5zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zygs9qrsgqrrzc4cvfue4zp3hggxp47ag7xnrlr8vgcmkjxk3j5jqethnumgkpqp23z9jclu3v0a7e0aruz366e9wqdykw6dxhdzcjjhldxq0w6wgqcnu43j";
+    const SUB_MSAT: &str = "lnbc2500000001p1pvjluezpp5qqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqypqdq5xysxxatsyp3k7enxv4jsxqzpusp5zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zygs9qrsgq0lzc236j96a95uv0m3umg28gclm5lqxtqqwk32uuk4k6673k6n5kfvx3d2h8s295fad45fdhmusm8sjudfhlf6dcsxmfvkeywmjdkxcp99202x";
+
+    #[test]
+    fn decodes_bip173_valid_strings() {
+        for s in [
+            "A12UEL5L",
+            "a12uel5l",
+            "an83characterlonghumanreadablepartthatcontainsthenumber1andtheexcludedcharactersbio1tt5tgs",
+            "abcdef1qpzry9x8gf2tvdw0s3jn54khce6mua7lmqqqxw",
+            "11qqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqc8247j",
+            "split1checkupstagehandshakeupstreamerranterredcaperred2y9e3w",
+            "?1ezyfcl",
+        ] {
+            assert!(decode_bech32(s).is_ok(), "{}", s);
+        }
+        let (hrp, data) = decode_bech32("A12UEL5L").unwrap();
+        assert_eq!((hrp.as_str(), data.len()), ("a", 0));
+    }
+
+    #[test]
+    fn rejects_bip173_invalid_strings() {
+        for s in [
+            "A1G7SGD8",
+            "pzry9x0s0muk",
+            "1pzry9x0s0muk",
+            "x1b4n0q5v",
+            "li1dgmt3",
+            "10a06t8",
+            "A12uEL5L",
+        ] {
+            assert!(decode_bech32(s).is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn bech32_round_trips() {
+        let bytes = b"https://example.com/lnurlp/alice";
+        let encoded = encode_bech32("lnurl", bytes);
+        assert_eq!(
+            decode_bech32(&encoded.to_ascii_uppercase()).unwrap(),
+            ("lnurl".to_string(), bytes.to_vec())
+        );
+    }
+
+    #[test]
+    fn reads_bolt11_fields() {
+        let pr: Bolt11 = DONATION.parse().unwrap();
+        assert_eq!(pr.network(), "mainnet");
+        assert_eq!(pr.amount(), Some(Msat::from_msat(250_000_000)));
+        assert_eq!(
+            pr.payment_hash().unwrap().to_string(),
+            "0001020304050607080900010203040506070809000102030405060708090102"
+        );
+        assert_eq!(pr.description_hash(), None);
+
+        let pr: Bolt11 = CHOCOLATE.parse().unwrap();
+        assert_eq!(pr.amount(), Some(Msat::from_msat(2_000_000_000)));
+        assert_eq!(
+            hex::encode(pr.description_hash().unwrap()),
+            "3925b6f67e2c340036ed12093dd44e0368df1b6ea26c53dbe4811f58fd5db8c1"
+        );
+
+        let pr: Bolt11 = format!("LIGHTNING:{}", TESTNET.to_ascii_uppercase())
+            .parse()
+            .unwrap();
+        assert_eq!(pr.network(), "testnet");
+        assert_eq!(pr.as_str(), TESTNET);
+        assert!(pr.payment_hash().is_some());
+    }
+
+    #[test]
+    fn bolt11_amount_needs_a_known_exact_multiplier() {
+        let pr: Bolt11 = UNKNOWN_MULTIPLIER.parse().unwrap();
+        assert_eq!(pr.amount(), None);
+        let pr: Bolt11 = SUB_MSAT.parse().unwrap();
+        assert_eq!(pr.amount(), None);
+    }
+
+    #[test]
+    fn rejects_bolt11_spec_invalid_strings() {
+        for s in [
+            BAD_CHECKSUM,
+            MIXED_CASE,
+            TOO_SHORT,
+            "lnxy1qqqqqqqqqqqqq",
+            "a12uel5l",
+        ] {
+            assert!(s.parse::<Bolt11>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn public_key_checks_length_and_prefix() {
+        let key = format!("02{}", "11".repeat(32));
+        assert_eq!(key.parse::<PublicKey>().unwrap().to_string(), key);
+        assert!(format!("04{}", "11".repeat(32))
+            .parse::<PublicKey>()
+            .is_err());
+        assert!(format!("02{}", "11".repeat(31))
+            .parse::<PublicKey>()
+            .is_err());
+    }
+
+    #[test]
+    fn txid_skips_witness_data() {
+        let segwit = hex::decode(
+            "02000000000101595895ea20179de87052b4046dfe6fd515860505d6511a9004cf12a1f93cac7c01000000\
+             00ffffffff01deb807000000000017a9140f3444e271620c736808aa7b33e370bd87cb5a078702483045022\
+             100fb60dad8df4af2841adc0346638c16d0b8035f5e3f3753b88db122e70c79f9370220756e6633b17fd271\
+             0e626347d28d60b0a2d6cbb41de51740644b9fb3ba7751040121028fa937ca8cba2197a37c007176ed89410\
+             55d3bcb8627d085e94553e62f057dcc00000000",
+        )
+        .unwrap();
+        assert_eq!(
+            txid(&segwit).unwrap(),
+            "f5864806e3565c34d1b41e716f72609d00b55ea5eac5b924c9719a842ef42206"
+        );
+
+        let legacy = hex::decode(
+            "0100000001a15d57094aa7a21a28cb20b59aab8fc7d1149a3bdbcddba9c622e4f5f6a99ece010000006c\
+             493046022100f93bb0e7d8db7bd46e40132d1f8242026e045f03a0efe71bbb8e3f475e970d790221009337\
+             cd7f1f929f00cc6ff01f03729b069a7c21b59b1736ddfee5db5946c5da8c0121033b9b137ee87d5a812d6f\
+             506efdd37f0affa7ffc310711c06c7f3e097c9447c52ffffffff0100e1f505000000001976a9140389035a\
+             9225b3839e2bbf32d826a1e222031fd888ac00000000",
+        )
+        .unwrap();
+        assert_eq!(
+            txid(&legacy).unwrap(),
+            "a6eab3c14ab5272a58a5ba91505ba1a4b6d7a3a9fcbd187b6cd99a7b6d548cb7"
+        );
+
+        assert!(txid(&segwit[..segwit.len() - 5]).is_err());
+        assert!(txid(&legacy[..60]).is_err());
+        assert!(txid(&[legacy.as_slice(), &[0]].concat()).is_err());
+    }
+}
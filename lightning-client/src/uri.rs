@@ -0,0 +1,159 @@
+// lightning-client/src/uri.rs
+use crate::config::{ClnConfig, LndConfig, NodeSettings, NwcConfig, Settings, DEFAULT_NODE_NAME};
+use anyhow::{anyhow, Result};
+use reqwest::Url;
+
+const LND_REST_PORT: u16 = 8080;
+const CLNREST_PORT: u16 = 3010;
+const LIGHTNING_PORT: u16 = 9735;
+
+/// Single-node settings from a connection string; see [`node_from_uri`].
+pub fn settings_from_uri(uri: &str) -> Result<Settings> {
+    let mut settings = Settings::default();
+    settings
+        .nodes
+        .insert(DEFAULT_NODE_NAME.to_string(), node_from_uri(uri)?);
+    Ok(settings)
+}
+
+/// Builds a node's backend config from a single connection string.
+///
+/// - `lndconnect://host:port?cert=...&macaroon=...` selects LND REST on
+///   port 8080 and LND gRPC otherwise.
+/// - `clnrest://host:port?rune=...` (add `protocol=http` for plain HTTP).
+/// - `commando://<node pubkey>@host:port?rune=...` reaches CLN as a
+///   Lightning peer (port 9735 by default) and calls it through Commando.
+/// - `nostr+walletconnect://...`
+pub fn node_from_uri(uri: &str) -> Result<NodeSettings> {
+    let uri = uri.trim();
+    let scheme = uri
+        .split_once("://")
+        .map(|(scheme, _)| scheme.to_ascii_lowercase())
+        .ok_or_else(|| anyhow!("Connection string has no scheme"))?;
+
+    match scheme.as_str() {
+        "lndconnect" => lndconnect(uri),
+        "clnrest" => clnrest(uri),
+        "commando" => commando(uri),
+        "nostr+walletconnect" => Ok(NodeSettings {
+            node_type: "nwc".into(),
+            nwc: Some(NwcConfig {
+                uri: uri.to_string(),
+            }),
+            ..Default::default()
+        }),
+        other => Err(anyhow!("Unsupported connection string scheme '{}'", other)),
+    }
+}
+
+fn lndconnect(uri: &str) -> Result<NodeSettings> {
+    let url = Url::parse(uri).map_err(|e| anyhow!("Invalid lndconnect URI: {}", e))?;
+    if url.host_str().is_none() {
+        return Err(anyhow!("lndconnect URI has no host"));
+    }
+    if !url.query_pairs().any(|(k, _)| k == "macaroon") {
+        return Err(anyhow!("lndconnect URI has no macaroon"));
+    }
+
+    let lnd = LndConfig {
+        lndconnect: Some(uri.to_string()),
+        ..Default::default()
+    };
+    Ok(if url.port() == Some(LND_REST_PORT) {
+        NodeSettings {
+            node_type: "lnd-rest".into(),
+            lnd_rest: Some(lnd),
+            ..Default::default()
+        }
+    } else {
+        NodeSettings {
+            node_type: "lnd-grpc".into(),
+            lnd_grpc: Some(lnd),
+            ..Default::default()
+        }
+    })
+}
+
+fn clnrest(uri: &str) -> Result<NodeSettings> {
+    let url = Url::parse(uri).map_err(|e| anyhow!("Invalid clnrest URI: {}", e))?;
+    let host = url
+        .host_str()
+        .ok_or_else(|| anyhow!("clnrest URI has no host"))?;
+
+    let mut rune = None;
+    let mut protocol = "https".to_string();
+    for (key, value) in url.query_pairs() {
+        match key.as_ref() {
+            "rune" => rune = Some(value.into_owned()),
+            "protocol" => protocol = value.to_ascii_lowercase(),
+            _ => {}
+        }
+    }
+    if protocol != "http" && protocol != "https" {
+        return Err(anyhow!("Unsupported clnrest protocol '{}'", protocol));
+    }
+
+    Ok(NodeSettings {
+        node_type: "cln".into(),
+        cln: Some(ClnConfig {
+            host: format!(
+                "{}://{}:{}",
+                protocol,
+                host,
+                url.port().unwrap_or(CLNREST_PORT)
+            ),
+            rune: Some(rune.ok_or_else(|| anyhow!("clnrest URI has no rune"))?),
+            node_id: None,
+        }),
+        ..Default::default()
+    })
+}
+
+fn commando(uri: &str) -> Result<NodeSettings> {
+    let url = Url::parse(uri).map_err(|e| anyhow!("Invalid Commando URI: {}", e))?;
+    let pubkey = url.username();
+    if pubkey.len() != 66 || hex::decode(pubkey).is_err() {
+        return Err(anyhow!("Commando URI needs <node pubkey>@host:port"));
+    }
+    let host = url
+        .host_str()
+        .ok_or_else(|| anyhow!("Commando URI has no host"))?;
+    let rune = url
+        .query_pairs()
+        .find(|(key, _)| key == "rune")
+        .map(|(_, value)| value.into_owned())
+        .ok_or_else(|| anyhow!("Commando URI has no rune"))?;
+
+    Ok(NodeSettings {
+        node_type: "cln".into(),
+        cln: Some(ClnConfig {
+            host: format!("{}:{}", host, url.port().unwrap_or(LIGHTNING_PORT)),
+            rune: Some(rune),
+            node_id: Some(pubkey.to_string()),
+        }),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NODE: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+
+    #[test]
+    fn commando_uri_becomes_a_cln_node() {
+        let node = node_from_uri(&format!("commando://{}@10.0.0.5?rune=abc%3D", NODE)).unwrap();
+        assert_eq!(node.node_type, "cln");
+        let cln = node.cln.unwrap();
+        assert_eq!(cln.host, "10.0.0.5:9735");
+        assert_eq!(cln.rune.as_deref(), Some("abc="));
+        assert_eq!(cln.node_id.as_deref(), Some(NODE));
+
+        let node = node_from_uri(&format!("commando://{}@[::1]:19735?rune=abc", NODE)).unwrap();
+        assert_eq!(node.cln.unwrap().host, "[::1]:19735");
+
+        assert!(node_from_uri(&format!("commando://{}@10.0.0.5", NODE)).is_err());
+        assert!(node_from_uri("commando://10.0.0.5?rune=abc").is_err());
+    }
+}
@@ -0,0 +1,334 @@
+// lightning-client/tests/common/mod.rs
+//! Helpers shared by the integration tests.
+#![allow(dead_code)]
+
+const CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+// ---------------------------------------------------------------------
+// bech32 encoding of raw 5-bit groups, for building invoices
+// ---------------------------------------------------------------------
+
+fn polymod(values: impl Iterator<Item = u8>) -> u32 {
+    const GEN: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+    let mut chk = 1u32;
+    for v in values {
+        let top = chk >> 25;
+        chk = ((chk & 0x1ff_ffff) << 5) ^ u32::from(v);
+        for (i, g) in GEN.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                chk ^= g;
+            }
+        }
+    }
+    chk
+}
+
+pub fn to_base32(bytes: &[u8]) -> Vec<u8> {
+    let mut groups = Vec::new();
+    let (mut acc, mut bits) = (0u32, 0);
+    for b in bytes {
+        acc = (acc << 8) | u32::from(*b);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            groups.push(((acc >> bits) & 31) as u8);
+        }
+    }
+    if bits > 0 {
+        groups.push(((acc << (5 - bits)) & 31) as u8);
+    }
+    groups
+}
+
+pub fn bech32(hrp: &str, data: &[u8]) -> String {
+    let expand = hrp
+        .bytes()
+        .map(|b| b >> 5)
+        .chain([0])
+        .chain(hrp.bytes().map(|b| b & 31));
+    let chk = polymod(expand.chain(data.iter().copied()).chain([0; 6])) ^ 1;
+    let checksum = (0..6).map(|i| ((chk >> (5 * (5 - i))) & 31) as u8);
+
+    let mut s = format!("{}1", hrp);
+    s.extend(
+        data.iter()
+            .copied()
+            .chain(checksum)
+            .map(|g| CHARSET[g as usize] as char),
+    );
+    s
+}
+
+// ---------------------------------------------------------------------
+// Invoices
+// ---------------------------------------------------------------------
+
+/// Regtest invoice for `msat` (none when `None`) with a payment hash and
+/// optionally a description hash; the signature is zeros, which the client
+/// does not check.
+pub fn invoice(
+    msat: Option<u64>,
+    payment_hash: [u8; 32],
+    description_hash: Option<[u8; 32]>,
+) -> String {
+    let mut data = vec![0; 7];
+    data.extend([1, 1, 20]);
+    data.extend(to_base32(&payment_hash));
+    if let Some(hash) = description_hash {
+        data.extend([23, 1, 20]);
+        data.extend(to_base32(&hash));
+    }
+    data.extend([0; 104]);
+    match msat {
+        Some(msat) => bech32(&format!("lnbcrt{}p", msat * 10), &data),
+        None => bech32("lnbcrt", &data),
+    }
+}
+
+// ---------------------------------------------------------------------
+// Mock node
+// ---------------------------------------------------------------------
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use lightning_client::*;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// A node that makes invoices and pays them, recording what it was asked.
+#[derive(Default)]
+pub struct MockNode {
+    pub created: Mutex<Vec<(Msat, Option<String>)>>,
+    /// Every invoice `pay_invoice` was called with, including refused ones.
+    pub payments: Mutex<Vec<Bolt11>>,
+    /// Outbound liquidity `get_balance` reports.
+    pub channel_msat: AtomicU64,
+    /// Fails every call the way an unreachable node does.
+    pub down: AtomicBool,
+    /// Answers every call with an error, like a node refusing the request.
+    pub refuse: AtomicBool,
+    /// Preimage `pay_invoice` reports for every payment.
+    pub preimage: Mutex<Option<Preimage>>,
+    /// Payments `lookup_payment` reports as in flight; every other hash is
+    /// unknown.
+    pub in_flight: Mutex<Vec<PaymentHash>>,
+}
+
+impl MockNode {
+    pub fn with_liquidity(msat: u64) -> Self {
+        let node = Self::default();
+        node.channel_msat.store(msat, Ordering::SeqCst);
+        node
+    }
+
+    pub fn set_down(&self, down: bool) {
+        self.down.store(down, Ordering::SeqCst);
+    }
+
+    pub fn set_refuse(&self, refuse: bool) {
+        self.refuse.store(refuse, Ordering::SeqCst);
+    }
+
+    fn answer(&self) -> Result<()> {
+        if self.down.load(Ordering::SeqCst) {
+            return Err(io::Error::from(io::ErrorKind::ConnectionRefused).into());
+        }
+        if self.refuse.load(Ordering::SeqCst) {
+            return Err(anyhow!("mock node refused the request"));
+        }
+        Ok(())
+    }
+}
+
+macro_rules! not_mocked {
+    ($name:literal) => {
+        Err(anyhow!("{} is not mocked", $name))
+    };
+}
+
+#[async_trait]
+impl LightningClient for MockNode {
+    async fn get_info(&self) -> Result<NodeInfo> {
+        self.answer()?;
+        Ok(NodeInfo {
+            implementation: "mock".into(),
+            network: Some("regtest".into()),
+            ..Default::default()
+        })
+    }
+
+    async fn create_invoice(
+        &self,
+        amount: Msat,
+        _label: Option<&str>,
+        desc: Option<&str>,
+    ) -> Result<Bolt11> {
+        self.answer()?;
+        self.created
+            .lock()
+            .unwrap()
+            .push((amount, desc.map(ToString::to_string)));
+        invoice(Some(amount.as_msat()), rand::random(), None).parse()
+    }
+
+    async fn create_invoice_hashed(
+        &self,
+        _amount: Msat,
+        _label: Option<&str>,
+        _description: &str,
+    ) -> Result<Bolt11> {
+        not_mocked!("create_invoice_hashed")
+    }
+
+    async fn get_balance(&self) -> Result<Balance> {
+        self.answer()?;
+        Ok(Balance {
+            onchain_sat: Sat::ZERO,
+            channel_msat: Msat::from_msat(self.channel_msat.load(Ordering::SeqCst)),
+        })
+    }
+
+    async fn list_invoices(&self, _limit: Option<usize>) -> Result<Vec<Invoice>> {
+        not_mocked!("list_invoices")
+    }
+
+    async fn lookup_invoice(&self, _hash: &PaymentHash) -> Result<Option<Invoice>> {
+        not_mocked!("lookup_invoice")
+    }
+
+    async fn decode_invoice(&self, bolt11: &Bolt11) -> Result<DecodedInvoice> {
+        self.answer()?;
+        Ok(DecodedInvoice {
+            amount_msat: bolt11.amount(),
+            desc: None,
+            payee: None,
+        })
+    }
+
+    async fn pay_invoice(&self, bolt11: &Bolt11) -> Result<PaymentResult> {
+        self.payments.lock().unwrap().push(bolt11.clone());
+        self.answer()?;
+        Ok(PaymentResult {
+            hash: bolt11
+                .payment_hash()
+                .ok_or_else(|| anyhow!("Invoice has no payment hash"))?,
+            amount_msat: bolt11.amount(),
+            fee_msat: Some(Msat::ZERO),
+            preimage: *self.preimage.lock().unwrap(),
+        })
+    }
+
+    async fn lookup_payment(&self, hash: &PaymentHash) -> Result<PaymentState> {
+        self.answer()?;
+        Ok(if self.in_flight.lock().unwrap().contains(hash) {
+            PaymentState::Pending
+        } else {
+            PaymentState::Unknown
+        })
+    }
+
+    async fn sign_message(&self, _message: &str) -> Result<String> {
+        not_mocked!("sign_message")
+    }
+
+    async fn verify_message(&self, _message: &str, _signature: &str) -> Result<SignatureCheck> {
+        not_mocked!("verify_message")
+    }
+
+    async fn update_channel_policy(
+        &self,
+        _channel: &ChannelSelector,
+        _policy: &ChannelPolicy,
+    ) -> Result<()> {
+        not_mocked!("update_channel_policy")
+    }
+
+    async fn list_forwards(&self, _query: &ForwardQuery) -> Result<ForwardPage> {
+        not_mocked!("list_forwards")
+    }
+
+    async fn query_routes(&self, _dest: &PublicKey, _amount: Msat) -> Result<Route> {
+        not_mocked!("query_routes")
+    }
+
+    async fn get_node(&self, _pubkey: &PublicKey) -> Result<GraphNode> {
+        not_mocked!("get_node")
+    }
+
+    async fn get_channel(&self, _scid: ShortChannelId) -> Result<GraphChannel> {
+        not_mocked!("get_channel")
+    }
+
+    async fn probe_payment(&self, _bolt11: &Bolt11) -> Result<ProbeResult> {
+        not_mocked!("probe_payment")
+    }
+
+    async fn export_channel_backup(&self) -> Result<ChannelBackup> {
+        not_mocked!("export_channel_backup")
+    }
+
+    async fn verify_channel_backup(&self, _data: &[u8]) -> Result<BackupCheck> {
+        not_mocked!("verify_channel_backup")
+    }
+
+    async fn open_channel_psbt(&self, _open: &PsbtChannelOpen) -> Result<PsbtFunding> {
+        not_mocked!("open_channel_psbt")
+    }
+
+    async fn update_channel_psbt(&self, _id: &str, _psbt: &Psbt) -> Result<Psbt> {
+        not_mocked!("update_channel_psbt")
+    }
+
+    async fn finalize_channel_psbt(&self, _id: &str, _signed: &Psbt) -> Result<PsbtChannelOpened> {
+        not_mocked!("finalize_channel_psbt")
+    }
+
+    async fn cancel_channel_psbt(&self, _id: &str) -> Result<()> {
+        not_mocked!("cancel_channel_psbt")
+    }
+
+    async fn fund_psbt(&self, _request: &FundPsbtRequest) -> Result<FundedPsbt> {
+        not_mocked!("fund_psbt")
+    }
+
+    async fn sign_psbt(&self, _psbt: &Psbt) -> Result<Psbt> {
+        not_mocked!("sign_psbt")
+    }
+
+    async fn finalize_psbt(&self, _psbt: &Psbt) -> Result<BroadcastTx> {
+        not_mocked!("finalize_psbt")
+    }
+
+    async fn estimate_fee(&self, _target_conf: u32) -> Result<FeeEstimate> {
+        not_mocked!("estimate_fee")
+    }
+
+    async fn estimate_onchain_send(
+        &self,
+        _address: &str,
+        _amount: Sat,
+        _target_conf: u32,
+    ) -> Result<OnchainSendEstimate> {
+        self.answer()?;
+        Ok(OnchainSendEstimate {
+            fee_sat: Sat::from_sat(141),
+            sat_per_vbyte: 1.0,
+        })
+    }
+
+    async fn capabilities(&self) -> Result<Capabilities> {
+        self.answer()?;
+        Ok(Capabilities {
+            backend: "mock".into(),
+            version: None,
+            network: Some("regtest".into()),
+            supported: [
+                Capability::CreateInvoice,
+                Capability::PayInvoice,
+                Capability::FeeEstimates,
+            ]
+            .into(),
+        })
+    }
+}
@@ -0,0 +1,392 @@
+// lightning-client/tests/lnurl.rs
+//! LNURL-pay against a local stand-in service.
+mod common;
+
+use aes::cipher::{block_padding::Pkcs7, BlockEncryptMut, KeyIvInit};
+use base64::{engine::general_purpose, Engine as _};
+use common::{invoice, MockNode};
+use lightning_client::lnurl::{encode_lnurl, lnurl_url, LnurlClient, SuccessAction};
+use lightning_client::{Msat, Preimage};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use url::Url;
+
+const METADATA: &str =
+    r#"[["text/plain","Coffee for alice"],["text/identifier","alice@127.0.0.1"]]"#;
+const PREIMAGE: [u8; 32] = [7; 32];
+const IV: [u8; 16] = [9; 16];
+const SECRET: &str = "Your voucher: 1234-5678";
+const WITHDRAW_K1: &str = "e2af6254a8df433264fa23f67eb8188635d15ce883e8fc020989d5f82ae6f11e";
+
+// ---------------------------------------------------------------------
+// Stand-in service
+// ---------------------------------------------------------------------
+
+fn encrypt(message: &str) -> String {
+    let mut buf = vec![0; (message.len() / 16 + 1) * 16];
+    buf[..message.len()].copy_from_slice(message.as_bytes());
+    let ciphertext = cbc::Encryptor::<aes::Aes256>::new_from_slices(&PREIMAGE, &IV)
+        .unwrap()
+        .encrypt_padded_mut::<Pkcs7>(&mut buf, message.len())
+        .unwrap();
+    general_purpose::STANDARD.encode(ciphertext)
+}
+
+fn respond(base: &str, url: &Url) -> Value {
+    let query = |key: &str| {
+        url.query_pairs()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.into_owned())
+    };
+    let hash: [u8; 32] = Sha256::digest(METADATA.as_bytes()).into();
+
+    if let Some(user) = url.path().strip_prefix("/.well-known/lnurlp/") {
+        if user == "closed" {
+            return json!({"status": "ERROR", "reason": "Account closed"});
+        }
+        return json!({
+            "tag": "payRequest",
+            "callback": format!("{}/cb/{}", base, user),
+            "minSendable": 1_000,
+            "maxSendable": 1_000_000,
+            "metadata": METADATA,
+            "commentAllowed": 32,
+        });
+    }
+    match url.path() {
+        "/withdraw" => {
+            return json!({
+                "tag": "withdrawRequest",
+                "callback": format!("{}/withdraw/cb", base),
+                "k1": WITHDRAW_K1,
+                "defaultDescription": "Voucher",
+                "minWithdrawable": 1_000,
+                "maxWithdrawable": 50_000,
+            })
+        }
+        "/withdraw/empty" => {
+            return json!({
+                "tag": "withdrawRequest",
+                "callback": format!("{}/withdraw/cb", base),
+                "k1": WITHDRAW_K1,
+                "minWithdrawable": 50_000,
+                "maxWithdrawable": 1_000,
+            })
+        }
+        "/withdraw/cb" if query("k1").as_deref() != Some(WITHDRAW_K1) => {
+            return json!({"status": "ERROR", "reason": "Unknown k1"});
+        }
+        "/withdraw/cb" if query("pr").is_some_and(|pr| pr.starts_with("lnbcrt")) => {
+            return json!({"status": "OK"});
+        }
+        "/withdraw/cb" => return json!({"status": "ERROR", "reason": "Bad invoice"}),
+        _ => {}
+    }
+
+    let amount: u64 = query("amount").unwrap().parse().unwrap();
+    let aes = json!({
+        "tag": "aes",
+        "description": "Voucher",
+        "ciphertext": encrypt(SECRET),
+        "iv": general_purpose::STANDARD.encode(IV),
+    });
+    match url.path() {
+        "/cb/alice" => {
+            json!({"pr": invoice(Some(amount), [1; 32], Some(hash)), "routes": [], "successAction": aes})
+        }
+        "/cb/bob" => json!({
+            "pr": invoice(Some(amount), [1; 32], Some(hash)),
+            "routes": [],
+            "successAction": {"tag": "message", "message": query("comment").unwrap_or_default()},
+        }),
+        "/cb/wrong-hash" => {
+            json!({"pr": invoice(Some(amount), [1; 32], Some([0; 32])), "routes": []})
+        }
+        "/cb/wrong-amount" => {
+            json!({"pr": invoice(Some(amount + 1_000), [1; 32], Some(hash)), "routes": []})
+        }
+        "/cb/phish" => json!({
+            "pr": invoice(Some(amount), [1; 32], Some(hash)),
+            "routes": [],
+            "successAction": {"tag": "url", "description": "Receipt", "url": "https://evil.example/r"},
+        }),
+        _ => json!({"status": "ERROR", "reason": "Unknown callback"}),
+    }
+}
+
+/// Serves until the test ends; returns `127.0.0.1:port`.
+async fn stand_in() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let host = listener.local_addr().unwrap().to_string();
+    let base = format!("http://{}", host);
+
+    tokio::spawn(async move {
+        loop {
+            let Ok((mut sock, _)) = listener.accept().await else {
+                return;
+            };
+            let base = base.clone();
+            tokio::spawn(async move {
+                let mut req = Vec::new();
+                let mut buf = [0; 1024];
+                while !req.windows(4).any(|w| w == b"\r\n\r\n") {
+                    match sock.read(&mut buf).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => req.extend_from_slice(&buf[..n]),
+                    }
+                }
+                let req = String::from_utf8_lossy(&req);
+                let target = req.split(' ').nth(1).unwrap_or("/");
+                let body = respond(&base, &Url::parse(&format!("{}{}", base, target)).unwrap())
+                    .to_string();
+                let res = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = sock.write_all(res.as_bytes()).await;
+            });
+        }
+    });
+    host
+}
+
+// ---------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------
+
+#[test]
+fn targets_resolve_to_urls() {
+    assert_eq!(
+        lnurl_url("alice@example.com").unwrap().as_str(),
+        "https://example.com/.well-known/lnurlp/alice"
+    );
+    assert_eq!(
+        lnurl_url("lightning:Bob@abc.onion").unwrap().as_str(),
+        "http://abc.onion/.well-known/lnurlp/bob"
+    );
+    assert_eq!(
+        lnurl_url("lnurlp://example.com/pay/1").unwrap().as_str(),
+        "https://example.com/pay/1"
+    );
+
+    let lnurl = encode_lnurl("https://example.com/pay?q=1");
+    assert_eq!(
+        lnurl_url(&lnurl.to_ascii_lowercase()).unwrap().as_str(),
+        "https://example.com/pay?q=1"
+    );
+
+    let plain = encode_lnurl("http://example.com/pay");
+    assert!(lnurl_url(&plain).is_err());
+    assert!(lnurl_url(&lnurl[..lnurl.len() - 1]).is_err());
+    assert!(lnurl_url("al ice@example.com").is_err());
+    assert!(lnurl_url("lnbc1...").is_err());
+}
+
+#[tokio::test]
+async fn resolves_address_and_lnurl() {
+    let host = stand_in().await;
+    let client = LnurlClient::new();
+
+    let pay = client.resolve(&format!("alice@{}", host)).await.unwrap();
+    assert_eq!(pay.min_sendable, Msat::from_msat(1_000));
+    assert_eq!(pay.max_sendable, Msat::from_msat(1_000_000));
+    assert_eq!(pay.description().as_deref(), Some("Coffee for alice"));
+    assert_eq!(pay.identifier().as_deref(), Some("alice@127.0.0.1"));
+
+    let url = format!("http://{}/.well-known/lnurlp/alice", host);
+    let lnurl = encode_lnurl(&url);
+    let same = client.resolve(&lnurl).await.unwrap();
+    assert_eq!(same.callback, pay.callback);
+
+    let err = client
+        .resolve(&format!("closed@{}", host))
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("Account closed"), "{}", err);
+
+    let withdraw = encode_lnurl(&format!("http://{}/withdraw", host));
+    let err = client.resolve(&withdraw).await.unwrap_err();
+    assert!(err.to_string().contains("withdrawRequest"), "{}", err);
+}
+
+#[tokio::test]
+async fn checks_amount_and_comment_limits() {
+    let host = stand_in().await;
+    let client = LnurlClient::new();
+    let pay = client.resolve(&format!("bob@{}", host)).await.unwrap();
+
+    for msat in [999, 1_000_001] {
+        let err = client
+            .fetch_invoice(&pay, Msat::from_msat(msat), None)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("outside"), "{}", err);
+    }
+
+    let long = "x".repeat(33);
+    assert!(client
+        .fetch_invoice(&pay, Msat::from_msat(5_000), Some(&long))
+        .await
+        .is_err());
+
+    let invoice = client
+        .fetch_invoice(&pay, Msat::from_msat(5_000), Some("thanks!"))
+        .await
+        .unwrap();
+    assert_eq!(invoice.bolt11.amount(), Some(Msat::from_msat(5_000)));
+    match invoice.success_action {
+        Some(SuccessAction::Message { message }) => assert_eq!(message, "thanks!"),
+        other => panic!("unexpected success action {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn rejects_invoices_that_do_not_match() {
+    let host = stand_in().await;
+    let client = LnurlClient::new();
+
+    for (user, expected) in [
+        ("wrong-hash", "description hash"),
+        ("wrong-amount", "instead of"),
+        ("phish", "callback's domain"),
+    ] {
+        let pay = client.resolve(&format!("{}@{}", user, host)).await.unwrap();
+        let err = client
+            .fetch_invoice(&pay, Msat::from_msat(2_000), None)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains(expected), "{}: {}", user, err);
+    }
+}
+
+#[tokio::test]
+async fn decrypts_aes_success_action() {
+    let host = stand_in().await;
+    let client = LnurlClient::new();
+    let pay = client.resolve(&format!("alice@{}", host)).await.unwrap();
+    let invoice = client
+        .fetch_invoice(&pay, Msat::from_msat(21_000), None)
+        .await
+        .unwrap();
+    let hash: [u8; 32] = Sha256::digest(METADATA.as_bytes()).into();
+    assert_eq!(invoice.bolt11.description_hash(), Some(hash));
+
+    let action = invoice.success_action.unwrap();
+    let preimage = Preimage::from_slice(&PREIMAGE).unwrap();
+    assert_eq!(action.decrypt(&preimage).unwrap(), SECRET);
+
+    let wrong = Preimage::from_slice(&[8; 32]).unwrap();
+    assert!(action.decrypt(&wrong).is_err());
+}
+
+#[tokio::test]
+async fn payment_reports_an_undecryptable_success_action() {
+    let host = stand_in().await;
+    let client = LnurlClient::new();
+    let node = MockNode::default();
+    let target = format!("alice@{}", host);
+
+    *node.preimage.lock().unwrap() = Some(Preimage::from_slice(&PREIMAGE).unwrap());
+    let paid = client
+        .pay(&node, &target, Msat::from_msat(21_000), None)
+        .await
+        .unwrap();
+    assert_eq!(paid.decrypted.as_deref(), Some(SECRET));
+    assert!(paid.decrypt_error.is_none());
+
+    // Paid all the same; only the message is lost.
+    *node.preimage.lock().unwrap() = Some(Preimage::from_slice(&[8; 32]).unwrap());
+    let paid = client
+        .pay(&node, &target, Msat::from_msat(21_000), None)
+        .await
+        .unwrap();
+    assert!(paid.decrypted.is_none());
+    assert!(paid.decrypt_error.unwrap().contains("preimage"));
+    assert_eq!(node.payments.lock().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn withdraws_within_the_offered_range() {
+    let host = stand_in().await;
+    let client = LnurlClient::new();
+    let lnurl = encode_lnurl(&format!("http://{}/withdraw", host));
+
+    let withdraw = client.resolve_withdraw(&lnurl).await.unwrap();
+    assert_eq!(withdraw.k1, WITHDRAW_K1);
+    assert_eq!(withdraw.min_withdrawable, Msat::from_msat(1_000));
+    assert_eq!(withdraw.max_withdrawable, Msat::from_msat(50_000));
+
+    let node = MockNode::default();
+    let bolt11 = client
+        .withdraw(&node, &withdraw, Msat::from_msat(21_000), None)
+        .await
+        .unwrap();
+    assert_eq!(bolt11.amount(), Some(Msat::from_msat(21_000)));
+    assert_eq!(
+        *node.created.lock().unwrap(),
+        vec![(Msat::from_msat(21_000), Some("Voucher".to_string()))]
+    );
+
+    client
+        .withdraw(&node, &withdraw, Msat::from_msat(2_000), Some("Change"))
+        .await
+        .unwrap();
+    assert_eq!(node.created.lock().unwrap()[1].1.as_deref(), Some("Change"));
+}
+
+#[tokio::test]
+async fn withdraw_outside_the_range_makes_no_invoice() {
+    let host = stand_in().await;
+    let client = LnurlClient::new();
+    let withdraw = client
+        .resolve_withdraw(&format!("lnurlw://{}/withdraw", host))
+        .await
+        .unwrap();
+
+    let node = MockNode::default();
+    for msat in [999, 50_001] {
+        let err = client
+            .withdraw(&node, &withdraw, Msat::from_msat(msat), None)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("outside"), "{}", err);
+    }
+    assert!(node.created.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn withdraw_needs_the_service_to_confirm() {
+    let host = stand_in().await;
+    let client = LnurlClient::new();
+    let mut withdraw = client
+        .resolve_withdraw(&format!("lnurlw://{}/withdraw", host))
+        .await
+        .unwrap();
+    withdraw.k1 = "00".repeat(32);
+
+    let err = client
+        .withdraw(
+            &MockNode::default(),
+            &withdraw,
+            Msat::from_msat(5_000),
+            None,
+        )
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("Unknown k1"), "{}", err);
+
+    let err = client
+        .resolve_withdraw(&format!("lnurlw://{}/withdraw/empty", host))
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("empty amount range"), "{}", err);
+
+    let err = client
+        .resolve_withdraw(&format!("alice@{}", host))
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("withdrawRequest"), "{}", err);
+}
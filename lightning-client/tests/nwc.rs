@@ -0,0 +1,264 @@
+// lightning-client/tests/nwc.rs
+//! NWC payments against a local stand-in relay that also plays the wallet.
+#![cfg(feature = "nwc")]
+mod common;
+
+use common::invoice;
+use futures_util::{SinkExt, StreamExt};
+use lightning_client::nwc::NwcClient;
+use lightning_client::{
+    Bolt11, LightningClient, Msat, PaymentHash, PaymentPending, PaymentState, Preimage,
+};
+use nostr::event::FinalizeEvent;
+use nostr::nips::nip47::{
+    ErrorCode, LookupInvoiceResponse, Method, NIP47Error, Nip47Ciphers, NostrWalletConnectUri,
+    PayInvoiceResponse, Request, RequestParams, Response, ResponseResult, TransactionState,
+    TransactionType,
+};
+use nostr::prelude::{Event, EventBuilder, Keys, Kind, RelayUrl, Tag, Timestamp};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio_tungstenite::tungstenite::Message;
+
+const PREIMAGE: [u8; 32] = [5; 32];
+
+/// How the wallet answers a request; `None` leaves it unanswered.
+type Wallet = Arc<dyn Fn(RequestParams) -> Option<Response> + Send + Sync>;
+
+// ---------------------------------------------------------------------
+// Stand-in relay and wallet
+// ---------------------------------------------------------------------
+
+fn pay_reply(fees: u64) -> Response {
+    Response {
+        result_type: Method::PayInvoice,
+        error: None,
+        result: Some(ResponseResult::PayInvoice(PayInvoiceResponse {
+            preimage: hex::encode(PREIMAGE),
+            fees_paid: Some(fees),
+        })),
+    }
+}
+
+fn error_reply(method: Method, code: ErrorCode) -> Response {
+    Response {
+        result_type: method,
+        error: Some(NIP47Error {
+            code,
+            message: "stand-in says no".into(),
+        }),
+        result: None,
+    }
+}
+
+fn lookup_reply(state: TransactionState, amount: u64, fees: u64) -> Response {
+    let hash = Preimage::from_slice(&PREIMAGE).unwrap().payment_hash();
+    Response {
+        result_type: Method::LookupInvoice,
+        error: None,
+        result: Some(ResponseResult::LookupInvoice(LookupInvoiceResponse {
+            transaction_type: Some(TransactionType::Outgoing),
+            state: Some(state),
+            invoice: None,
+            description: None,
+            description_hash: None,
+            preimage: Some(hex::encode(PREIMAGE)),
+            payment_hash: hash.to_string(),
+            amount,
+            fees_paid: fees,
+            created_at: Timestamp::from(0),
+            expires_at: None,
+            settled_at: None,
+            metadata: None,
+        })),
+    }
+}
+
+/// Answers a wallet request event the way `wallet` says.
+fn answer(keys: &Keys, wallet: &Wallet, event: &Event) -> Option<Event> {
+    let cipher = Nip47Ciphers::NIP04;
+    let plain = cipher
+        .decrypt(keys.secret_key(), &event.pubkey, &event.content)
+        .unwrap();
+    let req = Request::from_value(serde_json::from_str(&plain).unwrap()).unwrap();
+    let res = wallet(req.params)?;
+    let content = cipher
+        .encrypt(
+            keys.secret_key(),
+            &event.pubkey,
+            &serde_json::to_string(&res).unwrap(),
+        )
+        .unwrap();
+    EventBuilder::new(Kind::WalletConnectResponse, content)
+        .tag(Tag::public_key(event.pubkey))
+        .tag(Tag::event(event.id))
+        .finalize(keys)
+        .ok()
+}
+
+/// Relays the client's requests straight to `wallet` and sends its replies
+/// to every open subscription. Serves until the test ends; returns the
+/// connection URI.
+async fn stand_in(wallet: Wallet) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    let keys = Keys::generate();
+    let uri = NostrWalletConnectUri::new(
+        keys.public_key(),
+        vec![RelayUrl::parse(&url).unwrap()],
+        Keys::generate().secret_key().clone(),
+        None,
+    );
+
+    tokio::spawn(async move {
+        loop {
+            let Ok((sock, _)) = listener.accept().await else {
+                return;
+            };
+            let (keys, wallet) = (keys.clone(), Arc::clone(&wallet));
+            tokio::spawn(async move {
+                let Ok(ws) = tokio_tungstenite::accept_async(sock).await else {
+                    return;
+                };
+                let (mut write, mut read) = ws.split();
+                let mut subs: Vec<String> = Vec::new();
+                while let Some(Ok(Message::Text(text))) = read.next().await {
+                    let msg: Value = serde_json::from_str(&text).unwrap();
+                    let mut out = Vec::new();
+                    match msg[0].as_str() {
+                        Some("REQ") => {
+                            let sub = msg[1].as_str().unwrap().to_string();
+                            // An info event without ciphers, so NIP-04 is used.
+                            if sub == "nwc-info" {
+                                let info = EventBuilder::new(
+                                    Kind::WalletConnectInfo,
+                                    "pay_invoice lookup_invoice",
+                                )
+                                .finalize(&keys)
+                                .unwrap();
+                                out.push(json!(["EVENT", sub, info]));
+                            }
+                            subs.push(sub);
+                        }
+                        Some("CLOSE") => subs.retain(|s| msg[1] != *s),
+                        Some("EVENT") => {
+                            let event: Event = serde_json::from_value(msg[1].clone()).unwrap();
+                            if let Some(reply) = answer(&keys, &wallet, &event) {
+                                out.extend(subs.iter().map(|s| json!(["EVENT", s, reply])));
+                            }
+                        }
+                        _ => {}
+                    }
+                    for msg in out {
+                        if write.send(Message::Text(msg.to_string())).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+            });
+        }
+    });
+    uri.to_string()
+}
+
+fn bolt11(msat: Option<u64>) -> Bolt11 {
+    let hash = Preimage::from_slice(&PREIMAGE).unwrap().payment_hash();
+    invoice(msat, *hash.as_bytes(), None).parse().unwrap()
+}
+
+// ---------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------
+
+#[tokio::test]
+async fn amount_comes_from_the_invoice() {
+    let wallet: Wallet = Arc::new(|params| match params {
+        RequestParams::PayInvoice(_) => Some(pay_reply(12)),
+        _ => panic!("no lookup needed"),
+    });
+    let client = NwcClient::connect(&stand_in(wallet).await).await.unwrap();
+
+    let payment = client.pay_invoice(&bolt11(Some(5_000))).await.unwrap();
+    assert_eq!(payment.amount_msat, Some(Msat::from_msat(5_000)));
+    assert_eq!(payment.fee_msat, Some(Msat::from_msat(12)));
+    assert_eq!(
+        payment.preimage,
+        Some(Preimage::from_slice(&PREIMAGE).unwrap())
+    );
+    assert_eq!(
+        payment.hash,
+        Preimage::from_slice(&PREIMAGE).unwrap().payment_hash()
+    );
+}
+
+#[tokio::test]
+async fn amountless_invoice_takes_the_amount_from_the_wallet() {
+    let wallet: Wallet = Arc::new(|params| match params {
+        RequestParams::PayInvoice(_) => Some(pay_reply(3)),
+        RequestParams::LookupInvoice(_) => Some(lookup_reply(TransactionState::Settled, 7_000, 3)),
+        _ => None,
+    });
+    let client = NwcClient::connect(&stand_in(wallet).await).await.unwrap();
+
+    let payment = client.pay_invoice(&bolt11(None)).await.unwrap();
+    assert_eq!(payment.amount_msat, Some(Msat::from_msat(7_000)));
+    assert_eq!(payment.fee_msat, Some(Msat::from_msat(3)));
+}
+
+#[tokio::test]
+async fn amountless_invoice_is_paid_even_when_the_amount_is_unknown() {
+    let wallet: Wallet = Arc::new(|params| match params {
+        RequestParams::PayInvoice(_) => Some(pay_reply(0)),
+        RequestParams::LookupInvoice(_) => Some(error_reply(
+            Method::LookupInvoice,
+            ErrorCode::NotImplemented,
+        )),
+        _ => None,
+    });
+    let client = NwcClient::connect(&stand_in(wallet).await).await.unwrap();
+
+    let payment = client.pay_invoice(&bolt11(None)).await.unwrap();
+    assert_eq!(payment.amount_msat, None);
+    assert!(payment.preimage.is_some());
+}
+
+#[tokio::test]
+async fn wallet_error_is_a_definite_failure() {
+    let wallet: Wallet = Arc::new(|params| match params {
+        RequestParams::PayInvoice(_) => {
+            Some(error_reply(Method::PayInvoice, ErrorCode::PaymentFailed))
+        }
+        _ => None,
+    });
+    let client = NwcClient::connect(&stand_in(wallet).await).await.unwrap();
+
+    let err = client.pay_invoice(&bolt11(Some(1_000))).await.unwrap_err();
+    assert!(!PaymentPending::is(&err), "{}", err);
+    assert!(err.to_string().contains("stand-in says no"), "{}", err);
+}
+
+#[tokio::test]
+async fn lookup_reports_payment_state() {
+    let wallet: Wallet = Arc::new(|params| match params {
+        RequestParams::LookupInvoice(req)
+            if req.payment_hash.as_deref() == Some(&"00".repeat(32)) =>
+        {
+            Some(error_reply(Method::LookupInvoice, ErrorCode::NotFound))
+        }
+        RequestParams::LookupInvoice(_) => Some(lookup_reply(TransactionState::Pending, 1_000, 0)),
+        _ => None,
+    });
+    let client = NwcClient::connect(&stand_in(wallet).await).await.unwrap();
+
+    let hash = Preimage::from_slice(&PREIMAGE).unwrap().payment_hash();
+    assert!(matches!(
+        client.lookup_payment(&hash).await.unwrap(),
+        PaymentState::Pending
+    ));
+    let unknown = PaymentHash::from_slice(&[0; 32]).unwrap();
+    assert!(matches!(
+        client.lookup_payment(&unknown).await.unwrap(),
+        PaymentState::Unknown
+    ));
+}
@@ -0,0 +1,184 @@
+// lightning-client/tests/pool.rs
+//! Pool failover and payment pinning over mock members.
+mod common;
+
+use common::{invoice, MockNode};
+use lightning_client::config::{InvoiceStrategy, PaymentStrategy, PoolConfig};
+use lightning_client::pool::LightningPool;
+use lightning_client::{Bolt11, Capability, LightningClient, LightningClientDyn, Msat, Sat};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+fn pool(members: &[&Arc<MockNode>], payment_strategy: PaymentStrategy) -> LightningPool {
+    let cfg = PoolConfig {
+        members: Vec::new(),
+        invoice_strategy: InvoiceStrategy::Failover,
+        payment_strategy,
+    };
+    let members = members
+        .iter()
+        .enumerate()
+        .map(|(i, node)| {
+            let client: LightningClientDyn = Arc::clone(node) as LightningClientDyn;
+            (format!("node{}", i), client)
+        })
+        .collect();
+    LightningPool::new(members, &cfg).unwrap()
+}
+
+fn healthy(pool: &LightningPool) -> Vec<bool> {
+    pool.health().into_iter().map(|m| m.healthy).collect()
+}
+
+fn bolt11(msat: u64, hash: u8) -> Bolt11 {
+    invoice(Some(msat), [hash; 32], None).parse().unwrap()
+}
+
+#[tokio::test]
+async fn unreachable_member_is_skipped() {
+    let (a, b) = (Arc::new(MockNode::default()), Arc::new(MockNode::default()));
+    let pool = pool(&[&a, &b], PaymentStrategy::Failover);
+
+    a.set_down(true);
+    pool.create_invoice(Msat::from_msat(1_000), None, None)
+        .await
+        .unwrap();
+    assert_eq!(healthy(&pool), [false, true]);
+
+    // Back up, but still skipped until its retry window comes round.
+    a.set_down(false);
+    pool.create_invoice(Msat::from_msat(2_000), None, None)
+        .await
+        .unwrap();
+    assert!(a.created.lock().unwrap().is_empty());
+    assert_eq!(b.created.lock().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn refused_request_does_not_mark_a_member_down() {
+    let (a, b) = (Arc::new(MockNode::default()), Arc::new(MockNode::default()));
+    let pool = pool(&[&a, &b], PaymentStrategy::Failover);
+
+    a.set_refuse(true);
+    pool.create_invoice(Msat::from_msat(1_000), None, None)
+        .await
+        .unwrap();
+    assert_eq!(b.created.lock().unwrap().len(), 1);
+    assert_eq!(healthy(&pool), [true, true]);
+
+    a.set_refuse(false);
+    pool.create_invoice(Msat::from_msat(2_000), None, None)
+        .await
+        .unwrap();
+    assert_eq!(a.created.lock().unwrap().len(), 1);
+
+    b.set_refuse(true);
+    a.set_refuse(true);
+    let err = pool
+        .create_invoice(Msat::from_msat(3_000), None, None)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("node0"), "{}", err);
+    assert!(err.to_string().contains("node1"), "{}", err);
+    assert_eq!(healthy(&pool), [true, true]);
+}
+
+#[tokio::test]
+async fn refused_capabilities_call_does_not_mark_a_member_down() {
+    let (a, b) = (Arc::new(MockNode::default()), Arc::new(MockNode::default()));
+    let pool = pool(&[&a, &b], PaymentStrategy::Failover);
+
+    a.set_refuse(true);
+    let caps = pool.capabilities().await.unwrap();
+    assert!(caps.supports(Capability::PayInvoice));
+    assert_eq!(healthy(&pool), [true, true]);
+
+    b.set_down(true);
+    pool.capabilities().await.unwrap_err();
+    assert_eq!(healthy(&pool), [true, false]);
+}
+
+#[tokio::test]
+async fn advertised_fee_estimates_work() {
+    let (a, b) = (Arc::new(MockNode::default()), Arc::new(MockNode::default()));
+    let pool = pool(&[&a, &b], PaymentStrategy::Failover);
+
+    assert!(pool
+        .capabilities()
+        .await
+        .unwrap()
+        .supports(Capability::FeeEstimates));
+    a.set_down(true);
+    let estimate = pool
+        .estimate_onchain_send("bcrt1qaddress", Sat::from_sat(10_000), 6)
+        .await
+        .unwrap();
+    assert_eq!(estimate.fee_sat, Sat::from_sat(141));
+}
+
+#[tokio::test]
+async fn payment_goes_to_the_first_member_with_liquidity() {
+    let a = Arc::new(MockNode::with_liquidity(1_000));
+    let b = Arc::new(MockNode::with_liquidity(100_000));
+    let pool = pool(&[&a, &b], PaymentStrategy::Liquidity);
+
+    let payment = pool.pay_invoice(&bolt11(5_000, 1)).await.unwrap();
+    assert_eq!(payment.amount_msat, Some(Msat::from_msat(5_000)));
+    assert!(a.payments.lock().unwrap().is_empty());
+    assert_eq!(b.payments.lock().unwrap().len(), 1);
+
+    pool.pay_invoice(&bolt11(500, 2)).await.unwrap();
+    assert_eq!(a.payments.lock().unwrap().len(), 1);
+
+    let err = pool.pay_invoice(&bolt11(500_000, 3)).await.unwrap_err();
+    assert!(err.to_string().contains("liquidity"), "{}", err);
+}
+
+#[tokio::test]
+async fn invoice_stays_pinned_to_its_first_payer() {
+    let a = Arc::new(MockNode::with_liquidity(0));
+    let b = Arc::new(MockNode::with_liquidity(100_000));
+    let pool = pool(&[&a, &b], PaymentStrategy::Liquidity);
+    let pr = bolt11(5_000, 1);
+    pool.pay_invoice(&pr).await.unwrap();
+
+    // An earlier attempt may have sent HTLCs, so a retry never moves, even
+    // when another member could now pay or the first one is unreachable.
+    a.channel_msat.store(100_000, Ordering::SeqCst);
+    b.channel_msat.store(0, Ordering::SeqCst);
+    pool.pay_invoice(&pr).await.unwrap();
+    b.set_down(true);
+    pool.pay_invoice(&pr).await.unwrap_err();
+
+    assert!(a.payments.lock().unwrap().is_empty());
+    assert_eq!(b.payments.lock().unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn unpinned_invoice_goes_to_the_member_that_knows_it() {
+    let a = Arc::new(MockNode::with_liquidity(100_000));
+    let b = Arc::new(MockNode::with_liquidity(100_000));
+    let pr = bolt11(5_000, 1);
+    b.in_flight.lock().unwrap().push(pr.payment_hash().unwrap());
+
+    // A fresh pool, as after a restart: nothing is pinned, yet b already
+    // has the payment in flight.
+    let pool = pool(&[&a, &b], PaymentStrategy::Liquidity);
+    pool.pay_invoice(&pr).await.unwrap();
+    assert!(a.payments.lock().unwrap().is_empty());
+    assert_eq!(b.payments.lock().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn unpinned_invoice_waits_for_every_member_to_answer() {
+    let a = Arc::new(MockNode::with_liquidity(100_000));
+    let b = Arc::new(MockNode::with_liquidity(100_000));
+    let pool = pool(&[&a, &b], PaymentStrategy::Liquidity);
+
+    // b may be the one that took the invoice before a restart.
+    b.set_down(true);
+    let err = pool.pay_invoice(&bolt11(5_000, 1)).await.unwrap_err();
+    assert!(format!("{:#}", err).contains("node1"), "{:#}", err);
+    assert!(a.payments.lock().unwrap().is_empty());
+    assert!(b.payments.lock().unwrap().is_empty());
+}
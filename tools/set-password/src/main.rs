@@ -52,6 +52,11 @@ fn main() -> Result<()> {
 
 // Find config.toml at workspace root (robust)
 fn find_config_toml() -> Result<PathBuf> {
+    // 0. Same override the API server honours
+    if let Ok(path) = env::var("LIGHTNING_DRIVER_CONFIG") {
+        return Ok(PathBuf::from(path));
+    }
+
     // 1. Use CARGO_MANIFEST_DIR (set by `cargo run`) - go up 2 levels for tools/set-password
     if let Ok(manifest_dir) = env::var("CARGO_MANIFEST_DIR") {
         let path = Path::new(&manifest_dir)